@@ -0,0 +1,83 @@
+//! Inspect the shared state of a running NF: list the entries of its directory and dump shared vectors.
+extern crate e2d2;
+extern crate getopts;
+use e2d2::shared_state::directory::DirectoryReader;
+use e2d2::shared_state::SharedVecReader;
+use getopts::Options;
+use std::env;
+use std::process;
+
+fn print_usage(program: &str, opts: &Options) {
+    let brief = format!("Usage: {} -d DIRECTORY [-v VECTOR]", program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn hex_dump(bytes: &[u8], value_size: usize) {
+    for (i, value) in bytes.chunks(value_size.max(1)).enumerate() {
+        let hex: Vec<String> = value.iter().map(|b| format!("{:02x}", b)).collect();
+        println!("{:6}: {}", i, hex.join(" "));
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("d", "directory", "shared memory name of the directory", "name");
+    opts.optmulti("v", "vector", "dump a shared vector", "name");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(f) => {
+            eprintln!("{}", f);
+            process::exit(1);
+        }
+    };
+    if matches.opt_present("h") || (!matches.opt_present("d") && !matches.opt_present("v")) {
+        print_usage(&program, &opts);
+        process::exit(0);
+    }
+
+    if let Some(name) = matches.opt_str("d") {
+        let entries = DirectoryReader::open(&name).and_then(|directory| {
+            let version = directory.version();
+            directory.entries().map(|entries| (version, entries))
+        });
+        match entries {
+            Ok((version, entries)) => {
+                println!("directory {} version {}, {} entries", name, version, entries.len());
+                for entry in entries {
+                    println!("  {}", entry);
+                }
+            }
+            Err(e) => {
+                eprintln!("cannot read directory {}: {}", name, e);
+                process::exit(1);
+            }
+        }
+    }
+
+    for name in matches.opt_strs("v") {
+        let dump = SharedVecReader::open(&name).and_then(|vec| {
+            let bytes = vec.read_bytes()?;
+            Ok((vec.version(), vec.value_size(), vec.capacity(), bytes))
+        });
+        match dump {
+            Ok((version, value_size, capacity, bytes)) => {
+                println!(
+                    "vector {} version {}, {} of {} values of {} bytes",
+                    name,
+                    version,
+                    bytes.len() / value_size.max(1),
+                    capacity,
+                    value_size
+                );
+                hex_dump(&bytes, value_size);
+            }
+            Err(e) => {
+                eprintln!("cannot read vector {}: {}", name, e);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -120,6 +120,7 @@ pub enum ErrorKind {
     FailedToInitializePort(u16),
     FailedToInitializeOvsPort(i32),
     FailedToInitializeBessPort(i32),
+    FailedToInitializeRingPort(i32),
     FailedToInitializeKni(String),
     BadQueue,
     CannotSend,
@@ -1,9 +1,9 @@
 use super::super::interface::{FlowSteeringMode, NetSpec};
-use super::{DriverType, NetbricksConfiguration, PortConfiguration};
+use super::{DriverType, MempoolSelection, NetbricksConfiguration, PortConfiguration};
 use common::errors;
 use common::errors::ErrorKind;
 use eui48::MacAddress;
-use ipnet::Ipv4Net;
+use ipnet::{Ipv4Net, Ipv6Net};
 use native::zcsi::{RteEthIpv4Flow, RteFdirConf, RteFdirMode, RteFdirPballocType};
 use std::clone::Clone;
 use std::collections::BTreeMap;
@@ -19,6 +19,18 @@ use toml::{self, Value};
 
 /// Default configuration values
 pub const DEFAULT_MBUF_CNT: u32 = 65535;
+/// Lower bound for automatically sized mempools.
+pub const MIN_MBUF_CNT: u32 = 4095;
+/// Mbufs reserved per core for packets held in operator queues, tx buffers, etc. when sizing mempools.
+pub const MBUFS_IN_FLIGHT_PER_CORE: u32 = 2048;
+/// Headroom in front of the packet data, see RTE_PKTMBUF_HEADROOM.
+pub const MBUF_HEADROOM: u16 = 128;
+/// Default mbuf data room (RTE_MBUF_DEFAULT_BUF_SIZE), sufficient for a 1500 byte MTU.
+pub const DEFAULT_MBUF_DATA_ROOM: u16 = 2048 + MBUF_HEADROOM;
+/// Smallest MTU accepted for a port (RTE_ETHER_MIN_MTU).
+pub const MIN_MTU: u16 = 68;
+/// Largest MTU accepted for a port, enough for 9000 byte jumbo frames plus encapsulation overhead.
+pub const MAX_MTU: u16 = 9600;
 pub const DEFAULT_POOL_SIZE: u32 = 2048;
 pub const DEFAULT_CACHE_SIZE: u32 = 32;
 pub const DEFAULT_SECONDARY: bool = false;
@@ -111,6 +123,12 @@ fn read_port(value: &Value) -> errors::Result<PortConfiguration> {
                 v => return Err(ErrorKind::ConfigurationError(format!("Could not parse ipnet spec {:?}", v)).into()),
             };
 
+            let ipv6_net = match port_def.get("ipv6net") {
+                Some(&Value::String(ref s_ipnet)) => s_ipnet.parse::<Ipv6Net>().ok(),
+                None => None,
+                v => return Err(ErrorKind::ConfigurationError(format!("Could not parse ipv6net spec {:?}", v)).into()),
+            };
+
             let mac = match port_def.get("mac") {
                 Some(&Value::String(ref s_mac)) => s_mac.parse::<MacAddress>().ok(),
                 None => None,
@@ -274,6 +292,41 @@ fn read_port(value: &Value) -> errors::Result<PortConfiguration> {
                 None => None,
             };
 
+            let mempool = match port_def.get("mempool") {
+                Some(&Value::String(ref selection)) => match &selection[..] {
+                    "local" => MempoolSelection::Local,
+                    "device" => MempoolSelection::Device,
+                    _ => {
+                        return Err(ErrorKind::ConfigurationError(format!(
+                            "Could not parse mempool selection {}",
+                            selection
+                        ))
+                        .into())
+                    }
+                },
+                Some(&Value::Integer(socket)) if socket >= 0 => MempoolSelection::Socket(socket as i32),
+                None => MempoolSelection::Local,
+                v => {
+                    return Err(ErrorKind::ConfigurationError(format!(
+                        "Could not parse mempool selection {:?}",
+                        v
+                    ))
+                    .into())
+                }
+            };
+
+            let mtu = match port_def.get("mtu") {
+                Some(&Value::Integer(mtu)) if mtu >= MIN_MTU as i64 && mtu <= MAX_MTU as i64 => Some(mtu as u16),
+                None => None,
+                v => {
+                    return Err(ErrorKind::ConfigurationError(format!(
+                        "Could not parse mtu {:?}, must be within [{}, {}]",
+                        v, MIN_MTU, MAX_MTU
+                    ))
+                    .into())
+                }
+            };
+
             let driver = match port_def.get("driver") {
                 //TODO replace unwrap() with error conversion
                 Some(v) => v.clone().try_into::<DriverType>().unwrap(),
@@ -282,6 +335,7 @@ fn read_port(value: &Value) -> errors::Result<PortConfiguration> {
 
             let net_spec = NetSpec {
                 ip_net,
+                ipv6_net,
                 mac,
                 nsname,
                 ..Default::default()
@@ -289,6 +343,7 @@ fn read_port(value: &Value) -> errors::Result<PortConfiguration> {
 
             let has_netspec = net_spec.mac.is_some()
                 || net_spec.ip_net.is_some()
+                || net_spec.ipv6_net.is_some()
                 || net_spec.port.is_some()
                 || net_spec.nsname.is_some();
 
@@ -307,6 +362,8 @@ fn read_port(value: &Value) -> errors::Result<PortConfiguration> {
                 flow_steering,
                 driver,
                 net_spec: if has_netspec { Some(net_spec) } else { None },
+                mempool,
+                mtu,
             })
         }
         _ => Err(ErrorKind::ConfigurationError(String::from("Could not understand port spec")).into()),
@@ -387,14 +444,25 @@ pub fn read_configuration_from_str(configuration: &str, filename: &str) -> error
 
     // Get mbuf count for the mbuf pool
     let mbuf_cnt = match toml.get("mbuf_cnt") {
-        Some(&Value::Integer(cnt)) => cnt as u32,
-        None => DEFAULT_MBUF_CNT,
+        Some(&Value::Integer(cnt)) => Some(cnt as u32),
+        Some(&Value::String(ref cnt)) if cnt == "auto" => None,
+        None => Some(DEFAULT_MBUF_CNT),
         _ => {
             error!("Could not parse mbuf count");
             return Err(ErrorKind::ConfigurationError(String::from("Could not parse mbuf count")).into());
         }
     };
 
+    // Get size of the mbuf data buffers, e.g. 9216 + 128 for jumbo frames
+    let mbuf_data_room = match toml.get("mbuf_data_room") {
+        Some(&Value::Integer(size)) if size > MBUF_HEADROOM as i64 && size <= u16::max_value() as i64 => size as u16,
+        None => DEFAULT_MBUF_DATA_ROOM,
+        v => {
+            error!("Could not parse mbuf data room size");
+            return Err(ErrorKind::ConfigurationError(format!("Could not parse mbuf data room size {:?}", v)).into());
+        }
+    };
+
     // Is process a secondary process
     let secondary = match toml.get("secondary") {
         Some(&Value::Boolean(secondary)) => secondary,
@@ -480,6 +548,7 @@ pub fn read_configuration_from_str(configuration: &str, filename: &str) -> error
         ports,
         vdevs,
         mbuf_cnt,
+        mbuf_data_room,
     })
 }
 
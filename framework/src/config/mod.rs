@@ -1,6 +1,7 @@
 pub use self::config_reader::*;
 pub use self::flag_reader::*;
 use native::zcsi::RteFdirConf;
+use std::cmp::max;
 use std::fmt;
 use interface::{FlowSteeringMode, NetSpec};
 
@@ -32,8 +33,12 @@ pub struct NetbricksConfiguration {
     pub pool_size: u32,
     /// Size of the per-core mempool cache.
     pub cache_size: u32,
-    /// number of mbufs in the mbuf pool, should be (2**N - 1) for some positive integral N, default 65535
-    pub mbuf_cnt: u32,
+    /// number of mbufs in the mbuf pool of each NUMA socket, should be (2**N - 1) for some positive integral N.
+    /// Defaults to `DEFAULT_MBUF_CNT`, `None` (`mbuf_cnt = "auto"`) sizes the pools from the port and queue topology,
+    /// see `mbufs_per_socket`.
+    pub mbuf_cnt: Option<u32>,
+    /// Size of the data buffer of an mbuf including the headroom, must be increased for jumbo frames.
+    pub mbuf_data_room: u16,
 }
 
 /// Create an empty `NetbricksConfiguration`, useful when initializing through arguments.
@@ -49,7 +54,8 @@ impl Default for NetbricksConfiguration {
             secondary: false,
             ports: vec![],
             vdevs: vec![],
-            mbuf_cnt: DEFAULT_MBUF_CNT,
+            mbuf_cnt: Some(DEFAULT_MBUF_CNT),
+            mbuf_data_room: DEFAULT_MBUF_DATA_ROOM,
         }
     }
 }
@@ -70,14 +76,80 @@ impl NetbricksConfiguration {
         }
         m
     }
+
+    /// Number of mbufs of the mempool on each NUMA socket, indexed by socket id. Sockets without pool get 0.
+    /// `socket_of_core` maps a core to its NUMA socket, `socket_of_device` a port name to the socket of its device, if
+    /// known.
+    ///
+    /// If `mbuf_cnt` is set, each socket used by a core (or selected by a port) gets a pool of this size. Otherwise a
+    /// socket gets the rx and tx descriptors of all queues served by its cores, plus the mempool cache and
+    /// `MBUFS_IN_FLIGHT_PER_CORE` for each of its cores, rounded up to the next (2**N - 1).
+    pub fn mbufs_per_socket<F, G>(&self, socket_of_core: F, socket_of_device: G) -> Vec<u32>
+    where
+        F: Fn(i32) -> i32,
+        G: Fn(&str) -> Option<i32>,
+    {
+        fn add(counts: &mut Vec<u32>, socket: i32, n: u32) {
+            let socket = max(socket, 0) as usize;
+            if counts.len() <= socket {
+                counts.resize(socket + 1, 0);
+            }
+            counts[socket] += n;
+        }
+
+        let mut cores = self.cores.clone();
+        cores.push(self.primary_core);
+        for port in &self.ports {
+            cores.extend(port.rx_queues.iter().chain(port.tx_queues.iter()));
+        }
+        cores.sort();
+        cores.dedup();
+
+        let mut counts = Vec::with_capacity(4);
+        for &core in &cores {
+            add(
+                &mut counts,
+                socket_of_core(core),
+                self.cache_size * 3 / 2 + MBUFS_IN_FLIGHT_PER_CORE,
+            );
+        }
+        for port in &self.ports {
+            let device_socket = match port.mempool {
+                MempoolSelection::Device => socket_of_device(&port.name),
+                _ => None,
+            };
+            for &core in &port.rx_queues {
+                let socket = match port.mempool {
+                    MempoolSelection::Socket(s) => s,
+                    _ => device_socket.unwrap_or_else(|| socket_of_core(core)),
+                };
+                add(&mut counts, socket, port.rxd as u32);
+            }
+            for &core in &port.tx_queues {
+                add(&mut counts, socket_of_core(core), port.txd as u32);
+            }
+        }
+
+        for n in counts.iter_mut().filter(|n| **n > 0) {
+            *n = match self.mbuf_cnt {
+                Some(cnt) => cnt,
+                None => (max(*n, MIN_MBUF_CNT) + 1).next_power_of_two() - 1,
+            };
+        }
+        counts
+    }
 }
 
 impl fmt::Display for NetbricksConfiguration {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mbuf_cnt = match self.mbuf_cnt {
+            Some(cnt) => cnt.to_string(),
+            None => String::from("auto"),
+        };
         write!(
             f,
-            "name: {}\nmempool size: {}\ncore cache: {}\n mbuf count: {}\nprimary core: {}\n",
-            self.name, self.pool_size, self.cache_size, self.mbuf_cnt, self.primary_core
+            "name: {}\nmempool size: {}\ncore cache: {}\n mbuf count: {}\n mbuf data room: {}\nprimary core: {}\n",
+            self.name, self.pool_size, self.cache_size, mbuf_cnt, self.mbuf_data_room, self.primary_core
         )?;
         write!(f, "Virtual Devices:\n")?;
         for dev in &self.vdevs {
@@ -102,6 +174,23 @@ pub enum DriverType {
     I40e = 2,
 }
 
+/// Selects the mempool from which a port allocates the mbufs of its rx queues.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MempoolSelection {
+    /// The pool on the NUMA socket of the core serving the queue.
+    Local,
+    /// The pool on the NUMA socket the PCI device is attached to, falls back to `Local` if the socket is not known.
+    Device,
+    /// The pool on the given NUMA socket.
+    Socket(i32),
+}
+
+impl Default for MempoolSelection {
+    fn default() -> MempoolSelection {
+        MempoolSelection::Local
+    }
+}
+
 #[derive(Clone)]
 /// Configuration for each port (network device) in `NetBricks`.
 pub struct PortConfiguration {
@@ -131,6 +220,10 @@ pub struct PortConfiguration {
     pub flow_steering: Option<FlowSteeringMode>,
     pub driver: DriverType,
     pub net_spec: Option<NetSpec>,
+    /// mempool for the mbufs of the rx queues
+    pub mempool: MempoolSelection,
+    /// MTU of the device, None keeps the device default. MTUs above 1500 enable jumbo frames.
+    pub mtu: Option<u16>,
 }
 
 impl Default for PortConfiguration {
@@ -150,6 +243,8 @@ impl Default for PortConfiguration {
             flow_steering: None,
             driver: DriverType::Unknown,
             net_spec: None,
+            mempool: MempoolSelection::Local,
+            mtu: None,
         }
     }
 }
@@ -179,7 +274,7 @@ impl fmt::Display for PortConfiguration {
         let tx_queue_str = tx_queues_str_vec.join(" ");
         write!(
             f,
-            "Port {}, RXQ_Count: {}, RX_Queues: [ {} ], TXQ_Count: {}, TX_Queues: [ {} ], RXD: {}, TXD: {}, Loopback: {}, ChecksumOffload: {}",
+            "Port {}, RXQ_Count: {}, RX_Queues: [ {} ], TXQ_Count: {}, TX_Queues: [ {} ], RXD: {}, TXD: {}, Loopback: {}, ChecksumOffload: {}, Mempool: {:?}, MTU: {}",
             self.name,
             self.rx_queues.len(),
             rx_queue_str,
@@ -189,6 +284,8 @@ impl fmt::Display for PortConfiguration {
             self.txd,
             self.loopback,
             self.csum,
+            self.mempool,
+            self.mtu.map_or("default".to_string(), |mtu| mtu.to_string()),
         )
     }
 }
@@ -4,6 +4,13 @@ use std::net::Ipv4Addr;
 
 use super::{EndOffset, HeaderKind};
 
+/// Ethertype of ARP packets.
+pub const ETYPE_ARP: u16 = 0x0806;
+pub const ARP_OP_REQUEST: u16 = 1;
+pub const ARP_OP_REPLY: u16 = 2;
+/// hardware type of Ethernet
+pub const ARP_HW_TYPE_ETHERNET: u16 = 1;
+
 #[derive(Debug, Default, Clone, Copy)]
 #[repr(C, packed)]
 pub struct ArpIpv4Header {
@@ -56,11 +63,103 @@ impl fmt::Display for ArpIpv4Header {
 }
 
 impl ArpIpv4Header {
+    /// An IPv4 over Ethernet header with the given operation, all addresses are zero.
+    #[inline]
+    pub fn new(operation: u16) -> ArpIpv4Header {
+        let mut arp = ArpIpv4Header::default();
+        arp.set_hw_type(ARP_HW_TYPE_ETHERNET);
+        arp.set_proto_etype(0x0800);
+        arp.hw_addr_len = 6;
+        arp.proto_addr_len = 4;
+        arp.set_operation(operation);
+        arp
+    }
+
+    /// A request asking for the hardware address of `target_ip`.
+    #[inline]
+    pub fn new_request(sender_mac: &MacAddress, sender_ip: Ipv4Addr, target_ip: Ipv4Addr) -> ArpIpv4Header {
+        let mut arp = ArpIpv4Header::new(ARP_OP_REQUEST);
+        arp.set_sender_hw_addr(sender_mac);
+        arp.set_sender_ip_addr(sender_ip);
+        arp.set_target_ip_addr(target_ip);
+        arp
+    }
+
+    /// A reply to `request`, announcing `mac` as the hardware address of the requested IP address.
+    #[inline]
+    pub fn new_reply(request: &ArpIpv4Header, mac: &MacAddress) -> ArpIpv4Header {
+        let mut arp = ArpIpv4Header::new(ARP_OP_REPLY);
+        arp.set_sender_hw_addr(mac);
+        arp.set_sender_ip_addr(request.target_ip_addr());
+        arp.set_target_hw_addr(&request.sender_hw_addr());
+        arp.set_target_ip_addr(request.sender_ip_addr());
+        arp
+    }
+
+    /// Turn a request into the reply, swapping sender and target.
+    #[inline]
+    pub fn make_reply(&mut self, mac: &MacAddress) {
+        let reply = ArpIpv4Header::new_reply(self, mac);
+        *self = reply;
+    }
+
     #[inline]
     pub fn hw_type(&self) -> u16 {
         u16::from_be(self.hw_type)
     }
     #[inline]
+    pub fn set_hw_type(&mut self, hw_type: u16) {
+        self.hw_type = u16::to_be(hw_type);
+    }
+    #[inline]
+    pub fn set_proto_etype(&mut self, etype: u16) {
+        self.proto_etype = u16::to_be(etype);
+    }
+    #[inline]
+    pub fn set_operation(&mut self, operation: u16) {
+        self.operation = u16::to_be(operation);
+    }
+    #[inline]
+    pub fn sender_hw_addr(&self) -> MacAddress {
+        self.sender_hw_addr
+    }
+    #[inline]
+    pub fn set_sender_hw_addr(&mut self, mac: &MacAddress) {
+        self.sender_hw_addr = *mac;
+    }
+    #[inline]
+    pub fn target_hw_addr(&self) -> MacAddress {
+        self.target_hw_addr
+    }
+    #[inline]
+    pub fn set_target_hw_addr(&mut self, mac: &MacAddress) {
+        self.target_hw_addr = *mac;
+    }
+    #[inline]
+    pub fn set_sender_proto_addr(&mut self, addr: u32) {
+        self.sender_proto_addr = u32::to_be(addr);
+    }
+    #[inline]
+    pub fn set_target_proto_addr(&mut self, addr: u32) {
+        self.target_proto_addr = u32::to_be(addr);
+    }
+    #[inline]
+    pub fn set_sender_ip_addr(&mut self, addr: Ipv4Addr) {
+        self.set_sender_proto_addr(u32::from(addr));
+    }
+    #[inline]
+    pub fn set_target_ip_addr(&mut self, addr: Ipv4Addr) {
+        self.set_target_proto_addr(u32::from(addr));
+    }
+    #[inline]
+    pub fn is_request(&self) -> bool {
+        self.operation() == ARP_OP_REQUEST
+    }
+    #[inline]
+    pub fn is_reply(&self) -> bool {
+        self.operation() == ARP_OP_REPLY
+    }
+    #[inline]
     pub fn proto_etype(&self) -> u16 {
         u16::from_be(self.proto_etype)
     }
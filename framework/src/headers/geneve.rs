@@ -0,0 +1,122 @@
+use super::{EndOffset, HeaderKind};
+use std::default::Default;
+use std::fmt;
+use std::slice;
+
+/// UDP destination port assigned to Geneve.
+pub const GENEVE_PORT: u16 = 6081;
+
+const FLAG_OAM: u8 = 0x80;
+const FLAG_CRITICAL: u8 = 0x40;
+
+/// Geneve header (RFC 8926) without options. The variable length options follow the fixed part and are covered by
+/// `offset`.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct GeneveHeader {
+    ver_opt_len: u8,
+    flags: u8,
+    protocol: u16,
+    vni_reserved: u32,
+}
+
+impl fmt::Display for GeneveHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "geneve version: {} opt_len: {} flags: 0x{:02x} protocol: 0x{:04x} vni: {}",
+            self.version(),
+            self.opt_len(),
+            self.flags,
+            self.protocol(),
+            self.vni()
+        )
+    }
+}
+
+impl EndOffset for GeneveHeader {
+    #[inline]
+    fn offset(&self) -> usize {
+        8 + self.opt_len() as usize * 4
+    }
+
+    #[inline]
+    fn size() -> usize {
+        8
+    }
+
+    #[inline]
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    #[inline]
+    fn header_kind(&self) -> HeaderKind {
+        HeaderKind::Geneve
+    }
+}
+
+impl GeneveHeader {
+    #[inline]
+    pub fn new() -> GeneveHeader {
+        Default::default()
+    }
+
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.ver_opt_len >> 6
+    }
+
+    /// length of the options in multiples of 4 bytes
+    #[inline]
+    pub fn opt_len(&self) -> u8 {
+        self.ver_opt_len & 0x3f
+    }
+
+    /// Set the length of the options. The options themselves must be written behind the header.
+    #[inline]
+    pub fn set_opt_len(&mut self, opt_len: u8) {
+        self.ver_opt_len = (self.ver_opt_len & 0xc0) | (opt_len & 0x3f);
+    }
+
+    /// The raw options (TLVs) following the fixed header.
+    #[inline]
+    pub fn options(&self) -> &[u8] {
+        unsafe {
+            let start = (self as *const GeneveHeader as *const u8).offset(GeneveHeader::size() as isize);
+            slice::from_raw_parts(start, self.opt_len() as usize * 4)
+        }
+    }
+
+    #[inline]
+    pub fn oam_flag(&self) -> bool {
+        self.flags & FLAG_OAM != 0
+    }
+
+    #[inline]
+    pub fn critical_flag(&self) -> bool {
+        self.flags & FLAG_CRITICAL != 0
+    }
+
+    /// Ethertype of the inner frame, 0x6558 for Ethernet.
+    #[inline]
+    pub fn protocol(&self) -> u16 {
+        u16::from_be(self.protocol)
+    }
+
+    #[inline]
+    pub fn set_protocol(&mut self, protocol: u16) {
+        self.protocol = u16::to_be(protocol);
+    }
+
+    /// 24 bit virtual network identifier
+    #[inline]
+    pub fn vni(&self) -> u32 {
+        u32::from_be(self.vni_reserved) >> 8
+    }
+
+    #[inline]
+    pub fn set_vni(&mut self, vni: u32) {
+        self.vni_reserved = u32::to_be((vni & 0x00ff_ffff) << 8);
+    }
+}
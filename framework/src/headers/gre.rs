@@ -0,0 +1,176 @@
+use super::{EndOffset, HeaderKind};
+use std::default::Default;
+use std::fmt;
+use std::ptr;
+
+/// IP protocol number of GRE.
+pub const IP_PROTO_GRE: u8 = 47;
+/// Protocol type for transparent Ethernet bridging, used by NVGRE and Geneve to carry Ethernet frames.
+pub const ETYPE_TRANSPARENT_ETHERNET: u16 = 0x6558;
+
+const FLAG_CHECKSUM: u16 = 0x8000;
+const FLAG_KEY: u16 = 0x2000;
+const FLAG_SEQ: u16 = 0x1000;
+
+/// GRE header (RFC 2784, RFC 2890). The optional checksum, key and sequence number fields follow the fixed part and
+/// are covered by `offset`. NVGRE (RFC 7637) is GRE with a key carrying the virtual subnet id.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct GreHeader {
+    flags_version: u16,
+    protocol: u16,
+}
+
+impl fmt::Display for GreHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "gre flags: 0x{:04x} protocol: 0x{:04x} key: {:?} seq: {:?}",
+            self.flags(),
+            self.protocol(),
+            self.key(),
+            self.seq_num()
+        )
+    }
+}
+
+impl EndOffset for GreHeader {
+    #[inline]
+    fn offset(&self) -> usize {
+        let flags = self.flags();
+        let mut offset = 4;
+        for flag in &[FLAG_CHECKSUM, FLAG_KEY, FLAG_SEQ] {
+            if flags & flag != 0 {
+                offset += 4;
+            }
+        }
+        offset
+    }
+
+    #[inline]
+    fn size() -> usize {
+        4
+    }
+
+    #[inline]
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    #[inline]
+    fn header_kind(&self) -> HeaderKind {
+        HeaderKind::Gre
+    }
+}
+
+impl GreHeader {
+    #[inline]
+    pub fn new() -> GreHeader {
+        Default::default()
+    }
+
+    /// A NVGRE header for transparent Ethernet bridging with key present.
+    #[inline]
+    pub fn new_nvgre() -> GreHeader {
+        let mut gre = GreHeader::new();
+        gre.flags_version = u16::to_be(FLAG_KEY);
+        gre.set_protocol(ETYPE_TRANSPARENT_ETHERNET);
+        gre
+    }
+
+    /// flags and version as a host order u16
+    #[inline]
+    pub fn flags(&self) -> u16 {
+        u16::from_be(self.flags_version)
+    }
+
+    #[inline]
+    pub fn version(&self) -> u8 {
+        (self.flags() & 0x7) as u8
+    }
+
+    #[inline]
+    pub fn checksum_present(&self) -> bool {
+        self.flags() & FLAG_CHECKSUM != 0
+    }
+
+    #[inline]
+    pub fn key_present(&self) -> bool {
+        self.flags() & FLAG_KEY != 0
+    }
+
+    #[inline]
+    pub fn seq_present(&self) -> bool {
+        self.flags() & FLAG_SEQ != 0
+    }
+
+    /// Ethertype of the payload.
+    #[inline]
+    pub fn protocol(&self) -> u16 {
+        u16::from_be(self.protocol)
+    }
+
+    #[inline]
+    pub fn set_protocol(&mut self, protocol: u16) {
+        self.protocol = u16::to_be(protocol);
+    }
+
+    /// the optional 32 bit field at `index` in network byte order, the fields are not necessarily aligned
+    #[inline]
+    fn optional_field(&self, index: usize) -> u32 {
+        unsafe { ptr::read_unaligned((self as *const GreHeader as *const u8).add(4 + 4 * index) as *const u32) }
+    }
+
+    #[inline]
+    fn set_optional_field(&mut self, index: usize, value: u32) {
+        unsafe { ptr::write_unaligned((self as *mut GreHeader as *mut u8).add(4 + 4 * index) as *mut u32, value) }
+    }
+
+    #[inline]
+    fn key_index(&self) -> usize {
+        if self.checksum_present() {
+            1
+        } else {
+            0
+        }
+    }
+
+    #[inline]
+    pub fn key(&self) -> Option<u32> {
+        if self.key_present() {
+            Some(u32::from_be(self.optional_field(self.key_index())))
+        } else {
+            None
+        }
+    }
+
+    /// Set the key. The key field must be present, i.e. the header was created by `new_nvgre` or received with key.
+    #[inline]
+    pub fn set_key(&mut self, key: u32) {
+        assert!(self.key_present());
+        let index = self.key_index();
+        self.set_optional_field(index, u32::to_be(key));
+    }
+
+    #[inline]
+    pub fn seq_num(&self) -> Option<u32> {
+        if self.seq_present() {
+            let index = self.key_index() + if self.key_present() { 1 } else { 0 };
+            Some(u32::from_be(self.optional_field(index)))
+        } else {
+            None
+        }
+    }
+
+    /// NVGRE virtual subnet id, the upper 24 bits of the key.
+    #[inline]
+    pub fn vsid(&self) -> Option<u32> {
+        self.key().map(|key| key >> 8)
+    }
+
+    /// NVGRE flow id, the lower 8 bits of the key.
+    #[inline]
+    pub fn flow_id(&self) -> Option<u8> {
+        self.key().map(|key| key as u8)
+    }
+}
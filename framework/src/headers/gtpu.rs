@@ -0,0 +1,279 @@
+use super::{EndOffset, HeaderKind};
+use std::default::Default;
+use std::fmt;
+use std::slice;
+
+/// UDP port assigned to GTP-U.
+pub const GTPU_PORT: u16 = 2152;
+
+/// Message type of a G-PDU, i.e. a GTP-U message carrying a user plane packet.
+pub const GTPU_MSG_GPDU: u8 = 0xff;
+pub const GTPU_MSG_ECHO_REQUEST: u8 = 1;
+pub const GTPU_MSG_ECHO_RESPONSE: u8 = 2;
+pub const GTPU_MSG_ERROR_INDICATION: u8 = 26;
+pub const GTPU_MSG_END_MARKER: u8 = 254;
+
+/// Extension header type of the PDU session container (3GPP TS 38.415), which carries the QFI.
+pub const GTPU_EXT_PDU_SESSION_CONTAINER: u8 = 0x85;
+
+const VERSION_1: u8 = 0x20;
+const FLAG_PT: u8 = 0x10;
+const FLAG_EXT: u8 = 0x04;
+const FLAG_SEQ: u8 = 0x02;
+const FLAG_NPDU: u8 = 0x01;
+
+/// length of the optional sequence number, N-PDU number and next extension type fields
+const OPTIONAL_LEN: usize = 4;
+
+/// extension headers followed at most, longer chains are treated as malformed
+const MAX_EXTENSIONS: usize = 16;
+
+/// GTPv1-U header (3GPP TS 29.281) without the optional fields. If any of the E, S or PN flags is set, the sequence
+/// number, N-PDU number and next extension header type follow, and then the chain of extension headers. `offset`
+/// covers all of them.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct GtpuHeader {
+    flags: u8,
+    msg_type: u8,
+    length: u16,
+    teid: u32,
+}
+
+/// A GTP-U extension header: its type and its content without the length and the next type octets.
+#[derive(Debug, PartialEq)]
+pub struct GtpuExtension<'a> {
+    pub ext_type: u8,
+    pub content: &'a [u8],
+}
+
+/// Iterator over the extension headers of a `GtpuHeader`.
+pub struct GtpuExtensions<'a> {
+    next_type: u8,
+    ptr: *const u8,
+    remaining: usize,
+    phantom: ::std::marker::PhantomData<&'a u8>,
+}
+
+impl<'a> Iterator for GtpuExtensions<'a> {
+    type Item = GtpuExtension<'a>;
+
+    fn next(&mut self) -> Option<GtpuExtension<'a>> {
+        if self.next_type == 0 || self.remaining == 0 {
+            return None;
+        }
+        // length is in units of 4 octets and includes the length and next type octets
+        let len = unsafe { *self.ptr } as usize * 4;
+        if len == 0 || len > self.remaining {
+            self.remaining = 0;
+            return None;
+        }
+        let ext = GtpuExtension {
+            ext_type: self.next_type,
+            content: unsafe { slice::from_raw_parts(self.ptr.offset(1), len - 2) },
+        };
+        self.next_type = unsafe { *self.ptr.offset(len as isize - 1) };
+        self.ptr = unsafe { self.ptr.offset(len as isize) };
+        self.remaining -= len;
+        Some(ext)
+    }
+}
+
+impl fmt::Display for GtpuHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "gtpu version: {} flags: 0x{:02x} type: {} length: {} teid: 0x{:08x}",
+            self.version(),
+            self.flags & 0x0f,
+            self.msg_type(),
+            self.length(),
+            self.teid()
+        )
+    }
+}
+
+impl EndOffset for GtpuHeader {
+    /// The walk over the extension headers is bounded by the length field, see `header_len`.
+    #[inline]
+    fn offset(&self) -> usize {
+        self.header_len(usize::max_value()).unwrap_or(8)
+    }
+
+    #[inline]
+    fn size() -> usize {
+        8
+    }
+
+    #[inline]
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    #[inline]
+    fn header_kind(&self) -> HeaderKind {
+        HeaderKind::Gtpu
+    }
+}
+
+impl GtpuHeader {
+    /// A version 1 G-PDU header without optional fields.
+    #[inline]
+    pub fn new() -> GtpuHeader {
+        GtpuHeader {
+            flags: VERSION_1 | FLAG_PT,
+            msg_type: GTPU_MSG_GPDU,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.flags >> 5
+    }
+
+    /// protocol type, true for GTP, false for GTP'
+    #[inline]
+    pub fn protocol_type(&self) -> bool {
+        self.flags & FLAG_PT != 0
+    }
+
+    #[inline]
+    pub fn ext_present(&self) -> bool {
+        self.flags & FLAG_EXT != 0
+    }
+
+    #[inline]
+    pub fn seq_present(&self) -> bool {
+        self.flags & FLAG_SEQ != 0
+    }
+
+    #[inline]
+    pub fn npdu_present(&self) -> bool {
+        self.flags & FLAG_NPDU != 0
+    }
+
+    /// true if the sequence number, N-PDU number and next extension type fields are present
+    #[inline]
+    pub fn optional_present(&self) -> bool {
+        self.flags & (FLAG_EXT | FLAG_SEQ | FLAG_NPDU) != 0
+    }
+
+    #[inline]
+    pub fn msg_type(&self) -> u8 {
+        self.msg_type
+    }
+
+    #[inline]
+    pub fn set_msg_type(&mut self, msg_type: u8) {
+        self.msg_type = msg_type;
+    }
+
+    /// length of the payload following the mandatory part of the header, i.e. including optional fields and
+    /// extension headers
+    #[inline]
+    pub fn length(&self) -> u16 {
+        u16::from_be(self.length)
+    }
+
+    #[inline]
+    pub fn set_length(&mut self, length: u16) {
+        self.length = u16::to_be(length);
+    }
+
+    /// tunnel endpoint identifier
+    #[inline]
+    pub fn teid(&self) -> u32 {
+        u32::from_be(self.teid)
+    }
+
+    #[inline]
+    pub fn set_teid(&mut self, teid: u32) {
+        self.teid = u32::to_be(teid);
+    }
+
+    #[inline]
+    fn optional_field(&self, index: isize) -> u8 {
+        unsafe { *(self as *const GtpuHeader as *const u8).offset(8 + index) }
+    }
+
+    /// The sequence number, if the S flag is set. The header must be followed by the optional fields.
+    #[inline]
+    pub fn seq_num(&self) -> Option<u16> {
+        if self.seq_present() {
+            Some(((self.optional_field(0) as u16) << 8) | self.optional_field(1) as u16)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn npdu_num(&self) -> Option<u8> {
+        if self.npdu_present() {
+            Some(self.optional_field(2))
+        } else {
+            None
+        }
+    }
+
+    /// Type of the first extension header, if the E flag is set.
+    #[inline]
+    pub fn next_ext_type(&self) -> Option<u8> {
+        if self.ext_present() {
+            Some(self.optional_field(3))
+        } else {
+            None
+        }
+    }
+
+    /// The extension headers following the optional fields. The header must be followed by the bytes indicated by
+    /// `offset`.
+    #[inline]
+    pub fn extensions(&self) -> GtpuExtensions {
+        let ptr = unsafe { (self as *const GtpuHeader as *const u8).offset((8 + OPTIONAL_LEN) as isize) };
+        GtpuExtensions {
+            next_type: self.next_ext_type().unwrap_or(0),
+            ptr,
+            remaining: self.offset().saturating_sub(8 + OPTIONAL_LEN),
+            phantom: ::std::marker::PhantomData,
+        }
+    }
+
+    /// The QoS flow identifier from a PDU session container extension header, if present.
+    #[inline]
+    pub fn qfi(&self) -> Option<u8> {
+        self.extensions()
+            .find(|ext| ext.ext_type == GTPU_EXT_PDU_SESSION_CONTAINER)
+            .and_then(|ext| ext.content.get(1).map(|b| b & 0x3f))
+    }
+
+    /// Length of the header including optional fields and extension headers, if it does not exceed `available`
+    /// bytes nor the length field. Returns None for a truncated or malformed extension header chain, or for a chain of
+    /// more than 16 extension headers.
+    pub fn header_len(&self, available: usize) -> Option<usize> {
+        let available = available.min(8 + self.length() as usize);
+        if !self.optional_present() {
+            return if available >= 8 { Some(8) } else { None };
+        }
+        let mut len = 8 + OPTIONAL_LEN;
+        if available < len {
+            return None;
+        }
+        let base = self as *const GtpuHeader as *const u8;
+        let mut next_type = self.next_ext_type().unwrap_or(0);
+        let mut extensions = 0;
+        while next_type != 0 {
+            if available < len + 1 || extensions == MAX_EXTENSIONS {
+                return None;
+            }
+            extensions += 1;
+            let ext_len = unsafe { *base.offset(len as isize) } as usize * 4;
+            if ext_len == 0 || available < len + ext_len {
+                return None;
+            }
+            next_type = unsafe { *base.offset((len + ext_len - 1) as isize) };
+            len += ext_len;
+        }
+        Some(len)
+    }
+}
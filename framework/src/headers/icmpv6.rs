@@ -0,0 +1,291 @@
+use super::{EndOffset, HeaderKind};
+use eui48::MacAddress;
+use std::default::Default;
+use std::fmt;
+use std::net::Ipv6Addr;
+
+/// Next header value of ICMPv6.
+pub const IP_PROTO_ICMPV6: u8 = 58;
+
+pub const ICMPV6_ECHO_REQUEST: u8 = 128;
+pub const ICMPV6_ECHO_REPLY: u8 = 129;
+/// Neighbor Discovery messages (RFC 4861).
+pub const ICMPV6_ROUTER_SOLICITATION: u8 = 133;
+pub const ICMPV6_ROUTER_ADVERTISEMENT: u8 = 134;
+pub const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+pub const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// Neighbor Discovery option types.
+pub const NDP_OPT_SOURCE_LL_ADDR: u8 = 1;
+pub const NDP_OPT_TARGET_LL_ADDR: u8 = 2;
+pub const NDP_OPT_PREFIX_INFO: u8 = 3;
+pub const NDP_OPT_MTU: u8 = 5;
+
+/// Flags of neighbor advertisements.
+pub const NDP_NA_FLAG_ROUTER: u32 = 0x8000_0000;
+pub const NDP_NA_FLAG_SOLICITED: u32 = 0x4000_0000;
+pub const NDP_NA_FLAG_OVERRIDE: u32 = 0x2000_0000;
+
+/// Hop limit of all Neighbor Discovery messages, messages with a different hop limit are not accepted.
+pub const NDP_HOP_LIMIT: u8 = 255;
+
+/// ICMPv6 header (RFC 4443), the message body follows as payload.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct Icmpv6Header {
+    msg_type: u8,
+    code: u8,
+    checksum: u16,
+}
+
+impl fmt::Display for Icmpv6Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "icmpv6 type: {} code: {} checksum: 0x{:04x}",
+            self.msg_type(),
+            self.code(),
+            self.checksum()
+        )
+    }
+}
+
+impl EndOffset for Icmpv6Header {
+    #[inline]
+    fn offset(&self) -> usize {
+        4
+    }
+
+    #[inline]
+    fn size() -> usize {
+        4
+    }
+
+    #[inline]
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    #[inline]
+    fn header_kind(&self) -> HeaderKind {
+        HeaderKind::Icmpv6
+    }
+}
+
+impl Icmpv6Header {
+    #[inline]
+    pub fn new(msg_type: u8) -> Icmpv6Header {
+        let mut hdr = Icmpv6Header::default();
+        hdr.set_msg_type(msg_type);
+        hdr
+    }
+
+    #[inline]
+    pub fn msg_type(&self) -> u8 {
+        self.msg_type
+    }
+
+    #[inline]
+    pub fn set_msg_type(&mut self, msg_type: u8) {
+        self.msg_type = msg_type;
+    }
+
+    #[inline]
+    pub fn code(&self) -> u8 {
+        self.code
+    }
+
+    #[inline]
+    pub fn set_code(&mut self, code: u8) {
+        self.code = code;
+    }
+
+    #[inline]
+    pub fn checksum(&self) -> u16 {
+        u16::from_be(self.checksum)
+    }
+
+    #[inline]
+    pub fn set_checksum(&mut self, csum: u16) {
+        self.checksum = u16::to_be(csum);
+    }
+}
+
+/// Body of neighbor solicitations and advertisements, options follow.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct NdpNeighborMessage {
+    flags: u32,
+    target: [u8; 16],
+}
+
+impl NdpNeighborMessage {
+    #[inline]
+    pub fn new(target: Ipv6Addr, flags: u32) -> NdpNeighborMessage {
+        NdpNeighborMessage {
+            flags: u32::to_be(flags),
+            target: target.octets(),
+        }
+    }
+
+    #[inline]
+    pub fn size() -> usize {
+        20
+    }
+
+    /// flags of advertisements, reserved in solicitations
+    #[inline]
+    pub fn flags(&self) -> u32 {
+        u32::from_be(self.flags)
+    }
+
+    #[inline]
+    pub fn set_flags(&mut self, flags: u32) {
+        self.flags = u32::to_be(flags);
+    }
+
+    #[inline]
+    pub fn target(&self) -> Ipv6Addr {
+        Ipv6Addr::from(self.target)
+    }
+
+    #[inline]
+    pub fn set_target(&mut self, target: Ipv6Addr) {
+        self.target = target.octets();
+    }
+}
+
+/// Body of router advertisements, options follow.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct NdpRouterAdvertisement {
+    cur_hop_limit: u8,
+    flags: u8,
+    router_lifetime: u16,
+    reachable_time: u32,
+    retrans_timer: u32,
+}
+
+impl NdpRouterAdvertisement {
+    #[inline]
+    pub fn size() -> usize {
+        12
+    }
+
+    #[inline]
+    pub fn cur_hop_limit(&self) -> u8 {
+        self.cur_hop_limit
+    }
+
+    #[inline]
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// lifetime of the default route in seconds, 0 if the router is not a default router
+    #[inline]
+    pub fn router_lifetime(&self) -> u16 {
+        u16::from_be(self.router_lifetime)
+    }
+
+    /// in milliseconds, 0 if unspecified
+    #[inline]
+    pub fn reachable_time(&self) -> u32 {
+        u32::from_be(self.reachable_time)
+    }
+
+    /// in milliseconds, 0 if unspecified
+    #[inline]
+    pub fn retrans_timer(&self) -> u32 {
+        u32::from_be(self.retrans_timer)
+    }
+}
+
+/// A Neighbor Discovery option, `content` follows the type and length bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NdpOption<'a> {
+    pub opt_type: u8,
+    pub content: &'a [u8],
+}
+
+/// Iterates over the options of a Neighbor Discovery message. Stops at an option with length zero or exceeding the
+/// message, such messages must be discarded (RFC 4861, section 4.6).
+pub struct NdpOptions<'a> {
+    data: &'a [u8],
+    malformed: bool,
+}
+
+impl<'a> NdpOptions<'a> {
+    pub fn new(data: &'a [u8]) -> NdpOptions<'a> {
+        NdpOptions { data, malformed: false }
+    }
+
+    /// true if iteration stopped at a malformed option
+    pub fn malformed(&self) -> bool {
+        self.malformed
+    }
+}
+
+impl<'a> Iterator for NdpOptions<'a> {
+    type Item = NdpOption<'a>;
+
+    fn next(&mut self) -> Option<NdpOption<'a>> {
+        if self.data.len() < 2 {
+            self.malformed = !self.data.is_empty();
+            return None;
+        }
+        let len = self.data[1] as usize * 8;
+        if len == 0 || len > self.data.len() {
+            self.malformed = true;
+            self.data = &[];
+            return None;
+        }
+        let option = NdpOption {
+            opt_type: self.data[0],
+            content: &self.data[2..len],
+        };
+        self.data = &self.data[len..];
+        Some(option)
+    }
+}
+
+/// The link-layer address option with the given type for `mac`.
+pub fn ndp_ll_addr_option(opt_type: u8, mac: &MacAddress) -> [u8; 8] {
+    let mut option = [opt_type, 1, 0, 0, 0, 0, 0, 0];
+    option[2..].copy_from_slice(mac.as_bytes());
+    option
+}
+
+/// Checksum of an ICMPv6 `message` including the IPv6 pseudo header, computed with a zero checksum field. For a
+/// received message with a valid checksum the result is zero.
+pub fn icmpv6_checksum(src: &Ipv6Addr, dst: &Ipv6Addr, message: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    let mut add = |data: &[u8]| {
+        for chunk in data.chunks(2) {
+            let word = if chunk.len() == 2 {
+                (chunk[0] as u32) << 8 | chunk[1] as u32
+            } else {
+                (chunk[0] as u32) << 8
+            };
+            sum += word;
+        }
+    };
+    add(&src.octets());
+    add(&dst.octets());
+    let len = message.len() as u32;
+    add(&[
+        (len >> 24) as u8,
+        (len >> 16) as u8,
+        (len >> 8) as u8,
+        len as u8,
+        0,
+        0,
+        0,
+        IP_PROTO_ICMPV6,
+    ]);
+    add(message);
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
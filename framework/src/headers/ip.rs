@@ -7,6 +7,11 @@ use std::net::Ipv4Addr;
 use std::slice;
 use utils::{checksum, FiveTupleV4};
 
+/// flag in `IpHeader::flags`: don't fragment
+pub const IP_FLAG_DF: u8 = 0x2;
+/// flag in `IpHeader::flags`: more fragments follow
+pub const IP_FLAG_MF: u8 = 0x1;
+
 /// IP header using SSE
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C, packed)]
@@ -65,12 +70,14 @@ impl EndOffset for IpHeader {
 }
 
 impl IpHeader {
+    /// The five tuple of TCP and UDP packets. Only the first fragment of a fragmented packet carries the ports, other
+    /// fragments have no flow.
     #[inline]
     pub fn flow(&self) -> Option<FiveTupleV4> {
         let protocol = self.protocol();
         let src_ip = self.src();
         let dst_ip = self.dst();
-        if (protocol == 6 || protocol == 17) && self.payload_size(0) >= 4 {
+        if (protocol == 6 || protocol == 17) && self.fragment_offset() == 0 && self.payload_size(0) >= 4 {
             unsafe {
                 let self_as_u8 = (self as *const IpHeader) as *const u8;
                 let port_as_u8 = self_as_u8.offset(self.offset() as isize);
@@ -179,11 +186,10 @@ impl IpHeader {
         self.id_to_foffset = (self.id_to_foffset & !0x00e00000) | (((flags & 0x7) as u32) << (16 + 5));
     }
 
+    /// fragment offset in units of 8 bytes
     #[inline]
     pub fn fragment_offset(&self) -> u16 {
-        let id_flag_fragment = self.id_to_foffset;
-        let flag_fragment = (id_flag_fragment & 0xffff) as u16;
-        u16::from_be(flag_fragment & !0xe >> 3)
+        (u32::from_be(self.id_to_foffset) & 0x1fff) as u16
     }
 
     #[inline]
@@ -193,6 +199,22 @@ impl IpHeader {
         self.id_to_foffset = u32::to_be(id_to_offset_le & !0x1fff | offset_correct);
     }
 
+    #[inline]
+    pub fn dont_fragment(&self) -> bool {
+        self.flags() & IP_FLAG_DF != 0
+    }
+
+    #[inline]
+    pub fn more_fragments(&self) -> bool {
+        self.flags() & IP_FLAG_MF != 0
+    }
+
+    /// true for all fragments of a fragmented packet, including the first one
+    #[inline]
+    pub fn is_fragment(&self) -> bool {
+        self.more_fragments() || self.fragment_offset() != 0
+    }
+
     #[inline]
     pub fn version(&self) -> u8 {
         ((self.version_to_len & 0xf0) as u8) >> 4
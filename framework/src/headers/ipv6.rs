@@ -0,0 +1,169 @@
+use super::{EndOffset, HeaderKind};
+use std::default::Default;
+use std::fmt;
+use std::net::Ipv6Addr;
+
+/// Ethertype of IPv6 packets.
+pub const ETYPE_IPV6: u16 = 0x86DD;
+
+/// Next header values of the IPv6 extension headers.
+pub const IPV6_NEXT_HEADER_HOP_BY_HOP: u8 = 0;
+pub const IPV6_NEXT_HEADER_ROUTING: u8 = 43;
+pub const IPV6_NEXT_HEADER_FRAGMENT: u8 = 44;
+pub const IPV6_NEXT_HEADER_DEST_OPTIONS: u8 = 60;
+
+/// IPv6 header (RFC 8200) without extension headers.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct Ipv6Header {
+    version_to_flow: u32,
+    payload_len: u16,
+    next_header: u8,
+    hop_limit: u8,
+    src_ip: [u8; 16],
+    dst_ip: [u8; 16],
+}
+
+impl fmt::Display for Ipv6Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} > {} version: {} tc: {} flow: {} len: {} next: {} hop limit: {}",
+            self.src(),
+            self.dst(),
+            self.version(),
+            self.traffic_class(),
+            self.flow_label(),
+            self.payload_len(),
+            self.next_header(),
+            self.hop_limit()
+        )
+    }
+}
+
+impl EndOffset for Ipv6Header {
+    #[inline]
+    fn offset(&self) -> usize {
+        40
+    }
+
+    #[inline]
+    fn size() -> usize {
+        40
+    }
+
+    #[inline]
+    fn payload_size(&self, _: usize) -> usize {
+        self.payload_len() as usize
+    }
+
+    #[inline]
+    fn header_kind(&self) -> HeaderKind {
+        HeaderKind::Ipv6
+    }
+}
+
+impl Ipv6Header {
+    /// A version 6 header.
+    #[inline]
+    pub fn new() -> Ipv6Header {
+        let mut hdr = Ipv6Header::default();
+        hdr.set_version(6);
+        hdr
+    }
+
+    #[inline]
+    fn version_to_flow(&self) -> u32 {
+        u32::from_be(self.version_to_flow)
+    }
+
+    #[inline]
+    fn set_version_to_flow(&mut self, value: u32) {
+        self.version_to_flow = u32::to_be(value);
+    }
+
+    #[inline]
+    pub fn version(&self) -> u8 {
+        (self.version_to_flow() >> 28) as u8
+    }
+
+    #[inline]
+    pub fn set_version(&mut self, version: u8) {
+        let value = (self.version_to_flow() & 0x0fff_ffff) | ((version as u32 & 0xf) << 28);
+        self.set_version_to_flow(value);
+    }
+
+    #[inline]
+    pub fn traffic_class(&self) -> u8 {
+        (self.version_to_flow() >> 20) as u8
+    }
+
+    #[inline]
+    pub fn set_traffic_class(&mut self, tc: u8) {
+        let value = (self.version_to_flow() & 0xf00f_ffff) | ((tc as u32) << 20);
+        self.set_version_to_flow(value);
+    }
+
+    /// 20 bit flow label
+    #[inline]
+    pub fn flow_label(&self) -> u32 {
+        self.version_to_flow() & 0x000f_ffff
+    }
+
+    #[inline]
+    pub fn set_flow_label(&mut self, label: u32) {
+        let value = (self.version_to_flow() & 0xfff0_0000) | (label & 0x000f_ffff);
+        self.set_version_to_flow(value);
+    }
+
+    /// length of the payload including extension headers
+    #[inline]
+    pub fn payload_len(&self) -> u16 {
+        u16::from_be(self.payload_len)
+    }
+
+    #[inline]
+    pub fn set_payload_len(&mut self, len: u16) {
+        self.payload_len = u16::to_be(len);
+    }
+
+    #[inline]
+    pub fn next_header(&self) -> u8 {
+        self.next_header
+    }
+
+    #[inline]
+    pub fn set_next_header(&mut self, next_header: u8) {
+        self.next_header = next_header;
+    }
+
+    #[inline]
+    pub fn hop_limit(&self) -> u8 {
+        self.hop_limit
+    }
+
+    #[inline]
+    pub fn set_hop_limit(&mut self, hop_limit: u8) {
+        self.hop_limit = hop_limit;
+    }
+
+    #[inline]
+    pub fn src(&self) -> Ipv6Addr {
+        Ipv6Addr::from(self.src_ip)
+    }
+
+    #[inline]
+    pub fn set_src(&mut self, src: Ipv6Addr) {
+        self.src_ip = src.octets();
+    }
+
+    #[inline]
+    pub fn dst(&self) -> Ipv6Addr {
+        Ipv6Addr::from(self.dst_ip)
+    }
+
+    #[inline]
+    pub fn set_dst(&mut self, dst: Ipv6Addr) {
+        self.dst_ip = dst.octets();
+    }
+}
@@ -0,0 +1,105 @@
+use super::{EndOffset, HeaderKind};
+use std::default::Default;
+use std::fmt;
+
+const FLAG_MORE: u16 = 0x0001;
+const OFFSET_MASK: u16 = 0xfff8;
+
+/// IPv6 fragment extension header (RFC 8200, section 4.5).
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct Ipv6FragmentHeader {
+    next_header: u8,
+    reserved: u8,
+    offset_flags: u16,
+    id: u32,
+}
+
+impl fmt::Display for Ipv6FragmentHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ipv6 fragment next: {} offset: {} more: {} id: 0x{:08x}",
+            self.next_header(),
+            self.fragment_offset(),
+            self.more_fragments(),
+            self.id()
+        )
+    }
+}
+
+impl EndOffset for Ipv6FragmentHeader {
+    #[inline]
+    fn offset(&self) -> usize {
+        8
+    }
+
+    #[inline]
+    fn size() -> usize {
+        8
+    }
+
+    #[inline]
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    #[inline]
+    fn header_kind(&self) -> HeaderKind {
+        HeaderKind::Ipv6Fragment
+    }
+}
+
+impl Ipv6FragmentHeader {
+    #[inline]
+    pub fn new() -> Ipv6FragmentHeader {
+        Default::default()
+    }
+
+    #[inline]
+    pub fn next_header(&self) -> u8 {
+        self.next_header
+    }
+
+    #[inline]
+    pub fn set_next_header(&mut self, next_header: u8) {
+        self.next_header = next_header;
+    }
+
+    /// fragment offset in units of 8 bytes
+    #[inline]
+    pub fn fragment_offset(&self) -> u16 {
+        (u16::from_be(self.offset_flags) & OFFSET_MASK) >> 3
+    }
+
+    #[inline]
+    pub fn set_fragment_offset(&mut self, offset: u16) {
+        let value = (u16::from_be(self.offset_flags) & !OFFSET_MASK) | ((offset << 3) & OFFSET_MASK);
+        self.offset_flags = u16::to_be(value);
+    }
+
+    #[inline]
+    pub fn more_fragments(&self) -> bool {
+        u16::from_be(self.offset_flags) & FLAG_MORE != 0
+    }
+
+    #[inline]
+    pub fn set_more_fragments(&mut self, more: bool) {
+        let value = if more {
+            u16::from_be(self.offset_flags) | FLAG_MORE
+        } else {
+            u16::from_be(self.offset_flags) & !FLAG_MORE
+        };
+        self.offset_flags = u16::to_be(value);
+    }
+
+    #[inline]
+    pub fn id(&self) -> u32 {
+        u32::from_be(self.id)
+    }
+
+    #[inline]
+    pub fn set_id(&mut self, id: u32) {
+        self.id = u32::to_be(id);
+    }
+}
@@ -2,6 +2,7 @@ use super::{EndOffset, HeaderKind};
 use eui48::MacAddress;
 use std::default::Default;
 use std::fmt;
+use std::ptr;
 
 /// A packet's MAC header.
 #[derive(Debug, Default, Clone, Copy)]
@@ -18,6 +19,9 @@ impl fmt::Display for MacHeader {
     }
 }
 
+/// Ethertype of an 802.1Q tag.
+pub const ETYPE_VLAN: u16 = 0x8100;
+
 const HDR_SIZE: usize = 14;
 const HDR_SIZE_802_1Q: usize = HDR_SIZE + 4;
 const HDR_SIZE_802_1AD: usize = HDR_SIZE_802_1Q + 4;
@@ -25,8 +29,8 @@ const HDR_SIZE_802_1AD: usize = HDR_SIZE_802_1Q + 4;
 impl EndOffset for MacHeader {
     #[inline]
     fn offset(&self) -> usize {
-        match self.etype {
-            0x8100 => HDR_SIZE_802_1Q,
+        match self.etype() {
+            ETYPE_VLAN => HDR_SIZE_802_1Q,
             0x9100 => HDR_SIZE_802_1AD,
             _ => HDR_SIZE,
         }
@@ -62,6 +66,24 @@ impl MacHeader {
         self.etype = u16::to_be(etype)
     }
 
+    /// The Ethertype of the payload, which follows the 802.1Q tag of tagged frames. The tag must follow the header
+    /// in memory, as in parsed packets.
+    #[inline]
+    pub fn payload_etype(&self) -> u16 {
+        unsafe { u16::from_be(ptr::read_unaligned(self.payload_etype_ptr())) }
+    }
+
+    #[inline]
+    pub fn set_payload_etype(&mut self, etype: u16) {
+        unsafe { ptr::write_unaligned(self.payload_etype_ptr(), u16::to_be(etype)) }
+    }
+
+    /// the last two bytes of the header including the tag
+    #[inline]
+    fn payload_etype_ptr(&self) -> *mut u16 {
+        unsafe { (self as *const MacHeader as *mut u8).add(self.offset() - 2) as *mut u16 }
+    }
+
     #[inline]
     pub fn swap_addresses(&mut self) {
         let src: MacAddress = self.src;
@@ -1,18 +1,36 @@
 use std::fmt;
 
 pub use self::arp::*;
+pub use self::geneve::*;
+pub use self::gre::*;
+pub use self::gtpu::*;
+pub use self::icmpv6::*;
 pub use self::ip::*;
+pub use self::ipv6::*;
+pub use self::ipv6_fragment::*;
 pub use self::mac::*;
+pub use self::mpls::*;
 pub use self::null_header::*;
+pub use self::sctp::*;
 pub use self::tcp::*;
 pub use self::udp::*;
+pub use self::vxlan::*;
 
 mod arp;
+mod geneve;
+mod gre;
+mod gtpu;
+mod icmpv6;
 mod ip;
+mod ipv6;
+mod ipv6_fragment;
 mod mac;
+mod mpls;
 mod null_header;
+mod sctp;
 mod tcp;
 mod udp;
+mod vxlan;
 
 #[derive(Debug, PartialEq)]
 pub enum HeaderKind {
@@ -22,6 +40,15 @@ pub enum HeaderKind {
     Ip,
     Tcp,
     Udp,
+    Sctp,
+    Vxlan,
+    Gre,
+    Geneve,
+    Gtpu,
+    Mpls,
+    Ipv6,
+    Ipv6Fragment,
+    Icmpv6,
 }
 
 /// A trait implemented by all headers, used for reading them from a mbuf.
@@ -48,6 +75,15 @@ pub enum Header<'a> {
     Ip(&'a mut IpHeader),
     Tcp(&'a mut TcpHeader),
     Udp(&'a mut UdpHeader),
+    Sctp(&'a mut SctpHeader),
+    Vxlan(&'a mut VxlanHeader),
+    Gre(&'a mut GreHeader),
+    Geneve(&'a mut GeneveHeader),
+    Gtpu(&'a mut GtpuHeader),
+    Mpls(&'a mut MplsHeader),
+    Ipv6(&'a mut Ipv6Header),
+    Ipv6Fragment(&'a mut Ipv6FragmentHeader),
+    Icmpv6(&'a mut Icmpv6Header),
 }
 
 ///as Header contains mutable references, we can only clone Header::Null
@@ -67,7 +103,16 @@ impl<'a> Header<'a> {
                 HeaderKind::Ip => Header::Ip(&mut *(ptr as *mut IpHeader)),
                 HeaderKind::Tcp => Header::Tcp(&mut *(ptr as *mut TcpHeader)),
                 HeaderKind::Udp => Header::Udp(&mut *(ptr as *mut UdpHeader)),
+                HeaderKind::Sctp => Header::Sctp(&mut *(ptr as *mut SctpHeader)),
                 HeaderKind::ArpIpv4 => Header::ArpIpv4(&mut *(ptr as *mut ArpIpv4Header)),
+                HeaderKind::Vxlan => Header::Vxlan(&mut *(ptr as *mut VxlanHeader)),
+                HeaderKind::Gre => Header::Gre(&mut *(ptr as *mut GreHeader)),
+                HeaderKind::Geneve => Header::Geneve(&mut *(ptr as *mut GeneveHeader)),
+                HeaderKind::Gtpu => Header::Gtpu(&mut *(ptr as *mut GtpuHeader)),
+                HeaderKind::Mpls => Header::Mpls(&mut *(ptr as *mut MplsHeader)),
+                HeaderKind::Ipv6 => Header::Ipv6(&mut *(ptr as *mut Ipv6Header)),
+                HeaderKind::Ipv6Fragment => Header::Ipv6Fragment(&mut *(ptr as *mut Ipv6FragmentHeader)),
+                HeaderKind::Icmpv6 => Header::Icmpv6(&mut *(ptr as *mut Icmpv6Header)),
             }
         }
     }
@@ -112,6 +157,78 @@ impl<'a> Header<'a> {
         }
     }
 
+    #[inline]
+    pub fn as_sctp_mut(&mut self) -> Option<&mut SctpHeader> {
+        match self {
+            Header::Sctp(p) => Some(&mut **p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_vxlan_mut(&mut self) -> Option<&mut VxlanHeader> {
+        match self {
+            Header::Vxlan(p) => Some(&mut **p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_gre_mut(&mut self) -> Option<&mut GreHeader> {
+        match self {
+            Header::Gre(p) => Some(&mut **p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_geneve_mut(&mut self) -> Option<&mut GeneveHeader> {
+        match self {
+            Header::Geneve(p) => Some(&mut **p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_gtpu_mut(&mut self) -> Option<&mut GtpuHeader> {
+        match self {
+            Header::Gtpu(p) => Some(&mut **p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_mpls_mut(&mut self) -> Option<&mut MplsHeader> {
+        match self {
+            Header::Mpls(p) => Some(&mut **p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_ipv6_mut(&mut self) -> Option<&mut Ipv6Header> {
+        match self {
+            Header::Ipv6(p) => Some(&mut **p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_ipv6_fragment_mut(&mut self) -> Option<&mut Ipv6FragmentHeader> {
+        match self {
+            Header::Ipv6Fragment(p) => Some(&mut **p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_icmpv6_mut(&mut self) -> Option<&mut Icmpv6Header> {
+        match self {
+            Header::Icmpv6(p) => Some(&mut **p),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub fn as_mac(&self) -> Option<&MacHeader> {
         match self {
@@ -152,6 +269,78 @@ impl<'a> Header<'a> {
         }
     }
 
+    #[inline]
+    pub fn as_sctp(&self) -> Option<&SctpHeader> {
+        match self {
+            Header::Sctp(p) => Some(&**p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_vxlan(&self) -> Option<&VxlanHeader> {
+        match self {
+            Header::Vxlan(p) => Some(&**p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_gre(&self) -> Option<&GreHeader> {
+        match self {
+            Header::Gre(p) => Some(&**p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_geneve(&self) -> Option<&GeneveHeader> {
+        match self {
+            Header::Geneve(p) => Some(&**p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_gtpu(&self) -> Option<&GtpuHeader> {
+        match self {
+            Header::Gtpu(p) => Some(&**p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_mpls(&self) -> Option<&MplsHeader> {
+        match self {
+            Header::Mpls(p) => Some(&**p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_ipv6(&self) -> Option<&Ipv6Header> {
+        match self {
+            Header::Ipv6(p) => Some(&**p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_ipv6_fragment(&self) -> Option<&Ipv6FragmentHeader> {
+        match self {
+            Header::Ipv6Fragment(p) => Some(&**p),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_icmpv6(&self) -> Option<&Icmpv6Header> {
+        match self {
+            Header::Icmpv6(p) => Some(&**p),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub fn kind(&self) -> HeaderKind {
         match self {
@@ -160,7 +349,16 @@ impl<'a> Header<'a> {
             Header::Ip(_) => HeaderKind::Ip,
             Header::Tcp(_) => HeaderKind::Tcp,
             Header::Udp(_) => HeaderKind::Udp,
+            Header::Sctp(_) => HeaderKind::Sctp,
             Header::ArpIpv4(_) => HeaderKind::ArpIpv4,
+            Header::Vxlan(_) => HeaderKind::Vxlan,
+            Header::Gre(_) => HeaderKind::Gre,
+            Header::Geneve(_) => HeaderKind::Geneve,
+            Header::Gtpu(_) => HeaderKind::Gtpu,
+            Header::Mpls(_) => HeaderKind::Mpls,
+            Header::Ipv6(_) => HeaderKind::Ipv6,
+            Header::Ipv6Fragment(_) => HeaderKind::Ipv6Fragment,
+            Header::Icmpv6(_) => HeaderKind::Icmpv6,
         }
     }
 
@@ -172,7 +370,16 @@ impl<'a> Header<'a> {
             Header::Ip(_) => Some(self.as_ip().unwrap().offset()),
             Header::Tcp(_) => Some(self.as_tcp().unwrap().offset()),
             Header::Udp(_) => Some(self.as_udp().unwrap().offset()),
+            Header::Sctp(_) => Some(self.as_sctp().unwrap().offset()),
             Header::ArpIpv4(_) => Some(self.as_arpipv4().unwrap().offset()),
+            Header::Vxlan(_) => Some(self.as_vxlan().unwrap().offset()),
+            Header::Gre(_) => Some(self.as_gre().unwrap().offset()),
+            Header::Geneve(_) => Some(self.as_geneve().unwrap().offset()),
+            Header::Gtpu(_) => Some(self.as_gtpu().unwrap().offset()),
+            Header::Mpls(_) => Some(self.as_mpls().unwrap().offset()),
+            Header::Ipv6(_) => Some(self.as_ipv6().unwrap().offset()),
+            Header::Ipv6Fragment(_) => Some(self.as_ipv6_fragment().unwrap().offset()),
+            Header::Icmpv6(_) => Some(self.as_icmpv6().unwrap().offset()),
         }
     }
 
@@ -184,7 +391,16 @@ impl<'a> Header<'a> {
             Header::Ip(p) => Some(*p as *mut IpHeader as *mut u8),
             Header::Tcp(p) => Some(*p as *mut TcpHeader as *mut u8),
             Header::Udp(p) => Some(*p as *mut UdpHeader as *mut u8),
+            Header::Sctp(p) => Some(*p as *mut SctpHeader as *mut u8),
             Header::ArpIpv4(p) => Some(*p as *mut ArpIpv4Header as *mut u8),
+            Header::Vxlan(p) => Some(*p as *mut VxlanHeader as *mut u8),
+            Header::Gre(p) => Some(*p as *mut GreHeader as *mut u8),
+            Header::Geneve(p) => Some(*p as *mut GeneveHeader as *mut u8),
+            Header::Gtpu(p) => Some(*p as *mut GtpuHeader as *mut u8),
+            Header::Mpls(p) => Some(*p as *mut MplsHeader as *mut u8),
+            Header::Ipv6(p) => Some(*p as *mut Ipv6Header as *mut u8),
+            Header::Ipv6Fragment(p) => Some(*p as *mut Ipv6FragmentHeader as *mut u8),
+            Header::Icmpv6(p) => Some(*p as *mut Icmpv6Header as *mut u8),
         }
     }
 
@@ -196,7 +412,16 @@ impl<'a> Header<'a> {
             Header::Ip(p) => Some(*p as *const IpHeader as *const u8),
             Header::Tcp(p) => Some(*p as *const TcpHeader as *const u8),
             Header::Udp(p) => Some(*p as *const UdpHeader as *const u8),
+            Header::Sctp(p) => Some(*p as *const SctpHeader as *const u8),
             Header::ArpIpv4(p) => Some(*p as *const ArpIpv4Header as *const u8),
+            Header::Vxlan(p) => Some(*p as *const VxlanHeader as *const u8),
+            Header::Gre(p) => Some(*p as *const GreHeader as *const u8),
+            Header::Geneve(p) => Some(*p as *const GeneveHeader as *const u8),
+            Header::Gtpu(p) => Some(*p as *const GtpuHeader as *const u8),
+            Header::Mpls(p) => Some(*p as *const MplsHeader as *const u8),
+            Header::Ipv6(p) => Some(*p as *const Ipv6Header as *const u8),
+            Header::Ipv6Fragment(p) => Some(*p as *const Ipv6FragmentHeader as *const u8),
+            Header::Icmpv6(p) => Some(*p as *const Icmpv6Header as *const u8),
         }
     }
 }
@@ -209,7 +434,16 @@ impl<'a> fmt::Display for Header<'a> {
             Header::Ip(_) => write!(f, "{ }", self.as_ip().unwrap()),
             Header::Tcp(_) => write!(f, "{ }", self.as_tcp().unwrap()),
             Header::Udp(_) => write!(f, "{:?}", self.as_udp().unwrap()),
+            Header::Sctp(_) => write!(f, "{ }", self.as_sctp().unwrap()),
             Header::ArpIpv4(_) => write!(f, "{:?}", self.as_arpipv4().unwrap()),
+            Header::Vxlan(_) => write!(f, "{ }", self.as_vxlan().unwrap()),
+            Header::Gre(_) => write!(f, "{ }", self.as_gre().unwrap()),
+            Header::Geneve(_) => write!(f, "{ }", self.as_geneve().unwrap()),
+            Header::Gtpu(_) => write!(f, "{ }", self.as_gtpu().unwrap()),
+            Header::Mpls(_) => write!(f, "{ }", self.as_mpls().unwrap()),
+            Header::Ipv6(_) => write!(f, "{ }", self.as_ipv6().unwrap()),
+            Header::Ipv6Fragment(_) => write!(f, "{ }", self.as_ipv6_fragment().unwrap()),
+            Header::Icmpv6(_) => write!(f, "{ }", self.as_icmpv6().unwrap()),
         }
     }
 }
@@ -226,4 +460,5 @@ fn test_headers() {
     assert!(header.as_mac().is_none());
     assert!(header.as_tcp().is_none());
     assert!(header.as_udp().is_none());
+    assert!(header.as_sctp().is_none());
 }
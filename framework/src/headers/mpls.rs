@@ -0,0 +1,131 @@
+use super::{EndOffset, HeaderKind};
+use std::default::Default;
+use std::fmt;
+
+/// Ethertype of MPLS unicast packets.
+pub const ETYPE_MPLS_UNICAST: u16 = 0x8847;
+/// Ethertype of MPLS multicast packets.
+pub const ETYPE_MPLS_MULTICAST: u16 = 0x8848;
+
+/// largest 20 bit label value
+pub const MPLS_MAX_LABEL: u32 = 0x000f_ffff;
+
+const LABEL_SHIFT: u32 = 12;
+const TC_SHIFT: u32 = 9;
+const TC_MASK: u32 = 0x0000_0e00;
+const BOTTOM_OF_STACK: u32 = 0x0000_0100;
+const TTL_MASK: u32 = 0x0000_00ff;
+
+/// A single MPLS label stack entry (RFC 3032). The entry with the bottom of stack flag set is followed by the payload.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C, packed)]
+pub struct MplsHeader {
+    entry: u32,
+}
+
+impl fmt::Display for MplsHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "mpls label: {} tc: {} bos: {} ttl: {}",
+            self.label(),
+            self.tc(),
+            self.bottom_of_stack(),
+            self.ttl()
+        )
+    }
+}
+
+impl EndOffset for MplsHeader {
+    #[inline]
+    fn offset(&self) -> usize {
+        4
+    }
+
+    #[inline]
+    fn size() -> usize {
+        4
+    }
+
+    #[inline]
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    #[inline]
+    fn header_kind(&self) -> HeaderKind {
+        HeaderKind::Mpls
+    }
+}
+
+impl MplsHeader {
+    #[inline]
+    pub fn new(label: u32, tc: u8, bottom_of_stack: bool, ttl: u8) -> MplsHeader {
+        let mut hdr = MplsHeader::default();
+        hdr.set_label(label);
+        hdr.set_tc(tc);
+        hdr.set_bottom_of_stack(bottom_of_stack);
+        hdr.set_ttl(ttl);
+        hdr
+    }
+
+    #[inline]
+    fn entry(&self) -> u32 {
+        u32::from_be(self.entry)
+    }
+
+    #[inline]
+    fn set_entry(&mut self, entry: u32) {
+        self.entry = u32::to_be(entry);
+    }
+
+    /// 20 bit label value
+    #[inline]
+    pub fn label(&self) -> u32 {
+        self.entry() >> LABEL_SHIFT
+    }
+
+    #[inline]
+    pub fn set_label(&mut self, label: u32) {
+        let entry = (self.entry() & !(MPLS_MAX_LABEL << LABEL_SHIFT)) | ((label & MPLS_MAX_LABEL) << LABEL_SHIFT);
+        self.set_entry(entry);
+    }
+
+    /// 3 bit traffic class
+    #[inline]
+    pub fn tc(&self) -> u8 {
+        ((self.entry() & TC_MASK) >> TC_SHIFT) as u8
+    }
+
+    #[inline]
+    pub fn set_tc(&mut self, tc: u8) {
+        let entry = (self.entry() & !TC_MASK) | (((tc as u32) << TC_SHIFT) & TC_MASK);
+        self.set_entry(entry);
+    }
+
+    #[inline]
+    pub fn bottom_of_stack(&self) -> bool {
+        self.entry() & BOTTOM_OF_STACK != 0
+    }
+
+    #[inline]
+    pub fn set_bottom_of_stack(&mut self, bos: bool) {
+        let entry = if bos {
+            self.entry() | BOTTOM_OF_STACK
+        } else {
+            self.entry() & !BOTTOM_OF_STACK
+        };
+        self.set_entry(entry);
+    }
+
+    #[inline]
+    pub fn ttl(&self) -> u8 {
+        (self.entry() & TTL_MASK) as u8
+    }
+
+    #[inline]
+    pub fn set_ttl(&mut self, ttl: u8) {
+        let entry = (self.entry() & !TTL_MASK) | ttl as u32;
+        self.set_entry(entry);
+    }
+}
@@ -0,0 +1,97 @@
+use super::{EndOffset, HeaderKind};
+use std::default::Default;
+use std::fmt;
+
+/// SCTP common header (RFC 4960). Chunks follow as payload.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct SctpHeader {
+    src_port: u16,
+    dst_port: u16,
+    vtag: u32,
+    csum: u32,
+}
+
+impl fmt::Display for SctpHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "sctp src_port: {} dst_port: {} vtag: {:x} checksum: {:x}",
+            self.src_port(),
+            self.dst_port(),
+            self.vtag(),
+            self.checksum()
+        )
+    }
+}
+
+impl EndOffset for SctpHeader {
+    #[inline]
+    fn offset(&self) -> usize {
+        12
+    }
+
+    #[inline]
+    fn size() -> usize {
+        12
+    }
+
+    #[inline]
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    #[inline]
+    fn header_kind(&self) -> HeaderKind {
+        HeaderKind::Sctp
+    }
+}
+
+impl SctpHeader {
+    #[inline]
+    pub fn new() -> SctpHeader {
+        Default::default()
+    }
+
+    #[inline]
+    pub fn src_port(&self) -> u16 {
+        u16::from_be(self.src_port)
+    }
+
+    #[inline]
+    pub fn dst_port(&self) -> u16 {
+        u16::from_be(self.dst_port)
+    }
+
+    #[inline]
+    pub fn set_src_port(&mut self, port: u16) {
+        self.src_port = u16::to_be(port);
+    }
+
+    #[inline]
+    pub fn set_dst_port(&mut self, port: u16) {
+        self.dst_port = u16::to_be(port);
+    }
+
+    /// Verification tag
+    #[inline]
+    pub fn vtag(&self) -> u32 {
+        u32::from_be(self.vtag)
+    }
+
+    #[inline]
+    pub fn set_vtag(&mut self, vtag: u32) {
+        self.vtag = u32::to_be(vtag);
+    }
+
+    /// CRC32c checksum over the complete SCTP packet, which is sent in little-endian byte order (RFC 4960, appendix B)
+    #[inline]
+    pub fn checksum(&self) -> u32 {
+        u32::from_le(self.csum)
+    }
+
+    #[inline]
+    pub fn set_checksum(&mut self, csum: u32) {
+        self.csum = u32::to_le(csum);
+    }
+}
@@ -1,8 +1,21 @@
 use super::{EndOffset, HeaderKind};
 use std::default::Default;
 use std::fmt;
+use std::slice;
 use utils::update_checksum_incremental;
 
+/// TCP option kinds.
+pub const TCP_OPT_END: u8 = 0;
+pub const TCP_OPT_NOP: u8 = 1;
+pub const TCP_OPT_MSS: u8 = 2;
+pub const TCP_OPT_WINDOW_SCALE: u8 = 3;
+pub const TCP_OPT_SACK_PERMITTED: u8 = 4;
+pub const TCP_OPT_SACK: u8 = 5;
+pub const TCP_OPT_TIMESTAMP: u8 = 8;
+
+/// Options take at most 40 bytes, the data offset is limited to 15 words.
+pub const TCP_MAX_OPTIONS_LEN: usize = 40;
+
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C, packed)]
 pub struct TcpHeader {
@@ -388,4 +401,254 @@ impl TcpHeader {
     pub fn set_urgent(&mut self, urgent: u16) {
         self.urgent = u16::to_be(urgent);
     }
+
+    /// The options following the header, including padding. `available` is the number of bytes from the start of the
+    /// header which can be read, e.g. up to the end of the first mbuf segment. None if the options exceed them.
+    #[inline]
+    pub fn option_bytes(&self, available: usize) -> Option<&[u8]> {
+        if self.offset() > available {
+            return None;
+        }
+        let len = self.offset().saturating_sub(TcpHeader::size());
+        Some(unsafe { slice::from_raw_parts((self as *const TcpHeader as *const u8).offset(20), len) })
+    }
+
+    /// Mutable `option_bytes`, the checksum is not updated.
+    #[inline]
+    pub fn option_bytes_mut(&mut self, available: usize) -> Option<&mut [u8]> {
+        if self.offset() > available {
+            return None;
+        }
+        let len = self.offset().saturating_sub(TcpHeader::size());
+        Some(unsafe { slice::from_raw_parts_mut((self as *mut TcpHeader as *mut u8).offset(20), len) })
+    }
+
+    /// Iterate over the options, see `option_bytes`.
+    #[inline]
+    pub fn options(&self, available: usize) -> Option<TcpOptions> {
+        self.option_bytes(available).map(TcpOptions::new)
+    }
+
+    /// The MSS option, if present, see `option_bytes`.
+    pub fn mss(&self, available: usize) -> Option<u16> {
+        self.options(available)?.find_map(|option| match option {
+            TcpOption::Mss(mss) => Some(mss),
+            _ => None,
+        })
+    }
+
+    /// Lower the MSS option to `mss` if it announces a larger MSS, updating the checksum incrementally. Returns true if
+    /// the option was changed. Used to avoid fragmentation of TCP packets which are tunneled. See `option_bytes` for
+    /// `available`.
+    pub fn clamp_mss(&mut self, mss: u16, available: usize) -> bool {
+        let offset = {
+            let mut options = match self.options(available) {
+                Some(options) => options,
+                None => return false,
+            };
+            let mut found = None;
+            while let Some(option) = options.next() {
+                if let TcpOption::Mss(current) = option {
+                    if current > mss {
+                        found = Some(options.offset());
+                    }
+                    break;
+                }
+            }
+            found
+        };
+        match offset {
+            Some(offset) => {
+                // the option may start at any offset, the checksum is updated per 16-bit word of the header
+                let bytes = self.option_bytes_mut(available).unwrap();
+                let start = (offset + 2) & !1;
+                let end = (offset + 5) & !1;
+                let old: Vec<u8> = bytes[start..end].to_vec();
+                bytes[offset + 2] = (mss >> 8) as u8;
+                bytes[offset + 3] = mss as u8;
+                let new: Vec<u8> = bytes[start..end].to_vec();
+                for (old_word, new_word) in old.chunks(2).zip(new.chunks(2)) {
+                    self.update_checksum_incremental(
+                        (old_word[0] as u16) << 8 | old_word[1] as u16,
+                        (new_word[0] as u16) << 8 | new_word[1] as u16,
+                    );
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Pairs of left and right edges of SACK blocks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SackBlocks<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> SackBlocks<'a> {
+    pub fn new(data: &'a [u8]) -> SackBlocks<'a> {
+        SackBlocks { data }
+    }
+
+    /// the blocks as in the option
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl<'a> Iterator for SackBlocks<'a> {
+    type Item = (u32, u32);
+
+    fn next(&mut self) -> Option<(u32, u32)> {
+        if self.data.len() < 8 {
+            return None;
+        }
+        let block = (read_u32(&self.data[..4]), read_u32(&self.data[4..8]));
+        self.data = &self.data[8..];
+        Some(block)
+    }
+}
+
+#[inline]
+fn read_u32(data: &[u8]) -> u32 {
+    (data[0] as u32) << 24 | (data[1] as u32) << 16 | (data[2] as u32) << 8 | data[3] as u32
+}
+
+/// A TCP option, options of known kinds with an unexpected length are returned as `Other`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TcpOption<'a> {
+    Mss(u16),
+    WindowScale(u8),
+    SackPermitted,
+    Sack(SackBlocks<'a>),
+    Timestamp {
+        tsval: u32,
+        tsecr: u32,
+    },
+    /// `data` follows the kind and length bytes
+    Other {
+        kind: u8,
+        data: &'a [u8],
+    },
+}
+
+impl<'a> TcpOption<'a> {
+    pub fn kind(&self) -> u8 {
+        match *self {
+            TcpOption::Mss(_) => TCP_OPT_MSS,
+            TcpOption::WindowScale(_) => TCP_OPT_WINDOW_SCALE,
+            TcpOption::SackPermitted => TCP_OPT_SACK_PERMITTED,
+            TcpOption::Sack(_) => TCP_OPT_SACK,
+            TcpOption::Timestamp { .. } => TCP_OPT_TIMESTAMP,
+            TcpOption::Other { kind, .. } => kind,
+        }
+    }
+
+    /// Length of the encoded option, including kind and length bytes.
+    pub fn len(&self) -> usize {
+        match *self {
+            TcpOption::Mss(_) => 4,
+            TcpOption::WindowScale(_) => 3,
+            TcpOption::SackPermitted => 2,
+            TcpOption::Sack(blocks) => 2 + blocks.as_bytes().len(),
+            TcpOption::Timestamp { .. } => 10,
+            TcpOption::Other { data, .. } => 2 + data.len(),
+        }
+    }
+
+    /// True if the option carries no data besides its kind and length, e.g. SACK permitted.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 2
+    }
+
+    /// Append the encoded option to `out`.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.kind());
+        out.push(self.len() as u8);
+        match *self {
+            TcpOption::Mss(mss) => out.extend_from_slice(&[(mss >> 8) as u8, mss as u8]),
+            TcpOption::WindowScale(shift) => out.push(shift),
+            TcpOption::SackPermitted => (),
+            TcpOption::Sack(blocks) => out.extend_from_slice(blocks.as_bytes()),
+            TcpOption::Timestamp { tsval, tsecr } => {
+                for value in &[tsval, tsecr] {
+                    out.extend_from_slice(&[
+                        (value >> 24) as u8,
+                        (value >> 16) as u8,
+                        (value >> 8) as u8,
+                        *value as u8,
+                    ]);
+                }
+            }
+            TcpOption::Other { data, .. } => out.extend_from_slice(data),
+        }
+    }
+}
+
+/// Iterates over TCP options, skipping padding. Stops at the end of option list option, or at an option which is
+/// truncated or has an invalid length, see `malformed`.
+#[derive(Clone)]
+pub struct TcpOptions<'a> {
+    data: &'a [u8],
+    pos: usize,
+    offset: usize,
+    malformed: bool,
+}
+
+impl<'a> TcpOptions<'a> {
+    pub fn new(data: &'a [u8]) -> TcpOptions<'a> {
+        TcpOptions {
+            data,
+            pos: 0,
+            offset: 0,
+            malformed: false,
+        }
+    }
+
+    /// Offset of the option returned last in the option bytes.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// true if iteration stopped at a malformed option
+    pub fn malformed(&self) -> bool {
+        self.malformed
+    }
+}
+
+impl<'a> Iterator for TcpOptions<'a> {
+    type Item = TcpOption<'a>;
+
+    fn next(&mut self) -> Option<TcpOption<'a>> {
+        while self.pos < self.data.len() && self.data[self.pos] == TCP_OPT_NOP {
+            self.pos += 1;
+        }
+        let rest = &self.data[self.pos..];
+        if rest.is_empty() || rest[0] == TCP_OPT_END {
+            self.pos = self.data.len();
+            return None;
+        }
+        if rest.len() < 2 || rest[1] < 2 || rest[1] as usize > rest.len() {
+            self.malformed = true;
+            self.pos = self.data.len();
+            return None;
+        }
+        let (kind, len) = (rest[0], rest[1] as usize);
+        let data = &rest[2..len];
+        let option = match (kind, len) {
+            (TCP_OPT_MSS, 4) => TcpOption::Mss((data[0] as u16) << 8 | data[1] as u16),
+            (TCP_OPT_WINDOW_SCALE, 3) => TcpOption::WindowScale(data[0]),
+            (TCP_OPT_SACK_PERMITTED, 2) => TcpOption::SackPermitted,
+            (TCP_OPT_SACK, _) if (len - 2) % 8 == 0 => TcpOption::Sack(SackBlocks::new(data)),
+            (TCP_OPT_TIMESTAMP, 10) => TcpOption::Timestamp {
+                tsval: read_u32(&data[..4]),
+                tsecr: read_u32(&data[4..]),
+            },
+            _ => TcpOption::Other { kind, data },
+        };
+        self.offset = self.pos;
+        self.pos += len;
+        Some(option)
+    }
 }
@@ -0,0 +1,73 @@
+use super::{EndOffset, HeaderKind};
+use std::default::Default;
+use std::fmt;
+
+/// UDP destination port assigned to VXLAN.
+pub const VXLAN_PORT: u16 = 4789;
+
+const FLAG_VNI: u8 = 0x08;
+
+/// VXLAN header (RFC 7348), followed by the inner Ethernet frame.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct VxlanHeader {
+    flags: u8,
+    reserved: [u8; 3],
+    vni_reserved: u32,
+}
+
+impl fmt::Display for VxlanHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "vxlan flags: 0x{:02x} vni: {}", self.flags, self.vni())
+    }
+}
+
+impl EndOffset for VxlanHeader {
+    #[inline]
+    fn offset(&self) -> usize {
+        8
+    }
+
+    #[inline]
+    fn size() -> usize {
+        8
+    }
+
+    #[inline]
+    fn payload_size(&self, hint: usize) -> usize {
+        hint - self.offset()
+    }
+
+    #[inline]
+    fn header_kind(&self) -> HeaderKind {
+        HeaderKind::Vxlan
+    }
+}
+
+impl VxlanHeader {
+    /// A header with the VNI flag set.
+    #[inline]
+    pub fn new() -> VxlanHeader {
+        VxlanHeader {
+            flags: FLAG_VNI,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn vni_valid(&self) -> bool {
+        self.flags & FLAG_VNI != 0
+    }
+
+    /// 24 bit VXLAN network identifier
+    #[inline]
+    pub fn vni(&self) -> u32 {
+        u32::from_be(self.vni_reserved) >> 8
+    }
+
+    #[inline]
+    pub fn set_vni(&mut self, vni: u32) {
+        self.vni_reserved = u32::to_be((vni & 0x00ff_ffff) << 8);
+        self.flags |= FLAG_VNI;
+    }
+}
@@ -0,0 +1,207 @@
+use common::errors;
+use common::errors::ErrorKind;
+use eui48::MacAddress;
+use headers::*;
+use interface::{send_pdus, PacketTx, Pdu, PmdPort};
+use ipnet::Ipv4Net;
+use state::{NeighborCache, NeighborConfig, Resolution};
+use std::net::Ipv4Addr;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Instant;
+
+/// What `ArpService::handle_arp` did with a packet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArpAction {
+    /// not an ARP packet, the packet is unchanged
+    NotArp,
+    /// the packet was turned into a reply which should be sent back on the receiving port
+    Reply,
+    /// the packet was processed and can be dropped
+    Consumed,
+}
+
+/// ARP for the IPv4 address of a port: answers requests for the address and resolves the MAC address of the next
+/// hop of outgoing packets. Clones share the neighbor cache: give each core a clone, so that a reply received on any
+/// core resolves the addresses requested by the others, and `poll` on any core sends each request once.
+#[derive(Clone)]
+pub struct ArpService {
+    mac: MacAddress,
+    ip_net: Ipv4Net,
+    gateway: Option<Ipv4Addr>,
+    cache: Arc<RwLock<NeighborCache<Ipv4Addr>>>,
+}
+
+impl ArpService {
+    /// A service for the address in the `NetSpec` of `port`. The MAC address of the spec is used if given, otherwise
+    /// the one of the device.
+    pub fn new(port: &PmdPort, config: NeighborConfig) -> errors::Result<ArpService> {
+        let spec = match *port.net_spec() {
+            Some(ref spec) => spec,
+            None => {
+                return Err(ErrorKind::ConfigurationError(format!(
+                    "port {} has no network specification",
+                    port.name()
+                )))
+            }
+        };
+        let ip_net = match spec.ip_net {
+            Some(ip_net) => ip_net,
+            None => {
+                return Err(ErrorKind::ConfigurationError(format!(
+                    "port {} has no IP address",
+                    port.name()
+                )))
+            }
+        };
+        let mac = spec.mac.unwrap_or_else(|| port.mac_address());
+        Ok(ArpService::with_address(mac, ip_net, config))
+    }
+
+    pub fn with_address(mac: MacAddress, ip_net: Ipv4Net, config: NeighborConfig) -> ArpService {
+        ArpService {
+            mac,
+            ip_net,
+            gateway: None,
+            cache: Arc::new(RwLock::new(NeighborCache::new(config))),
+        }
+    }
+
+    pub fn mac(&self) -> MacAddress {
+        self.mac
+    }
+
+    pub fn ip_addr(&self) -> Ipv4Addr {
+        self.ip_net.addr()
+    }
+
+    /// Packets to destinations outside of the subnet of the port are sent to the gateway.
+    pub fn set_gateway(&mut self, gateway: Option<Ipv4Addr>) {
+        self.gateway = gateway;
+    }
+
+    pub fn gateway(&self) -> Option<Ipv4Addr> {
+        self.gateway
+    }
+
+    pub fn cache<'a>(&'a self) -> RwLockReadGuard<'a, NeighborCache<Ipv4Addr>> {
+        self.cache.read().unwrap()
+    }
+
+    pub fn cache_mut<'a>(&'a mut self) -> RwLockWriteGuard<'a, NeighborCache<Ipv4Addr>> {
+        self.cache.write().unwrap()
+    }
+
+    /// The neighbor a packet to `dst` is sent to, None if `dst` is outside of the subnet and there is no gateway.
+    pub fn next_hop(&self, dst: Ipv4Addr) -> Option<Ipv4Addr> {
+        if self.ip_net.contains(&dst) {
+            Some(dst)
+        } else {
+            self.gateway
+        }
+    }
+
+    /// Learn the sender of an ARP packet (RFC 826) and turn requests for the address of the port into replies, in
+    /// place. The packet must be parsed.
+    pub fn handle_arp(&mut self, pdu: &mut Pdu, now: Instant) -> ArpAction {
+        let count = pdu.headers().count();
+        let arp_ix = match (0..count).find(|&i| pdu.headers().get(i).as_arpipv4().is_some()) {
+            Some(arp_ix) => arp_ix,
+            None => return ArpAction::NotArp,
+        };
+        let (sender_ip, sender_mac, target_ip, request) = {
+            let arp = pdu.headers().arp(arp_ix);
+            (
+                arp.sender_ip_addr(),
+                arp.sender_hw_addr(),
+                arp.target_ip_addr(),
+                arp.is_request(),
+            )
+        };
+        let for_us = target_ip == self.ip_addr();
+        // 0.0.0.0 is the sender of address probes (RFC 5227)
+        if !sender_ip.is_unspecified() && sender_ip != self.ip_addr() {
+            let mut cache = self.cache_mut();
+            if for_us {
+                cache.update(sender_ip, sender_mac, now);
+            } else {
+                cache.update_existing(sender_ip, sender_mac, now);
+            }
+        }
+        if !(for_us && request) {
+            return ArpAction::Consumed;
+        }
+        let mac = self.mac;
+        let headers = pdu.headers_mut();
+        headers.arp_mut(arp_ix).make_reply(&mac);
+        let eth = headers.mac_mut(0);
+        eth.set_dmac(&sender_mac);
+        eth.set_smac(&mac);
+        ArpAction::Reply
+    }
+
+    /// Set the MAC addresses of an outgoing IPv4 packet to the port and the next hop. If the next hop is not resolved
+    /// yet, the packet is not modified and a request is sent by the next `poll`.
+    pub fn set_destination(&mut self, pdu: &mut Pdu, now: Instant) -> Resolution {
+        let count = pdu.headers().count();
+        let dst = match pdu.headers().get_slice(0..count).iter().find_map(|h| h.as_ip()) {
+            Some(ip) => Ipv4Addr::from(ip.dst()),
+            None => return Resolution::Unreachable,
+        };
+        let next_hop = match self.next_hop(dst) {
+            Some(next_hop) => next_hop,
+            None => return Resolution::Unreachable,
+        };
+        // resolved neighbors only take the read lock
+        let cached = self.cache().lookup(&next_hop, now);
+        let resolution = match cached {
+            Some(resolution) => resolution,
+            None => self.cache_mut().resolve(next_hop, now),
+        };
+        if let Resolution::Resolved(dmac) = resolution {
+            let eth = pdu.headers_mut().mac_mut(0);
+            eth.set_dmac(&dmac);
+            eth.set_smac(&self.mac);
+        }
+        resolution
+    }
+
+    /// A broadcast request for the MAC address of `target`.
+    pub fn request(&self, target: Ipv4Addr) -> errors::Result<Pdu<'static>> {
+        let mut pdu = match Pdu::new_pdu() {
+            Some(pdu) => pdu,
+            None => return Err(ErrorKind::FailedAllocation),
+        };
+        let mut eth = MacHeader::new();
+        eth.set_dmac(&MacAddress::broadcast());
+        eth.set_smac(&self.mac);
+        eth.set_etype(ETYPE_ARP);
+        let arp = ArpIpv4Header::new_request(&self.mac, self.ip_addr(), target);
+        if !pdu.push_header(&eth) || !pdu.push_header(&arp) {
+            pdu.free();
+            return Err(ErrorKind::FailedAllocation);
+        }
+        Ok(pdu)
+    }
+
+    /// Advance the timers of the cache and return the requests which are due, the caller owns the packets.
+    pub fn poll(&mut self, now: Instant) -> errors::Result<Vec<Pdu<'static>>> {
+        let targets = self.cache_mut().poll(now);
+        let mut requests = Vec::with_capacity(targets.len());
+        for target in targets {
+            match self.request(target) {
+                Ok(pdu) => requests.push(pdu),
+                Err(e) => {
+                    requests.into_iter().for_each(|pdu: Pdu| pdu.free());
+                    return Err(e);
+                }
+            }
+        }
+        Ok(requests)
+    }
+
+    /// `poll` and send the requests on `tx`, requests which could not be sent are dropped.
+    pub fn send_requests<T: PacketTx>(&mut self, tx: &mut T, now: Instant) -> errors::Result<u32> {
+        let requests = self.poll(now)?;
+        send_pdus(tx, requests)
+    }
+}
@@ -1,8 +1,10 @@
-use config::{NetbricksConfiguration, DEFAULT_CACHE_SIZE, DEFAULT_MBUF_CNT, DEFAULT_POOL_SIZE};
+use config::{NetbricksConfiguration, DEFAULT_CACHE_SIZE, DEFAULT_MBUF_CNT, DEFAULT_MBUF_DATA_ROOM, DEFAULT_POOL_SIZE};
 use native::libnuma;
 use native::zcsi;
 use std::cell::Cell;
+use std::cmp::max;
 use std::ffi::CString;
+use std::fs;
 
 pub const METADATA_SLOTS: u16 = 16; // slot size is 8, see mempool.c
 
@@ -16,6 +18,34 @@ pub fn init_system_wl_with_mempool(
     cache_size: u32,
     mbuf_cnt: u32,
     vdevs: &Vec<String>,
+) {
+    // sockets with lcores but without an explicit size get the largest size given
+    init_system_wl_with_mempools(
+        name,
+        lcore_mask,
+        core,
+        pci,
+        pool_size,
+        cache_size,
+        &[mbuf_cnt],
+        DEFAULT_MBUF_DATA_ROOM,
+        vdevs,
+    )
+}
+
+/// Initialize the system, whitelisting some set of NICs and allocating one mempool per NUMA socket.
+/// `mbufs_per_socket[sid]` is the number of mbufs in the pool of socket `sid`, `data_room` the size of the mbuf data
+/// buffers including headroom.
+pub fn init_system_wl_with_mempools(
+    name: &str,
+    lcore_mask: u64,
+    core: i32,
+    pci: &[String],
+    pool_size: u32,
+    cache_size: u32,
+    mbufs_per_socket: &[u32],
+    data_room: u16,
+    vdevs: &Vec<String>,
 ) {
     let name_cstr = CString::new(name).unwrap();
     let pci_cstr: Vec<_> = pci.iter().map(|p| CString::new(&p[..]).unwrap()).collect();
@@ -32,7 +62,9 @@ pub fn init_system_wl_with_mempool(
             pci.len() as i32,
             pool_size,
             cache_size,
-            mbuf_cnt,
+            mbufs_per_socket.as_ptr(),
+            mbufs_per_socket.len() as i32,
+            data_room,
             METADATA_SLOTS,
             vdevs_ptr.as_mut_ptr(),
             vdevs.len() as i32,
@@ -88,20 +120,44 @@ pub fn init_system(config: &NetbricksConfiguration) {
         // We do not have control over any of the other settings in this case.
         init_system_secondary(&config.name[..], config.lcore_mask(), config.primary_core);
     } else {
-        init_system_wl_with_mempool(
+        let mbufs_per_socket = config.mbufs_per_socket(socket_of_core, socket_of_device);
+        info!("mbufs per NUMA socket: {:?}", mbufs_per_socket);
+        init_system_wl_with_mempools(
             &config.name[..],
             config.lcore_mask(),
             config.primary_core,
             &[],
             config.pool_size,
             config.cache_size,
-            config.mbuf_cnt,
+            &mbufs_per_socket,
+            config.mbuf_data_room,
             &config.vdevs,
         );
     }
     set_numa_domain();
 }
 
+/// NUMA socket of a core as reported by libnuma, 0 if NUMA is not available.
+pub fn socket_of_core(core: i32) -> i32 {
+    unsafe {
+        if libnuma::numa_available() == -1 {
+            0
+        } else {
+            max(libnuma::numa_node_of_cpu(core), 0)
+        }
+    }
+}
+
+/// NUMA socket of the PCI device `name`, e.g. "0000:01:00.0", as reported by sysfs. None for other ports and devices
+/// without NUMA information. The devices are not probed yet when the mempools are sized, so DPDK cannot tell.
+pub fn socket_of_device(name: &str) -> Option<i32> {
+    let path = format!("/sys/bus/pci/devices/{}/numa_node", name);
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|node| node.trim().parse::<i32>().ok())
+        .filter(|&node| node >= 0)
+}
+
 thread_local!(static NUMA_DOMAIN: Cell<i32> = Cell::new(-1));
 
 fn set_numa_domain() {
@@ -0,0 +1,338 @@
+use common::errors;
+use common::errors::ErrorKind;
+use headers::*;
+use interface::Pdu;
+use std::mem;
+use std::net::Ipv6Addr;
+use std::slice;
+
+/// largest IP datagram, also the limit for reassembled packets
+pub const MAX_DATAGRAM_SIZE: usize = 65535;
+
+/// Identifies the datagram a fragment belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FragmentKey {
+    Ipv4 { src: u32, dst: u32, protocol: u8, id: u16 },
+    Ipv6 { src: Ipv6Addr, dst: Ipv6Addr, id: u32 },
+}
+
+/// Describes a fragment of an IPv4 or IPv6 packet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FragmentInfo {
+    pub key: FragmentKey,
+    /// position of the fragment data in the payload of the original packet
+    pub offset: usize,
+    /// length of the fragment data
+    pub len: usize,
+    /// more fragments follow, false for the last fragment
+    pub more: bool,
+    /// protocol of the payload of the original packet
+    pub next_header: u8,
+    /// length of the Ethernet and IP headers which are kept in the reassembled packet, i.e. without the IPv6 fragment
+    /// header
+    pub header_len: usize,
+    /// start of the fragment data in the packet
+    pub data_offset: usize,
+}
+
+#[inline]
+fn header_bytes<T>(header: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(header as *const T as *const u8, mem::size_of::<T>()) }
+}
+
+impl<'a> Pdu<'a> {
+    /// Index of the first IPv4 or IPv6 header.
+    #[inline]
+    fn first_ip_index(&self) -> Option<usize> {
+        (0..self.headers().count()).find(|&i| {
+            let kind = self.headers().get(i).kind();
+            kind == HeaderKind::Ip || kind == HeaderKind::Ipv6
+        })
+    }
+
+    /// Describes the packet if it is an IPv4 fragment or carries an IPv6 fragment header directly after the IPv6
+    /// header. Only the first IP header is considered, i.e. the outer packet of a tunnel.
+    pub fn fragment_info(&self) -> Option<FragmentInfo> {
+        let ip_ix = self.first_ip_index()?;
+        let headers = self.headers();
+        if let Some(ip) = headers.get(ip_ix).as_ip() {
+            let ip_header_len = ip.offset();
+            if !ip.is_fragment() || (ip.length() as usize) < ip_header_len {
+                return None;
+            }
+            let header_len = self.header_offset(ip_ix) + ip_header_len;
+            Some(FragmentInfo {
+                key: FragmentKey::Ipv4 {
+                    src: ip.src(),
+                    dst: ip.dst(),
+                    protocol: ip.protocol(),
+                    id: ip.id(),
+                },
+                offset: ip.fragment_offset() as usize * 8,
+                len: ip.length() as usize - ip_header_len,
+                more: ip.more_fragments(),
+                next_header: ip.protocol(),
+                header_len,
+                data_offset: header_len,
+            })
+        } else {
+            let ipv6 = headers.ipv6(ip_ix);
+            if ip_ix + 1 >= headers.count() {
+                return None;
+            }
+            let frag = headers.get(ip_ix + 1).as_ipv6_fragment()?;
+            let header_len = self.header_offset(ip_ix + 1);
+            Some(FragmentInfo {
+                key: FragmentKey::Ipv6 {
+                    src: ipv6.src(),
+                    dst: ipv6.dst(),
+                    id: frag.id(),
+                },
+                offset: frag.fragment_offset() as usize * 8,
+                len: (ipv6.payload_len() as usize).saturating_sub(Ipv6FragmentHeader::size()),
+                more: frag.more_fragments(),
+                next_header: frag.next_header(),
+                header_len,
+                data_offset: header_len + Ipv6FragmentHeader::size(),
+            })
+        }
+    }
+
+    /// the data carried by a fragment
+    pub fn fragment_data(&self, info: &FragmentInfo) -> Vec<u8> {
+        let mut data = Vec::with_capacity(info.len);
+        let mut skip = info.data_offset;
+        for segment in self.segments() {
+            if skip < segment.len() {
+                let n = ::std::cmp::min(segment.len() - skip, info.len - data.len());
+                data.extend_from_slice(&segment[skip..skip + n]);
+                skip = 0;
+            } else {
+                skip -= segment.len();
+            }
+            if data.len() == info.len {
+                break;
+            }
+        }
+        data
+    }
+
+    /// the first `len` bytes of the packet, which are always in the first mbuf segment
+    #[inline]
+    fn leading_bytes(&self, len: usize) -> &[u8] {
+        &self.segments().next().unwrap()[..len]
+    }
+
+    /// Build a packet from the headers of the first fragment and the reassembled payload. Lengths, fragment fields
+    /// and the IPv4 checksum are updated, the IPv6 fragment header is not part of `header`.
+    pub fn from_fragments(header: &[u8], next_header: u8, payload: &[u8], port: u16) -> errors::Result<Pdu<'static>> {
+        Pdu::from_fragments_with(header, next_header, payload, port, Pdu::new_pdu)
+    }
+
+    /// Same as `from_fragments`, but the packet is allocated by `alloc`.
+    pub fn from_fragments_with<F>(
+        header: &[u8],
+        next_header: u8,
+        payload: &[u8],
+        port: u16,
+        mut alloc: F,
+    ) -> errors::Result<Pdu<'static>>
+    where
+        F: FnMut() -> Option<Pdu<'static>>,
+    {
+        let mut pdu = match alloc() {
+            Some(pdu) => pdu,
+            None => return Err(ErrorKind::FailedAllocation),
+        };
+        let len = header.len() + payload.len();
+        let added = pdu.add_to_payload_tail(len);
+        if added.is_err() {
+            pdu.free();
+            return Err(ErrorKind::FailedAllocation);
+        }
+        pdu.write_at(0, header);
+        pdu.write_at(header.len(), payload);
+        pdu.set_port_id(port);
+        pdu.parse();
+        let ip_ix = match pdu.first_ip_index() {
+            Some(ip_ix) => ip_ix,
+            None => {
+                pdu.free();
+                return Err(ErrorKind::HeaderMismatch);
+            }
+        };
+        if pdu.headers().get(ip_ix).kind() == HeaderKind::Ip {
+            let ip = pdu.headers_mut().ip_mut(ip_ix);
+            let ip_header_len = ip.offset();
+            ip.set_length((ip_header_len + payload.len()) as u16);
+            let flags = ip.flags();
+            ip.set_flags(flags & !IP_FLAG_MF);
+            ip.set_fragment_offset(0);
+            ip.update_checksum();
+        } else {
+            let ipv6 = pdu.headers_mut().ipv6_mut(ip_ix);
+            ipv6.set_next_header(next_header);
+            ipv6.set_payload_len(payload.len() as u16);
+        }
+        // lengths were wrong when parsing the first time
+        pdu.reparse();
+        Ok(pdu)
+    }
+
+    /// Split an IPv4 packet into fragments with an IP length of at most `mtu` bytes. All IP options are copied into
+    /// each fragment. Fragmenting a fragment yields fragments of the original packet. Fails if the don't fragment flag
+    /// is set. The original packet is not modified, the caller owns the returned packets.
+    pub fn ipv4_fragments(&self, mtu: usize) -> errors::Result<Vec<Pdu<'static>>> {
+        self.ipv4_fragments_with(mtu, Pdu::new_pdu)
+    }
+
+    /// Same as `ipv4_fragments`, but the fragments are allocated by `alloc`.
+    pub fn ipv4_fragments_with<F>(&self, mtu: usize, mut alloc: F) -> errors::Result<Vec<Pdu<'static>>>
+    where
+        F: FnMut() -> Option<Pdu<'static>>,
+    {
+        let ip_ix = match self.first_ip_index() {
+            Some(ip_ix) if self.headers().get(ip_ix).kind() == HeaderKind::Ip => ip_ix,
+            _ => return Err(ErrorKind::HeaderMismatch),
+        };
+        let (ip_header_len, ip_len, flags, base_offset) = {
+            let ip = self.headers().ip(ip_ix);
+            (
+                ip.offset(),
+                ip.length() as usize,
+                ip.flags(),
+                ip.fragment_offset() as usize * 8,
+            )
+        };
+        if flags & IP_FLAG_DF != 0 {
+            return Err(ErrorKind::RunTimeError("don't fragment flag is set".to_string()));
+        }
+        let chunk_len = mtu.saturating_sub(ip_header_len) & !7;
+        if chunk_len == 0 {
+            return Err(ErrorKind::BadSize(mtu, "mtu too small for fragmentation".to_string()));
+        }
+        let header_len = self.header_offset(ip_ix) + ip_header_len;
+        let mut payload = self.payload_to_vec(ip_ix);
+        // drop Ethernet padding
+        payload.truncate(ip_len.saturating_sub(ip_header_len));
+        let header = self.leading_bytes(header_len);
+
+        let mut fragments: Vec<Pdu<'static>> = Vec::with_capacity((payload.len() + chunk_len - 1) / chunk_len);
+        for (i, chunk) in payload.chunks(chunk_len).enumerate() {
+            let mut fragment = self.new_fragment(header, &[], chunk, &mut fragments, &mut alloc)?;
+            let last = (i + 1) * chunk_len >= payload.len();
+            {
+                let ip = fragment.headers_mut().ip_mut(ip_ix);
+                ip.set_length((ip_header_len + chunk.len()) as u16);
+                ip.set_fragment_offset(((base_offset + i * chunk_len) / 8) as u16);
+                if !last {
+                    ip.set_flags(flags | IP_FLAG_MF);
+                }
+                ip.update_checksum();
+            }
+            fragment.reparse();
+            fragments.push(fragment);
+        }
+        Ok(fragments)
+    }
+
+    /// Split an IPv6 packet into fragments of at most `mtu` bytes, including the IPv6 header and the fragment header
+    /// with identification `id`. Packets with extension headers are not supported. The original packet is not
+    /// modified, the caller owns the returned packets.
+    pub fn ipv6_fragments(&self, mtu: usize, id: u32) -> errors::Result<Vec<Pdu<'static>>> {
+        self.ipv6_fragments_with(mtu, id, Pdu::new_pdu)
+    }
+
+    /// Same as `ipv6_fragments`, but the fragments are allocated by `alloc`.
+    pub fn ipv6_fragments_with<F>(&self, mtu: usize, id: u32, mut alloc: F) -> errors::Result<Vec<Pdu<'static>>>
+    where
+        F: FnMut() -> Option<Pdu<'static>>,
+    {
+        let ip_ix = match self.first_ip_index() {
+            Some(ip_ix) if self.headers().get(ip_ix).kind() == HeaderKind::Ipv6 => ip_ix,
+            _ => return Err(ErrorKind::HeaderMismatch),
+        };
+        let (next_header, payload_len) = {
+            let ipv6 = self.headers().ipv6(ip_ix);
+            (ipv6.next_header(), ipv6.payload_len() as usize)
+        };
+        match next_header {
+            IPV6_NEXT_HEADER_HOP_BY_HOP
+            | IPV6_NEXT_HEADER_ROUTING
+            | IPV6_NEXT_HEADER_FRAGMENT
+            | IPV6_NEXT_HEADER_DEST_OPTIONS => {
+                return Err(ErrorKind::RunTimeError(
+                    "fragmentation of IPv6 packets with extension headers is not supported".to_string(),
+                ))
+            }
+            _ => (),
+        }
+        let chunk_len = mtu.saturating_sub(Ipv6Header::size() + Ipv6FragmentHeader::size()) & !7;
+        if chunk_len == 0 {
+            return Err(ErrorKind::BadSize(mtu, "mtu too small for fragmentation".to_string()));
+        }
+        let header_len = self.header_offset(ip_ix) + Ipv6Header::size();
+        let mut payload = self.payload_to_vec(ip_ix);
+        payload.truncate(payload_len);
+        let header = self.leading_bytes(header_len);
+
+        let mut fragments: Vec<Pdu<'static>> = Vec::with_capacity((payload.len() + chunk_len - 1) / chunk_len);
+        for (i, chunk) in payload.chunks(chunk_len).enumerate() {
+            let mut frag = Ipv6FragmentHeader::new();
+            frag.set_next_header(next_header);
+            frag.set_id(id);
+            frag.set_fragment_offset((i * chunk_len / 8) as u16);
+            frag.set_more_fragments((i + 1) * chunk_len < payload.len());
+            let mut fragment = self.new_fragment(header, header_bytes(&frag), chunk, &mut fragments, &mut alloc)?;
+            {
+                let ipv6 = fragment.headers_mut().ipv6_mut(ip_ix);
+                ipv6.set_next_header(IPV6_NEXT_HEADER_FRAGMENT);
+                ipv6.set_payload_len((Ipv6FragmentHeader::size() + chunk.len()) as u16);
+            }
+            fragment.reparse();
+            fragments.push(fragment);
+        }
+        Ok(fragments)
+    }
+
+    /// allocate a packet consisting of `header`, `extension` and `chunk`, frees `fragments` on failure
+    fn new_fragment<F>(
+        &self,
+        header: &[u8],
+        extension: &[u8],
+        chunk: &[u8],
+        fragments: &mut Vec<Pdu<'static>>,
+        alloc: &mut F,
+    ) -> errors::Result<Pdu<'static>>
+    where
+        F: FnMut() -> Option<Pdu<'static>>,
+    {
+        let mut fragment = match alloc() {
+            Some(fragment) => fragment,
+            None => {
+                free_fragments(fragments);
+                return Err(ErrorKind::FailedAllocation);
+            }
+        };
+        if fragment
+            .add_to_payload_tail(header.len() + extension.len() + chunk.len())
+            .is_err()
+        {
+            fragment.free();
+            free_fragments(fragments);
+            return Err(ErrorKind::FailedAllocation);
+        }
+        fragment.write_at(0, header);
+        fragment.write_at(header.len(), extension);
+        fragment.write_at(header.len() + extension.len(), chunk);
+        fragment.set_port_id(self.port_id());
+        fragment.parse();
+        Ok(fragment)
+    }
+}
+
+fn free_fragments(fragments: &mut Vec<Pdu<'static>>) {
+    for fragment in fragments.drain(..) {
+        fragment.free();
+    }
+}
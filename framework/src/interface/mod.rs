@@ -1,8 +1,18 @@
+pub use self::arp::*;
+pub use self::fragments::*;
+pub use self::ndp::*;
 pub use self::pdu::*;
 pub use self::port::*;
+pub use self::tunnel::*;
+mod arp;
 pub mod dpdk;
+mod fragments;
+mod mpls;
+mod ndp;
 mod pdu;
 mod port;
+mod tcp_options;
+mod tunnel;
 use common::errors;
 use native::zcsi::MBuf;
 
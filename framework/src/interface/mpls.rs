@@ -0,0 +1,85 @@
+use common::errors;
+use common::errors::ErrorKind;
+use headers::*;
+use interface::Pdu;
+use std::mem;
+use std::slice;
+
+impl<'a> Pdu<'a> {
+    /// index of the top label stack entry, which directly follows the Ethernet header and its 802.1Q tag
+    #[inline]
+    fn top_label_index(&self) -> Option<usize> {
+        if self.headers().count() > 1 && self.headers().get(1).as_mpls().is_some() {
+            Some(1)
+        } else {
+            None
+        }
+    }
+
+    /// The top label stack entry, if the packet carries MPLS.
+    #[inline]
+    pub fn top_label(&self) -> Option<MplsHeader> {
+        self.top_label_index().map(|i| *self.headers().mpls(i))
+    }
+
+    /// Push a label stack entry on top of the label stack, or start a label stack with the bottom of stack flag set
+    /// if the packet carries no MPLS yet. The entry is inserted after the Ethernet header and its 802.1Q tag, using
+    /// the headroom of the mbuf.
+    pub fn push_label(&mut self, label: u32, tc: u8, ttl: u8) -> errors::Result<()> {
+        if label > MPLS_MAX_LABEL {
+            return Err(ErrorKind::BadSize(label as usize, "MPLS label exceeds 20 bits".to_string()));
+        }
+        let (etype, mac_len) = match self.headers().get(0).as_mac() {
+            Some(mac) => (mac.payload_etype(), mac.offset()),
+            None => return Err(ErrorKind::HeaderMismatch),
+        };
+        let bottom_of_stack = etype != ETYPE_MPLS_UNICAST && etype != ETYPE_MPLS_MULTICAST;
+        if bottom_of_stack {
+            self.headers_mut().mac_mut(0).set_payload_etype(ETYPE_MPLS_UNICAST);
+        }
+        let entry = MplsHeader::new(label, tc, bottom_of_stack, ttl);
+        let bytes =
+            unsafe { slice::from_raw_parts(&entry as *const MplsHeader as *const u8, mem::size_of::<MplsHeader>()) };
+        let result = self.insert_bytes(mac_len, bytes);
+        if result.is_err() && bottom_of_stack {
+            self.headers_mut().mac_mut(0).set_payload_etype(etype);
+        }
+        result
+    }
+
+    /// Remove the top label stack entry and return it. When the bottom of stack is popped, the Ethertype is set
+    /// according to the IP version of the payload; fails with `HeaderMismatch` if the payload is not IP.
+    pub fn pop_label(&mut self) -> errors::Result<MplsHeader> {
+        let top = match self.top_label_index() {
+            Some(i) => i,
+            None => return Err(ErrorKind::HeaderMismatch),
+        };
+        let entry = *self.headers().mpls(top);
+        let offset = self.header_offset(top);
+        if entry.bottom_of_stack() {
+            let etype = match self.get_payload(top).first().map(|b| b >> 4) {
+                Some(4) => 0x0800,
+                Some(6) => 0x86DD,
+                _ => return Err(ErrorKind::HeaderMismatch),
+            };
+            self.headers_mut().mac_mut(0).set_payload_etype(etype);
+        }
+        self.remove_bytes(offset, MplsHeader::size())?;
+        Ok(entry)
+    }
+
+    /// Replace the label of the top label stack entry. Traffic class, bottom of stack flag and TTL are kept, the TTL
+    /// can be changed through `headers_mut().mpls_mut(1)`.
+    pub fn swap_label(&mut self, label: u32) -> errors::Result<()> {
+        if label > MPLS_MAX_LABEL {
+            return Err(ErrorKind::BadSize(label as usize, "MPLS label exceeds 20 bits".to_string()));
+        }
+        match self.top_label_index() {
+            Some(i) => {
+                self.headers_mut().mpls_mut(i).set_label(label);
+                Ok(())
+            }
+            None => Err(ErrorKind::HeaderMismatch),
+        }
+    }
+}
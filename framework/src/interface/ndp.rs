@@ -0,0 +1,525 @@
+use common::errors;
+use common::errors::ErrorKind;
+use eui48::MacAddress;
+use headers::*;
+use interface::{send_pdus, PacketTx, Pdu, PmdPort};
+use ipnet::Ipv6Net;
+use state::{NeighborCache, NeighborConfig, Resolution};
+use std::mem;
+use std::net::Ipv6Addr;
+use std::ptr;
+use std::slice;
+use std::time::{Duration, Instant};
+
+/// all-nodes multicast address, destination of unsolicited advertisements
+pub const IPV6_ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+/// The link-local address of an interface with the given MAC address (RFC 4291, modified EUI-64).
+pub fn link_local_address(mac: &MacAddress) -> Ipv6Addr {
+    let m = mac.as_bytes();
+    let mut octets = [0u8; 16];
+    octets[0] = 0xfe;
+    octets[1] = 0x80;
+    octets[8..11].copy_from_slice(&m[..3]);
+    // invert the universal/local bit
+    octets[8] ^= 0x02;
+    octets[11] = 0xff;
+    octets[12] = 0xfe;
+    octets[13..].copy_from_slice(&m[3..]);
+    Ipv6Addr::from(octets)
+}
+
+/// The solicited-node multicast address of `addr`, solicitations for `addr` are sent to it.
+pub fn solicited_node_address(addr: &Ipv6Addr) -> Ipv6Addr {
+    let o = addr.octets();
+    Ipv6Addr::from([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xff, o[13], o[14], o[15]])
+}
+
+/// The Ethernet address an IPv6 multicast address is mapped to (RFC 2464).
+pub fn multicast_mac(addr: &Ipv6Addr) -> MacAddress {
+    let o = addr.octets();
+    MacAddress::new([0x33, 0x33, o[12], o[13], o[14], o[15]])
+}
+
+#[inline]
+fn is_link_local(addr: &Ipv6Addr) -> bool {
+    addr.segments()[0] & 0xffc0 == 0xfe80
+}
+
+#[inline]
+fn bytes_of<T>(value: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) }
+}
+
+#[inline]
+fn read_body<T: Copy>(data: &[u8]) -> Option<T> {
+    if data.len() < mem::size_of::<T>() {
+        None
+    } else {
+        Some(unsafe { ptr::read_unaligned(data.as_ptr() as *const T) })
+    }
+}
+
+/// the link-layer address in the first option of type `opt_type`, the outer None for malformed options
+fn ll_addr_option(options: &[u8], opt_type: u8) -> Option<Option<MacAddress>> {
+    let mut iter = NdpOptions::new(options);
+    let mut mac = None;
+    for option in &mut iter {
+        if option.opt_type == opt_type && mac.is_none() && option.content.len() >= 6 {
+            mac = MacAddress::from_bytes(&option.content[..6]).ok();
+        }
+    }
+    if iter.malformed() {
+        None
+    } else {
+        Some(mac)
+    }
+}
+
+/// Duplicate address detection state of an address of the port (RFC 4862).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressState {
+    /// DAD in progress, the address is not used yet
+    Tentative {
+        sent: u32,
+        last: Option<Instant>,
+    },
+    Preferred,
+    /// another node uses the address
+    Duplicate,
+}
+
+/// What `NdpService::handle_ndp` did with a packet.
+pub enum NdpAction {
+    /// not a Neighbor Discovery message
+    NotNdp,
+    /// the message was processed, the packet can be dropped and the advertisement should be sent on the receiving port
+    Reply(Pdu<'static>),
+    /// the message was processed or is invalid, the packet can be dropped
+    Consumed,
+}
+
+/// IPv6 Neighbor Discovery (RFC 4861) for the addresses of a port: the link-local address derived from the MAC address
+/// and the address of the `NetSpec`. Performs duplicate address detection, answers solicitations, learns the default
+/// router from router advertisements and resolves the MAC address of the next hop of outgoing packets.
+pub struct NdpService {
+    mac: MacAddress,
+    ip_net: Ipv6Net,
+    /// link-local and global address
+    addresses: [(Ipv6Addr, AddressState); 2],
+    dad_transmits: u32,
+    /// default router, with the expiry of advertised routers
+    router: Option<(Ipv6Addr, Option<Instant>)>,
+    cache: NeighborCache<Ipv6Addr>,
+}
+
+impl NdpService {
+    /// A service for the IPv6 address in the `NetSpec` of `port`. The MAC address of the spec is used if given,
+    /// otherwise the one of the device.
+    pub fn new(port: &PmdPort, config: NeighborConfig) -> errors::Result<NdpService> {
+        let spec = match *port.net_spec() {
+            Some(ref spec) => spec,
+            None => {
+                return Err(ErrorKind::ConfigurationError(format!(
+                    "port {} has no network specification",
+                    port.name()
+                )))
+            }
+        };
+        let ip_net = match spec.ipv6_net {
+            Some(ip_net) => ip_net,
+            None => {
+                return Err(ErrorKind::ConfigurationError(format!(
+                    "port {} has no IPv6 address",
+                    port.name()
+                )))
+            }
+        };
+        let mac = spec.mac.unwrap_or_else(|| port.mac_address());
+        Ok(NdpService::with_address(mac, ip_net, config))
+    }
+
+    /// Both addresses are tentative until `poll` completed duplicate address detection.
+    pub fn with_address(mac: MacAddress, ip_net: Ipv6Net, config: NeighborConfig) -> NdpService {
+        let tentative = AddressState::Tentative { sent: 0, last: None };
+        NdpService {
+            mac,
+            ip_net,
+            addresses: [(link_local_address(&mac), tentative), (ip_net.addr(), tentative)],
+            dad_transmits: 1,
+            router: None,
+            cache: NeighborCache::new(config),
+        }
+    }
+
+    pub fn mac(&self) -> MacAddress {
+        self.mac
+    }
+
+    pub fn ip_addr(&self) -> Ipv6Addr {
+        self.ip_net.addr()
+    }
+
+    pub fn link_local(&self) -> Ipv6Addr {
+        self.addresses[0].0
+    }
+
+    pub fn address_state(&self, addr: &Ipv6Addr) -> Option<AddressState> {
+        self.addresses.iter().find(|a| a.0 == *addr).map(|a| a.1)
+    }
+
+    /// Solicitations sent for duplicate address detection, 0 disables it. Only affects addresses which are still
+    /// tentative.
+    pub fn set_dad_transmits(&mut self, transmits: u32) {
+        self.dad_transmits = transmits;
+        if transmits == 0 {
+            for address in self.addresses.iter_mut() {
+                if let AddressState::Tentative { .. } = address.1 {
+                    address.1 = AddressState::Preferred;
+                }
+            }
+        }
+    }
+
+    /// Configure a static default router, it is replaced by routers learned from advertisements.
+    pub fn set_gateway(&mut self, gateway: Option<Ipv6Addr>) {
+        self.router = gateway.map(|gateway| (gateway, None));
+    }
+
+    pub fn gateway(&self) -> Option<Ipv6Addr> {
+        self.router.map(|router| router.0)
+    }
+
+    pub fn cache(&self) -> &NeighborCache<Ipv6Addr> {
+        &self.cache
+    }
+
+    pub fn cache_mut(&mut self) -> &mut NeighborCache<Ipv6Addr> {
+        &mut self.cache
+    }
+
+    /// The neighbor a packet to `dst` is sent to, None if `dst` is not on-link and there is no default router.
+    pub fn next_hop(&self, dst: Ipv6Addr) -> Option<Ipv6Addr> {
+        if is_link_local(&dst) || self.ip_net.contains(&dst) {
+            Some(dst)
+        } else {
+            self.gateway()
+        }
+    }
+
+    /// the preferred address used as source for packets to `dst`
+    fn source_for(&self, dst: &Ipv6Addr) -> Option<Ipv6Addr> {
+        let (link_local, global) = (self.addresses[0], self.addresses[1]);
+        if !is_link_local(dst) && global.1 == AddressState::Preferred {
+            Some(global.0)
+        } else if link_local.1 == AddressState::Preferred {
+            Some(link_local.0)
+        } else {
+            None
+        }
+    }
+
+    /// Process a received Neighbor Discovery message. The packet must be parsed, it is not modified.
+    pub fn handle_ndp(&mut self, pdu: &Pdu, now: Instant) -> errors::Result<NdpAction> {
+        let headers = pdu.headers();
+        let ip_ix = match (0..headers.count()).find(|&i| headers.get(i).as_ipv6().is_some()) {
+            Some(ip_ix) if ip_ix + 1 < headers.count() => ip_ix,
+            _ => return Ok(NdpAction::NotNdp),
+        };
+        let msg_type = match headers.get(ip_ix + 1).as_icmpv6() {
+            Some(icmp) => icmp.msg_type(),
+            None => return Ok(NdpAction::NotNdp),
+        };
+        match msg_type {
+            ICMPV6_ROUTER_SOLICITATION
+            | ICMPV6_ROUTER_ADVERTISEMENT
+            | ICMPV6_NEIGHBOR_SOLICITATION
+            | ICMPV6_NEIGHBOR_ADVERTISEMENT => (),
+            _ => return Ok(NdpAction::NotNdp),
+        }
+        let (src, dst, hop_limit, payload_len) = {
+            let ipv6 = headers.ipv6(ip_ix);
+            (ipv6.src(), ipv6.dst(), ipv6.hop_limit(), ipv6.payload_len() as usize)
+        };
+        let mut message = pdu.payload_to_vec(ip_ix);
+        if message.len() < payload_len {
+            return Ok(NdpAction::Consumed);
+        }
+        message.truncate(payload_len);
+        // validation of RFC 4861, sections 6.1 and 7.1
+        if hop_limit != NDP_HOP_LIMIT || message[1] != 0 || icmpv6_checksum(&src, &dst, &message) != 0 {
+            return Ok(NdpAction::Consumed);
+        }
+        let eth_src = headers.get(0).as_mac().map(|eth| eth.src);
+        let body = &message[Icmpv6Header::size()..];
+        match msg_type {
+            ICMPV6_NEIGHBOR_SOLICITATION => self.handle_solicitation(src, eth_src, body, now),
+            ICMPV6_NEIGHBOR_ADVERTISEMENT => {
+                self.handle_advertisement(body, now);
+                Ok(NdpAction::Consumed)
+            }
+            ICMPV6_ROUTER_ADVERTISEMENT => {
+                self.handle_router_advertisement(src, body, now);
+                Ok(NdpAction::Consumed)
+            }
+            _ => Ok(NdpAction::Consumed),
+        }
+    }
+
+    fn handle_solicitation(
+        &mut self,
+        src: Ipv6Addr,
+        eth_src: Option<MacAddress>,
+        body: &[u8],
+        now: Instant,
+    ) -> errors::Result<NdpAction> {
+        let target = match read_body::<NdpNeighborMessage>(body) {
+            Some(ns) if !ns.target().is_multicast() => ns.target(),
+            _ => return Ok(NdpAction::Consumed),
+        };
+        let sll_addr = match ll_addr_option(&body[NdpNeighborMessage::size()..], NDP_OPT_SOURCE_LL_ADDR) {
+            Some(sll_addr) => sll_addr,
+            None => return Ok(NdpAction::Consumed),
+        };
+        let dad = src.is_unspecified();
+        // solicitations for duplicate address detection carry no source link-layer address
+        if dad && sll_addr.is_some() {
+            return Ok(NdpAction::Consumed);
+        }
+        let state = match self.addresses.iter_mut().find(|a| a.0 == target) {
+            Some(address) => &mut address.1,
+            None => return Ok(NdpAction::Consumed),
+        };
+        match *state {
+            AddressState::Preferred => (),
+            AddressState::Tentative { .. } => {
+                // another node performs duplicate address detection for the same address
+                if dad {
+                    *state = AddressState::Duplicate;
+                }
+                return Ok(NdpAction::Consumed);
+            }
+            AddressState::Duplicate => return Ok(NdpAction::Consumed),
+        }
+        if dad {
+            let reply = self.advertisement(target, IPV6_ALL_NODES, multicast_mac(&IPV6_ALL_NODES), false)?;
+            return Ok(NdpAction::Reply(reply));
+        }
+        if let Some(mac) = sll_addr {
+            self.cache.update(src, mac, now);
+        }
+        match sll_addr.or(eth_src) {
+            Some(dst_mac) => Ok(NdpAction::Reply(self.advertisement(target, src, dst_mac, true)?)),
+            None => Ok(NdpAction::Consumed),
+        }
+    }
+
+    fn handle_advertisement(&mut self, body: &[u8], now: Instant) {
+        let na = match read_body::<NdpNeighborMessage>(body) {
+            Some(na) => na,
+            None => return,
+        };
+        let target = na.target();
+        let tll_addr = match ll_addr_option(&body[NdpNeighborMessage::size()..], NDP_OPT_TARGET_LL_ADDR) {
+            Some(tll_addr) => tll_addr,
+            None => return,
+        };
+        if let Some(address) = self.addresses.iter_mut().find(|a| a.0 == target) {
+            if let AddressState::Tentative { .. } = address.1 {
+                address.1 = AddressState::Duplicate;
+            }
+            return;
+        }
+        // only neighbors we are resolving are updated, without link-layer address an advertisement confirms the
+        // known address
+        if let Some(mac) = tll_addr.or_else(|| self.cache.get(&target)) {
+            if na.flags() & NDP_NA_FLAG_SOLICITED != 0 || na.flags() & NDP_NA_FLAG_OVERRIDE != 0 {
+                self.cache.update_existing(target, mac, now);
+            }
+        }
+    }
+
+    fn handle_router_advertisement(&mut self, src: Ipv6Addr, body: &[u8], now: Instant) {
+        if !is_link_local(&src) {
+            return;
+        }
+        let ra = match read_body::<NdpRouterAdvertisement>(body) {
+            Some(ra) => ra,
+            None => return,
+        };
+        match ll_addr_option(&body[NdpRouterAdvertisement::size()..], NDP_OPT_SOURCE_LL_ADDR) {
+            Some(Some(mac)) => self.cache.update(src, mac, now),
+            Some(None) => (),
+            None => return,
+        }
+        let lifetime = ra.router_lifetime();
+        if lifetime > 0 {
+            self.router = Some((src, Some(now + Duration::from_secs(lifetime as u64))));
+        } else if self.gateway() == Some(src) {
+            self.router = None;
+        }
+    }
+
+    /// Set the MAC addresses of an outgoing IPv6 packet to the port and the next hop. If the next hop is not resolved
+    /// yet, the packet is not modified and a solicitation is sent by the next `poll`.
+    pub fn set_destination(&mut self, pdu: &mut Pdu, now: Instant) -> Resolution {
+        let count = pdu.headers().count();
+        let dst = match pdu.headers().get_slice(0..count).iter().find_map(|h| h.as_ipv6()) {
+            Some(ipv6) => ipv6.dst(),
+            None => return Resolution::Unreachable,
+        };
+        let resolution = if dst.is_multicast() {
+            Resolution::Resolved(multicast_mac(&dst))
+        } else {
+            match self.next_hop(dst) {
+                Some(next_hop) => self.cache.resolve(next_hop, now),
+                None => Resolution::Unreachable,
+            }
+        };
+        if let Resolution::Resolved(dmac) = resolution {
+            let eth = pdu.headers_mut().mac_mut(0);
+            eth.set_dmac(&dmac);
+            eth.set_smac(&self.mac);
+        }
+        resolution
+    }
+
+    /// A Neighbor Discovery packet with the given message, the checksum is filled in.
+    fn ndp_packet(
+        &self,
+        dst_mac: MacAddress,
+        src: Ipv6Addr,
+        dst: Ipv6Addr,
+        mut message: Vec<u8>,
+    ) -> errors::Result<Pdu<'static>> {
+        let csum = icmpv6_checksum(&src, &dst, &message);
+        message[2] = (csum >> 8) as u8;
+        message[3] = csum as u8;
+        let mut eth = MacHeader::new();
+        eth.set_dmac(&dst_mac);
+        eth.set_smac(&self.mac);
+        eth.set_etype(ETYPE_IPV6);
+        let mut ipv6 = Ipv6Header::new();
+        ipv6.set_payload_len(message.len() as u16);
+        ipv6.set_next_header(IP_PROTO_ICMPV6);
+        ipv6.set_hop_limit(NDP_HOP_LIMIT);
+        ipv6.set_src(src);
+        ipv6.set_dst(dst);
+        let mut pdu = match Pdu::new_pdu() {
+            Some(pdu) => pdu,
+            None => return Err(ErrorKind::FailedAllocation),
+        };
+        if !pdu.push_header(&eth) || !pdu.push_header(&ipv6) || pdu.add_to_payload_tail(message.len()).is_err() {
+            pdu.free();
+            return Err(ErrorKind::FailedAllocation);
+        }
+        pdu.write_at(MacHeader::size() + Ipv6Header::size(), &message);
+        pdu.reparse();
+        Ok(pdu)
+    }
+
+    fn advertisement(
+        &self,
+        target: Ipv6Addr,
+        dst: Ipv6Addr,
+        dst_mac: MacAddress,
+        solicited: bool,
+    ) -> errors::Result<Pdu<'static>> {
+        let flags = if solicited {
+            NDP_NA_FLAG_SOLICITED | NDP_NA_FLAG_OVERRIDE
+        } else {
+            NDP_NA_FLAG_OVERRIDE
+        };
+        let mut message = bytes_of(&Icmpv6Header::new(ICMPV6_NEIGHBOR_ADVERTISEMENT)).to_vec();
+        message.extend_from_slice(bytes_of(&NdpNeighborMessage::new(target, flags)));
+        message.extend_from_slice(&ndp_ll_addr_option(NDP_OPT_TARGET_LL_ADDR, &self.mac));
+        self.ndp_packet(dst_mac, target, dst, message)
+    }
+
+    /// A solicitation for the MAC address of `target`, sent to its solicited-node multicast address or, when probing
+    /// a known neighbor, to the neighbor. Fails while the port has no preferred address.
+    pub fn solicitation(&self, target: Ipv6Addr) -> errors::Result<Pdu<'static>> {
+        let src = match self.source_for(&target) {
+            Some(src) => src,
+            None => return Err(ErrorKind::RunTimeError("no preferred IPv6 address".to_string())),
+        };
+        let (dst, dst_mac) = match self.cache.get(&target) {
+            Some(mac) => (target, mac),
+            None => {
+                let dst = solicited_node_address(&target);
+                (dst, multicast_mac(&dst))
+            }
+        };
+        let mut message = bytes_of(&Icmpv6Header::new(ICMPV6_NEIGHBOR_SOLICITATION)).to_vec();
+        message.extend_from_slice(bytes_of(&NdpNeighborMessage::new(target, 0)));
+        message.extend_from_slice(&ndp_ll_addr_option(NDP_OPT_SOURCE_LL_ADDR, &self.mac));
+        self.ndp_packet(dst_mac, src, dst, message)
+    }
+
+    /// a solicitation for duplicate address detection of `addr`
+    fn dad_solicitation(&self, addr: Ipv6Addr) -> errors::Result<Pdu<'static>> {
+        let dst = solicited_node_address(&addr);
+        let mut message = bytes_of(&Icmpv6Header::new(ICMPV6_NEIGHBOR_SOLICITATION)).to_vec();
+        message.extend_from_slice(bytes_of(&NdpNeighborMessage::new(addr, 0)));
+        self.ndp_packet(multicast_mac(&dst), Ipv6Addr::UNSPECIFIED, dst, message)
+    }
+
+    /// Advance duplicate address detection, the router lifetime and the timers of the cache. Returns the
+    /// solicitations which are due, the caller owns the packets.
+    pub fn poll(&mut self, now: Instant) -> errors::Result<Vec<Pdu<'static>>> {
+        let retry_interval = self.cache.config().retry_interval;
+        let mut dad = Vec::new();
+        for address in self.addresses.iter_mut() {
+            if let AddressState::Tentative { sent, last } = address.1 {
+                let due = last.map_or(true, |last| now.duration_since(last) >= retry_interval);
+                if !due {
+                    continue;
+                }
+                if sent < self.dad_transmits {
+                    dad.push(address.0);
+                    address.1 = AddressState::Tentative {
+                        sent: sent + 1,
+                        last: Some(now),
+                    };
+                } else {
+                    address.1 = AddressState::Preferred;
+                }
+            }
+        }
+        if let Some((_, Some(expiry))) = self.router {
+            if now >= expiry {
+                self.router = None;
+            }
+        }
+        let targets = self.cache.poll(now);
+
+        let mut packets = Vec::with_capacity(dad.len() + targets.len());
+        let mut result = Ok(());
+        for addr in dad {
+            match self.dad_solicitation(addr) {
+                Ok(pdu) => packets.push(pdu),
+                Err(e) => result = Err(e),
+            }
+        }
+        // without a preferred address, resolution fails after the retries
+        for target in targets.into_iter().filter(|t| self.source_for(t).is_some()) {
+            match self.solicitation(target) {
+                Ok(pdu) => packets.push(pdu),
+                Err(e) => result = Err(e),
+            }
+        }
+        match result {
+            Ok(()) => Ok(packets),
+            Err(e) => {
+                packets.into_iter().for_each(|pdu: Pdu| pdu.free());
+                Err(e)
+            }
+        }
+    }
+
+    /// `poll` and send the solicitations on `tx`, solicitations which could not be sent are dropped.
+    pub fn send_solicitations<T: PacketTx>(&mut self, tx: &mut T, now: Instant) -> errors::Result<u32> {
+        let solicitations = self.poll(now)?;
+        send_pdus(tx, solicitations)
+    }
+}
@@ -1,5 +1,6 @@
 use std::cmp;
 use std::fmt;
+use std::marker::PhantomData;
 use std::mem;
 use std::ops::Range;
 use std::ptr;
@@ -7,34 +8,62 @@ use std::slice;
 
 use common::errors;
 use common::errors::ErrorKind;
-use headers::{ArpIpv4Header, EndOffset, Header, IpHeader, MacHeader, TcpHeader, UdpHeader};
+use headers::*;
+use interface::PacketTx;
 use native::zcsi::MBuf;
-use native::zcsi::{mbuf_alloc, mbuf_alloc_bulk, validate_tx_offload};
+use native::zcsi::{ipv4_phdr_chksum, mbuf_alloc, mbuf_alloc_bulk, mbuf_free, validate_tx_offload};
 use utils::ipv4_checksum;
 
-const MAX_HEADERS: usize = 5;
+// The deepest stack without MPLS is a UDP tunnel with fragmented IPv6 in and outside, i.e. Ethernet, IPv6, fragment
+// header, UDP, VXLAN, Ethernet, IPv6, fragment header and the layer 4 header. This leaves room for 7 MPLS labels,
+// parsing stops when the stack is full.
+const MAX_HEADERS: usize = 16;
 
 #[derive(Clone, Debug)]
 pub struct HeaderStack<'a> {
     stack: [Header<'a>; MAX_HEADERS],
     /// header count
     hc: usize,
+    /// index of the first header of a tunneled frame, 0 if there is none
+    inner: usize,
 }
 
-/// there are no bound checks!
 impl<'a> HeaderStack<'a> {
     #[inline]
     pub fn new() -> HeaderStack<'a> {
         HeaderStack {
-            stack: [Header::Null, Header::Null, Header::Null, Header::Null, Header::Null],
+            stack: [
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+                Header::Null,
+            ],
             hc: 0,
+            inner: 0,
         }
     }
 
+    /// Returns false if the stack is full, i.e. `h` was not pushed.
     #[inline]
-    pub fn push(&mut self, h: Header<'a>) {
+    pub fn push(&mut self, h: Header<'a>) -> bool {
+        if self.is_full() {
+            return false;
+        }
         self.stack[self.hc] = h;
         self.hc += 1;
+        true
     }
 
     #[inline]
@@ -42,6 +71,28 @@ impl<'a> HeaderStack<'a> {
         self.hc
     }
 
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.hc == MAX_HEADERS
+    }
+
+    /// Index of the first header of the inner frame of a tunneled packet (VXLAN, GRE, Geneve, GTP-U), i.e. the headers
+    /// `0..inner` belong to the outer frame.
+    #[inline]
+    pub fn inner_start(&self) -> Option<usize> {
+        if self.inner > 0 && self.inner < self.hc {
+            Some(self.inner)
+        } else {
+            None
+        }
+    }
+
+    /// the header following the tunnel header starts the inner frame
+    #[inline]
+    fn mark_inner(&mut self) {
+        self.inner = self.hc;
+    }
+
     #[inline]
     pub fn get(&self, which: usize) -> &Header<'a> {
         &self.stack[which]
@@ -77,6 +128,16 @@ impl<'a> HeaderStack<'a> {
         self.stack[which].as_arpipv4_mut().unwrap()
     }
 
+    #[inline]
+    pub fn udp_mut(&mut self, which: usize) -> &mut UdpHeader {
+        self.stack[which].as_udp_mut().unwrap()
+    }
+
+    #[inline]
+    pub fn sctp_mut(&mut self, which: usize) -> &mut SctpHeader {
+        self.stack[which].as_sctp_mut().unwrap()
+    }
+
     #[inline]
     pub fn tcp(&self, which: usize) -> &TcpHeader {
         self.stack[which].as_tcp().unwrap()
@@ -96,6 +157,76 @@ impl<'a> HeaderStack<'a> {
     pub fn arp(&self, which: usize) -> &ArpIpv4Header {
         self.stack[which].as_arpipv4().unwrap()
     }
+
+    #[inline]
+    pub fn vxlan(&self, which: usize) -> &VxlanHeader {
+        self.stack[which].as_vxlan().unwrap()
+    }
+
+    #[inline]
+    pub fn gre(&self, which: usize) -> &GreHeader {
+        self.stack[which].as_gre().unwrap()
+    }
+
+    #[inline]
+    pub fn geneve(&self, which: usize) -> &GeneveHeader {
+        self.stack[which].as_geneve().unwrap()
+    }
+
+    #[inline]
+    pub fn gtpu(&self, which: usize) -> &GtpuHeader {
+        self.stack[which].as_gtpu().unwrap()
+    }
+
+    #[inline]
+    pub fn gtpu_mut(&mut self, which: usize) -> &mut GtpuHeader {
+        self.stack[which].as_gtpu_mut().unwrap()
+    }
+
+    #[inline]
+    pub fn ipv6(&self, which: usize) -> &Ipv6Header {
+        self.stack[which].as_ipv6().unwrap()
+    }
+
+    #[inline]
+    pub fn ipv6_mut(&mut self, which: usize) -> &mut Ipv6Header {
+        self.stack[which].as_ipv6_mut().unwrap()
+    }
+
+    #[inline]
+    pub fn ipv6_fragment(&self, which: usize) -> &Ipv6FragmentHeader {
+        self.stack[which].as_ipv6_fragment().unwrap()
+    }
+
+    #[inline]
+    pub fn icmpv6(&self, which: usize) -> &Icmpv6Header {
+        self.stack[which].as_icmpv6().unwrap()
+    }
+
+    #[inline]
+    pub fn icmpv6_mut(&mut self, which: usize) -> &mut Icmpv6Header {
+        self.stack[which].as_icmpv6_mut().unwrap()
+    }
+
+    #[inline]
+    pub fn mpls(&self, which: usize) -> &MplsHeader {
+        self.stack[which].as_mpls().unwrap()
+    }
+
+    #[inline]
+    pub fn mpls_mut(&mut self, which: usize) -> &mut MplsHeader {
+        self.stack[which].as_mpls_mut().unwrap()
+    }
+
+    #[inline]
+    pub fn udp(&self, which: usize) -> &UdpHeader {
+        self.stack[which].as_udp().unwrap()
+    }
+
+    #[inline]
+    pub fn sctp(&self, which: usize) -> &SctpHeader {
+        self.stack[which].as_sctp().unwrap()
+    }
 }
 
 impl<'a> fmt::Display for HeaderStack<'a> {
@@ -113,6 +244,28 @@ impl<'a> fmt::Display for HeaderStack<'a> {
     }
 }
 
+/// Iterator over the data of the mbuf segments of a packet.
+pub struct Segments<'b> {
+    seg: *const MBuf,
+    phantom: PhantomData<&'b MBuf>,
+}
+
+impl<'b> Iterator for Segments<'b> {
+    type Item = &'b [u8];
+
+    fn next(&mut self) -> Option<&'b [u8]> {
+        if self.seg.is_null() {
+            None
+        } else {
+            unsafe {
+                let seg = &*self.seg;
+                self.seg = seg.next_segment();
+                Some(slice::from_raw_parts(seg.data_address(0), seg.data_len()))
+            }
+        }
+    }
+}
+
 #[repr(align(16))]
 pub struct Pdu<'a> {
     header_stack: HeaderStack<'a>,
@@ -131,6 +284,9 @@ impl<'a> fmt::Display for Pdu<'a> {
     }
 }
 
+/// Allocates a packet, e.g. `Pdu::new_pdu` which allocates from the mempool of the current core.
+pub type PduAllocFn = fn() -> Option<Pdu<'static>>;
+
 impl<'a> Pdu<'a> {
     /// Allocate a new pdu.
     #[inline]
@@ -199,7 +355,7 @@ impl<'a> Pdu<'a> {
         Pdu::pdu_from_mbuf_no_increment(mbuf)
     }
 
-    /// copy gets us a new mbuf
+    /// copy gets us a new mbuf, only the first segment of a chained packet is copied
     #[inline]
     pub unsafe fn copy(&self) -> Pdu {
         // This sets refcnt = 1
@@ -226,32 +382,116 @@ impl<'a> Pdu<'a> {
     }
 
     #[inline]
-    fn parse_tcp(&mut self, offset: usize) {
+    fn parse_tcp(&mut self, offset: usize, length: usize) {
         let hdr = unsafe { (*self.mbuf).data_address(offset) as *mut TcpHeader };
-        unsafe {
-            self.header_stack.push(Header::Tcp(&mut *hdr));
+        // the options must be in the first segment as well, the payload may continue in further segments
+        let header_len = unsafe { (*hdr).offset() };
+        if header_len < TcpHeader::size() || header_len > length || self.data_len() < offset + header_len {
+            return;
         }
+        unsafe { self.header_stack.push(Header::Tcp(&mut *hdr)) };
+    }
+
+    #[inline]
+    fn parse_udp(&mut self, offset: usize) {
+        let hdr = unsafe { (*self.mbuf).data_address(offset) as *mut UdpHeader };
+        if !unsafe { self.header_stack.push(Header::Udp(&mut *hdr)) } {
+            return;
+        }
+        // only a single level of tunnels is parsed
+        if self.header_stack.inner_start().is_none() {
+            match unsafe { (*hdr).dst_port() } {
+                VXLAN_PORT => self.parse_vxlan(offset + UdpHeader::size()),
+                GENEVE_PORT => self.parse_geneve(offset + UdpHeader::size()),
+                GTPU_PORT => self.parse_gtpu(offset + UdpHeader::size()),
+                _ => (),
+            }
+        }
+    }
+
+    #[inline]
+    fn parse_sctp(&mut self, offset: usize) {
+        let hdr = unsafe { (*self.mbuf).data_address(offset) as *mut SctpHeader };
+        unsafe { self.header_stack.push(Header::Sctp(&mut *hdr)) };
     }
 
     #[inline]
     fn parse_ipv4(&mut self, offset: usize) {
         let hdr = unsafe { (*self.mbuf).data_address(offset) as *mut IpHeader };
-        unsafe {
-            self.header_stack.push(Header::Ip(&mut *hdr));
+        if !unsafe { self.header_stack.push(Header::Ip(&mut *hdr)) } {
+            return;
         }
-        let ip_length;
-        let ip_protocol;
-        let ip_offset;
-        unsafe {
-            let ip = &mut *hdr;
-            ip_length = ip.length();
-            ip_protocol = ip.protocol();
-            ip_offset = ip.offset();
+        let (ip_length, ip_protocol, ip_offset, later_fragment) = unsafe {
+            let ip = &*hdr;
+            (
+                ip.length() as usize,
+                ip.protocol(),
+                ip.offset(),
+                ip.fragment_offset() != 0,
+            )
+        };
+        // only the first fragment starts with the layer 4 header
+        if !later_fragment && ip_length >= ip_offset {
+            self.parse_ip_payload(ip_protocol, offset + ip_offset, ip_length - ip_offset);
         }
-        match ip_protocol {
+    }
+
+    #[inline]
+    fn parse_ipv6(&mut self, offset: usize) {
+        let hdr = unsafe { (*self.mbuf).data_address(offset) as *mut Ipv6Header };
+        if !unsafe { self.header_stack.push(Header::Ipv6(&mut *hdr)) } {
+            return;
+        }
+        let (mut next_header, mut payload_len) = unsafe { ((*hdr).next_header(), (*hdr).payload_len() as usize) };
+        let mut offset = offset + Ipv6Header::size();
+        if next_header == IPV6_NEXT_HEADER_FRAGMENT {
+            if payload_len < Ipv6FragmentHeader::size() || self.data_len() < offset + Ipv6FragmentHeader::size() {
+                return;
+            }
+            let frag = unsafe { (*self.mbuf).data_address(offset) as *mut Ipv6FragmentHeader };
+            if !unsafe { self.header_stack.push(Header::Ipv6Fragment(&mut *frag)) } {
+                return;
+            }
+            // only the first fragment starts with the layer 4 header
+            if unsafe { (*frag).fragment_offset() } != 0 {
+                return;
+            }
+            next_header = unsafe { (*frag).next_header() };
+            offset += Ipv6FragmentHeader::size();
+            payload_len -= Ipv6FragmentHeader::size();
+        }
+        if next_header == IP_PROTO_ICMPV6 {
+            if payload_len >= Icmpv6Header::size() && self.data_len() >= offset + Icmpv6Header::size() {
+                let icmp = unsafe { (*self.mbuf).data_address(offset) as *mut Icmpv6Header };
+                unsafe { self.header_stack.push(Header::Icmpv6(&mut *icmp)) };
+            }
+        } else {
+            self.parse_ip_payload(next_header, offset, payload_len);
+        }
+    }
+
+    /// parse the layer 4 header following an IP header, `length` is the length of the IP payload
+    #[inline]
+    fn parse_ip_payload(&mut self, protocol: u8, offset: usize, length: usize) {
+        match protocol {
             6 => {
-                if self.data_len() >= ip_length as usize + offset {
-                    self.parse_tcp(offset + ip_offset);
+                if length >= TcpHeader::size() && self.data_len() >= offset + TcpHeader::size() {
+                    self.parse_tcp(offset, length);
+                }
+            }
+            17 => {
+                if length >= UdpHeader::size() && self.data_len() >= offset + UdpHeader::size() {
+                    self.parse_udp(offset);
+                }
+            }
+            132 => {
+                if length >= SctpHeader::size() && self.data_len() >= offset + SctpHeader::size() {
+                    self.parse_sctp(offset);
+                }
+            }
+            IP_PROTO_GRE => {
+                if length >= GreHeader::size() && self.data_len() >= offset + GreHeader::size() {
+                    self.parse_gre(offset);
                 }
             }
             _ => {}
@@ -265,25 +505,49 @@ impl<'a> Pdu<'a> {
 
         unsafe {
             let arp = &mut *hdr;
-            if arp.hw_type() == 1 && arp.proto_etype() == 0x0800 {
+            if arp.hw_type() == ARP_HW_TYPE_ETHERNET
+                && arp.proto_etype() == 0x0800
+                && arp.hw_addr_len == 6
+                && arp.proto_addr_len == 4
+            {
                 self.header_stack.push(Header::ArpIpv4(&mut *hdr));
             }
         }
     }
 
-    /// assumes an Ethernet frame and parses the frame up to Layer 4 if possible
+    /// assumes an Ethernet frame and parses the frame up to Layer 4 if possible, tunneled frames are parsed as well
     #[inline]
     pub fn parse(&mut self) -> usize {
-        let l = self.data_len();
-        if l < MacHeader::size() {
-            return 0;
+        self.parse_mac(0);
+        self.header_stack.count()
+    }
+
+    #[inline]
+    fn parse_mac(&mut self, offset: usize) {
+        if self.data_len() < offset + MacHeader::size() {
+            return;
         };
-        let hdr = unsafe { (*self.mbuf).data_address(0) as *mut MacHeader };
-        unsafe {
-            self.header_stack.push(Header::Mac(&mut *hdr));
+        let hdr = unsafe { (*self.mbuf).data_address(offset) as *mut MacHeader };
+        if !unsafe { self.header_stack.push(Header::Mac(&mut *hdr)) } {
+            return;
         }
         let mac = unsafe { *hdr };
-        match mac.etype() {
+        let payload = offset + mac.offset();
+        let etype = match mac.etype() {
+            // the 802.1Q tag is part of the MAC header, it ends with the Ethertype of the payload
+            ETYPE_VLAN if self.data_len() >= payload => unsafe {
+                u16::from_be(ptr::read_unaligned((*self.mbuf).data_address(payload - 2) as *const u16))
+            },
+            ETYPE_VLAN => return,
+            etype => etype,
+        };
+        self.parse_etype(etype, payload);
+    }
+
+    #[inline]
+    fn parse_etype(&mut self, etype: u16, offset: usize) {
+        let l = self.data_len();
+        match etype {
             0x8100 => {
                 warn!("received 802.1Q frame");
             }
@@ -292,19 +556,141 @@ impl<'a> Pdu<'a> {
             }
             //private etype packets are IP packets:
             0x0800 | 0x08FE | 0x08FF => {
-                if l >= mac.offset() + IpHeader::size() {
-                    self.parse_ipv4(mac.offset());
+                if l >= offset + IpHeader::size() {
+                    self.parse_ipv4(offset);
+                }
+            }
+            ETYPE_IPV6 => {
+                if l >= offset + Ipv6Header::size() {
+                    self.parse_ipv6(offset);
                 }
             }
-            0x86DD => {} // IPv6
+            ETYPE_MPLS_UNICAST | ETYPE_MPLS_MULTICAST => self.parse_mpls(offset),
             0x0806 => {
-                if l >= mac.offset() + ArpIpv4Header::size() {
-                    self.parse_arp(mac.offset());
+                if l >= offset + ArpIpv4Header::size() {
+                    self.parse_arp(offset);
                 }
             } // ARP
             e => warn!("received Ethertype {:x}", e),
         }
-        self.header_stack.count()
+    }
+
+    /// parse the label stack up to the entry with the bottom of stack flag, and an IP payload
+    #[inline]
+    fn parse_mpls(&mut self, offset: usize) {
+        let mut offset = offset;
+        loop {
+            if self.data_len() < offset + MplsHeader::size() {
+                return;
+            }
+            let hdr = unsafe { (*self.mbuf).data_address(offset) as *mut MplsHeader };
+            let bos = unsafe { (*hdr).bottom_of_stack() };
+            if !unsafe { self.header_stack.push(Header::Mpls(&mut *hdr)) } {
+                debug!("MPLS label stack too deep, not parsing the payload");
+                return;
+            }
+            offset += MplsHeader::size();
+            if bos {
+                break;
+            }
+        }
+        if self.data_len() > offset {
+            // there is no protocol field, guess the payload from the IP version
+            match unsafe { *(*self.mbuf).data_address(offset) } >> 4 {
+                4 => self.parse_etype(0x0800, offset),
+                6 => self.parse_etype(0x86DD, offset),
+                _ => (),
+            }
+        }
+    }
+
+    /// parse the payload of a tunnel header, which is either an Ethernet frame or an IP packet
+    #[inline]
+    fn parse_tunneled(&mut self, protocol: u16, offset: usize) {
+        match protocol {
+            ETYPE_TRANSPARENT_ETHERNET => {
+                self.header_stack.mark_inner();
+                self.parse_mac(offset);
+            }
+            0x0800 | 0x86DD => {
+                self.header_stack.mark_inner();
+                self.parse_etype(protocol, offset);
+            }
+            p => debug!("not parsing tunneled protocol {:x}", p),
+        }
+    }
+
+    #[inline]
+    fn parse_vxlan(&mut self, offset: usize) {
+        if self.data_len() < offset + VxlanHeader::size() {
+            return;
+        }
+        let hdr = unsafe { (*self.mbuf).data_address(offset) as *mut VxlanHeader };
+        if !unsafe { self.header_stack.push(Header::Vxlan(&mut *hdr)) } {
+            return;
+        }
+        self.parse_tunneled(ETYPE_TRANSPARENT_ETHERNET, offset + VxlanHeader::size());
+    }
+
+    #[inline]
+    fn parse_geneve(&mut self, offset: usize) {
+        if self.data_len() < offset + GeneveHeader::size() {
+            return;
+        }
+        let hdr = unsafe { (*self.mbuf).data_address(offset) as *mut GeneveHeader };
+        let (protocol, geneve_offset) = unsafe { ((*hdr).protocol(), (*hdr).offset()) };
+        if self.data_len() >= offset + geneve_offset {
+            if !unsafe { self.header_stack.push(Header::Geneve(&mut *hdr)) } {
+                return;
+            }
+            self.parse_tunneled(protocol, offset + geneve_offset);
+        }
+    }
+
+    #[inline]
+    fn parse_gtpu(&mut self, offset: usize) {
+        let available = self.data_len().saturating_sub(offset);
+        if available < GtpuHeader::size() {
+            return;
+        }
+        let hdr = unsafe { (*self.mbuf).data_address(offset) as *mut GtpuHeader };
+        let (msg_type, gtpu_offset) = unsafe { ((*hdr).msg_type(), (*hdr).header_len(available)) };
+        if let Some(gtpu_offset) = gtpu_offset {
+            if !unsafe { self.header_stack.push(Header::Gtpu(&mut *hdr)) } {
+                return;
+            }
+            // only G-PDUs carry a user plane packet, which is IPv4 or IPv6
+            if msg_type == GTPU_MSG_GPDU && available > gtpu_offset {
+                let version = unsafe { *(*self.mbuf).data_address(offset + gtpu_offset) } >> 4;
+                match version {
+                    4 => self.parse_tunneled(0x0800, offset + gtpu_offset),
+                    6 => self.parse_tunneled(0x86DD, offset + gtpu_offset),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn parse_gre(&mut self, offset: usize) {
+        let hdr = unsafe { (*self.mbuf).data_address(offset) as *mut GreHeader };
+        let (protocol, gre_offset) = unsafe { ((*hdr).protocol(), (*hdr).offset()) };
+        if self.data_len() >= offset + gre_offset {
+            if !unsafe { self.header_stack.push(Header::Gre(&mut *hdr)) } {
+                return;
+            }
+            // only a single level of tunnels is parsed
+            if self.header_stack.inner_start().is_none() {
+                self.parse_tunneled(protocol, offset + gre_offset);
+            }
+        }
+    }
+
+    /// discard the header stack and parse the packet again
+    #[inline]
+    pub fn reparse(&mut self) -> usize {
+        self.header_stack = HeaderStack::new();
+        self.parse()
     }
 
     /// Get the mbuf reference by this packet.
@@ -325,11 +711,62 @@ impl<'a> Pdu<'a> {
     }
 
     // this includes ethernet padding if it is present, sta
+    /// length of the data in the first mbuf segment, headers are always parsed from this segment
     #[inline]
     pub fn data_len(&self) -> usize {
         unsafe { (*self.mbuf).data_len() }
     }
 
+    /// length of the packet across all mbuf segments
+    #[inline]
+    pub fn pkt_len(&self) -> usize {
+        unsafe { (*self.mbuf).pkt_len() }
+    }
+
+    #[inline]
+    pub fn nb_segments(&self) -> u16 {
+        unsafe { (*self.mbuf).nb_segs() }
+    }
+
+    /// true if the packet consists of a single mbuf segment, e.g. it is not a jumbo frame scattered by the device
+    #[inline]
+    pub fn is_contiguous(&self) -> bool {
+        unsafe { (*self.mbuf).is_contiguous() }
+    }
+
+    /// iterate over the data of all mbuf segments
+    #[inline]
+    pub fn segments(&self) -> Segments {
+        Segments {
+            seg: self.mbuf,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Copy a chained packet into its first mbuf segment and free the other segments. Afterwards `get_payload` covers
+    /// the complete payload. Fails with `BadSize` if the first segment is too small for the packet, in this case the
+    /// packet is not modified.
+    pub fn linearize(&mut self) -> errors::Result<()> {
+        if self.is_contiguous() {
+            return Ok(());
+        }
+        match unsafe { (*self.mbuf).linearize() } {
+            Some(chain) => {
+                if !chain.is_null() {
+                    unsafe { mbuf_free(chain) };
+                }
+                // header references are still valid, but headers may have been cut at the segment boundary
+                self.reparse();
+                Ok(())
+            }
+            None => Err(ErrorKind::BadSize(
+                self.pkt_len(),
+                format!("packet exceeds mbuf of {} bytes", unsafe { (*self.mbuf).buf_len() }),
+            )),
+        }
+    }
+
+    /// tailroom of the first mbuf segment
     #[inline]
     pub fn get_tailroom(&self) -> usize {
         unsafe { (*self.mbuf).pkt_tailroom() }
@@ -357,9 +794,26 @@ impl<'a> Pdu<'a> {
                 Header::Ip(ref mut p) => ptr::copy_nonoverlapping(hdr.as_ip().unwrap() as *const IpHeader, *p, 1),
                 Header::Tcp(ref mut p) => ptr::copy_nonoverlapping(hdr.as_tcp().unwrap() as *const TcpHeader, *p, 1),
                 Header::Udp(ref mut p) => ptr::copy_nonoverlapping(hdr.as_udp().unwrap() as *const UdpHeader, *p, 1),
+                Header::Sctp(ref mut p) => ptr::copy_nonoverlapping(hdr.as_sctp().unwrap() as *const SctpHeader, *p, 1),
                 Header::ArpIpv4(ref mut p) => {
                     ptr::copy_nonoverlapping(hdr.as_arpipv4().unwrap() as *const ArpIpv4Header, *p, 1)
                 }
+                Header::Vxlan(ref mut p) => {
+                    ptr::copy_nonoverlapping(hdr.as_vxlan().unwrap() as *const VxlanHeader, *p, 1)
+                }
+                Header::Gre(ref mut p) => ptr::copy_nonoverlapping(hdr.as_gre().unwrap() as *const GreHeader, *p, 1),
+                Header::Geneve(ref mut p) => {
+                    ptr::copy_nonoverlapping(hdr.as_geneve().unwrap() as *const GeneveHeader, *p, 1)
+                }
+                Header::Gtpu(ref mut p) => ptr::copy_nonoverlapping(hdr.as_gtpu().unwrap() as *const GtpuHeader, *p, 1),
+                Header::Mpls(ref mut p) => ptr::copy_nonoverlapping(hdr.as_mpls().unwrap() as *const MplsHeader, *p, 1),
+                Header::Ipv6(ref mut p) => ptr::copy_nonoverlapping(hdr.as_ipv6().unwrap() as *const Ipv6Header, *p, 1),
+                Header::Ipv6Fragment(ref mut p) => {
+                    ptr::copy_nonoverlapping(hdr.as_ipv6_fragment().unwrap() as *const Ipv6FragmentHeader, *p, 1)
+                }
+                Header::Icmpv6(ref mut p) => {
+                    ptr::copy_nonoverlapping(hdr.as_icmpv6().unwrap() as *const Icmpv6Header, *p, 1)
+                }
             };
         }
     }
@@ -370,6 +824,9 @@ impl<'a> Pdu<'a> {
 
     /// Append a header to the header stack of a packet
     pub fn push_header<T: EndOffset>(&mut self, header: &T) -> bool {
+        if self.header_stack.is_full() {
+            return false;
+        }
         let size = header.offset();
         let added = unsafe { (*self.mbuf).add_data_end(size) };
         if added < size {
@@ -419,6 +876,175 @@ impl<'a> Pdu<'a> {
         }
     }
 
+    /// Let the device split this TCP/IPv4 packet into segments carrying at most `mss` bytes of TCP payload. Sets the
+    /// offload flags, the header lengths and `tso_segsz`, and seeds the checksums as the device expects. The port must
+    /// have been configured with `tso`, otherwise use `tcp_segments` or the `gso` operator.
+    pub fn set_tcp_segmentation_offload(&mut self, mss: u16) -> errors::Result<()> {
+        let (ip_ix, tcp_ix) = match self.tcp_ipv4_indices() {
+            Some(ixs) => ixs,
+            None => return Err(ErrorKind::RunTimeError("TSO requires a TCP/IPv4 packet".to_string())),
+        };
+        if mss == 0 {
+            return Err(ErrorKind::BadSize(0, "TSO requires a non-zero mss".to_string()));
+        }
+        let l2_len = if ip_ix > 0 { self.payload_offset(ip_ix - 1) } else { 0 };
+        let l3_len = self.header_stack.ip(ip_ix).offset();
+        let l4_len = self.header_stack.tcp(tcp_ix).offset();
+        unsafe {
+            let mbuf = &mut *self.mbuf;
+            mbuf.set_tcp_ipv4_segmentation_tx_offload();
+            mbuf.set_l2_len(l2_len as u64);
+            mbuf.set_l3_len(l3_len as u64);
+            mbuf.set_l4_len(l4_len as u64);
+            mbuf.set_tso_segsz(mss as u64);
+        }
+        let ol_flags = self.ol_flags();
+        let phdr_csum = {
+            let ip = self.header_stack.ip_mut(ip_ix);
+            ip.set_csum(0);
+            unsafe { ipv4_phdr_chksum(ip as *const IpHeader, ol_flags) }
+        };
+        // the pseudo header checksum is returned in network byte order
+        self.header_stack.tcp_mut(tcp_ix).set_checksum(u16::from_be(phdr_csum));
+        Ok(())
+    }
+
+    #[inline]
+    pub fn tcp_segmentation_offload(&self) -> bool {
+        unsafe { (*self.mbuf).tcp_segmentation_tx_offload() }
+    }
+
+    #[inline]
+    pub fn tso_segsz(&self) -> u64 {
+        unsafe { (*self.mbuf).tso_segsz() }
+    }
+
+    /// Split a TCP/IPv4 packet into packets carrying at most `mss` bytes of TCP payload each, i.e. segmentation in
+    /// software for devices without TSO. Headers are replicated, IP id, lengths, sequence numbers, flags and checksums
+    /// are adjusted per segment. The original packet is not modified, the caller owns the returned packets. A packet
+    /// without TCP payload yields no segments. Lengths and checksums of tunnel headers before the TCP/IPv4 packet are
+    /// adjusted as well, an outer UDP checksum is set to zero.
+    pub fn tcp_segments(&self, mss: usize) -> errors::Result<Vec<Pdu<'static>>> {
+        self.tcp_segments_with(mss, Pdu::new_pdu)
+    }
+
+    /// Same as `tcp_segments`, but the segments are allocated by `alloc`, e.g. from a dedicated pool.
+    pub fn tcp_segments_with<F>(&self, mss: usize, mut alloc: F) -> errors::Result<Vec<Pdu<'static>>>
+    where
+        F: FnMut() -> Option<Pdu<'static>>,
+    {
+        let (ip_ix, tcp_ix) = match self.tcp_ipv4_indices() {
+            Some(ixs) => ixs,
+            None => return Err(ErrorKind::RunTimeError("segmentation requires a TCP/IPv4 packet".to_string())),
+        };
+        if mss == 0 {
+            return Err(ErrorKind::BadSize(0, "segmentation requires a non-zero mss".to_string()));
+        }
+        let hdr_len = self.payload_offset(tcp_ix);
+        let (ip_id, l3_l4_len, ip_payload_len) = {
+            let ip = self.header_stack.ip(ip_ix);
+            let l3_l4_len = ip.offset() + self.header_stack.tcp(tcp_ix).offset();
+            (ip.id(), l3_l4_len, ip.length() as usize)
+        };
+        let (seq, fin, psh) = {
+            let tcp = self.header_stack.tcp(tcp_ix);
+            (tcp.seq_num(), tcp.fin_flag(), tcp.psh_flag())
+        };
+        let mut payload = self.payload_to_vec(tcp_ix);
+        // drop Ethernet padding
+        payload.truncate(ip_payload_len.saturating_sub(l3_l4_len));
+        let headers = unsafe { slice::from_raw_parts((*self.mbuf).data_address(0), hdr_len) };
+
+        let mut segments: Vec<Pdu<'static>> = Vec::with_capacity((payload.len() + mss - 1) / mss);
+        for (i, chunk) in payload.chunks(mss).enumerate() {
+            let mut seg = match alloc() {
+                Some(seg) => seg,
+                None => {
+                    free_pdus(segments);
+                    return Err(ErrorKind::FailedAllocation);
+                }
+            };
+            if hdr_len + chunk.len() > seg.get_tailroom() {
+                segments.push(seg);
+                free_pdus(segments);
+                return Err(ErrorKind::BadSize(hdr_len + chunk.len(), "segment exceeds mbuf".to_string()));
+            }
+            seg.increase_payload_size(hdr_len + chunk.len());
+            seg.write_at(0, headers);
+            seg.write_at(hdr_len, chunk);
+            unsafe { (*seg.mbuf).port = (*self.mbuf).port };
+            seg.parse();
+            // the original ip length prevents parsing of the tcp header
+            seg.header_stack.ip_mut(ip_ix).set_length((l3_l4_len + chunk.len()) as u16);
+            seg.shrink_outer_lengths(ip_ix, payload.len() - chunk.len());
+            seg.reparse();
+            let (src, dst) = {
+                let ip = seg.header_stack.ip_mut(ip_ix);
+                ip.set_id(ip_id.wrapping_add(i as u16));
+                ip.update_checksum();
+                (ip.src(), ip.dst())
+            };
+            let last = (i + 1) * mss >= payload.len();
+            let tcp = seg.header_stack.tcp_mut(tcp_ix);
+            tcp.set_seq_num(seq.wrapping_add((i * mss) as u32));
+            if i > 0 {
+                tcp.unset_cwr_flag();
+            }
+            if !last || !fin {
+                tcp.unset_fin_flag();
+            }
+            if !last || !psh {
+                tcp.unset_psh_flag();
+            }
+            let tcp_len = tcp.offset() + chunk.len();
+            update_tcp_checksum_(tcp, tcp_len, src, dst);
+            segments.push(seg);
+        }
+        Ok(segments)
+    }
+
+    /// reduce the lengths in the headers before the header `ix` by `delta` bytes
+    fn shrink_outer_lengths(&mut self, ix: usize, delta: usize) {
+        let delta = delta as u16;
+        for i in 0..ix {
+            match self.header_stack.get(i).kind() {
+                HeaderKind::Ip => {
+                    let ip = self.header_stack.ip_mut(i);
+                    let len = ip.length();
+                    ip.set_length(len - delta);
+                    ip.update_checksum();
+                }
+                HeaderKind::Ipv6 => {
+                    let ipv6 = self.header_stack.ipv6_mut(i);
+                    let len = ipv6.payload_len();
+                    ipv6.set_payload_len(len - delta);
+                }
+                HeaderKind::Udp => {
+                    let udp = self.header_stack.udp_mut(i);
+                    let len = udp.length();
+                    udp.set_length(len - delta);
+                    udp.set_checksum(0);
+                }
+                HeaderKind::Gtpu => {
+                    let gtpu = self.header_stack.gtpu_mut(i);
+                    let len = gtpu.length();
+                    gtpu.set_length(len - delta);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// index of the ip and of the tcp header, if this is a TCP/IPv4 packet
+    #[inline]
+    fn tcp_ipv4_indices(&self) -> Option<(usize, usize)> {
+        (1..self.header_stack.count())
+            .find(|&i| {
+                self.header_stack.get(i - 1).kind() == HeaderKind::Ip && self.header_stack.get(i).kind() == HeaderKind::Tcp
+            })
+            .map(|i| (i - 1, i))
+    }
+
     #[inline]
     pub fn ipv4_checksum_tx_offload(&self) -> bool {
         unsafe { (*self.mbuf).ipv4_checksum_tx_offload() }
@@ -496,15 +1122,38 @@ impl<'a> Pdu<'a> {
         unsafe { (*self.mbuf).add_data_end(increase_by) }
     }
 
-    #[inline]
+    /// Extend the packet by `size` bytes at its end. When the tailroom of the last segment is exhausted, new segments
+    /// are allocated and chained to the packet. Fails with `FailedAllocation` if not enough mbufs are available, in
+    /// this case the packet is not modified.
     pub fn add_to_payload_tail(&mut self, size: usize) -> errors::Result<()> {
         unsafe {
-            let added = (*self.mbuf).add_data_end(size);
-            if added >= size {
-                Ok(())
-            } else {
-                Err(ErrorKind::FailedAllocation.into())
+            let in_last = cmp::min(size, self.last_segment_tailroom());
+            // allocate all segments first, so that we do not leave a partially extended packet behind
+            let mut chain: *mut MBuf = ptr::null_mut();
+            let mut remaining = size - in_last;
+            while remaining > 0 {
+                let seg = mbuf_alloc();
+                if seg.is_null() {
+                    if !chain.is_null() {
+                        mbuf_free(chain);
+                    }
+                    return Err(ErrorKind::FailedAllocation.into());
+                }
+                // segments following the first one need no headroom
+                (*seg).data_off = 0;
+                let added = (*seg).add_data_end(cmp::min(remaining, (*seg).pkt_tailroom()));
+                remaining -= added;
+                if chain.is_null() {
+                    chain = seg;
+                } else {
+                    (*chain).chain_segments(seg);
+                }
+            }
+            (*self.mbuf).add_data_end_last_segment(in_last);
+            if !chain.is_null() {
+                (*self.mbuf).chain_segments(chain);
             }
+            Ok(())
         }
     }
 
@@ -540,6 +1189,7 @@ impl<'a> Pdu<'a> {
         }
     }
 
+    /// the payload in the first mbuf segment, use `linearize` or `payload_to_vec` for chained packets
     #[inline]
     pub fn get_payload(&self, which: usize) -> &[u8] {
         unsafe {
@@ -556,40 +1206,154 @@ impl<'a> Pdu<'a> {
         }
     }
 
-    /// may include padding
     #[inline]
-    pub fn payload_size(&self, which: usize) -> usize {
+    fn payload_offset(&self, which: usize) -> usize {
         // sum up the header offsets
-        let sum = self
-            .header_stack
+        self.header_stack
             .get_slice(0..which as usize + 1)
             .iter()
-            .fold(0, |sum, value| sum + value.offset().unwrap());
-        self.data_len() - sum
+            .fold(0, |sum, value| sum + value.offset().unwrap())
+    }
+
+    /// offset of the header `which` from the start of the packet
+    #[inline]
+    pub fn header_offset(&self, which: usize) -> usize {
+        if which == 0 {
+            0
+        } else {
+            self.payload_offset(which - 1)
+        }
+    }
+
+    /// bytes of the first mbuf segment from the start of the header `which`, i.e. the bytes the header can be read from
+    #[inline]
+    pub fn header_available(&self, which: usize) -> usize {
+        self.data_len().saturating_sub(self.header_offset(which))
+    }
+
+    /// bytes in front of the packet which `insert_bytes` can use
+    #[inline]
+    pub fn headroom(&self) -> usize {
+        unsafe { (*self.mbuf).pkt_headroom() }
+    }
+
+    /// Insert `bytes` at `offset` from the start of the packet, using the headroom of the mbuf: the first `offset`
+    /// bytes are moved towards the front. The packet is parsed again.
+    pub fn insert_bytes(&mut self, offset: usize, bytes: &[u8]) -> errors::Result<()> {
+        let len = bytes.len();
+        if offset > self.data_len() {
+            return Err(ErrorKind::BadOffset(offset));
+        }
+        unsafe {
+            let mbuf = &mut *self.mbuf;
+            if mbuf.add_data_beginning(len) < len {
+                return Err(ErrorKind::BadSize(len, "not enough headroom".to_string()));
+            }
+            ptr::copy(mbuf.data_address(len), mbuf.data_address(0), offset);
+            ptr::copy_nonoverlapping(bytes.as_ptr(), mbuf.data_address(offset), len);
+        }
+        self.reparse();
+        Ok(())
+    }
+
+    /// Remove `len` bytes at `offset` from the start of the packet: the first `offset` bytes are moved towards the end
+    /// and the freed space becomes headroom. The packet is parsed again.
+    pub fn remove_bytes(&mut self, offset: usize, len: usize) -> errors::Result<()> {
+        if offset + len > self.data_len() {
+            return Err(ErrorKind::BadOffset(offset + len));
+        }
+        unsafe {
+            let mbuf = &mut *self.mbuf;
+            ptr::copy(mbuf.data_address(0), mbuf.data_address(len), offset);
+            mbuf.remove_data_beginning(len);
+        }
+        self.reparse();
+        Ok(())
+    }
+
+    /// size of the payload in the first mbuf segment, may include padding
+    #[inline]
+    pub fn payload_size(&self, which: usize) -> usize {
+        self.data_len() - self.payload_offset(which)
+    }
+
+    /// size of the payload across all mbuf segments, may include padding
+    #[inline]
+    pub fn total_payload_size(&self, which: usize) -> usize {
+        self.pkt_len() - self.payload_offset(which)
+    }
+
+    /// copies the payload of all mbuf segments into a vector
+    pub fn payload_to_vec(&self, which: usize) -> Vec<u8> {
+        let mut skip = self.payload_offset(which);
+        let mut v = Vec::with_capacity(self.total_payload_size(which));
+        for data in self.segments() {
+            if skip < data.len() {
+                v.extend_from_slice(&data[skip..]);
+                skip = 0;
+            } else {
+                skip -= data.len();
+            }
+        }
+        v
     }
 
+    /// Write `data` into the payload starting at `offset`, scattering it across the mbuf segments. The packet is not
+    /// extended, returns the number of bytes written.
+    #[inline]
+    pub fn write_payload(&mut self, which: usize, offset: usize, data: &[u8]) -> usize {
+        let offset = self.payload_offset(which) + offset;
+        self.write_at(offset, data)
+    }
+
+    /// Write `data` at `offset` from the start of the packet, scattering it across the mbuf segments. The packet is not
+    /// extended and the headers are not parsed again, returns the number of bytes written.
+    pub fn write_at(&mut self, offset: usize, data: &[u8]) -> usize {
+        let mut skip = offset;
+        let mut written = 0;
+        let mut seg = self.mbuf;
+        unsafe {
+            while !seg.is_null() && written < data.len() {
+                let seg_len = (*seg).data_len();
+                if skip < seg_len {
+                    let n = cmp::min(seg_len - skip, data.len() - written);
+                    ptr::copy_nonoverlapping(data.as_ptr().offset(written as isize), (*seg).data_address(skip), n);
+                    written += n;
+                    skip = 0;
+                } else {
+                    skip -= seg_len;
+                }
+                seg = (*seg).next_segment();
+            }
+        }
+        written
+    }
+
+    /// Copy `payload` into the payload of the packet, extending the packet by additional mbuf segments if the payload
+    /// does not fit. Returns the number of bytes copied, which is less than the payload size if no mbufs are left.
     #[inline]
     pub fn copy_payload_from_u8_slice(&mut self, payload: &[u8], which: usize) -> usize {
         let copy_len = payload.len();
         if copy_len > 0 {
-            let dst = self.payload_mut(which).unwrap();
-            let src = payload.as_ptr();
-            let payload_size = self.payload_size(which);
-            let should_copy = if payload_size < copy_len {
+            let payload_size = self.total_payload_size(which);
+            if payload_size < copy_len {
                 let increment = copy_len - payload_size;
-                payload_size + self.increase_payload_size(increment)
-            } else {
-                copy_len
-            };
-            unsafe {
-                ptr::copy_nonoverlapping(src, dst, should_copy);
-                should_copy
+                if self.add_to_payload_tail(increment).is_err() {
+                    // copy at least what fits into the tailroom
+                    unsafe { (*self.mbuf).add_data_end_last_segment(cmp::min(increment, self.last_segment_tailroom())) };
+                }
             }
+            self.write_payload(which, 0, payload)
         } else {
             0usize
         }
     }
 
+    #[inline]
+    fn last_segment_tailroom(&self) -> usize {
+        unsafe { (*(*self.mbuf).last_segment()).pkt_tailroom() }
+    }
+
     /// fills the end of the payload with <len> bytes of value <byte>
     #[inline]
     pub fn write_from_tail_down(&mut self, len: usize, byte: u8) -> usize {
@@ -615,6 +1379,37 @@ impl<'a> Pdu<'a> {
     pub fn port_id(&self) -> u16 {
         unsafe { (*self.mbuf).port }
     }
+
+    #[inline]
+    pub fn set_port_id(&mut self, port: u16) {
+        unsafe { (*self.mbuf).port = port }
+    }
+
+    /// Free the mbuf of a packet owned by the caller, e.g. a packet from `new_pdu` which is not going to be sent.
+    #[inline]
+    pub fn free(self) {
+        unsafe { mbuf_free(self.get_mbuf()) };
+    }
+}
+
+fn free_pdus(pdus: Vec<Pdu<'static>>) {
+    for pdu in pdus {
+        pdu.free();
+    }
+}
+
+/// Send packets created by the application on `tx`, packets which could not be sent are freed.
+pub fn send_pdus<T: PacketTx>(tx: &mut T, pdus: Vec<Pdu<'static>>) -> errors::Result<u32> {
+    if pdus.is_empty() {
+        return Ok(0);
+    }
+    let mut mbufs: Vec<*mut MBuf> = pdus.into_iter().map(|pdu| unsafe { pdu.get_mbuf() }).collect();
+    let sent = tx.send(&mut mbufs);
+    let n_sent = *sent.as_ref().unwrap_or(&0) as usize;
+    for mbuf in &mbufs[n_sent..] {
+        unsafe { mbuf_free(*mbuf) };
+    }
+    sent
 }
 
 #[inline]
@@ -3,21 +3,25 @@ use super::PortStats;
 use allocators::*;
 use common::errors;
 use common::errors::ErrorKind;
-use config::{DriverType, PortConfiguration, NUM_RXD, NUM_TXD};
+use config::{DriverType, MempoolSelection, PortConfiguration, NUM_RXD, NUM_TXD};
 use eui48::MacAddress;
+use interface::dpdk::socket_of_device;
 use interface::port::fdir::FlowSteeringMode;
 use interface::PortType::Physical;
-use ipnet::Ipv4Net;
+use ipnet::{Ipv4Net, Ipv6Net};
 use libc::if_indextoname;
 use native::zcsi::rte_ethdev_api::{
-    rte_eth_dev_info, rte_eth_dev_info_get, rte_eth_dev_rx_offload_name, rte_eth_dev_tx_offload_name,
-    rte_eth_macaddr_get, rte_eth_rx_mq_mode_ETH_MQ_RX_NONE, rte_eth_rx_mq_mode_ETH_MQ_RX_RSS, rte_ether_addr, rte_flow,
+    rte_eth_dev_get_mtu, rte_eth_dev_info, rte_eth_dev_info_get, rte_eth_dev_rx_offload_name, rte_eth_dev_set_mtu,
+    rte_eth_dev_tx_offload_name, rte_eth_macaddr_get, rte_eth_rx_mq_mode_ETH_MQ_RX_NONE,
+    rte_eth_rx_mq_mode_ETH_MQ_RX_RSS, rte_eth_stats, rte_eth_stats_get, rte_eth_xstat, rte_eth_xstat_name,
+    rte_eth_xstats_get, rte_eth_xstats_get_names, rte_eth_xstats_reset, rte_ether_addr, rte_flow,
 };
-use native::zcsi::rte_ethdev_api::{RTE_ETH_FLOW_MAX, RTE_ETH_FLOW_UNKNOWN};
+use native::zcsi::rte_ethdev_api::{DEV_TX_OFFLOAD_TCP_TSO, RTE_ETH_FLOW_MAX, RTE_ETH_FLOW_UNKNOWN};
 use native::zcsi::{
     add_tcp_flow, attach_device, eth_rx_burst, eth_rx_queue_count, eth_tx_burst, eth_tx_prepare, init_bess_eth_ring,
-    init_ovs_eth_ring, init_pmd_port, kni_alloc, kni_get_name, max_rxqs, max_txqs, num_pmd_ports, rss_flow_name,
-    rte_kni_rx_burst, rte_kni_tx_burst, KniPortParams, MBuf, RteFdirConf, RteFlowError, RteKni,
+    init_named_eth_ring, init_ovs_eth_ring, init_pmd_port, kni_alloc, kni_get_name, max_rxqs, max_txqs, num_pmd_ports,
+    rss_flow_name, rte_eth_stats_reset, rte_kni_rx_burst, rte_kni_tx_burst, KniPortParams, MBuf, RteFdirConf,
+    RteFlowError, RteKni, RTE_ETHDEV_QUEUE_STAT_CNTRS, RTE_ETH_XSTATS_NAME_SIZE,
 };
 use regex::Regex;
 use std::cell::RefCell;
@@ -26,14 +30,14 @@ use std::collections::VecDeque;
 use std::ffi::{CStr, CString};
 use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::ptr;
 use std::ptr::Unique;
 use std::rc::Rc;
 use std::string::ToString;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use utils::{rdtsc_unsafe, FiveTupleV4};
+use utils::{rdtsc_unsafe, round_to_power_of_2, FiveTupleV4};
 
 /// A DPDK based PMD port. Send and receive should not be called directly on this structure but on the port queue
 /// structure instead.
@@ -44,6 +48,8 @@ pub enum PortType {
     Virtio,
     Bess,
     Ovs,
+    /// rings shared with another NetBricks process
+    Ring,
     Null,
 }
 
@@ -56,6 +62,7 @@ impl fmt::Display for PortType {
             Kni => "KNI",
             Bess => "BESS",
             Ovs => "OVS",
+            Ring => "RING",
             Null => "NULL",
         };
         write!(f, "{}", printable)
@@ -66,10 +73,26 @@ impl fmt::Display for PortType {
 pub struct NetSpec {
     pub mac: Option<MacAddress>,
     pub ip_net: Option<Ipv4Net>,
+    pub ipv6_net: Option<Ipv6Net>,
     pub nsname: Option<String>,
     pub port: Option<u16>,
 }
 
+/// Hardware counters of a single RX/TX queue pair, as far as supported by the driver.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct EthQueueStats {
+    pub ipackets: u64,
+    pub opackets: u64,
+    pub ibytes: u64,
+    pub obytes: u64,
+    pub errors: u64,
+}
+
+/// True if the extended statistic `name` counts packets dropped or received with errors by the NIC.
+pub fn is_drop_or_error_xstat(name: &str) -> bool {
+    name.contains("error") || name.contains("drop") || name.contains("miss") || name.contains("nombuf")
+}
+
 pub struct PmdPort {
     name: String,
     kni_name: Option<String>,
@@ -598,6 +621,14 @@ impl PmdPort {
         }
     }
 
+    #[inline]
+    pub fn ipv6_addr(&self) -> Option<Ipv6Addr> {
+        self.net_spec
+            .as_ref()
+            .and_then(|spec| spec.ipv6_net)
+            .map(|net| net.addr())
+    }
+
     #[inline]
     /// Number of configured RXQs.
     pub fn rxqs(&self) -> u16 {
@@ -653,6 +684,15 @@ impl PmdPort {
         *self.port_type() == PortType::Physical
     }
 
+    /// True if the port is backed by a DPDK ethernet device of its own, i.e. it has hardware counters.
+    #[inline]
+    pub fn is_eth_dev(&self) -> bool {
+        match self.port_type {
+            PortType::Kni | PortType::Null => false,
+            _ => true,
+        }
+    }
+
     #[inline]
     pub fn get_rte_kni(&self) -> *mut RteKni {
         self.kni.unwrap().as_ptr()
@@ -717,8 +757,9 @@ impl PmdPort {
         )
     }
 
-    /// Get stats for an RX/TX queue pair.
-    fn queue_stats(&self, queue: u16) -> (usize, usize, usize, usize, usize, u64) {
+    /// Get detailed stats for an RX/TX queue pair:
+    /// (rx packets, tx packets, tx queued, max tx queue length, max rx queue length, rx cycles).
+    pub fn queue_stats(&self, queue: u16) -> (usize, usize, usize, usize, usize, u64) {
         let idx = queue as usize;
         (
             self.stats_rx[idx].stats.load(Ordering::Relaxed),
@@ -791,6 +832,165 @@ impl PmdPort {
         );
     }
 
+    fn check_eth_dev(&self) -> errors::Result<()> {
+        if self.is_eth_dev() {
+            Ok(())
+        } else {
+            Err(ErrorKind::RunTimeError(format!(
+                "port {} of type {} has no hardware statistics",
+                self.name, self.port_type
+            )))
+        }
+    }
+
+    /// Get the basic hardware statistics of the port. In contrast to the soft statistics these also count
+    /// packets dropped by the NIC, e.g. `imissed` (no free rx descriptor) and `rx_nombuf` (mbuf allocation failed).
+    pub fn eth_stats(&self) -> errors::Result<rte_eth_stats> {
+        self.check_eth_dev()?;
+        let mut stats = rte_eth_stats::new();
+        let ret = unsafe { rte_eth_stats_get(self.port, &mut stats as *mut rte_eth_stats) };
+        if ret == 0 {
+            Ok(stats)
+        } else {
+            Err(ErrorKind::RunTimeError(format!(
+                "rte_eth_stats_get failed for port {} ({})",
+                self.name, ret
+            )))
+        }
+    }
+
+    /// Get the hardware counters of an RX/TX queue pair. Drivers only provide these for the first
+    /// `RTE_ETHDEV_QUEUE_STAT_CNTRS` queues.
+    pub fn eth_queue_stats(&self, queue: u16) -> errors::Result<EthQueueStats> {
+        if queue as usize >= RTE_ETHDEV_QUEUE_STAT_CNTRS || (queue >= self.rxqs && queue >= self.txqs) {
+            return Err(ErrorKind::BadQueue);
+        }
+        let stats = self.eth_stats()?;
+        let q = queue as usize;
+        Ok(EthQueueStats {
+            ipackets: stats.q_ipackets[q],
+            opackets: stats.q_opackets[q],
+            ibytes: stats.q_ibytes[q],
+            obytes: stats.q_obytes[q],
+            errors: stats.q_errors[q],
+        })
+    }
+
+    pub fn reset_eth_stats(&self) -> errors::Result<()> {
+        self.check_eth_dev()?;
+        match unsafe { rte_eth_stats_reset(self.port) } {
+            0 => Ok(()),
+            ret => Err(ErrorKind::RunTimeError(format!(
+                "rte_eth_stats_reset failed for port {} ({})",
+                self.name, ret
+            ))),
+        }
+    }
+
+    /// Names of the extended statistics supported by the driver, in the order used by `xstats`.
+    pub fn xstat_names(&self) -> errors::Result<Vec<String>> {
+        self.check_eth_dev()?;
+        let n = unsafe { rte_eth_xstats_get_names(self.port, ptr::null_mut(), 0) };
+        if n < 0 {
+            return Err(ErrorKind::RunTimeError(format!(
+                "rte_eth_xstats_get_names failed for port {} ({})",
+                self.name, n
+            )));
+        }
+        let mut names = vec![rte_eth_xstat_name { name: [0; RTE_ETH_XSTATS_NAME_SIZE] }; n as usize];
+        let ret = unsafe { rte_eth_xstats_get_names(self.port, names.as_mut_ptr(), n as u32) };
+        if ret < 0 || ret > n {
+            return Err(ErrorKind::RunTimeError(format!(
+                "rte_eth_xstats_get_names failed for port {} ({})",
+                self.name, ret
+            )));
+        }
+        names.truncate(ret as usize);
+        Ok(names
+            .iter()
+            .map(|n| n.to_str().unwrap_or("<invalid>").to_string())
+            .collect())
+    }
+
+    /// Get all extended statistics of the port as (name, value) pairs. Besides the per-queue counters these contain
+    /// the error breakdown of the driver, e.g. `rx_crc_errors`, `rx_length_errors` or `rx_missed_errors`.
+    pub fn xstats(&self) -> errors::Result<Vec<(String, u64)>> {
+        let names = self.xstat_names()?;
+        let mut values = vec![rte_eth_xstat { id: 0, value: 0 }; names.len()];
+        let ret = unsafe { rte_eth_xstats_get(self.port, values.as_mut_ptr(), values.len() as u32) };
+        if ret < 0 || ret as usize > values.len() {
+            return Err(ErrorKind::RunTimeError(format!(
+                "rte_eth_xstats_get failed for port {} ({})",
+                self.name, ret
+            )));
+        }
+        values.truncate(ret as usize);
+        Ok(values
+            .iter()
+            .filter(|x| (x.id as usize) < names.len())
+            .map(|x| (names[x.id as usize].clone(), x.value))
+            .collect())
+    }
+
+    /// The extended statistics which count dropped or erroneous packets in the NIC, skipping zero counters.
+    pub fn xstats_drops_and_errors(&self) -> errors::Result<Vec<(String, u64)>> {
+        Ok(self
+            .xstats()?
+            .into_iter()
+            .filter(|&(ref name, value)| value > 0 && is_drop_or_error_xstat(name))
+            .collect())
+    }
+
+    pub fn reset_xstats(&self) -> errors::Result<()> {
+        self.check_eth_dev()?;
+        match unsafe { rte_eth_xstats_reset(self.port) } {
+            0 => Ok(()),
+            ret => Err(ErrorKind::RunTimeError(format!(
+                "rte_eth_xstats_reset failed for port {} ({})",
+                self.name, ret
+            ))),
+        }
+    }
+
+    /// True if the device can segment TCP packets, see `Pdu::set_tcp_segmentation_offload`. Otherwise use the `gso`
+    /// operator.
+    pub fn tso_capable(&self) -> bool {
+        if !self.is_eth_dev() {
+            return false;
+        }
+        let mut dev_info = rte_eth_dev_info::new_null();
+        unsafe {
+            rte_eth_dev_info_get(self.port, &mut dev_info as *mut rte_eth_dev_info);
+        }
+        dev_info.tx_offload_capa & DEV_TX_OFFLOAD_TCP_TSO as u64 != 0
+    }
+
+    /// The current MTU of the device.
+    pub fn mtu(&self) -> errors::Result<u16> {
+        self.check_eth_dev()?;
+        let mut mtu = 0u16;
+        match unsafe { rte_eth_dev_get_mtu(self.port, &mut mtu) } {
+            0 => Ok(mtu),
+            ret => Err(ErrorKind::RunTimeError(format!(
+                "rte_eth_dev_get_mtu failed for port {} ({})",
+                self.name, ret
+            ))),
+        }
+    }
+
+    /// Change the MTU of the device. Jumbo frames must have been enabled by configuring the port with a MTU above
+    /// 1500, otherwise the device may reject larger values.
+    pub fn set_mtu(&self, mtu: u16) -> errors::Result<()> {
+        self.check_eth_dev()?;
+        match unsafe { rte_eth_dev_set_mtu(self.port, mtu) } {
+            0 => Ok(()),
+            ret => Err(ErrorKind::RunTimeError(format!(
+                "rte_eth_dev_set_mtu({}) failed for port {} ({})",
+                mtu, self.name, ret
+            ))),
+        }
+    }
+
     pub fn print_eth_dev_info(port: u16) {
         let mut dev_info = rte_eth_dev_info::new_null();
         unsafe {
@@ -897,6 +1097,8 @@ impl PmdPort {
         flow_steering_mode: Option<FlowSteeringMode>,
         net_spec: Option<NetSpec>,
         associated_dpdk_port_id: Option<u16>,
+        mempool: MempoolSelection,
+        mtu: Option<u16>,
     ) -> errors::Result<Arc<PmdPort>> {
         let loopbackv = i32_from_bool(loopback);
         let tsov = i32_from_bool(tso);
@@ -922,6 +1124,13 @@ impl PmdPort {
             } else {
                 rte_eth_rx_mq_mode_ETH_MQ_RX_NONE
             };
+            // a negative socket id selects the pool local to the core of the queue
+            let pool_sid = match mempool {
+                MempoolSelection::Local => -1,
+                // the socket the pools were sized for, see `NetbricksConfiguration::mbufs_per_socket`
+                MempoolSelection::Device => socket_of_device(name).unwrap_or(-1),
+                MempoolSelection::Socket(sid) => sid,
+            };
             let ret = unsafe {
                 init_pmd_port(
                     port,
@@ -940,6 +1149,8 @@ impl PmdPort {
                     } else {
                         ptr::null()
                     },
+                    pool_sid,
+                    mtu.unwrap_or(0),
                 )
             };
             if ret == 0 {
@@ -1020,6 +1231,49 @@ impl PmdPort {
         }
     }
 
+    /// Create a port connected to another NetBricks process, which shares the DPDK memory of this process, i.e. one of
+    /// the processes runs as a secondary process. Both processes use a port of the same name, the primary process
+    /// creates its rings with `nrxd` and `ntxd` slots. The receive queues of one process are the transmit queues of the
+    /// other, the numbers of receive and transmit queues of the processes must match accordingly.
+    fn new_ring_port(
+        name: &str,
+        rx_cores: &[i32],
+        tx_cores: &[i32],
+        nrxd: u16,
+        ntxd: u16,
+    ) -> errors::Result<Arc<PmdPort>> {
+        let ring_name = CString::new(name).map_err(|_| ErrorKind::BadVdev(String::from(name)))?;
+        let port = unsafe {
+            init_named_eth_ring(
+                ring_name.as_ptr(),
+                rx_cores.len() as i32,
+                tx_cores.len() as i32,
+                round_to_power_of_2(nrxd as usize) as u32,
+                round_to_power_of_2(ntxd as usize) as u32,
+                rx_cores[0],
+            )
+        };
+        if port >= 0 {
+            Ok(Arc::new(PmdPort {
+                name: name.to_string(),
+                kni_name: None,
+                port_type: PortType::Ring,
+                port: port as u16,
+                rxqs: rx_cores.len() as u16,
+                txqs: tx_cores.len() as u16,
+                rx_cores: Some(rx_cores.to_vec()),
+                tx_cores: Some(tx_cores.to_vec()),
+                n_rx_desc: nrxd,
+                n_tx_desc: ntxd,
+                stats_rx: (0..rx_cores.len()).map(|_| Arc::new(PortStats::new())).collect(),
+                stats_tx: (0..tx_cores.len()).map(|_| Arc::new(PortStats::new())).collect(),
+                ..Default::default()
+            }))
+        } else {
+            Err(ErrorKind::FailedToInitializeRingPort(port).into())
+        }
+    }
+
     fn new_kni_port(
         name: &str,
         kni_port_params: Box<KniPortParams>,
@@ -1074,6 +1328,8 @@ impl PmdPort {
         flow_steering_mode: Option<FlowSteeringMode>,
         net_spec: Option<NetSpec>,
         associated_dpdk_port_id: Option<u16>,
+        mempool: MempoolSelection,
+        mtu: Option<u16>,
     ) -> errors::Result<Arc<PmdPort>> {
         let cannonical_spec = PmdPort::cannonicalize_pci(spec);
         debug!("attach_pmd_device, port = {:?}", cannonical_spec);
@@ -1109,6 +1365,8 @@ impl PmdPort {
                 flow_steering_mode,
                 net_spec,
                 associated_dpdk_port_id,
+                mempool,
+                mtu,
             )
         } else {
             Err(ErrorKind::BadDev(String::from(spec)).into())
@@ -1133,8 +1391,9 @@ impl PmdPort {
         /// Create a new port.
         ///
         /// Description
-        /// -   `name`: The name for a port. NetBricks currently supports Bess native vports, OVS shared memory ports and
-        ///     `dpdk` PMDs. DPDK PMDs can be used to input pcap (e.g., `dpdk:eth_pcap0,rx_pcap=<pcap_name>`), etc.
+        /// -   `name`: The name for a port. NetBricks currently supports Bess native vports, OVS shared memory ports,
+        ///     rings shared with another NetBricks process (`ring:<name>`) and `dpdk` PMDs. DPDK PMDs can be used to
+        ///     input pcap (e.g., `dpdk:eth_pcap0,rx_pcap=<pcap_name>`), etc.
         /// -   `rxqs`, `txqs`: Number of RX and TX queues.
         /// -   `tx_cores`, `rx_cores`: Core affinity of where the queues will be used.
         /// -   `nrxd`, `ntxd`: RX and TX descriptors.
@@ -1208,6 +1467,7 @@ impl PmdPort {
         match parts[0] {
             "bess" => PmdPort::new_bess_port(parts[1], rx_cores[0]),
             "ovs" => PmdPort::new_ovs_port(parts[1], rx_cores[0]),
+            "ring" => PmdPort::new_ring_port(parts[1], rx_cores, tx_cores, nrxd, ntxd),
             "virtio" | "dpdk" => {
                 let port_type = match parts[0] {
                     "dpdk" => PortType::Physical,
@@ -1241,6 +1501,8 @@ impl PmdPort {
                     port_config.flow_steering,
                     port_config.net_spec.clone(),
                     associated_port.map_or(None, |p| Some(p.port_id())),
+                    port_config.mempool,
+                    port_config.mtu,
                 )
             }
             "kni" => {
@@ -1285,6 +1547,8 @@ impl PmdPort {
                 port_config.flow_steering,
                 None,
                 associated_port.map_or(None, |p| Some(p.port_id())),
+                port_config.mempool,
+                port_config.mtu,
             ),
         }
     }
@@ -1311,6 +1575,8 @@ impl PmdPort {
             kni: None,
             driver: DriverType::Unknown,
             net_spec: None,
+            mempool: MempoolSelection::Local,
+            mtu: None,
         };
         PmdPort::new_port_from_configuration(&config, None)
     }
@@ -0,0 +1,171 @@
+use super::Pdu;
+use common::errors;
+use common::errors::ErrorKind;
+use headers::*;
+use utils::update_checksum_incremental;
+
+impl<'a> Pdu<'a> {
+    /// Lower the MSS option of all TCP headers of the packet to `mss`, e.g. at a tunnel endpoint to account for the
+    /// tunnel overhead. Returns true if an option was changed.
+    pub fn clamp_tcp_mss(&mut self, mss: u16) -> bool {
+        let mut clamped = false;
+        for i in 0..self.headers().count() {
+            if self.headers().get(i).kind() == HeaderKind::Tcp && self.headers().tcp(i).syn_flag() {
+                let available = self.header_available(i);
+                clamped |= self.headers_mut().tcp_mut(i).clamp_mss(mss, available);
+            }
+        }
+        clamped
+    }
+
+    /// Replace the options of the TCP header `which` by the encoded `options`, which are padded to a multiple of 4
+    /// bytes. The payload is moved if the length of the options changes, and the data offset, the length of the
+    /// preceding IPv4 or IPv6 header and the checksums are updated. The lengths of outer headers of tunneled packets are
+    /// not updated, so the length of the options of an inner header cannot change.
+    pub fn set_tcp_options(&mut self, which: usize, options: &[u8]) -> errors::Result<()> {
+        if which == 0 || which >= self.headers().count() || self.headers().get(which).kind() != HeaderKind::Tcp {
+            return Err(ErrorKind::RunTimeError(format!("header {} is not a TCP header", which)));
+        }
+        let ip_kind = self.headers().get(which - 1).kind();
+        if ip_kind != HeaderKind::Ip && ip_kind != HeaderKind::Ipv6 {
+            return Err(ErrorKind::RunTimeError(format!(
+                "TCP header {} does not follow an IP header",
+                which
+            )));
+        }
+        let mut new = options.to_vec();
+        if new.len() % 4 != 0 {
+            new.push(TCP_OPT_END);
+            while new.len() % 4 != 0 {
+                new.push(0);
+            }
+        }
+        if new.len() > TCP_MAX_OPTIONS_LEN {
+            return Err(ErrorKind::BadSize(new.len(), "TCP options exceed 40 bytes".to_string()));
+        }
+        let old = match self.headers().tcp(which).option_bytes(self.header_available(which)) {
+            Some(bytes) => bytes.to_vec(),
+            None => {
+                return Err(ErrorKind::RunTimeError(
+                    "TCP options exceed the first segment".to_string(),
+                ))
+            }
+        };
+        let inner = self.headers().inner_start().map_or(false, |inner| which >= inner);
+        if new.len() != old.len() && inner {
+            return Err(ErrorKind::RunTimeError(
+                "cannot change the length of TCP options of a tunneled packet".to_string(),
+            ));
+        }
+        if new.len() > old.len() + self.headroom() {
+            return Err(ErrorKind::BadSize(new.len() - old.len(), "not enough headroom".to_string()));
+        }
+
+        // the length of the segment is part of the pseudo header
+        let old_tcp_len = if ip_kind == HeaderKind::Ip {
+            let ip = self.headers().ip(which - 1);
+            (ip.length() as usize).saturating_sub(ip.offset())
+        } else {
+            self.headers().ipv6(which - 1).payload_len() as usize
+        };
+        let new_tcp_len = old_tcp_len + new.len() - old.len();
+        if ip_kind == HeaderKind::Ip {
+            let ip = self.headers_mut().ip_mut(which - 1);
+            let len = ip.length() as usize + new.len() - old.len();
+            ip.set_length(len as u16);
+            ip.update_checksum();
+        } else {
+            self.headers_mut()
+                .ipv6_mut(which - 1)
+                .set_payload_len(new_tcp_len as u16);
+        }
+
+        // the header is updated before the options are resized, so that the packet parses again afterwards
+        let tcp = self.headers_mut().tcp_mut(which);
+        let old_offset_word = (tcp.data_offset() as u16) << 12;
+        tcp.set_data_offset(((TcpHeader::size() + new.len()) / 4) as u8);
+        // the flags in the low byte of the word do not change
+        tcp.update_checksum_incremental(old_offset_word, (tcp.data_offset() as u16) << 12);
+        tcp.update_checksum_incremental(old_tcp_len as u16, new_tcp_len as u16);
+        let mut csum = tcp.checksum();
+        for i in 0..(old.len().max(new.len()) / 2) {
+            let word = |bytes: &[u8]| {
+                if 2 * i < bytes.len() {
+                    (bytes[2 * i] as u16) << 8 | bytes[2 * i + 1] as u16
+                } else {
+                    0
+                }
+            };
+            csum = update_checksum_incremental(csum, word(&old), word(&new));
+        }
+        tcp.set_checksum(csum);
+
+        let options_offset = self.header_offset(which) + TcpHeader::size();
+        if new.len() > old.len() {
+            self.insert_bytes(options_offset, &vec![0u8; new.len() - old.len()])?;
+        } else if new.len() < old.len() {
+            self.remove_bytes(options_offset, old.len() - new.len())?;
+        }
+        self.write_at(options_offset, &new);
+        self.reparse();
+        Ok(())
+    }
+
+    /// Replace the first option of the same kind as `option` of the TCP header `which`, or append it. See
+    /// `set_tcp_options`.
+    pub fn set_tcp_option(&mut self, which: usize, option: &TcpOption) -> errors::Result<()> {
+        let mut options = Vec::with_capacity(TCP_MAX_OPTIONS_LEN);
+        let mut replaced = false;
+        for current in self.tcp_options_checked(which)? {
+            if current.kind() == option.kind() && !replaced {
+                option.encode(&mut options);
+                replaced = true;
+            } else {
+                current.encode(&mut options);
+            }
+        }
+        if !replaced {
+            option.encode(&mut options);
+        }
+        self.set_tcp_options(which, &options)
+    }
+
+    /// Remove the options of `kind` of the TCP header `which`, returns false if there were none. See
+    /// `set_tcp_options`.
+    pub fn remove_tcp_option(&mut self, which: usize, kind: u8) -> errors::Result<bool> {
+        let mut options = Vec::with_capacity(TCP_MAX_OPTIONS_LEN);
+        let mut removed = false;
+        for current in self.tcp_options_checked(which)? {
+            if current.kind() == kind {
+                removed = true;
+            } else {
+                current.encode(&mut options);
+            }
+        }
+        if removed {
+            self.set_tcp_options(which, &options)?;
+        }
+        Ok(removed)
+    }
+
+    /// the options of the TCP header `which`, an error if they are malformed and cannot be encoded again
+    fn tcp_options_checked(&self, which: usize) -> errors::Result<TcpOptions> {
+        if which >= self.headers().count() || self.headers().get(which).kind() != HeaderKind::Tcp {
+            return Err(ErrorKind::RunTimeError(format!("header {} is not a TCP header", which)));
+        }
+        let options = match self.headers().tcp(which).options(self.header_available(which)) {
+            Some(options) => options,
+            None => {
+                return Err(ErrorKind::RunTimeError(
+                    "TCP options exceed the first segment".to_string(),
+                ))
+            }
+        };
+        let mut iter = options.clone();
+        for _ in &mut iter {}
+        if iter.malformed() {
+            return Err(ErrorKind::RunTimeError("malformed TCP options".to_string()));
+        }
+        Ok(options)
+    }
+}
@@ -0,0 +1,171 @@
+use common::errors;
+use common::errors::ErrorKind;
+use eui48::MacAddress;
+use headers::*;
+use interface::Pdu;
+use std::mem;
+use std::net::Ipv4Addr;
+use std::slice;
+use utils::flow_hash;
+
+/// Tunnel protocol together with the identifier of the virtual network.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tunnel {
+    /// VXLAN with VNI
+    Vxlan(u32),
+    /// NVGRE with virtual subnet id and flow id
+    Nvgre(u32, u8),
+    /// Geneve with VNI, no options
+    Geneve(u32),
+    /// GTP-U with TEID, carries the IP packet without the Ethernet header
+    Gtpu(u32),
+}
+
+/// The outer headers for encapsulating packets.
+#[derive(Clone, Debug)]
+pub struct TunnelSpec {
+    pub src_mac: MacAddress,
+    pub dst_mac: MacAddress,
+    pub src_ip: Ipv4Addr,
+    pub dst_ip: Ipv4Addr,
+    pub ttl: u8,
+    pub tunnel: Tunnel,
+}
+
+impl TunnelSpec {
+    /// Bytes added to the packet by the encapsulation.
+    pub fn overhead(&self) -> usize {
+        let tunnel_len = match self.tunnel {
+            Tunnel::Vxlan(_) => MacHeader::size() + UdpHeader::size() + VxlanHeader::size(),
+            Tunnel::Geneve(_) => MacHeader::size() + UdpHeader::size() + GeneveHeader::size(),
+            Tunnel::Nvgre(_, _) => MacHeader::size() + GreHeader::size() + 4,
+            // the Ethernet header of the packet becomes the outer one
+            Tunnel::Gtpu(_) => UdpHeader::size() + GtpuHeader::size(),
+        };
+        IpHeader::size() + tunnel_len
+    }
+}
+
+/// source ports in the dynamic range carry the entropy of the inner flow, so that ECMP and RSS spread tunnels
+const ENTROPY_PORT_BASE: u16 = 49152;
+
+#[inline]
+fn push_header<T: EndOffset>(bytes: &mut Vec<u8>, header: &T) {
+    let raw = unsafe { slice::from_raw_parts(header as *const T as *const u8, mem::size_of::<T>()) };
+    bytes.extend_from_slice(raw);
+}
+
+impl<'a> Pdu<'a> {
+    /// Encapsulate the complete frame into the outer Ethernet/IPv4/UDP or GRE headers described by `spec`. For GTP-U
+    /// only the IP packet is encapsulated and its Ethernet header is rewritten into the outer one. Lengths and the IPv4
+    /// checksum are set, the UDP checksum is zero. The headroom of the mbuf must be large enough for the `overhead` of
+    /// the tunnel.
+    pub fn encap_tunnel(&mut self, spec: &TunnelSpec) -> errors::Result<()> {
+        // GTP-U packets keep their Ethernet header in front, the other tunnels prepend a new one
+        let insert_at = match spec.tunnel {
+            Tunnel::Gtpu(_) => {
+                let etype = match self.headers().get(0).as_mac() {
+                    Some(mac) => mac.etype(),
+                    None => return Err(ErrorKind::HeaderMismatch),
+                };
+                if etype != 0x0800 && etype != 0x86DD {
+                    return Err(ErrorKind::HeaderMismatch);
+                }
+                MacHeader::size()
+            }
+            _ => 0,
+        };
+        let overhead = spec.overhead();
+        let ip_len = self.pkt_len() + overhead - MacHeader::size();
+        if ip_len > u16::max_value() as usize {
+            return Err(ErrorKind::BadSize(ip_len, "encapsulated packet too large".to_string()));
+        }
+        let entropy = (0..self.headers().count())
+            .filter_map(|i| self.headers().get(i).as_ip().and_then(|ip| ip.flow()))
+            .next()
+            .map_or(0, |flow| flow_hash(&flow) as u16);
+
+        let mut bytes = Vec::with_capacity(overhead);
+        if insert_at == 0 {
+            let mut mac = MacHeader::new();
+            mac.set_smac(&spec.src_mac);
+            mac.set_dmac(&spec.dst_mac);
+            mac.set_etype(0x0800);
+            push_header(&mut bytes, &mac);
+        } else {
+            let mac = self.headers_mut().mac_mut(0);
+            mac.set_smac(&spec.src_mac);
+            mac.set_dmac(&spec.dst_mac);
+            mac.set_etype(0x0800);
+        }
+
+        let mut ip = IpHeader::new();
+        ip.set_version(4);
+        ip.set_ihl(5);
+        ip.set_ttl(spec.ttl);
+        ip.set_protocol(match spec.tunnel {
+            Tunnel::Nvgre(_, _) => IP_PROTO_GRE,
+            _ => 17,
+        });
+        ip.set_src(u32::from(spec.src_ip));
+        ip.set_dst(u32::from(spec.dst_ip));
+        ip.set_length(ip_len as u16);
+        ip.update_checksum();
+        push_header(&mut bytes, &ip);
+
+        let mut udp = UdpHeader::new();
+        udp.set_src_port(ENTROPY_PORT_BASE | (entropy & 0x3fff));
+        udp.set_length((ip_len - IpHeader::size()) as u16);
+        match spec.tunnel {
+            Tunnel::Vxlan(vni) => {
+                udp.set_dst_port(VXLAN_PORT);
+                push_header(&mut bytes, &udp);
+                let mut vxlan = VxlanHeader::new();
+                vxlan.set_vni(vni);
+                push_header(&mut bytes, &vxlan);
+            }
+            Tunnel::Geneve(vni) => {
+                udp.set_dst_port(GENEVE_PORT);
+                push_header(&mut bytes, &udp);
+                let mut geneve = GeneveHeader::new();
+                geneve.set_protocol(ETYPE_TRANSPARENT_ETHERNET);
+                geneve.set_vni(vni);
+                push_header(&mut bytes, &geneve);
+            }
+            Tunnel::Nvgre(vsid, flow_id) => {
+                push_header(&mut bytes, &GreHeader::new_nvgre());
+                bytes.extend_from_slice(&u32::to_be_bytes((vsid << 8) | flow_id as u32));
+            }
+            Tunnel::Gtpu(teid) => {
+                udp.set_dst_port(GTPU_PORT);
+                push_header(&mut bytes, &udp);
+                let mut gtpu = GtpuHeader::new();
+                gtpu.set_teid(teid);
+                gtpu.set_length((ip_len - IpHeader::size() - UdpHeader::size() - GtpuHeader::size()) as u16);
+                push_header(&mut bytes, &gtpu);
+            }
+        }
+        self.insert_bytes(insert_at, &bytes)
+    }
+
+    /// Remove the outer headers of a tunneled packet. If the tunnel carries an IP packet instead of an Ethernet
+    /// frame (GRE, Geneve, GTP-U), the outer Ethernet header is kept. Fails with `HeaderMismatch` if the packet is not
+    /// tunneled.
+    pub fn decap_tunnel(&mut self) -> errors::Result<()> {
+        let inner = match self.headers().inner_start() {
+            Some(inner) => inner,
+            None => return Err(ErrorKind::HeaderMismatch),
+        };
+        let inner_offset = self.header_offset(inner);
+        match self.headers().get(inner).kind() {
+            HeaderKind::Mac => self.remove_bytes(0, inner_offset),
+            kind @ HeaderKind::Ip | kind @ HeaderKind::Ipv6 => {
+                let mac_len = self.header_offset(1);
+                let etype = if kind == HeaderKind::Ip { 0x0800 } else { ETYPE_IPV6 };
+                self.headers_mut().mac_mut(0).set_etype(etype);
+                self.remove_bytes(mac_len, inner_offset - mac_len)
+            }
+            _ => Err(ErrorKind::HeaderMismatch),
+        }
+    }
+}
@@ -61,10 +61,12 @@ pub mod config;
 pub mod control;
 pub mod headers;
 pub mod interface;
+pub mod metrics;
 pub mod native;
 pub mod operators;
 pub mod queues;
 pub mod scheduler;
 pub mod shared_state;
 pub mod state;
+pub mod tcp;
 pub mod utils;
@@ -0,0 +1,316 @@
+use super::Metrics;
+use common::errors;
+use interface::PmdPort;
+use native::zcsi::rte_ethdev_api::RTE_MAX_NUMA_NODES;
+use native::zcsi::{mbuf_avail_count_pool, RTE_ETHDEV_QUEUE_STAT_CNTRS};
+use scheduler::NetBricksContext;
+use std::cmp::min;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Default interval between two collections of the metrics.
+pub const DEFAULT_METRICS_INTERVAL: Duration = Duration::from_secs(5);
+/// Time we wait for the schedulers to report their task statistics.
+const SCHEDULER_REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Serves the framework metrics in the Prometheus text format on a local TCP socket.
+///
+/// The HTTP server runs in its own thread and always returns the last collected snapshot. Collection must be
+/// triggered from the thread owning the `NetBricksContext`, e.g. by calling `poll` in the main loop of the NF:
+///
+/// ```ignore
+/// let mut exporter = MetricsExporter::new("127.0.0.1:9100".parse().unwrap())?;
+/// loop {
+///     exporter.poll(&context);
+///     thread::sleep(Duration::from_millis(100));
+/// }
+/// ```
+pub struct MetricsExporter {
+    local_addr: SocketAddr,
+    snapshot: Arc<RwLock<String>>,
+    interval: Duration,
+    last_update: Option<Instant>,
+}
+
+impl MetricsExporter {
+    pub fn new(address: SocketAddr) -> errors::Result<MetricsExporter> {
+        MetricsExporter::new_with_interval(address, DEFAULT_METRICS_INTERVAL)
+    }
+
+    pub fn new_with_interval(address: SocketAddr, interval: Duration) -> errors::Result<MetricsExporter> {
+        let listener = TcpListener::bind(address)?;
+        let local_addr = listener.local_addr()?;
+        let snapshot = Arc::new(RwLock::new(String::new()));
+        let server_snapshot = snapshot.clone();
+        thread::Builder::new()
+            .name("metrics".to_string())
+            .spawn(move || serve(listener, server_snapshot))?;
+        info!("serving metrics on {}", local_addr);
+        Ok(MetricsExporter {
+            local_addr,
+            snapshot,
+            interval,
+            last_update: None,
+        })
+    }
+
+    /// The address the exporter is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Collect and publish the metrics, if the collection interval has elapsed. Returns true if metrics were updated.
+    pub fn poll(&mut self, context: &NetBricksContext) -> bool {
+        let due = match self.last_update {
+            Some(t) => t.elapsed() >= self.interval,
+            None => true,
+        };
+        if due {
+            self.update(context);
+        }
+        due
+    }
+
+    /// Collect and publish the metrics immediately.
+    pub fn update(&mut self, context: &NetBricksContext) {
+        let metrics = collect(context);
+        *self.snapshot.write().unwrap() = metrics.render();
+        self.last_update = Some(Instant::now());
+    }
+}
+
+/// Collect port, scheduler and mempool statistics of the running system.
+pub fn collect(context: &NetBricksContext) -> Metrics {
+    let mut metrics = Metrics::new();
+    let mut ports: Vec<_> = context.ports.values().collect();
+    ports.sort_by(|a, b| a.name().cmp(b.name()));
+    for port in &ports {
+        collect_port_stats(&mut metrics, port);
+    }
+    for port in ports.iter().filter(|p| p.is_eth_dev()) {
+        collect_eth_stats(&mut metrics, port);
+        collect_xstats(&mut metrics, port);
+    }
+    collect_scheduler_stats(&mut metrics, &context.performance_data(SCHEDULER_REPLY_TIMEOUT));
+    collect_mempool_stats(&mut metrics);
+    metrics
+}
+
+fn collect_port_stats(metrics: &mut Metrics, port: &PmdPort) {
+    let name = &port.name()[..];
+    for q in 0..port.rxqs() {
+        let (rx_p, tx_p, tx_queued, tx_max_q_len, rx_max_q_len, rx_cycles) = port.queue_stats(q);
+        let queue = q.to_string();
+        let labels = [("port", name), ("queue", &queue[..])];
+        metrics.add_counter(
+            "netbricks_port_rx_packets_total",
+            "Packets received by the port queue.",
+            &labels,
+            rx_p as u64,
+        );
+        metrics.add_counter(
+            "netbricks_port_tx_packets_total",
+            "Packets sent by the port queue.",
+            &labels,
+            tx_p as u64,
+        );
+        metrics.add_gauge(
+            "netbricks_port_tx_queued_packets",
+            "Packets queued for transmission on the port queue.",
+            &labels,
+            tx_queued as f64,
+        );
+        metrics.add_gauge(
+            "netbricks_port_tx_max_queue_length",
+            "Maximum observed length of the tx queue.",
+            &labels,
+            tx_max_q_len as f64,
+        );
+        metrics.add_gauge(
+            "netbricks_port_rx_max_queue_length",
+            "Maximum observed length of the rx queue.",
+            &labels,
+            rx_max_q_len as f64,
+        );
+        metrics.add_counter(
+            "netbricks_port_rx_cycles_total",
+            "CPU cycles spent receiving on the port queue.",
+            &labels,
+            rx_cycles,
+        );
+    }
+}
+
+fn collect_eth_stats(metrics: &mut Metrics, port: &PmdPort) {
+    let stats = match port.eth_stats() {
+        Ok(stats) => stats,
+        Err(e) => {
+            debug!("no eth stats available for port {}: {}", port.name(), e);
+            return;
+        }
+    };
+    let name = &port.name()[..];
+    let labels = [("port", name)];
+    let totals = [
+        ("netbricks_eth_rx_packets_total", "Packets received by the device.", stats.ipackets),
+        ("netbricks_eth_tx_packets_total", "Packets sent by the device.", stats.opackets),
+        ("netbricks_eth_rx_bytes_total", "Bytes received by the device.", stats.ibytes),
+        ("netbricks_eth_tx_bytes_total", "Bytes sent by the device.", stats.obytes),
+        (
+            "netbricks_eth_rx_missed_total",
+            "Packets dropped by the device because no rx descriptor was available.",
+            stats.imissed,
+        ),
+        ("netbricks_eth_rx_errors_total", "Erroneous packets received by the device.", stats.ierrors),
+        ("netbricks_eth_tx_errors_total", "Packets the device failed to send.", stats.oerrors),
+        ("netbricks_eth_rx_nombuf_total", "Rx mbuf allocation failures.", stats.rx_nombuf),
+    ];
+    for &(metric, help, value) in totals.iter() {
+        metrics.add_counter(metric, help, &labels, value);
+    }
+    for q in 0..min(port.rxqs() as usize, RTE_ETHDEV_QUEUE_STAT_CNTRS) {
+        let queue = q.to_string();
+        let labels = [("port", name), ("queue", &queue[..])];
+        metrics.add_counter(
+            "netbricks_eth_queue_rx_packets_total",
+            "Packets received by the device queue.",
+            &labels,
+            stats.q_ipackets[q],
+        );
+        metrics.add_counter(
+            "netbricks_eth_queue_rx_bytes_total",
+            "Bytes received by the device queue.",
+            &labels,
+            stats.q_ibytes[q],
+        );
+        metrics.add_counter(
+            "netbricks_eth_queue_errors_total",
+            "Packets dropped by the device queue.",
+            &labels,
+            stats.q_errors[q],
+        );
+    }
+    for q in 0..min(port.txqs() as usize, RTE_ETHDEV_QUEUE_STAT_CNTRS) {
+        let queue = q.to_string();
+        let labels = [("port", name), ("queue", &queue[..])];
+        metrics.add_counter(
+            "netbricks_eth_queue_tx_packets_total",
+            "Packets sent by the device queue.",
+            &labels,
+            stats.q_opackets[q],
+        );
+        metrics.add_counter(
+            "netbricks_eth_queue_tx_bytes_total",
+            "Bytes sent by the device queue.",
+            &labels,
+            stats.q_obytes[q],
+        );
+    }
+}
+
+fn collect_xstats(metrics: &mut Metrics, port: &PmdPort) {
+    match port.xstats() {
+        Ok(xstats) => add_xstats(metrics, port.name(), &xstats),
+        Err(e) => debug!("no xstats available for port {}: {}", port.name(), e),
+    }
+}
+
+/// Add the extended statistics of `port` as reported by `PmdPort::xstats`.
+pub fn add_xstats(metrics: &mut Metrics, port: &str, xstats: &[(String, u64)]) {
+    for &(ref xstat, value) in xstats {
+        metrics.add_counter(
+            "netbricks_eth_xstat_total",
+            "Extended statistics reported by the driver.",
+            &[("port", port), ("name", &xstat[..])],
+            value,
+        );
+    }
+}
+
+/// Add the task statistics as reported by `SchedulerReply::PerformanceData`.
+pub fn collect_scheduler_stats<U: ToString>(
+    metrics: &mut Metrics,
+    performance_data: &HashMap<i32, HashMap<U, (String, u64, u64, u32)>>,
+) {
+    let mut cores: Vec<_> = performance_data.keys().collect();
+    cores.sort();
+    for core in cores {
+        let core_s = core.to_string();
+        let mut tasks: Vec<_> = performance_data[core]
+            .iter()
+            .map(|(uuid, t)| (uuid.to_string(), t))
+            .collect();
+        tasks.sort_by(|a, b| (&(a.1).0, &a.0).cmp(&(&(b.1).0, &b.0)));
+        for (uuid, &(ref task, cycles, count, queue_len)) in tasks {
+            let labels = [("core", &core_s[..]), ("task", &task[..]), ("uuid", &uuid[..])];
+            metrics.add_counter(
+                "netbricks_task_cycles_total",
+                "CPU cycles a task spent doing work.",
+                &labels,
+                cycles,
+            );
+            metrics.add_counter(
+                "netbricks_task_count_total",
+                "Packets (or a comparable metric) processed by a task.",
+                &labels,
+                count,
+            );
+            metrics.add_gauge(
+                "netbricks_task_max_queue_length",
+                "Maximum queue length observed by a task.",
+                &labels,
+                queue_len as f64,
+            );
+        }
+    }
+}
+
+fn collect_mempool_stats(metrics: &mut Metrics) {
+    for pool in 0..RTE_MAX_NUMA_NODES as i32 {
+        let avail = unsafe { mbuf_avail_count_pool(pool) };
+        if avail >= 0 {
+            let pool_s = pool.to_string();
+            metrics.add_gauge(
+                "netbricks_mempool_available_mbufs",
+                "Free mbufs in the packet mempool.",
+                &[("pool", &pool_s[..])],
+                avail as f64,
+            );
+        }
+    }
+}
+
+fn serve(listener: TcpListener, snapshot: Arc<RwLock<String>>) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_scrape(stream, &snapshot) {
+                    debug!("metrics request failed: {}", e);
+                }
+            }
+            Err(e) => warn!("metrics listener: accept failed: {}", e),
+        }
+    }
+}
+
+fn handle_scrape(mut stream: TcpStream, snapshot: &Arc<RwLock<String>>) -> errors::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf)?;
+    let response = if buf[..n].starts_with(b"GET ") {
+        let body = snapshot.read().unwrap();
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            *body
+        )
+    } else {
+        "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+    stream.write_all(response.as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,400 @@
+use common::errors;
+use state::{ExpiryFn, ExpiryReason, Mergeable};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use utils::FiveTupleV4;
+
+/// Template ID of the flow records, the first ID available for data sets.
+const TEMPLATE_ID: u16 = 256;
+const IPFIX_VERSION: u16 = 10;
+const IPFIX_HEADER_LEN: usize = 16;
+const IPFIX_TEMPLATE_SET_ID: u16 = 2;
+const NETFLOW_V9_VERSION: u16 = 9;
+const NETFLOW_V9_HEADER_LEN: usize = 20;
+const NETFLOW_V9_TEMPLATE_SET_ID: u16 = 0;
+const SET_HEADER_LEN: usize = 4;
+
+/// IPFIX information elements of a flow record (RFC 7012), with their encoded length.
+const IPFIX_FIELDS: [(u16, u16); 10] = [
+    (8, 4),   // sourceIPv4Address
+    (12, 4),  // destinationIPv4Address
+    (7, 2),   // sourceTransportPort
+    (11, 2),  // destinationTransportPort
+    (4, 1),   // protocolIdentifier
+    (6, 1),   // tcpControlBits, reduced size encoding
+    (2, 8),   // packetDeltaCount
+    (1, 8),   // octetDeltaCount
+    (152, 8), // flowStartMilliseconds
+    (153, 8), // flowEndMilliseconds
+];
+const IPFIX_END_REASON: (u16, u16) = (136, 1); // flowEndReason
+
+/// NetFlow v9 field types of a flow record (RFC 3954), with their encoded length.
+const NETFLOW_V9_FIELDS: [(u16, u16); 10] = [
+    (8, 4),  // IPV4_SRC_ADDR
+    (12, 4), // IPV4_DST_ADDR
+    (7, 2),  // L4_SRC_PORT
+    (11, 2), // L4_DST_PORT
+    (4, 1),  // PROTOCOL
+    (6, 1),  // TCP_FLAGS
+    (2, 8),  // IN_PKTS
+    (1, 8),  // IN_BYTES
+    (22, 4), // FIRST_SWITCHED
+    (21, 4), // LAST_SWITCHED
+];
+
+/// Milliseconds since the UNIX epoch.
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() * 1000 + d.subsec_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Per flow counters kept e.g. in a `FlowTable` and exported as a `FlowRecord` when the flow expires. Merging adds the
+/// counters and extends the time span.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlowStats {
+    pub packets: u64,
+    pub bytes: u64,
+    /// union of the TCP flags of all packets
+    pub tcp_flags: u8,
+    /// first and last packet in milliseconds since the UNIX epoch, see `unix_millis`
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+impl FlowStats {
+    /// Count a packet of `bytes` with `tcp_flags` seen at `now_ms`.
+    #[inline]
+    pub fn update(&mut self, bytes: u64, tcp_flags: u8, now_ms: u64) {
+        if self.packets == 0 {
+            self.start_ms = now_ms;
+        }
+        self.packets += 1;
+        self.bytes += bytes;
+        self.tcp_flags |= tcp_flags;
+        self.end_ms = now_ms;
+    }
+}
+
+impl Mergeable for FlowStats {
+    fn merge(&mut self, other: FlowStats) {
+        if other.packets == 0 {
+            return;
+        }
+        if self.packets == 0 {
+            *self = other;
+            return;
+        }
+        self.packets += other.packets;
+        self.bytes += other.bytes;
+        self.tcp_flags |= other.tcp_flags;
+        self.start_ms = self.start_ms.min(other.start_ms);
+        self.end_ms = self.end_ms.max(other.end_ms);
+    }
+}
+
+/// Why a flow record was exported, the values of the IPFIX flowEndReason.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlowEndReason {
+    IdleTimeout = 1,
+    ActiveTimeout = 2,
+    EndOfFlow = 3,
+    ForcedEnd = 4,
+    LackOfResources = 5,
+}
+
+impl From<ExpiryReason> for FlowEndReason {
+    fn from(reason: ExpiryReason) -> FlowEndReason {
+        match reason {
+            ExpiryReason::Idle => FlowEndReason::IdleTimeout,
+            ExpiryReason::Hard => FlowEndReason::ActiveTimeout,
+            ExpiryReason::Evicted => FlowEndReason::LackOfResources,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlowRecord {
+    pub flow: FiveTupleV4,
+    pub stats: FlowStats,
+    pub end_reason: FlowEndReason,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlowExportProtocol {
+    Ipfix,
+    NetflowV9,
+}
+
+#[derive(Clone, Debug)]
+pub struct FlowExportConfig {
+    pub collector: SocketAddr,
+    pub protocol: FlowExportProtocol,
+    /// IPFIX observation domain ID or NetFlow v9 source ID
+    pub domain_id: u32,
+    /// the template is resent in this interval, since collectors may miss it or restart
+    pub template_interval: Duration,
+    /// maximum size of a UDP payload
+    pub max_message_size: usize,
+    /// records of expired flows which can be outstanding until the next `flush`, further records are dropped
+    pub channel_size: usize,
+}
+
+impl FlowExportConfig {
+    pub fn new(collector: SocketAddr, protocol: FlowExportProtocol) -> FlowExportConfig {
+        FlowExportConfig {
+            collector,
+            protocol,
+            domain_id: 0,
+            template_interval: Duration::from_secs(60),
+            max_message_size: 1400,
+            channel_size: 4096,
+        }
+    }
+}
+
+/// Sends flow records in IPFIX (RFC 7011) or NetFlow v9 (RFC 3954) messages over UDP to a collector. Records are
+/// queued with `export` or from the expiry callback of a `FlowTable`, see `expiry_fn`, and sent by `flush`, which should
+/// be called periodically, e.g. from the control plane. All records use a single template, which is sent in the first
+/// message and then again after each `template_interval`.
+pub struct FlowExporter {
+    config: FlowExportConfig,
+    socket: UdpSocket,
+    queued: Vec<FlowRecord>,
+    sender: SyncSender<FlowRecord>,
+    receiver: Receiver<FlowRecord>,
+    /// records of expired flows dropped since the channel was full
+    dropped: Arc<AtomicUsize>,
+    /// IPFIX: data records sent, NetFlow v9: messages sent
+    sequence: u32,
+    /// reference of the NetFlow v9 system uptime
+    boot_ms: u64,
+    last_template: Option<Instant>,
+}
+
+impl FlowExporter {
+    pub fn new(config: FlowExportConfig) -> errors::Result<FlowExporter> {
+        let local = if config.collector.is_ipv4() {
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        } else {
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(config.collector)?;
+        let (sender, receiver) = sync_channel(config.channel_size);
+        Ok(FlowExporter {
+            config,
+            socket,
+            queued: Vec::new(),
+            sender,
+            receiver,
+            dropped: Arc::new(AtomicUsize::new(0)),
+            sequence: 0,
+            boot_ms: unix_millis(SystemTime::now()),
+            last_template: None,
+        })
+    }
+
+    pub fn config(&self) -> &FlowExportConfig {
+        &self.config
+    }
+
+    pub fn local_addr(&self) -> errors::Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Queue a record, e.g. of a TCP connection which ended.
+    pub fn export(&mut self, record: FlowRecord) {
+        self.queued.push(record);
+    }
+
+    /// A `FlowTable` expiry callback, which queues the records of expired flows for this exporter. The table may be
+    /// owned by another thread. The callback does not block, records are dropped if `channel_size` records are
+    /// outstanding, see `dropped`.
+    pub fn expiry_fn(&self) -> ExpiryFn<FlowStats> {
+        let sender = self.sender.clone();
+        let dropped = Arc::clone(&self.dropped);
+        Box::new(move |flow, stats, reason| {
+            let record = FlowRecord {
+                flow,
+                stats,
+                end_reason: reason.into(),
+            };
+            if let Err(TrySendError::Full(_)) = sender.try_send(record) {
+                dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        })
+    }
+
+    /// The number of records of expired flows dropped since the channel was full.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Send the queued records, returns the number of messages sent.
+    pub fn flush(&mut self) -> errors::Result<usize> {
+        while let Ok(record) = self.receiver.try_recv() {
+            self.queued.push(record);
+        }
+        let records = std::mem::replace(&mut self.queued, Vec::new());
+        let now = SystemTime::now();
+        let mut messages = 0;
+        let mut start = 0;
+        while start < records.len() || self.template_due() {
+            let with_template = self.template_due();
+            let (message, count) = self.encode(&records[start..], with_template, now);
+            if let Err(e) = self.socket.send(&message) {
+                // keep the records which were not sent for the next attempt
+                self.queued.extend_from_slice(&records[start..]);
+                return Err(e.into());
+            }
+            if with_template {
+                self.last_template = Some(Instant::now());
+            }
+            // IPFIX counts data records, NetFlow v9 export packets
+            let sent = match self.config.protocol {
+                FlowExportProtocol::Ipfix => count as u32,
+                FlowExportProtocol::NetflowV9 => 1,
+            };
+            self.sequence = self.sequence.wrapping_add(sent);
+            start += count;
+            messages += 1;
+        }
+        Ok(messages)
+    }
+
+    fn template_due(&self) -> bool {
+        self.last_template
+            .map_or(true, |sent| sent.elapsed() >= self.config.template_interval)
+    }
+
+    fn record_len(&self) -> usize {
+        let fields = match self.config.protocol {
+            FlowExportProtocol::Ipfix => &IPFIX_FIELDS,
+            FlowExportProtocol::NetflowV9 => &NETFLOW_V9_FIELDS,
+        };
+        let len: u16 = fields.iter().map(|&(_, len)| len).sum();
+        match self.config.protocol {
+            FlowExportProtocol::Ipfix => (len + IPFIX_END_REASON.1) as usize,
+            FlowExportProtocol::NetflowV9 => len as usize,
+        }
+    }
+
+    /// Encode a message with as many of `records` as fit, returns the message and the number of encoded records.
+    fn encode(&self, records: &[FlowRecord], with_template: bool, now: SystemTime) -> (Vec<u8>, usize) {
+        let ipfix = self.config.protocol == FlowExportProtocol::Ipfix;
+        let mut message = Vec::with_capacity(self.config.max_message_size);
+        // the lengths and counts of the header are filled in when the message is complete
+        message.resize(if ipfix { IPFIX_HEADER_LEN } else { NETFLOW_V9_HEADER_LEN }, 0);
+        let mut flowsets = 0;
+        if with_template {
+            let fields: Vec<(u16, u16)> = if ipfix {
+                IPFIX_FIELDS.iter().cloned().chain(Some(IPFIX_END_REASON)).collect()
+            } else {
+                NETFLOW_V9_FIELDS.to_vec()
+            };
+            let set_id = if ipfix {
+                IPFIX_TEMPLATE_SET_ID
+            } else {
+                NETFLOW_V9_TEMPLATE_SET_ID
+            };
+            put_u16(&mut message, set_id);
+            put_u16(&mut message, (SET_HEADER_LEN + 4 + 4 * fields.len()) as u16);
+            put_u16(&mut message, TEMPLATE_ID);
+            put_u16(&mut message, fields.len() as u16);
+            for (id, len) in fields {
+                put_u16(&mut message, id);
+                put_u16(&mut message, len);
+            }
+            flowsets += 1;
+        }
+
+        let record_len = self.record_len();
+        let space = self
+            .config
+            .max_message_size
+            .saturating_sub(message.len() + SET_HEADER_LEN + 3);
+        let mut count = records.len().min(space / record_len);
+        if count == 0 && flowsets == 0 {
+            // a message holds at least one record, even if it exceeds the maximum size
+            count = records.len().min(1);
+        }
+        if count > 0 {
+            let set_start = message.len();
+            put_u16(&mut message, TEMPLATE_ID);
+            put_u16(&mut message, 0);
+            for record in &records[..count] {
+                self.encode_record(&mut message, record, ipfix);
+            }
+            if !ipfix {
+                // NetFlow v9 flowsets are padded to 32 bits
+                while (message.len() - set_start) % 4 != 0 {
+                    message.push(0);
+                }
+            }
+            let set_len = (message.len() - set_start) as u16;
+            message[set_start + 2..set_start + 4].copy_from_slice(&set_len.to_be_bytes());
+        }
+
+        let now_ms = unix_millis(now);
+        let mut header = Vec::with_capacity(NETFLOW_V9_HEADER_LEN);
+        if ipfix {
+            put_u16(&mut header, IPFIX_VERSION);
+            put_u16(&mut header, message.len() as u16);
+            put_u32(&mut header, (now_ms / 1000) as u32);
+            put_u32(&mut header, self.sequence);
+            put_u32(&mut header, self.config.domain_id);
+        } else {
+            put_u16(&mut header, NETFLOW_V9_VERSION);
+            // the count includes the template record
+            put_u16(&mut header, (count + flowsets) as u16);
+            put_u32(&mut header, now_ms.saturating_sub(self.boot_ms) as u32);
+            put_u32(&mut header, (now_ms / 1000) as u32);
+            put_u32(&mut header, self.sequence);
+            put_u32(&mut header, self.config.domain_id);
+        }
+        message[..header.len()].copy_from_slice(&header);
+        (message, count)
+    }
+
+    fn encode_record(&self, message: &mut Vec<u8>, record: &FlowRecord, ipfix: bool) {
+        let flow = &record.flow;
+        let stats = &record.stats;
+        put_u32(message, flow.src_ip);
+        put_u32(message, flow.dst_ip);
+        put_u16(message, flow.src_port);
+        put_u16(message, flow.dst_port);
+        message.push(flow.proto);
+        message.push(stats.tcp_flags);
+        put_u64(message, stats.packets);
+        put_u64(message, stats.bytes);
+        if ipfix {
+            put_u64(message, stats.start_ms);
+            put_u64(message, stats.end_ms);
+            message.push(record.end_reason as u8);
+        } else {
+            // relative to the system uptime, i.e. the creation of the exporter
+            put_u32(message, stats.start_ms.saturating_sub(self.boot_ms) as u32);
+            put_u32(message, stats.end_ms.saturating_sub(self.boot_ms) as u32);
+        }
+    }
+}
+
+#[inline]
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+#[inline]
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+#[inline]
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
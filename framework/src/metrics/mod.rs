@@ -0,0 +1,131 @@
+//! Metrics of ports, schedulers and mempools in the Prometheus text exposition format (version 0.0.4).
+//!
+//! `Metrics` is a plain container which can be filled by any component, `MetricsExporter` collects the framework
+//! statistics periodically into such a container and serves the rendered text to scrapers on a local socket.
+//!
+//! `FlowExporter` exports flow records to IPFIX and NetFlow v9 collectors.
+pub use self::exporter::*;
+pub use self::flow_export::*;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write;
+
+mod exporter;
+mod flow_export;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+}
+
+impl fmt::Display for MetricType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let printable = match *self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+/// A metric family, i.e. all samples of a metric with the same name but different label values.
+pub struct MetricFamily {
+    pub name: String,
+    pub help: String,
+    pub metric_type: MetricType,
+    pub samples: Vec<(Vec<(String, String)>, f64)>,
+}
+
+/// A set of metric families, rendered in insertion order.
+#[derive(Default)]
+pub struct Metrics {
+    families: Vec<MetricFamily>,
+    index: HashMap<String, usize>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Add a sample to the family `name`. The family is created with `help` and `metric_type` on first use.
+    pub fn add(&mut self, name: &str, help: &str, metric_type: MetricType, labels: &[(&str, &str)], value: f64) {
+        let i = match self.index.get(name) {
+            Some(i) => *i,
+            None => {
+                self.families.push(MetricFamily {
+                    name: name.to_string(),
+                    help: help.to_string(),
+                    metric_type,
+                    samples: Vec::new(),
+                });
+                self.index.insert(name.to_string(), self.families.len() - 1);
+                self.families.len() - 1
+            }
+        };
+        let labels = labels.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect();
+        self.families[i].samples.push((labels, value));
+    }
+
+    #[inline]
+    pub fn add_counter(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: u64) {
+        self.add(name, help, MetricType::Counter, labels, value as f64);
+    }
+
+    #[inline]
+    pub fn add_gauge(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        self.add(name, help, MetricType::Gauge, labels, value);
+    }
+
+    pub fn families(&self) -> &[MetricFamily] {
+        &self.families
+    }
+
+    pub fn len(&self) -> usize {
+        self.families.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.families.is_empty()
+    }
+
+    /// Render all families in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::with_capacity(64 * self.families.len());
+        for family in &self.families {
+            write!(out, "# HELP {} {}\n", family.name, escape_help(&family.help)).unwrap();
+            write!(out, "# TYPE {} {}\n", family.name, family.metric_type).unwrap();
+            for &(ref labels, value) in &family.samples {
+                out.push_str(&family.name);
+                if !labels.is_empty() {
+                    out.push('{');
+                    for (i, &(ref k, ref v)) in labels.iter().enumerate() {
+                        if i > 0 {
+                            out.push(',');
+                        }
+                        write!(out, "{}=\"{}\"", k, escape_label_value(v)).unwrap();
+                    }
+                    out.push('}');
+                }
+                write!(out, " {}\n", value).unwrap();
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+fn escape_help(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label_value(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -1,4 +1,4 @@
-use native::zcsi::rte_mbuf_api::{rte_mbuf, PKT_TX_IPV4, PKT_TX_IP_CKSUM, PKT_TX_TCP_CKSUM};
+use native::zcsi::rte_mbuf_api::{rte_mbuf, PKT_TX_IPV4, PKT_TX_IP_CKSUM, PKT_TX_TCP_CKSUM, PKT_TX_TCP_SEG};
 use std::fmt;
 use std::ptr;
 
@@ -60,7 +60,7 @@ impl MBuf {
     }
 
     #[inline]
-    fn pkt_headroom(&self) -> usize {
+    pub fn pkt_headroom(&self) -> usize {
         self.data_off as usize
     }
 
@@ -83,8 +83,8 @@ impl MBuf {
         }
     }
 
-    /// Add data to the end of a packet buffer. This might fail (i.e., return 0) when no more tailroom is left. We do
-    /// not currently deal with packet with multiple segments.
+    /// Add data to the end of a packet buffer. This might fail (i.e., return 0) when no more tailroom is left. This
+    /// only extends this segment, use `add_data_end_last_segment` for chained packets.
     #[inline]
     pub fn add_data_end(&mut self, len: usize) -> usize {
         if len > self.pkt_tailroom() {
@@ -119,6 +119,96 @@ impl MBuf {
         }
     }
 
+    /// Number of segments of the packet. Only valid for the first segment.
+    #[inline]
+    pub fn nb_segs(&self) -> u16 {
+        self.nb_segs
+    }
+
+    /// The next segment of a chained packet, null for the last segment.
+    #[inline]
+    pub fn next_segment(&self) -> *mut MBuf {
+        self.next
+    }
+
+    #[inline]
+    pub fn is_contiguous(&self) -> bool {
+        self.next.is_null()
+    }
+
+    /// The last segment of a chained packet, this mbuf for a single segment packet.
+    #[inline]
+    pub fn last_segment(&mut self) -> *mut MBuf {
+        let mut seg = self as *mut MBuf;
+        unsafe {
+            while !(*seg).next.is_null() {
+                seg = (*seg).next;
+            }
+        }
+        seg
+    }
+
+    /// Add data to the end of the last segment of a packet. Must be called on the first segment, as the packet length
+    /// is only maintained there. Returns 0 when the tailroom of the last segment is too small.
+    #[inline]
+    pub fn add_data_end_last_segment(&mut self, len: usize) -> usize {
+        let last = self.last_segment();
+        if last == self as *mut MBuf {
+            self.add_data_end(len)
+        } else {
+            unsafe {
+                if len > (*last).pkt_tailroom() {
+                    0
+                } else {
+                    (*last).data_len += len as u16;
+                    self.pkt_len += len as u32;
+                    len
+                }
+            }
+        }
+    }
+
+    /// Append the segments of `tail` to this packet. Must be called on the first segment, `tail` must be the first
+    /// segment of a packet which is not referenced elsewhere.
+    #[inline]
+    pub fn chain_segments(&mut self, tail: *mut MBuf) {
+        unsafe {
+            let last = self.last_segment();
+            (*last).next = tail;
+            self.nb_segs += (*tail).nb_segs;
+            self.pkt_len += (*tail).pkt_len;
+        }
+    }
+
+    /// Copy the data of all following segments into this segment and detach them. Must be called on the first segment.
+    /// Returns the detached chain, which must be freed by the caller, or null if the packet was contiguous. Returns
+    /// `None` if the packet does not fit into this segment.
+    pub fn linearize(&mut self) -> Option<*mut MBuf> {
+        let chain = self.next;
+        if chain.is_null() {
+            return Some(chain);
+        }
+        if self.pkt_len() > self.data_len() + self.pkt_tailroom() {
+            return None;
+        }
+        let head_len = self.data_len();
+        let mut seg = chain;
+        unsafe {
+            while !seg.is_null() {
+                let len = (*seg).data_len();
+                ptr::copy_nonoverlapping((*seg).data_address(0), self.data_address(self.data_len()), len);
+                self.data_len += len as u16;
+                seg = (*seg).next;
+            }
+            // the detached chain becomes a packet of its own, so that it can be freed as a whole
+            (*chain).nb_segs = self.nb_segs - 1;
+            (*chain).pkt_len = (self.pkt_len() - head_len) as u32;
+        }
+        self.next = ptr::null_mut();
+        self.nb_segs = 1;
+        Some(chain)
+    }
+
     #[inline]
     pub fn refcnt(&self) -> u16 {
         self.refcnt
@@ -139,7 +229,7 @@ impl MBuf {
         self.refcnt = new_value;
     }
 
-    // copy payload and selected fields to target tmb
+    // copy payload and selected fields of this segment to target tmb
     #[inline]
     pub fn copy_to(&self, tmb: &mut MBuf) {
         (*tmb).data_len = (*self).data_len;
@@ -166,6 +256,17 @@ impl MBuf {
         self.ol_flags |= PKT_TX_IPV4 | PKT_TX_IP_CKSUM | PKT_TX_TCP_CKSUM;
     }
 
+    /// TCP segmentation offload implies TCP and IPv4 checksum offload.
+    #[inline]
+    pub fn set_tcp_ipv4_segmentation_tx_offload(&mut self) {
+        self.ol_flags |= PKT_TX_IPV4 | PKT_TX_IP_CKSUM | PKT_TX_TCP_SEG;
+    }
+
+    #[inline]
+    pub fn tcp_segmentation_tx_offload(&self) -> bool {
+        self.ol_flags & PKT_TX_TCP_SEG != 0
+    }
+
     #[inline]
     pub fn ipv4_checksum_tx_offload(&mut self) -> bool {
         self.ol_flags & PKT_TX_IPV4 != 0 && self.ol_flags & PKT_TX_IP_CKSUM != 0
@@ -210,6 +311,18 @@ impl MBuf {
             self.__bindgen_anon_3.__bindgen_anon_1.set_l4_len(val);
         }
     }
+
+    #[inline]
+    pub fn tso_segsz(&self) -> u64 {
+        unsafe { self.__bindgen_anon_3.__bindgen_anon_1.tso_segsz() }
+    }
+
+    #[inline]
+    pub fn set_tso_segsz(&mut self, val: u64) {
+        unsafe {
+            self.__bindgen_anon_3.__bindgen_anon_1.set_tso_segsz(val);
+        }
+    }
 }
 
 impl fmt::Display for MBuf {
@@ -216,7 +216,7 @@ pub fn eth_dev_get_name_by_port(port_id: u16) -> Option<String> {
     }
 }
 
-const RTE_ETHDEV_QUEUE_STAT_CNTRS: usize = 16;
+pub const RTE_ETHDEV_QUEUE_STAT_CNTRS: usize = 16;
 
 impl rte_eth_stats {
     pub fn new() -> rte_eth_stats {
@@ -812,7 +812,9 @@ extern "C" {
         wlcount: i32,
         pool_size: u32,
         cache_size: u32,
-        mbuf_cnt: u32,
+        mbuf_cnt: *const u32,
+        n_sockets: i32,
+        data_room_size: u16,
         slots: u16,
         vdevs: *mut *const c_char,
         vdev_count: i32,
@@ -839,6 +841,8 @@ extern "C" {
         csumoffload: i32,
         rx_mq_mode: rte_eth_rx_mq_mode,
         fdir_conf_ptr: *const RteFdirConf,
+        pool_sid: i32,
+        mtu: u16,
     ) -> i32;
     pub fn free_pmd_port(port: u16) -> i32;
     pub fn fdir_get_infos(pmdport_id: u16);
@@ -852,6 +856,9 @@ extern "C" {
     pub fn num_pmd_ports() -> i32;
     pub fn init_bess_eth_ring(ifname: *const c_char, core: i32) -> i32;
     pub fn init_ovs_eth_ring(iface: i32, core: i32) -> i32;
+    /// returns the port id of a port connected to another NetBricks process by named rings
+    pub fn init_named_eth_ring(name: *const c_char, nrxq: i32, ntxq: i32, rx_size: u32, tx_size: u32, core: i32)
+        -> i32;
     //pub fn find_port_with_pci_address(pciaddr: *const c_char) -> i32;
     //pub fn attach_pmd_device(dev: *const c_char) -> i32;
     /// returns portids of all matching ports for the device
@@ -864,6 +871,7 @@ extern "C" {
     pub fn mbuf_alloc_bulk(array: *mut *mut rte_mbuf, cnt: u32) -> i32;
     pub fn mbuf_free_bulk(array: *mut *mut rte_mbuf, cnt: i32) -> i32;
     pub fn mbuf_avail_count() -> u32;
+    pub fn mbuf_avail_count_pool(pool_id: i32) -> i32;
     pub fn crc_hash_native(to_hash: *const u8, size: u32, iv: u32) -> u32;
     pub fn ipv4_cksum(payload: *const u8) -> u16;
     pub fn ipv4_phdr_chksum(ipv4_hdr: *const IpHeader, ol_flags: u64) -> u16;
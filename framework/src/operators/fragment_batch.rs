@@ -0,0 +1,292 @@
+use super::act::Act;
+use super::iterator::*;
+use super::packet_batch::PacketBatch;
+use super::Batch;
+use common::*;
+use headers::EndOffset;
+use interface::{PacketTx, Pdu, PduAllocFn, PmdPort};
+use native::zcsi::rte_ethdev_api::RTE_ETHER_MTU;
+use state::{Reassembler, ReassemblyConfig};
+use std::time::Instant;
+
+/// Fragments IPv4 and IPv6 packets whose IP packet exceeds the MTU of the output port. IPv4 packets with the don't
+/// fragment flag set, packets which cannot be fragmented and packets whose fragments do not fit into the batch are
+/// dropped. `act` returns the number of packets after fragmentation.
+pub struct FragmentBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    parent: V,
+    mtu: usize,
+    /// identification of the next fragmented IPv6 packet
+    next_ipv6_id: u32,
+    /// packets to replace, in ascending order of their index
+    fragmented: Vec<(usize, Vec<Pdu<'static>>)>,
+    alloc: PduAllocFn,
+    applied: bool,
+}
+
+impl<V> FragmentBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    /// Fragment to the MTU of `port`, ports without a MTU use the Ethernet MTU.
+    pub fn new(parent: V, port: &PmdPort) -> FragmentBatch<V> {
+        let mtu = port.mtu().unwrap_or_else(|e| {
+            warn!("fragmentation: using an MTU of {}: {}", RTE_ETHER_MTU, e);
+            RTE_ETHER_MTU as u16
+        });
+        FragmentBatch::with_mtu(parent, mtu as usize)
+    }
+
+    /// Fragment to `mtu`, e.g. the MTU of the port reduced by the overhead of a tunnel.
+    pub fn with_mtu(parent: V, mtu: usize) -> FragmentBatch<V> {
+        let capacity = parent.capacity() as usize;
+        FragmentBatch {
+            parent,
+            mtu,
+            next_ipv6_id: 1,
+            fragmented: Vec::with_capacity(capacity),
+            alloc: Pdu::new_pdu,
+            applied: false,
+        }
+    }
+
+    /// Allocate the fragments by `alloc` instead of `Pdu::new_pdu`.
+    pub fn set_allocator(&mut self, alloc: PduAllocFn) {
+        self.alloc = alloc;
+    }
+}
+
+batch_no_new! {FragmentBatch}
+
+impl<V> BatchIterator for FragmentBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn start(&mut self) -> usize {
+        self.parent.start()
+    }
+
+    #[inline]
+    fn next_payload(&mut self, idx: usize) -> Option<Pdu> {
+        self.parent.next_payload(idx)
+    }
+}
+
+impl<V> Act for FragmentBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn act(&mut self) -> (u32, i32) {
+        let mut count = 0;
+        let mut q_len = 0;
+        if !self.applied {
+            q_len = self.parent.act().1;
+            {
+                let iter = PayloadEnumerator::new(&mut self.parent);
+                while let Some(ParsedDescriptor { index, pdu }) = iter.next(&mut self.parent) {
+                    // length of the first IP packet and whether it is IPv4
+                    let ip_len = {
+                        let headers = pdu.headers();
+                        (0..headers.count())
+                            .filter_map(|i| {
+                                let h = headers.get(i);
+                                h.as_ip().map(|ip| (ip.length() as usize, true)).or_else(|| {
+                                    h.as_ipv6()
+                                        .map(|ipv6| (ipv6.offset() + ipv6.payload_len() as usize, false))
+                                })
+                            })
+                            .next()
+                    };
+                    let result = match ip_len {
+                        Some((len, true)) if len > self.mtu => pdu.ipv4_fragments_with(self.mtu, self.alloc),
+                        Some((len, false)) if len > self.mtu => {
+                            let id = self.next_ipv6_id;
+                            self.next_ipv6_id = self.next_ipv6_id.wrapping_add(1);
+                            pdu.ipv6_fragments_with(self.mtu, id, self.alloc)
+                        }
+                        _ => continue,
+                    };
+                    match result {
+                        Ok(fragments) => self.fragmented.push((index, fragments)),
+                        Err(e) => {
+                            // replacing by no fragments drops the packet
+                            debug!("fragmentation: dropping packet: {}", e);
+                            self.fragmented.push((index, Vec::new()));
+                        }
+                    }
+                }
+            }
+            count = self.parent.get_packet_batch().replace_packets(&mut self.fragmented) as u32;
+            self.applied = true;
+        }
+        (count, q_len)
+    }
+
+    #[inline]
+    fn done(&mut self) {
+        self.applied = false;
+        self.parent.done();
+    }
+
+    #[inline]
+    fn send_q(&mut self, port: &mut dyn PacketTx) -> errors::Result<u32> {
+        self.parent.send_q(port)
+    }
+
+    #[inline]
+    fn capacity(&self) -> i32 {
+        self.parent.capacity()
+    }
+
+    #[inline]
+    fn drop_packets(&mut self, idxes: &[usize]) -> Option<usize> {
+        self.parent.drop_packets(idxes)
+    }
+
+    #[inline]
+    fn drop_packets_all(&mut self) -> Option<usize> {
+        self.parent.drop_packets_all()
+    }
+
+    #[inline]
+    fn clear_packets(&mut self) {
+        self.parent.clear_packets()
+    }
+
+    #[inline]
+    fn get_packet_batch(&mut self) -> &mut PacketBatch {
+        self.parent.get_packet_batch()
+    }
+}
+
+/// Reassembles IPv4 and IPv6 fragments. Fragments are removed from the batch, the reassembled packet takes the place
+/// of the fragment which completed it. Other packets pass unchanged.
+pub struct ReassembleBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    parent: V,
+    reassembler: Reassembler,
+    /// packets to replace, in ascending order of their index
+    reassembled: Vec<(usize, Vec<Pdu<'static>>)>,
+    alloc: PduAllocFn,
+    applied: bool,
+}
+
+impl<V> ReassembleBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    pub fn new(parent: V, config: ReassemblyConfig) -> ReassembleBatch<V> {
+        let capacity = parent.capacity() as usize;
+        ReassembleBatch {
+            parent,
+            reassembler: Reassembler::new(config),
+            reassembled: Vec::with_capacity(capacity),
+            alloc: Pdu::new_pdu,
+            applied: false,
+        }
+    }
+
+    /// Allocate the reassembled packets by `alloc` instead of `Pdu::new_pdu`.
+    pub fn set_allocator(&mut self, alloc: PduAllocFn) {
+        self.alloc = alloc;
+    }
+
+    pub fn reassembler(&self) -> &Reassembler {
+        &self.reassembler
+    }
+}
+
+batch_no_new! {ReassembleBatch}
+
+impl<V> BatchIterator for ReassembleBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn start(&mut self) -> usize {
+        self.parent.start()
+    }
+
+    #[inline]
+    fn next_payload(&mut self, idx: usize) -> Option<Pdu> {
+        self.parent.next_payload(idx)
+    }
+}
+
+impl<V> Act for ReassembleBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn act(&mut self) -> (u32, i32) {
+        let mut count = 0;
+        let mut q_len = 0;
+        if !self.applied {
+            q_len = self.parent.act().1;
+            let now = Instant::now();
+            self.reassembler.expire(now);
+            {
+                let iter = PayloadEnumerator::new(&mut self.parent);
+                while let Some(ParsedDescriptor { index, pdu }) = iter.next(&mut self.parent) {
+                    let info = match pdu.fragment_info() {
+                        Some(info) => info,
+                        None => continue,
+                    };
+                    match self.reassembler.add_pdu_with(&pdu, &info, now, self.alloc) {
+                        Ok(Some(packet)) => self.reassembled.push((index, vec![packet])),
+                        Ok(None) => self.reassembled.push((index, Vec::new())),
+                        Err(e) => {
+                            debug!("reassembly: dropping datagram: {}", e);
+                            self.reassembled.push((index, Vec::new()));
+                        }
+                    }
+                }
+            }
+            count = self.parent.get_packet_batch().replace_packets(&mut self.reassembled) as u32;
+            self.applied = true;
+        }
+        (count, q_len)
+    }
+
+    #[inline]
+    fn done(&mut self) {
+        self.applied = false;
+        self.parent.done();
+    }
+
+    #[inline]
+    fn send_q(&mut self, port: &mut dyn PacketTx) -> errors::Result<u32> {
+        self.parent.send_q(port)
+    }
+
+    #[inline]
+    fn capacity(&self) -> i32 {
+        self.parent.capacity()
+    }
+
+    #[inline]
+    fn drop_packets(&mut self, idxes: &[usize]) -> Option<usize> {
+        self.parent.drop_packets(idxes)
+    }
+
+    #[inline]
+    fn drop_packets_all(&mut self) -> Option<usize> {
+        self.parent.drop_packets_all()
+    }
+
+    #[inline]
+    fn clear_packets(&mut self) {
+        self.parent.clear_packets()
+    }
+
+    #[inline]
+    fn get_packet_batch(&mut self) -> &mut PacketBatch {
+        self.parent.get_packet_batch()
+    }
+}
@@ -42,9 +42,8 @@ where
             while let Some(ParsedDescriptor { mut pdu, .. }) = iter.next(&mut self.parent) {
                 //let group = (self.group_fn)(&mut packet);
                 let group = (self.group_fn)(&mut pdu);
-                if !self.producers[group].enqueue_one(pdu) {
-                    warn!("queue overflow in GroupByProducer for group {}", group);
-                }
+                // a full queue drops packets according to its drop policy
+                self.producers[group].enqueue_or_drop(pdu);
                 count += 1;
             }
         }
@@ -66,11 +65,24 @@ where
         sched: &mut S,
         name: String,
         uuid: Uuid, // task id
+    ) -> GroupBy<V> {
+        GroupBy::new_with_queue_config(parent, groups, group_fn, sched, name, uuid, &QueueConfig::default())
+    }
+
+    /// Like `new`, with queues of the given size and drop policy between the producer and the groups.
+    pub fn new_with_queue_config<S: Scheduler + Sized>(
+        parent: V,
+        groups: usize,
+        group_fn: GroupFnPdu,
+        sched: &mut S,
+        name: String,
+        uuid: Uuid, // task id
+        queue_config: &QueueConfig,
     ) -> GroupBy<V> {
         let mut producers = Vec::with_capacity(groups);
         let mut consumers = HashMap::with_capacity(groups);
         for i in 0..groups {
-            let (prod, consumer) = new_mpsc_queue_pair();
+            let (prod, consumer) = new_mpsc_queue_pair_with_config(queue_config);
             producers.push(prod);
             consumers.insert(i, consumer);
         }
@@ -0,0 +1,129 @@
+use super::act::Act;
+use super::iterator::*;
+use super::packet_batch::PacketBatch;
+use super::Batch;
+use common::*;
+use interface::PacketTx;
+use interface::Pdu;
+
+/// Generic segmentation offload in software: splits TCP/IPv4 packets whose TCP payload exceeds `mss` into MSS-sized
+/// packets, as a fallback for devices without TSO. Packets marked for TSO are segmented as well, other packets pass
+/// unchanged. If segmentation fails (e.g., no mbufs left) or the segments do not fit into the batch, the packet is
+/// dropped. `act` returns the number of packets after segmentation.
+pub struct GsoBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    parent: V,
+    mss: usize,
+    /// packets to replace, in ascending order of their index
+    segmented: Vec<(usize, Vec<Pdu<'static>>)>,
+    applied: bool,
+}
+
+impl<V> GsoBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    pub fn new(parent: V, mss: usize) -> GsoBatch<V> {
+        assert!(mss > 0);
+        let capacity = parent.capacity() as usize;
+        GsoBatch {
+            parent,
+            mss,
+            segmented: Vec::with_capacity(capacity),
+            applied: false,
+        }
+    }
+}
+
+batch_no_new! {GsoBatch}
+
+impl<V> BatchIterator for GsoBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn start(&mut self) -> usize {
+        self.parent.start()
+    }
+
+    #[inline]
+    fn next_payload(&mut self, idx: usize) -> Option<Pdu> {
+        self.parent.next_payload(idx)
+    }
+}
+
+impl<V> Act for GsoBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn act(&mut self) -> (u32, i32) {
+        let mut count = 0;
+        let mut q_len = 0;
+        if !self.applied {
+            q_len = self.parent.act().1;
+            {
+                let iter = PayloadEnumerator::new(&mut self.parent);
+                while let Some(ParsedDescriptor { index, pdu }) = iter.next(&mut self.parent) {
+                    let hc = pdu.headers().count();
+                    let tcp_ix = match (0..hc).find(|&i| pdu.headers().get(i).as_tcp().is_some()) {
+                        Some(i) => i,
+                        None => continue,
+                    };
+                    if pdu.total_payload_size(tcp_ix) <= self.mss {
+                        continue;
+                    }
+                    match pdu.tcp_segments(self.mss) {
+                        Ok(segments) => self.segmented.push((index, segments)),
+                        Err(e) => {
+                            // replacing by no segments drops the packet
+                            debug!("gso: dropping packet: {}", e);
+                            self.segmented.push((index, Vec::new()));
+                        }
+                    }
+                }
+            }
+            count = self.parent.get_packet_batch().replace_packets(&mut self.segmented) as u32;
+            self.applied = true;
+        }
+        (count, q_len)
+    }
+
+    #[inline]
+    fn done(&mut self) {
+        self.applied = false;
+        self.parent.done();
+    }
+
+    #[inline]
+    fn send_q(&mut self, port: &mut dyn PacketTx) -> errors::Result<u32> {
+        self.parent.send_q(port)
+    }
+
+    #[inline]
+    fn capacity(&self) -> i32 {
+        self.parent.capacity()
+    }
+
+    #[inline]
+    fn drop_packets(&mut self, idxes: &[usize]) -> Option<usize> {
+        self.parent.drop_packets(idxes)
+    }
+
+    #[inline]
+    fn drop_packets_all(&mut self) -> Option<usize> {
+        self.parent.drop_packets_all()
+    }
+
+    #[inline]
+    fn clear_packets(&mut self) {
+        self.parent.clear_packets()
+    }
+
+    #[inline]
+    fn get_packet_batch(&mut self) -> &mut PacketBatch {
+        self.parent.get_packet_batch()
+    }
+}
@@ -0,0 +1,107 @@
+use super::act::Act;
+use super::iterator::*;
+use super::packet_batch::PacketBatch;
+use super::Batch;
+use common::*;
+use headers::HeaderKind;
+use interface::{PacketTx, Pdu};
+use state::HeavyHitterExporter;
+use utils::rdtsc_unsafe;
+
+/// Counts the TCP and UDP packets of IPv4 flows in the `HeavyHitterExporter` of the core, which reports the top flows
+/// and sampled packets to the control plane. Packets pass unchanged.
+pub struct HeavyHitterBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    parent: V,
+    exporter: HeavyHitterExporter,
+}
+
+impl<V> HeavyHitterBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    pub fn new(parent: V, exporter: HeavyHitterExporter) -> HeavyHitterBatch<V> {
+        HeavyHitterBatch { parent, exporter }
+    }
+}
+
+batch_no_new! {HeavyHitterBatch}
+
+impl<V> Act for HeavyHitterBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn act(&mut self) -> (u32, i32) {
+        let mut count = 0;
+        let pre = self.parent.act();
+        let now = rdtsc_unsafe();
+        {
+            let iter = PayloadEnumerator::new(&mut self.parent);
+            while let Some(ParsedDescriptor { pdu, .. }) = iter.next(&mut self.parent) {
+                let headers = pdu.headers();
+                if let Some(flow) = (0..headers.count())
+                    .find(|&i| headers.get(i).kind() == HeaderKind::Ip)
+                    .and_then(|i| headers.ip(i).flow())
+                {
+                    self.exporter.record(flow, pdu.pkt_len() as u64, now);
+                }
+                count += 1;
+            }
+        }
+        self.exporter.poll(now);
+        (count, pre.1)
+    }
+
+    #[inline]
+    fn done(&mut self) {
+        self.parent.done();
+    }
+
+    #[inline]
+    fn send_q(&mut self, port: &mut dyn PacketTx) -> errors::Result<u32> {
+        self.parent.send_q(port)
+    }
+
+    #[inline]
+    fn capacity(&self) -> i32 {
+        self.parent.capacity()
+    }
+
+    #[inline]
+    fn drop_packets(&mut self, idxes: &[usize]) -> Option<usize> {
+        self.parent.drop_packets(idxes)
+    }
+
+    #[inline]
+    fn drop_packets_all(&mut self) -> Option<usize> {
+        self.parent.drop_packets_all()
+    }
+
+    #[inline]
+    fn clear_packets(&mut self) {
+        self.parent.clear_packets()
+    }
+
+    #[inline]
+    fn get_packet_batch(&mut self) -> &mut PacketBatch {
+        self.parent.get_packet_batch()
+    }
+}
+
+impl<V> BatchIterator for HeavyHitterBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn start(&mut self) -> usize {
+        self.parent.start()
+    }
+
+    #[inline]
+    fn next_payload(&mut self, idx: usize) -> Option<Pdu> {
+        self.parent.next_payload(idx)
+    }
+}
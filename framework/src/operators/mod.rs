@@ -2,8 +2,11 @@ pub use self::act::Act;
 pub use self::composition_batch::CompositionBatch;
 pub use self::drop::DropBatch;
 pub use self::filter_batch::FilterBatch;
+pub use self::fragment_batch::{FragmentBatch, ReassembleBatch};
 use self::filter_batch::FilterFn;
 pub use self::group_by::*;
+pub use self::gso_batch::GsoBatch;
+pub use self::heavy_hitter_batch::HeavyHitterBatch;
 pub use self::iterator::BatchIterator;
 pub use self::map_batch::MapBatch;
 use self::map_batch::MapFn;
@@ -13,11 +16,18 @@ pub use self::merge_batch_auto::MergeBatchAuto;
 pub use self::packet_batch::PacketBatch;
 pub use self::receive_batch::ReceiveBatch;
 pub use self::send_batch::SendBatch;
+pub use self::splice_batch::SpliceBatch;
 pub use self::transform_batch::TransformBatch;
+pub use self::tunnel_batch::{DecapBatch, EncapBatch};
 use self::transform_batch::TransformFn;
 
 use interface::*;
+use queues::QueueConfig;
 use scheduler::Scheduler;
+use state::{HeavyHitterExporter, ReassemblyConfig};
+use std::cell::RefCell;
+use std::rc::Rc;
+use tcp::SpliceTable;
 use uuid::Uuid;
 
 #[macro_use]
@@ -26,7 +36,10 @@ mod act;
 mod composition_batch;
 mod drop;
 mod filter_batch;
+mod fragment_batch;
 mod group_by;
+mod gso_batch;
+mod heavy_hitter_batch;
 mod iterator;
 mod map_batch;
 mod merge_batch;
@@ -34,7 +47,9 @@ mod merge_batch_auto;
 mod packet_batch;
 mod receive_batch;
 mod send_batch;
+mod splice_batch;
 mod transform_batch;
+mod tunnel_batch;
 
 /// Merge a vector of batches into one batch. Currently this just round-robins between merged batches, but in the future
 /// the precise batch being processed will be determined by the scheduling policy used.
@@ -103,6 +118,71 @@ pub trait Batch: BatchIterator + Act {
         FilterBatch::<Self>::new(self, filter_f)
     }
 
+    /// Split TCP/IPv4 packets with more than `mss` bytes of payload into several packets, for ports without TSO.
+    fn gso(self, mss: usize) -> GsoBatch<Self>
+    where
+        Self: Sized,
+    {
+        GsoBatch::<Self>::new(self, mss)
+    }
+
+    /// Fragment IP packets which exceed the MTU of the output `port`.
+    fn fragment(self, port: &PmdPort) -> FragmentBatch<Self>
+    where
+        Self: Sized,
+    {
+        FragmentBatch::<Self>::new(self, port)
+    }
+
+    /// Fragment IP packets which exceed `mtu` bytes.
+    fn fragment_to_mtu(self, mtu: usize) -> FragmentBatch<Self>
+    where
+        Self: Sized,
+    {
+        FragmentBatch::<Self>::with_mtu(self, mtu)
+    }
+
+    /// Reassemble IPv4 and IPv6 fragments within the limits given by `config`.
+    fn reassemble(self, config: ReassemblyConfig) -> ReassembleBatch<Self>
+    where
+        Self: Sized,
+    {
+        ReassembleBatch::<Self>::new(self, config)
+    }
+
+    /// Encapsulate all packets into the tunnel described by `spec`.
+    fn encap(self, spec: TunnelSpec) -> EncapBatch<Self>
+    where
+        Self: Sized,
+    {
+        EncapBatch::<Self>::new(self, spec)
+    }
+
+    /// Remove the outer headers of tunneled packets.
+    fn decap(self) -> DecapBatch<Self>
+    where
+        Self: Sized,
+    {
+        DecapBatch::<Self>::new(self)
+    }
+
+    /// Rewrite the packets of TCP connections spliced in `table`, e.g. by a load balancer which handed a client
+    /// connection off to a backend.
+    fn splice(self, table: Rc<RefCell<SpliceTable>>) -> SpliceBatch<Self>
+    where
+        Self: Sized,
+    {
+        SpliceBatch::<Self>::new(self, table)
+    }
+
+    /// Count the flows of the packets in `exporter`, which reports the top flows and sampled packets of the core.
+    fn heavy_hitters(self, exporter: HeavyHitterExporter) -> HeavyHitterBatch<Self>
+    where
+        Self: Sized,
+    {
+        HeavyHitterBatch::<Self>::new(self, exporter)
+    }
+
     fn drop(self) -> DropBatch<Self>
     where
         Self: Sized,
@@ -124,6 +204,22 @@ pub trait Batch: BatchIterator + Act {
         GroupBy::<Self>::new(self, groups, group_f, sched, name, uuid)
     }
 
+    /// Like `group_by`, with queues of the given size, watermarks and drop policy between the groups.
+    fn group_by_with_queue_config<S: Scheduler + Sized>(
+        self,
+        groups: usize,
+        group_f: GroupFnPdu,
+        sched: &mut S,
+        name: String,
+        uuid: Uuid,
+        queue_config: &QueueConfig,
+    ) -> GroupBy<Self>
+    where
+        Self: Sized,
+    {
+        GroupBy::<Self>::new_with_queue_config(self, groups, group_f, sched, name, uuid, queue_config)
+    }
+
     fn compose(self) -> CompositionBatch
     where
         Self: Sized + 'static,
@@ -14,6 +14,8 @@ use std::result;
 pub struct PacketBatch {
     array: Vec<*mut MBuf>,
     scratch: Vec<*mut MBuf>,
+    /// number of packets received or allocated at once, the array never grows beyond
+    capacity: usize,
     /// if false the mbuf array will be de-allocated, each time new packets are received
     b_keep_mbuf: bool,
 }
@@ -27,6 +29,7 @@ impl PacketBatch {
         PacketBatch {
             array: Vec::<*mut MBuf>::with_capacity(cnt as usize),
             scratch: Vec::<*mut MBuf>::with_capacity(cnt as usize),
+            capacity: cnt as usize,
             b_keep_mbuf,
         }
     }
@@ -35,7 +38,7 @@ impl PacketBatch {
     /// a packet. We always allocate mbuf's of the same size.
     #[inline]
     pub fn allocate_batch_with_size(&mut self) -> errors::Result<&mut Self> {
-        let capacity = self.array.capacity() as i32;
+        let capacity = self.capacity as i32;
         self.alloc_packet_batch(capacity).and_then(|_| Ok(self))
     }
 
@@ -64,6 +67,12 @@ impl PacketBatch {
         self.array.len()
     }
 
+    /// Number of packets which can be added before the batch reaches its capacity.
+    #[inline]
+    pub fn free_slots(&self) -> usize {
+        self.capacity.saturating_sub(self.array.len())
+    }
+
     /// Receive packets from a PMD port queue.
     #[inline]
     pub fn recv<Rx: PacketRx>(&mut self, port: &Rx) -> errors::Result<(u32, i32)> {
@@ -78,7 +87,7 @@ impl PacketBatch {
     // Assumes we have already deallocated batch.
     #[inline]
     unsafe fn recv_internal<Rx: PacketRx>(&mut self, port: &Rx) -> errors::Result<(u32, i32)> {
-        let capacity = self.array.capacity();
+        let capacity = self.capacity;
         self.add_to_batch(capacity);
         match port.recv(self.packet_ptr()) {
             e @ Err(_) => e,
@@ -147,6 +156,44 @@ impl PacketBatch {
         }
     }
 
+    /// Replace the packet at `idx` by the packets `mbufs`, keeping the order of the batch. The replaced mbuf is freed,
+    /// the batch takes ownership of `mbufs`, an empty slice drops the packet. Used by operators producing several
+    /// packets out of one, e.g. segmentation. The additional packets must fit into `free_slots`.
+    pub fn replace_packet(&mut self, idx: usize, mbufs: &[*mut MBuf]) {
+        assert!(idx < self.array.len());
+        assert!(
+            mbufs.len() <= self.free_slots() + 1,
+            "replacement exceeds the batch capacity"
+        );
+        unsafe { mbuf_free(self.array[idx]) };
+        self.array.splice(idx..idx + 1, mbufs.iter().cloned());
+    }
+
+    /// Replace each packet at the given index by the given packets, `replacements` must be in ascending order of the
+    /// indices and is drained. Replacements which do not fit into the batch are freed and their packet is dropped,
+    /// earlier packets take precedence. Returns the number of packets in the batch afterwards.
+    pub fn replace_packets(&mut self, replacements: &mut Vec<(usize, Vec<Pdu<'static>>)>) -> usize {
+        let mut free_slots = self.free_slots();
+        for &mut (_, ref mut packets) in replacements.iter_mut() {
+            if packets.len() > free_slots + 1 {
+                debug!(
+                    "dropping packet, {} replacements exceed the batch capacity",
+                    packets.len()
+                );
+                for packet in packets.drain(..) {
+                    packet.free();
+                }
+            }
+            free_slots = free_slots + 1 - packets.len();
+        }
+        // replace from the end, so that the indices of the remaining packets stay valid
+        for (index, packets) in replacements.drain(..).rev() {
+            let mbufs: Vec<_> = packets.into_iter().map(|p| unsafe { p.get_mbuf() }).collect();
+            self.replace_packet(index, &mbufs);
+        }
+        self.array.len()
+    }
+
     // Some private utility functions.
     #[inline]
     unsafe fn packet_ptr(&mut self) -> &mut [*mut MBuf] {
@@ -176,7 +223,7 @@ impl PacketBatch {
     #[inline]
     fn alloc_packet_batch(&mut self, cnt: i32) -> errors::Result<()> {
         unsafe {
-            if self.array.capacity() < (cnt as usize) {
+            if self.capacity < (cnt as usize) {
                 Err(ErrorKind::FailedAllocation.into())
             } else {
                 let ret = mbuf_alloc_bulk(self.array.as_mut_ptr(), cnt as u32);
@@ -259,7 +306,7 @@ impl Act for PacketBatch {
 
     #[inline]
     fn capacity(&self) -> i32 {
-        self.array.capacity() as i32
+        self.capacity as i32
     }
 
     #[inline]
@@ -0,0 +1,111 @@
+use super::act::Act;
+use super::iterator::*;
+use super::packet_batch::PacketBatch;
+use super::Batch;
+use common::*;
+use interface::{PacketTx, Pdu};
+use std::cell::RefCell;
+use std::rc::Rc;
+use tcp::SpliceTable;
+
+/// Rewrites the packets of TCP connections spliced in a `SpliceTable`, other packets pass unchanged. The table is
+/// shared with the NF, which splices connections once they were classified.
+pub struct SpliceBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    parent: V,
+    table: Rc<RefCell<SpliceTable>>,
+    applied: bool,
+}
+
+impl<V> SpliceBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    pub fn new(parent: V, table: Rc<RefCell<SpliceTable>>) -> SpliceBatch<V> {
+        SpliceBatch {
+            parent,
+            table,
+            applied: false,
+        }
+    }
+}
+
+batch_no_new! {SpliceBatch}
+
+impl<V> Act for SpliceBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn act(&mut self) -> (u32, i32) {
+        let mut count = 0;
+        let mut q_len = 0;
+        // the deltas must be applied once per batch
+        if !self.applied {
+            q_len = self.parent.act().1;
+            {
+                let mut table = self.table.borrow_mut();
+                let iter = PayloadEnumerator::new(&mut self.parent);
+                while let Some(ParsedDescriptor { mut pdu, .. }) = iter.next(&mut self.parent) {
+                    table.apply(&mut pdu);
+                    count += 1;
+                }
+            }
+            self.applied = true;
+        }
+        (count, q_len)
+    }
+
+    #[inline]
+    fn done(&mut self) {
+        self.applied = false;
+        self.parent.done();
+    }
+
+    #[inline]
+    fn send_q(&mut self, port: &mut dyn PacketTx) -> errors::Result<u32> {
+        self.parent.send_q(port)
+    }
+
+    #[inline]
+    fn capacity(&self) -> i32 {
+        self.parent.capacity()
+    }
+
+    #[inline]
+    fn drop_packets(&mut self, idxes: &[usize]) -> Option<usize> {
+        self.parent.drop_packets(idxes)
+    }
+
+    #[inline]
+    fn drop_packets_all(&mut self) -> Option<usize> {
+        self.parent.drop_packets_all()
+    }
+
+    #[inline]
+    fn clear_packets(&mut self) {
+        self.parent.clear_packets()
+    }
+
+    #[inline]
+    fn get_packet_batch(&mut self) -> &mut PacketBatch {
+        self.parent.get_packet_batch()
+    }
+}
+
+impl<V> BatchIterator for SpliceBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn start(&mut self) -> usize {
+        self.parent.start()
+    }
+
+    #[inline]
+    fn next_payload(&mut self, idx: usize) -> Option<Pdu> {
+        self.parent.next_payload(idx)
+    }
+}
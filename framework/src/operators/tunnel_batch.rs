@@ -0,0 +1,204 @@
+use super::act::Act;
+use super::iterator::*;
+use super::packet_batch::PacketBatch;
+use super::Batch;
+use common::*;
+use interface::{PacketTx, Pdu, TunnelSpec};
+
+/// Encapsulates all packets of the batch into the tunnel given by a `TunnelSpec`. Packets which cannot be
+/// encapsulated, e.g. because the mbuf headroom is exhausted, are dropped.
+pub struct EncapBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    parent: V,
+    spec: TunnelSpec,
+    remove: Vec<usize>,
+}
+
+impl<V> EncapBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    pub fn new(parent: V, spec: TunnelSpec) -> EncapBatch<V> {
+        let capacity = parent.capacity() as usize;
+        EncapBatch {
+            parent,
+            spec,
+            remove: Vec::with_capacity(capacity),
+        }
+    }
+}
+
+batch_no_new! {EncapBatch}
+
+impl<V> Act for EncapBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn act(&mut self) -> (u32, i32) {
+        let mut count = 0;
+        let pre = self.parent.act();
+        {
+            let iter = PayloadEnumerator::new(&mut self.parent);
+            while let Some(ParsedDescriptor { index, mut pdu }) = iter.next(&mut self.parent) {
+                if let Err(e) = pdu.encap_tunnel(&self.spec) {
+                    debug!("encap: dropping packet: {}", e);
+                    self.remove.push(index);
+                }
+                count += 1;
+            }
+        }
+        if !self.remove.is_empty() {
+            self.parent
+                .drop_packets(&self.remove[..])
+                .expect("Encapsulation dropped packets incorrectly");
+        }
+        self.remove.clear();
+        (count, pre.1)
+    }
+
+    #[inline]
+    fn done(&mut self) {
+        self.parent.done();
+    }
+
+    #[inline]
+    fn send_q(&mut self, port: &mut dyn PacketTx) -> errors::Result<u32> {
+        self.parent.send_q(port)
+    }
+
+    #[inline]
+    fn capacity(&self) -> i32 {
+        self.parent.capacity()
+    }
+
+    #[inline]
+    fn drop_packets(&mut self, idxes: &[usize]) -> Option<usize> {
+        self.parent.drop_packets(idxes)
+    }
+
+    #[inline]
+    fn drop_packets_all(&mut self) -> Option<usize> {
+        self.parent.drop_packets_all()
+    }
+
+    #[inline]
+    fn clear_packets(&mut self) {
+        self.parent.clear_packets()
+    }
+
+    #[inline]
+    fn get_packet_batch(&mut self) -> &mut PacketBatch {
+        self.parent.get_packet_batch()
+    }
+}
+
+impl<V> BatchIterator for EncapBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn start(&mut self) -> usize {
+        self.parent.start()
+    }
+
+    #[inline]
+    fn next_payload(&mut self, idx: usize) -> Option<Pdu> {
+        self.parent.next_payload(idx)
+    }
+}
+
+/// Removes the outer headers of tunneled (VXLAN, GRE/NVGRE, Geneve) packets. Packets which are not tunneled pass
+/// unchanged, use `filter` to drop them.
+pub struct DecapBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    parent: V,
+}
+
+impl<V> DecapBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    pub fn new(parent: V) -> DecapBatch<V> {
+        DecapBatch { parent }
+    }
+}
+
+batch_no_new! {DecapBatch}
+
+impl<V> Act for DecapBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn act(&mut self) -> (u32, i32) {
+        let mut count = 0;
+        let pre = self.parent.act();
+        {
+            let iter = PayloadEnumerator::new(&mut self.parent);
+            while let Some(ParsedDescriptor { mut pdu, .. }) = iter.next(&mut self.parent) {
+                if pdu.headers().inner_start().is_some() {
+                    if let Err(e) = pdu.decap_tunnel() {
+                        debug!("decap failed: {}", e);
+                    }
+                }
+                count += 1;
+            }
+        }
+        (count, pre.1)
+    }
+
+    #[inline]
+    fn done(&mut self) {
+        self.parent.done();
+    }
+
+    #[inline]
+    fn send_q(&mut self, port: &mut dyn PacketTx) -> errors::Result<u32> {
+        self.parent.send_q(port)
+    }
+
+    #[inline]
+    fn capacity(&self) -> i32 {
+        self.parent.capacity()
+    }
+
+    #[inline]
+    fn drop_packets(&mut self, idxes: &[usize]) -> Option<usize> {
+        self.parent.drop_packets(idxes)
+    }
+
+    #[inline]
+    fn drop_packets_all(&mut self) -> Option<usize> {
+        self.parent.drop_packets_all()
+    }
+
+    #[inline]
+    fn clear_packets(&mut self) {
+        self.parent.clear_packets()
+    }
+
+    #[inline]
+    fn get_packet_batch(&mut self) -> &mut PacketBatch {
+        self.parent.get_packet_batch()
+    }
+}
+
+impl<V> BatchIterator for DecapBatch<V>
+where
+    V: Batch + BatchIterator + Act,
+{
+    #[inline]
+    fn start(&mut self) -> usize {
+        self.parent.start()
+    }
+
+    #[inline]
+    fn next_payload(&mut self, idx: usize) -> Option<Pdu> {
+        self.parent.next_payload(idx)
+    }
+}
@@ -0,0 +1,344 @@
+use super::{DropPolicy, QueueConfig};
+use native::zcsi::{mbuf_free, MBuf};
+use std::cmp::min;
+use std::default::Default;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use utils::{pause, round_to_power_of_2};
+
+/// Number of packets removed at once when dropping the head of a queue.
+const HEAD_DROP_BATCH: usize = 32;
+
+#[derive(Default)]
+struct QueueMetadata {
+    pub head: AtomicUsize,
+    pub tail: AtomicUsize,
+}
+
+/// A bounded ring of mbufs, the common part of the mbuf queues. This does not use a linked list (to avoid
+/// allocation). Producers and consumers either have exclusive access to their side of the ring (`enqueue_sp`,
+/// `dequeue_sc`) or reserve slots with a CAS (`enqueue_mp`, `dequeue_mc`); the queues pick the cheapest variant which
+/// is safe for the handles they hand out.
+pub struct MbufRing {
+    slots: usize, // Must be a power of 2
+    mask: usize,  // slots - 1
+    producer: QueueMetadata,
+    consumer: QueueMetadata,
+    queue: Vec<AtomicPtr<MBuf>>,
+    high_watermark: usize,
+    low_watermark: usize,
+    drop_policy: DropPolicy,
+    /// The fill level reached the high watermark and did not drop to the low watermark since.
+    congested: AtomicBool,
+    dropped: AtomicUsize,
+}
+
+impl MbufRing {
+    pub fn new(size: usize) -> MbufRing {
+        MbufRing::new_with_config(&QueueConfig::new(size))
+    }
+
+    pub fn new_with_config(config: &QueueConfig) -> MbufRing {
+        let size = config.size.max(2);
+        let slots = if size & (size - 1) != 0 {
+            round_to_power_of_2(size)
+        } else {
+            size
+        };
+        MbufRing {
+            slots,
+            mask: slots - 1,
+            queue: (0..slots).map(|_| Default::default()).collect(),
+            producer: Default::default(),
+            consumer: Default::default(),
+            high_watermark: min(config.high_watermark, slots - 1),
+            low_watermark: min(config.low_watermark, config.high_watermark),
+            drop_policy: config.drop_policy,
+            congested: AtomicBool::new(false),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    fn enqueue_mbufs(&self, start: usize, enqueue: usize, mbufs: &[*mut MBuf]) {
+        let mask = self.mask;
+        let len = self.slots;
+        let mut mbuf_idx = 0;
+        let mut queue_idx = start & mask;
+        // TODO: Unroll?
+        if queue_idx + enqueue >= len {
+            while queue_idx < len {
+                self.queue[queue_idx].store(mbufs[mbuf_idx], Ordering::Release);
+                mbuf_idx += 1;
+                queue_idx += 1;
+            }
+            queue_idx = 0;
+        }
+        while mbuf_idx < enqueue {
+            self.queue[queue_idx].store(mbufs[mbuf_idx], Ordering::Release);
+            mbuf_idx += 1;
+            queue_idx += 1;
+        }
+    }
+
+    // In the mp only version lots of time was being consumed in CAS (Compare and Swap). We want to allow for the mp case, but there is no
+    // need to waste cycles.
+    #[inline]
+    pub fn enqueue_sp(&self, mbufs: &[*mut MBuf]) -> usize {
+        let len = mbufs.len();
+
+        let producer_head = self.producer.head.load(Ordering::Acquire);
+        let consumer_tail = self.consumer.tail.load(Ordering::Acquire);
+
+        let free = self.mask.wrapping_add(consumer_tail).wrapping_sub(producer_head);
+        let insert = min(free, len);
+
+        if insert > 0 {
+            let producer_next = producer_head.wrapping_add(insert);
+            // Reserve slots by incrementing head
+            self.producer.head.store(producer_next, Ordering::Release);
+            // Write to reserved slot.
+            self.enqueue_mbufs(producer_head, insert, &mbufs[..insert]);
+            // Commit write by changing tail.
+            // Once this has been achieved, update tail. Any conflicting updates will wait on the previous spin lock.
+            self.producer.tail.store(producer_next, Ordering::Release);
+            self.update_congestion();
+            insert
+        } else {
+            0
+        }
+    }
+
+    #[inline]
+    pub fn enqueue_mp(&self, mbufs: &[*mut MBuf]) -> usize {
+        let len = mbufs.len();
+        let mut insert;
+        let mut producer_head;
+        let mut consumer_tail;
+        // First try and reserve memory by incrementing producer head.
+        while {
+            producer_head = self.producer.head.load(Ordering::Acquire);
+            consumer_tail = self.consumer.tail.load(Ordering::Acquire);
+            let free = self.mask.wrapping_add(consumer_tail).wrapping_sub(producer_head);
+            insert = min(free, len);
+            if insert == 0 {
+                // Short circuit, no insertion
+                false // This is the same as break in this construct.
+            } else {
+                let producer_next = producer_head.wrapping_add(insert);
+                self.producer
+                    .head
+                    .compare_exchange(producer_head, producer_next, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_err()
+            }
+        } {}
+
+        if insert > 0 {
+            // If we successfully reserved memory, write to memory.
+            let end = producer_head.wrapping_add(insert);
+            self.enqueue_mbufs(producer_head, insert, &mbufs[..insert]);
+            // Commit write by changing tail.
+            // Before committing we wait for any preceding writes to finish (this is important since we assume buffer is
+            // always available upto commit point.
+            while {
+                let producer_tail = self.producer.tail.load(Ordering::Acquire);
+                producer_tail != producer_head
+            } {
+                pause(); // Pausing is a nice thing to do during spin locks
+            }
+            // Once this has been achieved, update tail. Any conflicting updates will wait on the previous spin lock.
+            self.producer.tail.store(end, Ordering::Release);
+            self.update_congestion();
+            insert
+        } else {
+            0
+        }
+    }
+
+    /// Enqueue `mbufs` and apply the drop policy to those which do not fit. Returns the number of enqueued mbufs, the
+    /// others are freed.
+    pub fn enqueue_or_drop(&self, mbufs: &[*mut MBuf], single_producer: bool) -> usize {
+        let enqueue = |mbufs: &[*mut MBuf]| {
+            if single_producer {
+                self.enqueue_sp(mbufs)
+            } else {
+                self.enqueue_mp(mbufs)
+            }
+        };
+        let mbufs = if self.drop_policy == DropPolicy::HeadDrop {
+            // only the newest mbufs fit
+            let skip = mbufs.len().saturating_sub(self.mask);
+            self.drop_mbufs(&mbufs[..skip]);
+            &mbufs[skip..]
+        } else {
+            mbufs
+        };
+        let mut enqueued = enqueue(mbufs);
+        if self.drop_policy == DropPolicy::HeadDrop {
+            let mut head = [ptr::null_mut(); HEAD_DROP_BATCH];
+            while enqueued < mbufs.len() {
+                let make_room = min(mbufs.len() - enqueued, HEAD_DROP_BATCH);
+                let removed = self.dequeue_mc(&mut head[..make_room]);
+                if removed == 0 {
+                    break;
+                }
+                self.drop_mbufs(&head[..removed]);
+                enqueued += enqueue(&mbufs[enqueued..]);
+            }
+        }
+        self.drop_mbufs(&mbufs[enqueued..]);
+        enqueued
+    }
+
+    fn drop_mbufs(&self, mbufs: &[*mut MBuf]) {
+        if mbufs.is_empty() {
+            return;
+        }
+        for &mbuf in mbufs {
+            unsafe { mbuf_free(mbuf) };
+        }
+        self.dropped.fetch_add(mbufs.len(), Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn dequeue_mbufs(&self, start: usize, dequeue: usize, mbufs: &mut [*mut MBuf]) {
+        let mask = self.mask;
+        let len = self.slots;
+        // TODO: Unroll?
+        let mut mbuf_idx = 0;
+        let mut queue_idx = start & mask;
+        if queue_idx + dequeue >= len {
+            while queue_idx < len {
+                mbufs[mbuf_idx] = self.queue[queue_idx].load(Ordering::Acquire);
+                mbuf_idx += 1;
+                queue_idx += 1;
+            }
+            queue_idx = 0;
+        }
+        while mbuf_idx < dequeue {
+            mbufs[mbuf_idx] = self.queue[queue_idx].load(Ordering::Acquire);
+            mbuf_idx += 1;
+            queue_idx += 1;
+        }
+    }
+
+    /// Dequeue with a single consumer, unless producers drop the head of the queue.
+    #[inline]
+    pub fn dequeue(&self, mbufs: &mut [*mut MBuf], single_consumer: bool) -> usize {
+        if single_consumer && self.drop_policy == DropPolicy::TailDrop {
+            self.dequeue_sc(mbufs)
+        } else {
+            self.dequeue_mc(mbufs)
+        }
+    }
+
+    #[inline]
+    fn dequeue_sc(&self, mbufs: &mut [*mut MBuf]) -> usize {
+        let consumer_head = self.consumer.head.load(Ordering::Acquire);
+        let producer_tail = self.producer.tail.load(Ordering::Acquire);
+        let available_entries = producer_tail.wrapping_sub(consumer_head);
+        let dequeue = min(mbufs.len(), available_entries);
+        if dequeue > 0 {
+            let consumer_next = consumer_head.wrapping_add(dequeue);
+            // Reserve what we are going to dequeue.
+            self.consumer.head.store(consumer_next, Ordering::Release);
+            self.dequeue_mbufs(consumer_head, dequeue, mbufs);
+            // Commit that we have dequeued.
+            self.consumer.tail.store(consumer_next, Ordering::Release);
+            self.update_congestion();
+        }
+        dequeue
+    }
+
+    #[inline]
+    fn dequeue_mc(&self, mbufs: &mut [*mut MBuf]) -> usize {
+        let mut dequeue;
+        let mut consumer_head;
+        // Reserve entries by incrementing consumer head, like producers do in `enqueue_mp`.
+        while {
+            consumer_head = self.consumer.head.load(Ordering::Acquire);
+            let producer_tail = self.producer.tail.load(Ordering::Acquire);
+            dequeue = min(mbufs.len(), producer_tail.wrapping_sub(consumer_head));
+            if dequeue == 0 {
+                false
+            } else {
+                let consumer_next = consumer_head.wrapping_add(dequeue);
+                self.consumer
+                    .head
+                    .compare_exchange(consumer_head, consumer_next, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_err()
+            }
+        } {}
+
+        if dequeue > 0 {
+            self.dequeue_mbufs(consumer_head, dequeue, mbufs);
+            // Wait for preceding consumers, so that slots are only released in order.
+            while self.consumer.tail.load(Ordering::Acquire) != consumer_head {
+                pause();
+            }
+            self.consumer
+                .tail
+                .store(consumer_head.wrapping_add(dequeue), Ordering::Release);
+            self.update_congestion();
+        }
+        dequeue
+    }
+
+    /// Set or clear the congestion flag after the fill level changed. Producers and consumers race on the flag: a
+    /// thread which changed it checks the fill level again. The flag may still lag behind a concurrent update of the
+    /// other side, `is_congested` checks the fill level again while the flag is set.
+    #[inline]
+    fn update_congestion(&self) {
+        if self.high_watermark == 0 {
+            return;
+        }
+        loop {
+            let congested = self.congested.load(Ordering::Acquire);
+            let used = self.used_slots();
+            let next = if congested {
+                used > self.low_watermark
+            } else {
+                used >= self.high_watermark
+            };
+            if next == congested {
+                return;
+            }
+            // check again whether the flag matches the fill level after changing it
+            let _ = self
+                .congested
+                .compare_exchange(congested, next, Ordering::AcqRel, Ordering::Acquire);
+        }
+    }
+
+    #[inline]
+    pub fn free_slots(&self) -> usize {
+        self.mask - self.used_slots()
+    }
+
+    /// Slots filled or reserved by producers. The tail of the consumers is loaded first, so it is never ahead of the
+    /// head of the producers, and the difference is clamped as producers may fill the slots freed meanwhile.
+    #[inline]
+    pub fn used_slots(&self) -> usize {
+        let consumer_tail = self.consumer.tail.load(Ordering::Acquire);
+        let producer_head = self.producer.head.load(Ordering::Acquire);
+        min(producer_head.wrapping_sub(consumer_tail), self.mask)
+    }
+
+    /// True if the fill level reached the high watermark, producers should back off until this is false again.
+    #[inline]
+    pub fn is_congested(&self) -> bool {
+        if self.congested.load(Ordering::Acquire) {
+            // a consumer may have drained the queue without seeing the flag
+            self.update_congestion();
+            self.congested.load(Ordering::Acquire)
+        } else {
+            false
+        }
+    }
+
+    /// Number of packets dropped by the drop policy.
+    #[inline]
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
@@ -1,3 +1,10 @@
+pub use self::mpmc_mbuf_queue::*;
 pub use self::mpsc_mbuf_queue::*;
+pub use self::queue_config::*;
+pub use self::spsc_mbuf_queue::*;
 
+mod mbuf_ring;
+mod mpmc_mbuf_queue;
 mod mpsc_mbuf_queue;
+mod queue_config;
+mod spsc_mbuf_queue;
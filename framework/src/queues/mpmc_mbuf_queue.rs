@@ -0,0 +1,96 @@
+use super::mbuf_ring::MbufRing;
+use super::{QueueConfig, DEFAULT_QUEUE_SIZE};
+use common::*;
+use interface::{PacketRx, Pdu};
+use native::zcsi::MBuf;
+use std::sync::Arc;
+
+/// The producer of a multiproducer multiconsumer queue for mbufs. Consumers on several cores can take packets from the
+/// same queue, e.g. to steal work from a loaded core. Both sides always synchronize with a CAS, so prefer a
+/// `SpscProducer` or `MpscProducer` when there is a single consumer.
+#[derive(Clone)]
+pub struct MpmcProducer {
+    ring: Arc<MbufRing>,
+}
+
+impl MpmcProducer {
+    pub fn enqueue(&self, pdus: &mut Vec<Pdu>) -> usize {
+        let mbufs: Vec<_> = pdus.drain(..).map(|p| unsafe { p.get_mbuf() }).collect();
+        self.ring.enqueue_mp(&mbufs[..])
+    }
+
+    #[inline]
+    pub fn enqueue_mbufs(&self, mbufs: &[*mut MBuf]) -> usize {
+        self.ring.enqueue_mp(mbufs)
+    }
+
+    #[inline]
+    pub fn enqueue_one(&self, pdu: Pdu) -> bool {
+        unsafe { self.ring.enqueue_mp(&[pdu.get_mbuf()]) == 1 }
+    }
+
+    /// Enqueue `pdu`, or apply the drop policy of the queue if it is full. Returns false if `pdu` was dropped.
+    #[inline]
+    pub fn enqueue_or_drop(&self, pdu: Pdu) -> bool {
+        unsafe { self.ring.enqueue_or_drop(&[pdu.get_mbuf()], false) == 1 }
+    }
+
+    #[inline]
+    pub fn free_slots(&self) -> usize {
+        self.ring.free_slots()
+    }
+
+    #[inline]
+    pub fn used_slots(&self) -> usize {
+        self.ring.used_slots()
+    }
+
+    /// True if the queue reached its high watermark, producers should back off until it drained.
+    #[inline]
+    pub fn is_congested(&self) -> bool {
+        self.ring.is_congested()
+    }
+
+    /// Number of packets dropped by the drop policy of the queue.
+    #[inline]
+    pub fn dropped(&self) -> usize {
+        self.ring.dropped()
+    }
+}
+
+/// A consumer of a multiproducer multiconsumer queue, each clone takes a share of the packets.
+#[derive(Clone)]
+pub struct MpmcConsumer {
+    ring: Arc<MbufRing>,
+}
+
+impl PacketRx for MpmcConsumer {
+    #[inline]
+    fn recv(&self, mbufs: &mut [*mut MBuf]) -> errors::Result<(u32, i32)> {
+        Ok((self.ring.dequeue(mbufs, false) as u32, self.ring.used_slots() as i32))
+    }
+
+    #[inline]
+    fn queued(&self) -> usize {
+        self.ring.used_slots()
+    }
+}
+
+/// Create a queue, further producers and consumers are created by cloning the returned ones. Wrap a clone of the
+/// consumer into a `ReceiveBatch` on each core which should take packets from the queue.
+pub fn new_mpmc_queue_pair_with_config(config: &QueueConfig) -> (MpmcProducer, MpmcConsumer) {
+    mpmc_queue_pair(MbufRing::new_with_config(config))
+}
+
+pub fn new_mpmc_queue_pair_with_size(size: usize) -> (MpmcProducer, MpmcConsumer) {
+    mpmc_queue_pair(MbufRing::new(size))
+}
+
+fn mpmc_queue_pair(ring: MbufRing) -> (MpmcProducer, MpmcConsumer) {
+    let ring = Arc::new(ring);
+    (MpmcProducer { ring: ring.clone() }, MpmcConsumer { ring })
+}
+
+pub fn new_mpmc_queue_pair() -> (MpmcProducer, MpmcConsumer) {
+    new_mpmc_queue_pair_with_size(DEFAULT_QUEUE_SIZE)
+}
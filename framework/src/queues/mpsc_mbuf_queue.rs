@@ -1,46 +1,31 @@
+use super::mbuf_ring::MbufRing;
+use super::{QueueConfig, DEFAULT_QUEUE_SIZE};
 use common::*;
 use interface::{PacketRx, Pdu};
 use native::zcsi::MBuf;
 use operators::ReceiveBatch;
 use std::clone::Clone;
-use std::cmp::min;
 use std::default::Default;
-use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use utils::{pause, round_to_power_of_2};
-
-#[derive(Default)]
-struct QueueMetadata {
-    pub head: AtomicUsize,
-    pub tail: AtomicUsize,
-}
 
 /// A multiproducer single consumer queue for mbufs. The main difference when compared to `std::sync::mpsc` is that this
 /// does not use a linked list (to avoid allocation). The hope is to eventually turn this into something that can carry
-/// `Packets` or sufficient metadata to reconstruct that structure.
+/// `Packets` or sufficient metadata to reconstruct that structure. With a single producer, this is as cheap as a
+/// `SpscProducer`.
 struct MpscQueue {
-    slots: usize, // Must be a power of 2
-    mask: usize,  // slots - 1
-    // TODO: Watermark?
-    producer: QueueMetadata,
-    consumer: QueueMetadata,
-    queue: Vec<AtomicPtr<MBuf>>,
+    ring: MbufRing,
     n_producers: AtomicUsize, // Number of producers.
 }
 
 impl MpscQueue {
     pub fn new(size: usize) -> MpscQueue {
-        let slots = if size & (size - 1) != 0 {
-            round_to_power_of_2(size)
-        } else {
-            size
-        };
+        MpscQueue::new_with_config(&QueueConfig::new(size))
+    }
+
+    pub fn new_with_config(config: &QueueConfig) -> MpscQueue {
         MpscQueue {
-            slots: slots,
-            mask: slots - 1,
-            queue: (0..slots).map(|_| Default::default()).collect(),
-            producer: Default::default(),
-            consumer: Default::default(),
+            ring: MbufRing::new_with_config(config),
             n_producers: Default::default(),
         }
     }
@@ -52,119 +37,18 @@ impl MpscQueue {
     }
 
     #[inline]
-    fn enqueue_mbufs(&self, start: usize, enqueue: usize, mbufs: &[*mut MBuf]) {
-        let mask = self.mask;
-        let len = self.slots;
-        let mut mbuf_idx = 0;
-        let mut queue_idx = start & mask;
-        // TODO: Unroll?
-        if queue_idx + enqueue >= len {
-            while queue_idx < len {
-                self.queue[queue_idx].store(mbufs[mbuf_idx], Ordering::Release);
-                mbuf_idx += 1;
-                queue_idx += 1;
-            }
-            queue_idx = 0;
-        }
-        while mbuf_idx < enqueue {
-            self.queue[queue_idx].store(mbufs[mbuf_idx], Ordering::Release);
-            mbuf_idx += 1;
-            queue_idx += 1;
-        }
-    }
-
-    #[inline]
-    pub fn enqueue(&self, mbufs: &[*mut MBuf]) -> usize {
+    fn single_producer(&self) -> bool {
         let producers = self.n_producers.load(Ordering::Acquire);
         assert!(producers >= 1, "Insertion into a queue without producers");
-        if producers == 1 {
-            self.enqueue_sp(mbufs)
-        } else {
-            self.enqueue_mp(mbufs)
-        }
-    }
-
-    // In the mp only version lots of time was being consumed in CAS (Compare and Swap). We want to allow for the mp case, but there is no
-    // need to waste cycles.
-    #[inline]
-    fn enqueue_sp(&self, mbufs: &[*mut MBuf]) -> usize {
-        let len = mbufs.len();
-
-        let producer_head = self.producer.head.load(Ordering::Acquire);
-        let consumer_tail = self.consumer.tail.load(Ordering::Acquire);
-
-        let free = self.mask.wrapping_add(consumer_tail).wrapping_sub(producer_head);
-        let insert = min(free, len);
-
-        if insert > 0 {
-            let producer_next = producer_head.wrapping_add(insert);
-            // Reserve slots by incrementing head
-            self.producer.head.store(producer_next, Ordering::Release);
-            // Write to reserved slot.
-            self.enqueue_mbufs(producer_head, insert, &mbufs[..insert]);
-            // Commit write by changing tail.
-            // Once this has been achieved, update tail. Any conflicting updates will wait on the previous spin lock.
-            self.producer.tail.store(producer_next, Ordering::Release);
-            insert
-        } else {
-            0
-        }
-    }
-
-    #[inline]
-    fn free_slots(&self) -> usize {
-        let producer_head = self.producer.head.load(Ordering::Acquire);
-        let consumer_tail = self.consumer.tail.load(Ordering::Acquire);
-        self.mask.wrapping_add(consumer_tail).wrapping_sub(producer_head)
-    }
-
-    #[inline]
-    fn used_slots(&self) -> usize {
-        self.slots - self.free_slots() - 1
+        producers == 1
     }
 
     #[inline]
-    fn enqueue_mp(&self, mbufs: &[*mut MBuf]) -> usize {
-        let len = mbufs.len();
-        let mut insert;
-        let mut producer_head;
-        let mut consumer_tail;
-        // First try and reserve memory by incrementing producer head.
-        while {
-            producer_head = self.producer.head.load(Ordering::Acquire);
-            consumer_tail = self.consumer.tail.load(Ordering::Acquire);
-            let free = self.mask.wrapping_add(consumer_tail).wrapping_sub(producer_head);
-            insert = min(free, len);
-            if insert == 0 {
-                // Short circuit, no insertion
-                false // This is the same as break in this construct.
-            } else {
-                let producer_next = producer_head.wrapping_add(insert);
-                self.producer
-                    .head
-                    .compare_exchange(producer_head, producer_next, Ordering::AcqRel, Ordering::Relaxed)
-                    .is_err()
-            }
-        } {}
-
-        if insert > 0 {
-            // If we successfully reserved memory, write to memory.
-            let end = producer_head.wrapping_add(insert);
-            self.enqueue_mbufs(producer_head, insert, &mbufs[..insert]);
-            // Commit write by changing tail.
-            // Before committing we wait for any preceding writes to finish (this is important since we assume buffer is
-            // always available upto commit point.
-            while {
-                let producer_tail = self.producer.tail.load(Ordering::Acquire);
-                producer_tail != producer_head
-            } {
-                pause(); // Pausing is a nice thing to do during spin locks
-            }
-            // Once this has been achieved, update tail. Any conflicting updates will wait on the previous spin lock.
-            self.producer.tail.store(end, Ordering::Release);
-            insert
+    pub fn enqueue(&self, mbufs: &[*mut MBuf]) -> usize {
+        if self.single_producer() {
+            self.ring.enqueue_sp(mbufs)
         } else {
-            0
+            self.ring.enqueue_mp(mbufs)
         }
     }
 
@@ -174,43 +58,14 @@ impl MpscQueue {
     }
 
     #[inline]
-    fn dequeue_mbufs(&self, start: usize, dequeue: usize, mbufs: &mut [*mut MBuf]) {
-        let mask = self.mask;
-        let len = self.slots;
-        // TODO: Unroll?
-        let mut mbuf_idx = 0;
-        let mut queue_idx = start & mask;
-        if queue_idx + dequeue >= len {
-            while queue_idx < len {
-                mbufs[mbuf_idx] = self.queue[queue_idx].load(Ordering::Acquire);
-                mbuf_idx += 1;
-                queue_idx += 1;
-            }
-            queue_idx = 0;
-        }
-        while mbuf_idx < dequeue {
-            mbufs[mbuf_idx] = self.queue[queue_idx].load(Ordering::Acquire);
-            mbuf_idx += 1;
-            queue_idx += 1;
-        }
+    pub fn enqueue_or_drop(&self, mbufs: &[*mut MBuf]) -> usize {
+        self.ring.enqueue_or_drop(mbufs, self.single_producer())
     }
 
     #[inline]
     pub fn dequeue(&self, mbufs: &mut [*mut MBuf]) -> usize {
         // NOTE: This is a single consumer dequeue as assumed by this queue.
-        let consumer_head = self.consumer.head.load(Ordering::Acquire);
-        let producer_tail = self.producer.tail.load(Ordering::Acquire);
-        let available_entries = producer_tail.wrapping_sub(consumer_head);
-        let dequeue = min(mbufs.len(), available_entries);
-        if dequeue > 0 {
-            let consumer_next = consumer_head.wrapping_add(dequeue);
-            // Reserve what we are going to dequeue.
-            self.consumer.head.store(consumer_next, Ordering::Release);
-            self.dequeue_mbufs(consumer_head, dequeue, mbufs);
-            // Commit that we have dequeued.
-            self.consumer.tail.store(consumer_next, Ordering::Release);
-        }
-        dequeue
+        self.ring.dequeue(mbufs, true)
     }
 }
 
@@ -248,14 +103,32 @@ impl MpscProducer {
         unsafe { self.mpsc_queue.enqueue_one(pdu.get_mbuf()) }
     }
 
+    /// Enqueue `pdu`, or apply the drop policy of the queue if it is full. Returns false if `pdu` was dropped.
+    #[inline]
+    pub fn enqueue_or_drop(&self, pdu: Pdu) -> bool {
+        unsafe { self.mpsc_queue.enqueue_or_drop(&[pdu.get_mbuf()]) == 1 }
+    }
+
     #[inline]
     pub fn free_slots(&self) -> usize {
-        self.mpsc_queue.free_slots()
+        self.mpsc_queue.ring.free_slots()
     }
 
     #[inline]
     pub fn used_slots(&self) -> usize {
-        self.mpsc_queue.used_slots()
+        self.mpsc_queue.ring.used_slots()
+    }
+
+    /// True if the queue reached its high watermark, producers should back off until it drained.
+    #[inline]
+    pub fn is_congested(&self) -> bool {
+        self.mpsc_queue.ring.is_congested()
+    }
+
+    /// Number of packets dropped by the drop policy of the queue.
+    #[inline]
+    pub fn dropped(&self) -> usize {
+        self.mpsc_queue.ring.dropped()
     }
 }
 
@@ -268,18 +141,26 @@ impl PacketRx for MpscConsumer {
     fn recv(&self, mbufs: &mut [*mut MBuf]) -> errors::Result<(u32, i32)> {
         Ok((
             self.mpsc_queue.dequeue(mbufs) as u32,
-            self.mpsc_queue.used_slots() as i32,
+            self.mpsc_queue.ring.used_slots() as i32,
         ))
     }
 
     #[inline]
     fn queued(&self) -> usize {
-        self.mpsc_queue.used_slots()
+        self.mpsc_queue.ring.used_slots()
     }
 }
 
 pub fn new_mpsc_queue_pair_with_size(size: usize) -> (MpscProducer, ReceiveBatch<MpscConsumer>) {
-    let mpsc_q = Arc::new(MpscQueue::new(size));
+    mpsc_queue_pair(MpscQueue::new(size))
+}
+
+pub fn new_mpsc_queue_pair_with_config(config: &QueueConfig) -> (MpscProducer, ReceiveBatch<MpscConsumer>) {
+    mpsc_queue_pair(MpscQueue::new_with_config(config))
+}
+
+fn mpsc_queue_pair(mpsc_q: MpscQueue) -> (MpscProducer, ReceiveBatch<MpscConsumer>) {
+    let mpsc_q = Arc::new(mpsc_q);
     mpsc_q.reference_producers();
     (
         MpscProducer {
@@ -289,8 +170,6 @@ pub fn new_mpsc_queue_pair_with_size(size: usize) -> (MpscProducer, ReceiveBatch
     )
 }
 
-const DEFAULT_QUEUE_SIZE: usize = 1024;
-
 pub fn new_mpsc_queue_pair() -> (MpscProducer, ReceiveBatch<MpscConsumer>) {
     new_mpsc_queue_pair_with_size(DEFAULT_QUEUE_SIZE)
 }
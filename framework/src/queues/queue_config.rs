@@ -0,0 +1,45 @@
+/// Default number of slots of a queue.
+pub const DEFAULT_QUEUE_SIZE: usize = 1024;
+
+/// What a producer does with packets which do not fit into a full queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Drop the packets which are enqueued.
+    TailDrop,
+    /// Drop the oldest queued packets to make room, which favours fresh packets, e.g. for latency sensitive traffic.
+    /// Producers remove packets on the consumer side, so the consumers of such a queue always synchronize with each
+    /// other, even if there is only one.
+    HeadDrop,
+}
+
+/// Configuration of a mbuf queue. Watermarks are off unless `high_watermark` is set: once the fill level of a queue
+/// reaches the high watermark, the queue signals backpressure to its producers (see e.g. `SpscProducer::is_congested`)
+/// until it drained to the low watermark.
+#[derive(Clone, Copy, Debug)]
+pub struct QueueConfig {
+    /// number of slots, rounded up to a power of 2, one slot stays empty
+    pub size: usize,
+    /// fill level which signals backpressure, 0 disables backpressure
+    pub high_watermark: usize,
+    /// fill level which ends backpressure
+    pub low_watermark: usize,
+    pub drop_policy: DropPolicy,
+}
+
+impl QueueConfig {
+    /// A tail dropping queue of `size` slots without backpressure.
+    pub fn new(size: usize) -> QueueConfig {
+        QueueConfig {
+            size,
+            high_watermark: 0,
+            low_watermark: 0,
+            drop_policy: DropPolicy::TailDrop,
+        }
+    }
+}
+
+impl Default for QueueConfig {
+    fn default() -> QueueConfig {
+        QueueConfig::new(DEFAULT_QUEUE_SIZE)
+    }
+}
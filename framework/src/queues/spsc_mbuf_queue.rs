@@ -0,0 +1,95 @@
+use super::mbuf_ring::MbufRing;
+use super::{QueueConfig, DEFAULT_QUEUE_SIZE};
+use common::*;
+use interface::{PacketRx, Pdu};
+use native::zcsi::MBuf;
+use operators::ReceiveBatch;
+use std::sync::Arc;
+
+/// The producer of a single producer single consumer queue for mbufs. Neither side synchronizes with a CAS, which makes
+/// this the cheapest queue when one task feeds another. The producer can not be cloned, use a `MpscProducer` for
+/// several producers.
+pub struct SpscProducer {
+    ring: Arc<MbufRing>,
+}
+
+impl SpscProducer {
+    pub fn enqueue(&mut self, pdus: &mut Vec<Pdu>) -> usize {
+        let mbufs: Vec<_> = pdus.drain(..).map(|p| unsafe { p.get_mbuf() }).collect();
+        self.ring.enqueue_sp(&mbufs[..])
+    }
+
+    #[inline]
+    pub fn enqueue_mbufs(&mut self, mbufs: &[*mut MBuf]) -> usize {
+        self.ring.enqueue_sp(mbufs)
+    }
+
+    #[inline]
+    pub fn enqueue_one(&mut self, pdu: Pdu) -> bool {
+        unsafe { self.ring.enqueue_sp(&[pdu.get_mbuf()]) == 1 }
+    }
+
+    /// Enqueue `pdu`, or apply the drop policy of the queue if it is full. Returns false if `pdu` was dropped.
+    #[inline]
+    pub fn enqueue_or_drop(&mut self, pdu: Pdu) -> bool {
+        unsafe { self.ring.enqueue_or_drop(&[pdu.get_mbuf()], true) == 1 }
+    }
+
+    #[inline]
+    pub fn free_slots(&self) -> usize {
+        self.ring.free_slots()
+    }
+
+    #[inline]
+    pub fn used_slots(&self) -> usize {
+        self.ring.used_slots()
+    }
+
+    /// True if the queue reached its high watermark, the producer should back off until it drained.
+    #[inline]
+    pub fn is_congested(&self) -> bool {
+        self.ring.is_congested()
+    }
+
+    /// Number of packets dropped by the drop policy of the queue.
+    #[inline]
+    pub fn dropped(&self) -> usize {
+        self.ring.dropped()
+    }
+}
+
+pub struct SpscConsumer {
+    ring: Arc<MbufRing>,
+}
+
+impl PacketRx for SpscConsumer {
+    #[inline]
+    fn recv(&self, mbufs: &mut [*mut MBuf]) -> errors::Result<(u32, i32)> {
+        Ok((self.ring.dequeue(mbufs, true) as u32, self.ring.used_slots() as i32))
+    }
+
+    #[inline]
+    fn queued(&self) -> usize {
+        self.ring.used_slots()
+    }
+}
+
+pub fn new_spsc_queue_pair_with_config(config: &QueueConfig) -> (SpscProducer, ReceiveBatch<SpscConsumer>) {
+    spsc_queue_pair(MbufRing::new_with_config(config))
+}
+
+pub fn new_spsc_queue_pair_with_size(size: usize) -> (SpscProducer, ReceiveBatch<SpscConsumer>) {
+    spsc_queue_pair(MbufRing::new(size))
+}
+
+fn spsc_queue_pair(ring: MbufRing) -> (SpscProducer, ReceiveBatch<SpscConsumer>) {
+    let ring = Arc::new(ring);
+    (
+        SpscProducer { ring: ring.clone() },
+        ReceiveBatch::new(SpscConsumer { ring }),
+    )
+}
+
+pub fn new_spsc_queue_pair() -> (SpscProducer, ReceiveBatch<SpscConsumer>) {
+    new_spsc_queue_pair_with_size(DEFAULT_QUEUE_SIZE)
+}
@@ -4,11 +4,14 @@ use config::NetbricksConfiguration;
 use interface::dpdk::{init_system, init_thread};
 use interface::{PmdPort, PortQueue, VirtualPort, VirtualQueue};
 use scheduler::*;
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::sync::mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender};
 use std::sync::Arc;
 use std::thread::{self, JoinHandle, Thread};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
 
 type AlignedPortQueue = CacheAligned<PortQueue>;
 type AlignedVirtualQueue = CacheAligned<VirtualQueue>;
@@ -43,6 +46,8 @@ pub struct NetBricksContext {
     pub virtual_ports: HashMap<i32, Arc<VirtualPort>>,
     pub scheduler_channels: HashMap<i32, SyncSender<SchedulerCommand>>,
     pub reply_receiver: Option<Receiver<SchedulerReply>>,
+    /// round of the last performance data request, replies of earlier rounds arriving late are dropped
+    performance_round: Cell<u64>,
     scheduler_handles: HashMap<i32, JoinHandle<()>>,
 }
 
@@ -141,6 +146,47 @@ impl NetBricksContext {
         }
     }
 
+    /// Request the performance data of all tasks from all schedulers.
+    /// Returns for each core which answered within `timeout` a map from task uuid to (task name, cycles, count, queue_len).
+    pub fn performance_data(&self, timeout: Duration) -> HashMap<i32, HashMap<Uuid, (String, u64, u64, u32)>> {
+        let mut result = HashMap::with_capacity(self.scheduler_channels.len());
+        let receiver = match self.reply_receiver {
+            Some(ref r) => r,
+            None => return result,
+        };
+        let round = self.performance_round.get() + 1;
+        self.performance_round.set(round);
+        for channel in self.scheduler_channels.values() {
+            channel.send(SchedulerCommand::GetPerformance(round)).unwrap();
+        }
+        let deadline = Instant::now() + timeout;
+        while result.len() < self.scheduler_channels.len() {
+            let now = Instant::now();
+            if now >= deadline {
+                warn!(
+                    "performance data of {} scheduler(s) missing",
+                    self.scheduler_channels.len() - result.len()
+                );
+                break;
+            }
+            match receiver.recv_timeout(deadline - now) {
+                Ok(SchedulerReply::PerformanceData(core, reply_round, data)) => {
+                    if reply_round == round {
+                        result.insert(core, data);
+                    } else {
+                        debug!("dropping performance data of round {} from core {}", reply_round, core);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => {
+                    warn!("all schedulers stopped, performance data incomplete");
+                    break;
+                }
+            }
+        }
+        result
+    }
+
     /// Pause all schedulers, the returned `BarrierHandle` can be used to resume.
     pub fn barrier(&mut self) -> BarrierHandle {
         // TODO: If this becomes a problem, move this to the struct itself; but make sure to fix `stop` appropriately.
@@ -112,11 +112,12 @@ pub enum SchedulerCommand {
     Execute,
     Shutdown,
     Handshake(SyncSender<bool>),
-    GetPerformance,
+    /// Request the performance data of the tasks, the reply carries the given round.
+    GetPerformance(u64),
 }
 
 pub enum SchedulerReply {
-    PerformanceData(i32, HashMap<Uuid, (String, u64, u64, u32)>), //core id, uuid of task, task name, consumed cycles, count, queue_len
+    PerformanceData(i32, u64, HashMap<Uuid, (String, u64, u64, u32)>), //core id, round, uuid of task, task name, consumed cycles, count, queue_len
 }
 
 const DEFAULT_Q_SIZE: usize = 256;
@@ -227,13 +228,13 @@ impl StandaloneScheduler {
                     utils::rdtsc_unsafe().separated_string()
                 );
             }
-            SchedulerCommand::GetPerformance => {
+            SchedulerCommand::GetPerformance(round) => {
                 let mut data: HashMap<Uuid, (String, u64, u64, u32)> = HashMap::with_capacity(DEFAULT_Q_SIZE);
                 for r in &self.run_q {
                     data.insert(r.uuid, (r.name.clone(), r.cycles, r.count, r.queue_len));
                 }
                 self.sender
-                    .send(SchedulerReply::PerformanceData(self.core, data))
+                    .send(SchedulerReply::PerformanceData(self.core, round, data))
                     .unwrap();
             }
             SchedulerCommand::Handshake(chan) => {
@@ -1,4 +1,6 @@
-use super::{open_shared, SharedMemory};
+use super::{open_readonly, open_shared, read_consistent, ReadOnlyMemory, SharedMemory};
+use common::errors;
+use common::errors::ErrorKind;
 use std::mem::size_of;
 use std::sync::atomic::*;
 use utils::PAGE_SIZE;
@@ -8,9 +10,11 @@ const MAX_LEN: usize = 256; // 255 byte names
 const DIRECTORY_PAGES: usize = 2; // Dedicate 2 pages to the directory.
 const BYTE_SIZE: usize = DIRECTORY_PAGES * PAGE_SIZE;
 
-/// Directory header for shared data.
+/// Directory header for shared data. The layout is fixed since other processes read it, see `DirectoryReader`.
 //#[repr(packed, C)], sta: rustc 1.32.0-nightly gives an error E0588 here
+#[repr(C)]
 pub struct DirectoryHeader {
+    /// number of registered entries
     entries: AtomicUsize,
     // Used to signal that snapshotting is in progress.
     current_version: AtomicUsize,
@@ -62,8 +66,11 @@ impl Directory {
         } else {
             unsafe {
                 let entry_ptr = self.data.offset(entry as isize);
-                (*entry_ptr).name.copy_from_slice(name.as_bytes());
-                (*self.head).entries.store(entry, Ordering::Release);
+                // names are NUL terminated
+                let mut bytes = [0u8; MAX_LEN];
+                bytes[..name.len()].copy_from_slice(name.as_bytes());
+                (*entry_ptr).name = bytes;
+                (*self.head).entries.store(entry + 1, Ordering::Release);
             }
             self.entry += 1;
             Some(entry)
@@ -85,3 +92,51 @@ impl Directory {
         }
     }
 }
+
+/// Reads the directory of another process, e.g. of a running NF.
+pub struct DirectoryReader {
+    head: *const DirectoryHeader,
+    data: *const DirectoryEntry,
+    _memory: ReadOnlyMemory,
+}
+
+impl DirectoryReader {
+    /// Attach to the directory `name`.
+    pub fn open(name: &str) -> errors::Result<DirectoryReader> {
+        unsafe {
+            let memory = open_readonly(name)?;
+            let head = memory.mem as *const DirectoryHeader;
+            let entries = (BYTE_SIZE - size_of::<DirectoryHeader>()) / size_of::<DirectoryEntry>();
+            if memory.size != BYTE_SIZE || (*head).length != entries {
+                return Err(ErrorKind::ConfigurationError(format!("{} is not a directory", name)));
+            }
+            Ok(DirectoryReader {
+                head,
+                data: head.offset(1) as *const DirectoryEntry,
+                _memory: memory,
+            })
+        }
+    }
+
+    /// The last committed version.
+    pub fn version(&self) -> usize {
+        unsafe { (*self.head).committed_version.load(Ordering::Acquire) }
+    }
+
+    /// The names of the registered entries.
+    pub fn entries(&self) -> errors::Result<Vec<String>> {
+        unsafe {
+            let head = &*self.head;
+            read_consistent(&head.current_version, &head.committed_version, || {
+                let count = head.entries.load(Ordering::Acquire).min(head.length);
+                (0..count)
+                    .map(|i| {
+                        let name = (*self.data.add(i)).name;
+                        let len = name.iter().position(|&b| b == 0).unwrap_or(MAX_LEN);
+                        String::from_utf8_lossy(&name[..len]).into_owned()
+                    })
+                    .collect()
+            })
+        }
+    }
+}
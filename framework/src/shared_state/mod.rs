@@ -1,17 +1,30 @@
 /// Shareable data structures.
 pub mod directory;
+pub use self::persistent::*;
+pub use self::shared_hash_map::*;
 pub use self::shared_vec::*;
+mod persistent;
+mod shared_hash_map;
 mod shared_vec;
-use libc::{self, c_void, close, ftruncate, mmap, munmap, shm_open, shm_unlink};
+use common::errors;
+use libc::{self, c_void, close, flock, fstat, ftruncate, mmap, munmap, shm_open, shm_unlink};
 use std::ffi::CString;
 use std::io::Error;
+use std::mem;
 use std::ptr;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use std::thread;
 use utils::PAGE_SIZE;
 
+/// Attempts of a reader to get a consistent copy before giving up.
+const MAX_READ_ATTEMPTS: usize = 10000;
+
 struct SharedMemory<T> {
     pub mem: *mut T,
     name: CString,
     size: usize,
+    /// persistent regions are not unlinked when they are dropped, so that another process can attach to them
+    persistent: bool,
 }
 
 impl<T> Drop for SharedMemory<T> {
@@ -20,15 +33,19 @@ impl<T> Drop for SharedMemory<T> {
             let size = self.size;
             let _ret = munmap(self.mem as *mut c_void, size); // Unmap pages.
                                                               // Record munmap failure.
-            let shm_ret = shm_unlink(self.name.as_ptr());
-            assert!(shm_ret == 0, "Could not unlink shared memory region");
+            if !self.persistent {
+                let shm_ret = shm_unlink(self.name.as_ptr());
+                assert!(shm_ret == 0, "Could not unlink shared memory region");
+            }
         }
     }
 }
 
+/// Create the segment `name` of `size` bytes, replacing an existing segment of that name. Use `open_persistent` to
+/// attach to an existing segment instead.
 unsafe fn open_shared<T>(name: &str, size: usize) -> SharedMemory<T> {
     // Make sure size is page aligned
-    assert!(size & !PAGE_SIZE == 0);
+    assert!(size & (PAGE_SIZE - 1) == 0);
     let name = CString::new(name).unwrap();
     let mut fd = shm_open(name.as_ptr(), libc::O_CREAT | libc::O_EXCL | libc::O_RDWR, 0o700);
     if fd == -1 {
@@ -42,11 +59,12 @@ unsafe fn open_shared<T>(name: &str, size: usize) -> SharedMemory<T> {
     assert!(fd >= 0, "Could not create shared memory segment");
     let ftret = ftruncate(fd, size as i64);
     assert!(ftret == 0, "Could not truncate");
+    // shared, not private, so that readers in other processes, e.g. `shm-inspect`, see the writes
     let address = mmap(
         ptr::null_mut(),
         size,
         libc::PROT_READ | libc::PROT_WRITE,
-        libc::MAP_POPULATE | libc::MAP_PRIVATE,
+        libc::MAP_POPULATE | libc::MAP_SHARED,
         fd,
         0,
     );
@@ -60,5 +78,135 @@ unsafe fn open_shared<T>(name: &str, size: usize) -> SharedMemory<T> {
         mem: address as *mut T,
         name: name,
         size: size,
+        persistent: false,
+    }
+}
+
+/// Open the persistent segment `name` of `size` bytes, or create it if it does not exist. New segments are zeroed.
+/// `init` runs on the mapping with an exclusive lock on the segment held, so that a process attaching to a segment
+/// does not see it half initialized; it is passed true if the segment was created.
+unsafe fn open_persistent<T, F>(name: &str, size: usize, init: F) -> errors::Result<SharedMemory<T>>
+where
+    F: FnOnce(*mut T, bool) -> errors::Result<()>,
+{
+    assert!(size & (PAGE_SIZE - 1) == 0);
+    let c_name = CString::new(name)
+        .map_err(|_| errors::ErrorKind::ConfigurationError(format!("bad shared memory name {}", name)))?;
+    let fd = shm_open(c_name.as_ptr(), libc::O_CREAT | libc::O_RDWR, 0o700);
+    if fd == -1 {
+        return Err(Error::last_os_error().into());
+    }
+    // released by closing the descriptor on the error paths before the segment is mapped
+    if flock(fd, libc::LOCK_EX) != 0 {
+        let err = Error::last_os_error();
+        close(fd);
+        return Err(err.into());
+    }
+    // a segment without a size was just created, or its creator stopped before sizing it
+    let mut stat: libc::stat = mem::zeroed();
+    if fstat(fd, &mut stat) != 0 {
+        let err = Error::last_os_error();
+        close(fd);
+        return Err(err.into());
+    }
+    let created = stat.st_size == 0;
+    if created && ftruncate(fd, size as i64) != 0 {
+        let err = Error::last_os_error();
+        close(fd);
+        return Err(err.into());
+    }
+    if !created && stat.st_size as usize != size {
+        close(fd);
+        return Err(errors::ErrorKind::ConfigurationError(format!(
+            "shared memory segment {} does not have the expected size {}",
+            name, size
+        )));
+    }
+    let address = mmap(
+        ptr::null_mut(),
+        size,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_POPULATE | libc::MAP_SHARED,
+        fd,
+        0,
+    );
+    if address == libc::MAP_FAILED {
+        let err = Error::last_os_error();
+        close(fd);
+        return Err(err.into());
+    }
+    let shared = SharedMemory {
+        mem: address as *mut T,
+        name: c_name,
+        size,
+        persistent: true,
+    };
+    let result = init(shared.mem, created);
+    // the mapping refers to the descriptor, so closing it does not release the lock
+    flock(fd, libc::LOCK_UN);
+    close(fd);
+    result.map(|_| shared)
+}
+
+/// A read-only mapping of a segment of another process, which is not unlinked when it is dropped.
+struct ReadOnlyMemory {
+    mem: *const u8,
+    size: usize,
+}
+
+impl Drop for ReadOnlyMemory {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.mem as *mut c_void, self.size);
+        }
+    }
+}
+
+/// Map the existing segment `name` for reading.
+unsafe fn open_readonly(name: &str) -> errors::Result<ReadOnlyMemory> {
+    let c_name = CString::new(name)
+        .map_err(|_| errors::ErrorKind::ConfigurationError(format!("bad shared memory name {}", name)))?;
+    let fd = shm_open(c_name.as_ptr(), libc::O_RDONLY, 0);
+    if fd == -1 {
+        return Err(Error::last_os_error().into());
+    }
+    let mut stat: libc::stat = mem::zeroed();
+    if fstat(fd, &mut stat) != 0 {
+        let err = Error::last_os_error();
+        close(fd);
+        return Err(err.into());
+    }
+    let size = stat.st_size as usize;
+    let address = mmap(ptr::null_mut(), size, libc::PROT_READ, libc::MAP_SHARED, fd, 0);
+    close(fd);
+    if address == libc::MAP_FAILED {
+        return Err(Error::last_os_error().into());
+    }
+    Ok(ReadOnlyMemory {
+        mem: address as *const u8,
+        size,
+    })
+}
+
+/// Run `read` on shared data until it ran while no snapshot of the writer was in progress, i.e. the current and the
+/// committed version were the same and did not change meanwhile. Fails if the writer is always busy.
+fn read_consistent<R, F: FnMut() -> R>(
+    current: &AtomicUsize,
+    committed: &AtomicUsize,
+    mut read: F,
+) -> errors::Result<R> {
+    for _ in 0..MAX_READ_ATTEMPTS {
+        let version = current.load(Ordering::Acquire);
+        if committed.load(Ordering::Acquire) == version {
+            let value = read();
+            fence(Ordering::Acquire);
+            if current.load(Ordering::Relaxed) == version {
+                return Ok(value);
+            }
+        }
+        thread::yield_now();
     }
+    Err(errors::ErrorKind::RunTimeError(
+        "shared data changed during every read attempt".to_string(),
+    ))
 }
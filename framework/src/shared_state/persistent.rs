@@ -0,0 +1,159 @@
+use super::{open_persistent, SharedMemory};
+use common::errors;
+use common::errors::ErrorKind;
+use libc::shm_unlink;
+use std::ffi::CString;
+use std::mem::{align_of, size_of};
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU32, Ordering};
+use utils::round_to_pages;
+
+/// "NBPERSIS"
+const REGION_MAGIC: u64 = 0x4e42_5045_5253_4953;
+/// Version of the layout of `RegionHeader`.
+const HEADER_VERSION: u32 = 1;
+/// Space reserved for the header, the data starts at the next multiple of the alignment of the values.
+const HEADER_SPACE: usize = 64;
+
+/// Describes the content of a persistent region, so that a process attaching to it can check that it has the
+/// expected layout.
+#[repr(C)]
+struct RegionHeader {
+    magic: u64,
+    header_version: u32,
+    /// version of the data layout chosen by the application
+    version: u32,
+    value_size: u64,
+    value_align: u64,
+    len: u64,
+    /// set when the creator initialized the region, a region without it is initialized again
+    ready: AtomicU32,
+}
+
+/// A region of shared memory holding `len` values of `T`, which outlives the process, so that a restarted NF can
+/// attach to its state, e.g. flow tables or NAT bindings, and keep serving existing connections. A region is created
+/// zeroed if it does not exist, otherwise the existing region is mapped after checking that it holds values of the
+/// same size, alignment and number and with the same layout `version`. The application must increase the version
+/// whenever the layout of `T` changes.
+///
+/// The region is not removed when it is dropped, see `remove`. A process may have been stopped while it updated the
+/// values, structures kept in a persistent region must tolerate this.
+///
+/// Maps keyed by flow, e.g. NAT bindings, are kept by `SharedHashMap::open_persistent`. `FlowTable` keeps its entries
+/// in process memory, to keep its state across restarts store the values in a region and the index of their slot in
+/// a `FlowTable`; a restarted process inserts the flows of the occupied slots into a new table.
+pub struct PersistentRegion<T: Copy> {
+    shared: SharedMemory<u8>,
+    data: *mut T,
+    len: usize,
+    created: bool,
+}
+
+unsafe impl<T: Copy + Send> Send for PersistentRegion<T> {}
+
+impl<T: Copy> PersistentRegion<T> {
+    /// Attach to the region `name` holding `len` values of `T`, or create it.
+    ///
+    /// # Safety
+    /// All-zero bytes must be a valid `T`, and `T` must not contain pointers, which are not valid in another process.
+    pub unsafe fn open(name: &str, len: usize, version: u32) -> errors::Result<PersistentRegion<T>> {
+        let offset = data_offset::<T>();
+        let size = round_to_pages(offset + len * size_of::<T>());
+        let mut created = false;
+        let shared = open_persistent::<u8, _>(name, size, |mem, new| {
+            let header = &mut *(mem as *mut RegionHeader);
+            // the creator of the region may have stopped before it initialized the header
+            if header.ready.load(Ordering::Acquire) == 0 {
+                if !new {
+                    ptr::write_bytes(mem, 0, size);
+                }
+                header.magic = REGION_MAGIC;
+                header.header_version = HEADER_VERSION;
+                header.version = version;
+                header.value_size = size_of::<T>() as u64;
+                header.value_align = align_of::<T>() as u64;
+                header.len = len as u64;
+                header.ready.store(1, Ordering::Release);
+                created = true;
+                Ok(())
+            } else if header.magic != REGION_MAGIC || header.header_version != HEADER_VERSION {
+                Err(ErrorKind::ConfigurationError(format!(
+                    "shared memory region {} is not a persistent region",
+                    name
+                )))
+            } else if header.version != version
+                || header.value_size != size_of::<T>() as u64
+                || header.value_align != align_of::<T>() as u64
+                || header.len != len as u64
+            {
+                Err(ErrorKind::ConfigurationError(format!(
+                    "shared memory region {} has layout version {} with {} values of {} bytes, expected version {} \
+                     with {} values of {} bytes",
+                    name,
+                    header.version,
+                    header.len,
+                    header.value_size,
+                    version,
+                    len,
+                    size_of::<T>()
+                )))
+            } else {
+                Ok(())
+            }
+        })?;
+        let data = shared.mem.add(offset) as *mut T;
+        Ok(PersistentRegion {
+            shared,
+            data,
+            len,
+            created,
+        })
+    }
+
+    /// Remove the region `name`, see `remove_persistent`.
+    pub fn remove(name: &str) -> errors::Result<()> {
+        remove_persistent(name)
+    }
+
+    /// True if the region was created, false if the process attached to an existing region.
+    pub fn created(&self) -> bool {
+        self.created
+    }
+
+    /// Size of the mapping in bytes.
+    pub fn mapped_size(&self) -> usize {
+        self.shared.size
+    }
+}
+
+/// Remove the persistent region or map `name`. Processes which are attached to it keep their mapping.
+pub fn remove_persistent(name: &str) -> errors::Result<()> {
+    let c_name =
+        CString::new(name).map_err(|_| ErrorKind::ConfigurationError(format!("bad shared memory name {}", name)))?;
+    if unsafe { shm_unlink(c_name.as_ptr()) } != 0 {
+        return Err(::std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// the offset of the values from the start of the region
+fn data_offset<T>() -> usize {
+    let align = align_of::<T>().max(1);
+    (HEADER_SPACE.max(size_of::<RegionHeader>()) + align - 1) / align * align
+}
+
+impl<T: Copy> Deref for PersistentRegion<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.data, self.len) }
+    }
+}
+
+impl<T: Copy> DerefMut for PersistentRegion<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.data, self.len) }
+    }
+}
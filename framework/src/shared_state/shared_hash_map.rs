@@ -0,0 +1,461 @@
+use super::{open_persistent, open_readonly, open_shared, ReadOnlyMemory, SharedMemory, MAX_READ_ATTEMPTS};
+use common::errors;
+use common::errors::ErrorKind;
+use fnv::FnvHasher;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::ptr;
+use std::sync::atomic::{fence, AtomicU32, AtomicUsize, Ordering};
+use std::thread;
+use utils::round_to_pages;
+
+/// "NBSHRMAP"
+const SHARED_MAP_MAGIC: u64 = 0x4e42_5348_524d_4150;
+/// Space reserved for the header, the slots start at the next multiple of their alignment.
+const HEADER_SPACE: usize = 128;
+
+const EMPTY: u32 = 0;
+const FULL: u32 = 1;
+/// The key was removed, lookups continue probing after this slot.
+const REMOVED: u32 = 2;
+
+#[repr(C)]
+struct SharedMapHeader {
+    magic: u64,
+    /// version of the layout of the keys and values chosen by the application, 0 for maps which are not persistent
+    version: usize,
+    key_size: usize,
+    value_size: usize,
+    slot_size: usize,
+    /// number of slots, a power of two
+    slots: usize,
+    /// maximum number of entries
+    capacity: usize,
+    data_offset: usize,
+    len: AtomicUsize,
+    /// incremented before and after the writer rebuilds the table, i.e. odd while it moves keys
+    generation: AtomicUsize,
+}
+
+/// A slot of the table. The writer makes `seq` odd while it changes the slot, so readers can detect torn reads.
+#[repr(C)]
+struct Slot<K, V> {
+    seq: AtomicU32,
+    state: AtomicU32,
+    key: K,
+    value: V,
+}
+
+fn hash<K: Hash>(key: &K) -> usize {
+    // FNV is not seeded, so the writer and the readers hash keys the same way
+    let mut hasher = FnvHasher::default();
+    key.hash(&mut hasher);
+    hasher.finish() as usize
+}
+
+fn data_offset<K, V>() -> usize {
+    let align = align_of::<Slot<K, V>>();
+    (HEADER_SPACE + align - 1) / align * align
+}
+
+/// A fixed capacity hash map with open addressing in shared memory, e.g. for NAT bindings or block lists which a
+/// control daemon reads with a `SharedHashMapReader`. There is a single writer, which does not wait for readers.
+/// Removed keys leave markers which lookups probe past. The writer never reinserts keys on its own, since that touches
+/// every slot; the application calls `compact` off the data path once `needs_compaction` says so. Readers repeat
+/// lookups which overlap with this, so a reader sees every key which was in the map during its lookup.
+///
+/// Keys and values must be plain data without pointers, since they are read by other processes. A map opened with
+/// `open_persistent` survives the process, so that a restarted NF attaches to it.
+pub struct SharedHashMap<K: Copy + Eq + Hash, V: Copy> {
+    head: *mut SharedMapHeader,
+    slots: *mut Slot<K, V>,
+    mask: usize,
+    /// slots of removed keys which are not empty
+    removed: usize,
+    shared: SharedMemory<SharedMapHeader>,
+}
+
+unsafe impl<K: Copy + Eq + Hash + Send, V: Copy + Send> Send for SharedHashMap<K, V> {}
+
+impl<K: Copy + Eq + Hash, V: Copy> SharedHashMap<K, V> {
+    /// Create the map `name` holding up to `capacity` entries, replacing an existing segment of that name.
+    pub fn new_with_capacity(name: &str, capacity: usize) -> SharedHashMap<K, V> {
+        let header = SharedHashMap::<K, V>::header(capacity, 0);
+        let size = round_to_pages(header.data_offset + header.slots * header.slot_size);
+        unsafe {
+            // new segments are zeroed, i.e. all slots are empty
+            let shared = open_shared::<SharedMapHeader>(name, size);
+            ptr::write(shared.mem, header);
+            SharedHashMap::attach(shared)
+        }
+    }
+
+    /// Attach to the persistent map `name` left by a previous process, or create it, e.g. for NAT bindings which
+    /// should survive a restart. An existing map must have the same capacity, sizes of keys and values and layout
+    /// `version`; the application must increase the version whenever the layout of `K` or `V` changes. The map is not
+    /// removed when it is dropped, see `remove_persistent`.
+    ///
+    /// # Safety
+    /// `K` and `V` must be the types used by the previous process, and all-zero bytes must be a valid `K` and `V`.
+    pub unsafe fn open_persistent(name: &str, capacity: usize, version: usize) -> errors::Result<SharedHashMap<K, V>> {
+        let header = SharedHashMap::<K, V>::header(capacity, version);
+        let size = round_to_pages(header.data_offset + header.slots * header.slot_size);
+        let shared = open_persistent::<SharedMapHeader, _>(name, size, |head, created| {
+            let existing = &*head;
+            // the creator of the segment may have stopped before it wrote the header
+            if created || existing.magic == 0 {
+                ptr::write_bytes(head as *mut u8, 0, size);
+                ptr::write(head, header);
+                Ok(())
+            } else if existing.magic != SHARED_MAP_MAGIC {
+                Err(ErrorKind::ConfigurationError(format!("{} is not a shared hash map", name)))
+            } else if existing.version != version
+                || existing.key_size != header.key_size
+                || existing.value_size != header.value_size
+                || existing.slot_size != header.slot_size
+                || existing.slots != header.slots
+                || existing.capacity != capacity
+                || existing.data_offset != header.data_offset
+            {
+                Err(ErrorKind::ConfigurationError(format!(
+                    "shared hash map {} has layout version {} with {} entries of {} and {} bytes, expected version {} \
+                     with {} entries of {} and {} bytes",
+                    name,
+                    existing.version,
+                    existing.capacity,
+                    existing.key_size,
+                    existing.value_size,
+                    version,
+                    capacity,
+                    header.key_size,
+                    header.value_size
+                )))
+            } else {
+                Ok(())
+            }
+        })?;
+        Ok(SharedHashMap::attach(shared))
+    }
+
+    /// the header of a new map
+    fn header(capacity: usize, version: usize) -> SharedMapHeader {
+        // keep at least a quarter of the slots empty so that probe sequences stay short
+        let slots = (capacity * 4 / 3 + 1).next_power_of_two();
+        SharedMapHeader {
+            magic: SHARED_MAP_MAGIC,
+            version,
+            key_size: size_of::<K>(),
+            value_size: size_of::<V>(),
+            slot_size: size_of::<Slot<K, V>>(),
+            slots,
+            capacity,
+            data_offset: data_offset::<K, V>(),
+            len: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    /// the map in `shared`, whose header is initialized
+    unsafe fn attach(shared: SharedMemory<SharedMapHeader>) -> SharedHashMap<K, V> {
+        let head = shared.mem;
+        let mut map = SharedHashMap {
+            head,
+            slots: (head as *mut u8).add((*head).data_offset) as *mut Slot<K, V>,
+            mask: (*head).slots - 1,
+            removed: 0,
+            shared,
+        };
+        map.removed = (0..=map.mask)
+            .filter(|&index| (*map.slot(index)).state.load(Ordering::Relaxed) == REMOVED)
+            .count();
+        // a previous writer stopped while it rebuilt the table, the keys it did not reinsert yet are lost
+        if (*head).generation.load(Ordering::Relaxed) & 1 != 0 {
+            (*head).generation.fetch_add(1, Ordering::Release);
+        }
+        map
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        unsafe { (*self.head).len.load(Ordering::Relaxed) }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        unsafe { (*self.head).capacity }
+    }
+
+    /// Size of the shared segment in bytes.
+    pub fn mapped_size(&self) -> usize {
+        self.shared.size
+    }
+
+    #[inline]
+    fn slot(&self, index: usize) -> *mut Slot<K, V> {
+        unsafe { self.slots.add(index) }
+    }
+
+    /// The slot holding `key`, or else the slot where it should be inserted, if any.
+    fn find(&self, key: &K) -> Result<usize, Option<usize>> {
+        let start = hash(key);
+        let mut removed = None;
+        for probe in 0..=self.mask {
+            let index = (start + probe) & self.mask;
+            let slot = self.slot(index);
+            unsafe {
+                match (*slot).state.load(Ordering::Relaxed) {
+                    FULL if (*slot).key == *key => return Ok(index),
+                    EMPTY => return Err(removed.or(Some(index))),
+                    REMOVED if removed.is_none() => removed = Some(index),
+                    _ => {}
+                }
+            }
+        }
+        Err(removed)
+    }
+
+    fn write_slot(&mut self, index: usize, state: u32, key: K, value: V) {
+        let slot = self.slot(index);
+        unsafe {
+            let seq = (*slot).seq.load(Ordering::Relaxed);
+            (*slot).seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+            fence(Ordering::Release);
+            ptr::write_volatile(&mut (*slot).key, key);
+            ptr::write_volatile(&mut (*slot).value, value);
+            (*slot).state.store(state, Ordering::Relaxed);
+            (*slot).seq.store(seq.wrapping_add(2), Ordering::Release);
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.find(key).ok().map(|index| unsafe { (*self.slot(index)).value })
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_ok()
+    }
+
+    /// Insert or update `key`, returns false if the map is full.
+    pub fn insert(&mut self, key: K, value: V) -> bool {
+        match self.find(&key) {
+            Ok(index) => self.write_slot(index, FULL, key, value),
+            Err(Some(index)) if self.len() < self.capacity() => {
+                if unsafe { (*self.slot(index)).state.load(Ordering::Relaxed) } == REMOVED {
+                    self.removed -= 1;
+                }
+                self.write_slot(index, FULL, key, value);
+                unsafe { (*self.head).len.fetch_add(1, Ordering::Release) };
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let index = self.find(key).ok()?;
+        let (key, value) = unsafe { ((*self.slot(index)).key, (*self.slot(index)).value) };
+        // lookups stop at an empty slot, which they would reach right after this one anyway
+        let next = (index + 1) & self.mask;
+        let state = if unsafe { (*self.slot(next)).state.load(Ordering::Relaxed) } == EMPTY {
+            EMPTY
+        } else {
+            REMOVED
+        };
+        self.write_slot(index, state, key, value);
+        unsafe { (*self.head).len.fetch_sub(1, Ordering::Release) };
+        if state == REMOVED {
+            self.removed += 1;
+        }
+        Some(value)
+    }
+
+    /// Number of slots of removed keys which lookups still probe past.
+    #[inline]
+    pub fn removed_slots(&self) -> usize {
+        self.removed
+    }
+
+    /// Whether so many slots hold removed keys that lookups of missing keys get slow, i.e. more than an eighth of them.
+    #[inline]
+    pub fn needs_compaction(&self) -> bool {
+        self.removed > (self.mask + 1) / 8
+    }
+
+    /// Reinsert all keys to reclaim the slots of removed keys. This allocates and rewrites every slot, so call it
+    /// off the data path, e.g. from a periodic task when `needs_compaction` is true. Readers repeat lookups which
+    /// overlap with this.
+    pub fn compact(&mut self) {
+        let entries: Vec<(K, V)> = (0..=self.mask)
+            .map(|index| self.slot(index))
+            .filter(|&slot| unsafe { (*slot).state.load(Ordering::Relaxed) } == FULL)
+            .map(|slot| unsafe { ((*slot).key, (*slot).value) })
+            .collect();
+        let generation = unsafe { &(*self.head).generation };
+        generation.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.clear_slots();
+        for (key, value) in entries {
+            if let Err(Some(index)) = self.find(&key) {
+                self.write_slot(index, FULL, key, value);
+            }
+        }
+        generation.fetch_add(1, Ordering::Release);
+    }
+
+    fn clear_slots(&mut self) {
+        for index in 0..=self.mask {
+            let slot = self.slot(index);
+            if unsafe { (*slot).state.load(Ordering::Relaxed) } != EMPTY {
+                let (key, value) = unsafe { ((*slot).key, (*slot).value) };
+                self.write_slot(index, EMPTY, key, value);
+            }
+        }
+        self.removed = 0;
+    }
+
+    /// Remove all entries, this also reclaims the slots of removed keys.
+    pub fn clear(&mut self) {
+        self.clear_slots();
+        unsafe { (*self.head).len.store(0, Ordering::Release) };
+    }
+}
+
+/// Reads a `SharedHashMap` of another process, e.g. of a running NF.
+pub struct SharedHashMapReader<K: Copy + Eq + Hash, V: Copy> {
+    head: *const SharedMapHeader,
+    slots: *const Slot<K, V>,
+    mask: usize,
+    _memory: ReadOnlyMemory,
+    _types: PhantomData<(K, V)>,
+}
+
+impl<K: Copy + Eq + Hash, V: Copy> SharedHashMapReader<K, V> {
+    /// Attach to the map `name`.
+    ///
+    /// # Safety
+    /// `K` and `V` must be the types used by the writer, and any bit pattern must be a valid `K` and `V`, since a
+    /// slot is read before it is known whether the writer changed it meanwhile.
+    pub unsafe fn open(name: &str) -> errors::Result<SharedHashMapReader<K, V>> {
+        let memory = open_readonly(name)?;
+        let head = memory.mem as *const SharedMapHeader;
+        if memory.size < HEADER_SPACE || (*head).magic != SHARED_MAP_MAGIC {
+            return Err(ErrorKind::ConfigurationError(format!(
+                "{} is not a shared hash map",
+                name
+            )));
+        }
+        let header = &*head;
+        if header.key_size != size_of::<K>()
+            || header.value_size != size_of::<V>()
+            || header.slot_size != size_of::<Slot<K, V>>()
+            || header.data_offset != data_offset::<K, V>()
+            || !header.slots.is_power_of_two()
+            || header.data_offset + header.slots * header.slot_size > memory.size
+        {
+            return Err(ErrorKind::ConfigurationError(format!(
+                "shared hash map {} has keys of {} bytes and values of {} bytes, expected {} and {} bytes",
+                name,
+                header.key_size,
+                header.value_size,
+                size_of::<K>(),
+                size_of::<V>()
+            )));
+        }
+        Ok(SharedHashMapReader {
+            head,
+            slots: memory.mem.add(header.data_offset) as *const Slot<K, V>,
+            mask: header.slots - 1,
+            _memory: memory,
+            _types: PhantomData,
+        })
+    }
+
+    /// Number of entries, which may change at any time.
+    pub fn len(&self) -> usize {
+        unsafe { (*self.head).len.load(Ordering::Acquire) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        unsafe { (*self.head).capacity }
+    }
+
+    /// A consistent copy of the slot `index`.
+    fn read_slot(&self, index: usize) -> errors::Result<(u32, K, V)> {
+        unsafe {
+            let slot = &*self.slots.add(index);
+            for _ in 0..MAX_READ_ATTEMPTS {
+                let seq = slot.seq.load(Ordering::Acquire);
+                if seq & 1 == 0 {
+                    let state = slot.state.load(Ordering::Relaxed);
+                    let key = ptr::read_volatile(&slot.key);
+                    let value = ptr::read_volatile(&slot.value);
+                    fence(Ordering::Acquire);
+                    if slot.seq.load(Ordering::Relaxed) == seq {
+                        return Ok((state, key, value));
+                    }
+                }
+                thread::yield_now();
+            }
+        }
+        Err(ErrorKind::RunTimeError(format!(
+            "slot {} of the shared hash map changed during every read attempt",
+            index
+        )))
+    }
+
+    /// Repeat `read` until the writer did not rebuild the table meanwhile.
+    fn without_rebuild<T, F: Fn() -> errors::Result<T>>(&self, read: F) -> errors::Result<T> {
+        let generation = unsafe { &(*self.head).generation };
+        for _ in 0..MAX_READ_ATTEMPTS {
+            let before = generation.load(Ordering::Acquire);
+            if before & 1 == 0 {
+                let result = read()?;
+                fence(Ordering::Acquire);
+                if generation.load(Ordering::Relaxed) == before {
+                    return Ok(result);
+                }
+            }
+            thread::yield_now();
+        }
+        Err(ErrorKind::RunTimeError(
+            "the shared hash map was rebuilt during every read attempt".to_string(),
+        ))
+    }
+
+    pub fn get(&self, key: &K) -> errors::Result<Option<V>> {
+        let start = hash(key);
+        self.without_rebuild(|| {
+            for probe in 0..=self.mask {
+                match self.read_slot((start + probe) & self.mask)? {
+                    (FULL, k, value) if k == *key => return Ok(Some(value)),
+                    (EMPTY, _, _) => return Ok(None),
+                    _ => {}
+                }
+            }
+            Ok(None)
+        })
+    }
+
+    /// A copy of all entries. Each entry is consistent, but the writer may change the map while it is copied.
+    pub fn entries(&self) -> errors::Result<Vec<(K, V)>> {
+        self.without_rebuild(|| {
+            let mut entries = Vec::with_capacity(self.len());
+            for index in 0..=self.mask {
+                if let (FULL, key, value) = self.read_slot(index)? {
+                    entries.push((key, value));
+                }
+            }
+            Ok(entries)
+        })
+    }
+}
@@ -1,75 +1,241 @@
-use super::{open_shared, SharedMemory};
+use super::{open_readonly, open_shared, read_consistent, ReadOnlyMemory, SharedMemory};
+use common::errors;
+use common::errors::ErrorKind;
 use std::borrow::Borrow;
 use std::hash::{Hash, Hasher};
+use std::mem::{align_of, size_of};
 use std::ops::{Index, IndexMut, Range, RangeFrom, RangeTo};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use utils::round_to_pages;
 
-#[allow(dead_code)] // TODO: While WIP
-pub struct SharedVec<T: Sized + 'static> {
-    vec: Vec<T>,
-    shared: SharedMemory<T>,
-    modified: bool,
+/// "NBSHRVEC"
+const SHARED_VEC_MAGIC: u64 = 0x4e42_5348_5256_4543;
+/// Space reserved for the header, the values start at the next multiple of their alignment.
+const HEADER_SPACE: usize = 64;
+
+/// Header of the segment of a `SharedVec`, which describes the values for readers in other processes.
+#[repr(C)]
+struct SharedVecHeader {
+    magic: u64,
+    /// Used to signal that snapshotting is in progress, as in the `Directory`.
+    current_version: AtomicUsize,
+    committed_version: AtomicUsize,
+    value_size: usize,
+    data_offset: usize,
+    capacity: usize,
+    len: AtomicUsize,
+}
+
+/// A vector of plain values in shared memory, which other processes can read with a `SharedVecReader`. Changes
+/// should be made between `begin_snapshot` and `end_snapshot`, so that readers get a consistent copy.
+pub struct SharedVec<T: Sized + Copy + 'static> {
+    head: *mut SharedVecHeader,
+    data: *mut T,
+    shared: SharedMemory<SharedVecHeader>,
 }
 
-impl<T: Sized + 'static> SharedVec<T> {
+impl<T: Sized + Copy + 'static> SharedVec<T> {
     pub fn new_with_capacity(name: &str, capacity: usize) -> SharedVec<T> {
-        let capacity_pages = round_to_pages(capacity);
+        let data_offset = (HEADER_SPACE + align_of::<T>() - 1) / align_of::<T>() * align_of::<T>();
+        let capacity_pages = round_to_pages(data_offset + capacity * size_of::<T>());
         unsafe {
+            let shared = open_shared::<SharedVecHeader>(name, capacity_pages);
+            let head = shared.mem;
+            ptr::write(
+                head,
+                SharedVecHeader {
+                    magic: SHARED_VEC_MAGIC,
+                    current_version: AtomicUsize::new(1),
+                    committed_version: AtomicUsize::new(1),
+                    value_size: size_of::<T>(),
+                    data_offset,
+                    capacity,
+                    len: AtomicUsize::new(0),
+                },
+            );
             SharedVec {
-                vec: Vec::with_capacity(capacity),
-                shared: open_shared(name, capacity_pages),
-                modified: false,
+                head,
+                data: (head as *mut u8).add(data_offset) as *mut T,
+                shared,
             }
         }
     }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        unsafe { (*self.head).len.load(Ordering::Relaxed) }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        unsafe { (*self.head).capacity }
+    }
+
+    /// Append `value`, returns false if the vector is full.
+    pub fn push(&mut self, value: T) -> bool {
+        let len = self.len();
+        if len == self.capacity() {
+            return false;
+        }
+        unsafe {
+            ptr::write(self.data.add(len), value);
+            (*self.head).len.store(len + 1, Ordering::Release);
+        }
+        true
+    }
+
+    pub fn clear(&mut self) {
+        unsafe { (*self.head).len.store(0, Ordering::Release) }
+    }
+
+    #[inline]
+    pub fn begin_snapshot(&mut self) {
+        unsafe {
+            (*self.head).current_version.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[inline]
+    pub fn end_snapshot(&mut self) {
+        unsafe {
+            let version = (*self.head).current_version.load(Ordering::Acquire);
+            (*self.head).committed_version.store(version, Ordering::Release);
+        }
+    }
+
+    /// Size of the shared segment in bytes.
+    pub fn mapped_size(&self) -> usize {
+        self.shared.size
+    }
+
+    fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.data, self.len()) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.data, self.len()) }
+    }
 }
 
-impl<T: Sized + 'static> Borrow<[T]> for SharedVec<T> {
+impl<T: Sized + Copy + 'static> Borrow<[T]> for SharedVec<T> {
     fn borrow(&self) -> &[T] {
-        self.vec.borrow()
+        self.as_slice()
     }
 }
 
-impl<T: Sized + Hash + 'static> Hash for SharedVec<T> {
+impl<T: Sized + Copy + Hash + 'static> Hash for SharedVec<T> {
     fn hash<H>(&self, state: &mut H)
     where
         H: Hasher,
     {
-        self.vec.hash(state)
+        self.as_slice().hash(state)
     }
 }
 
-impl<T: Sized + 'static> Index<usize> for SharedVec<T> {
+impl<T: Sized + Copy + 'static> Index<usize> for SharedVec<T> {
     type Output = T;
     fn index(&self, index: usize) -> &T {
-        self.vec.index(index)
+        self.as_slice().index(index)
     }
 }
 
-impl<T: Sized + 'static> Index<Range<usize>> for SharedVec<T> {
+impl<T: Sized + Copy + 'static> Index<Range<usize>> for SharedVec<T> {
     type Output = [T];
     fn index(&self, index: Range<usize>) -> &[T] {
-        self.vec.index(index)
+        self.as_slice().index(index)
     }
 }
 
-impl<T: Sized + 'static> Index<RangeTo<usize>> for SharedVec<T> {
+impl<T: Sized + Copy + 'static> Index<RangeTo<usize>> for SharedVec<T> {
     type Output = [T];
     fn index(&self, index: RangeTo<usize>) -> &[T] {
-        self.vec.index(index)
+        self.as_slice().index(index)
     }
 }
 
-impl<T: Sized + 'static> Index<RangeFrom<usize>> for SharedVec<T> {
+impl<T: Sized + Copy + 'static> Index<RangeFrom<usize>> for SharedVec<T> {
     type Output = [T];
     fn index(&self, index: RangeFrom<usize>) -> &[T] {
-        self.vec.index(index)
+        self.as_slice().index(index)
     }
 }
 
-impl<T: Sized + 'static> IndexMut<usize> for SharedVec<T> {
+impl<T: Sized + Copy + 'static> IndexMut<usize> for SharedVec<T> {
     fn index_mut(&mut self, index: usize) -> &mut T {
-        self.modified = true;
-        self.vec.index_mut(index)
+        self.as_mut_slice().index_mut(index)
+    }
+}
+
+/// Reads a `SharedVec` of another process, e.g. of a running NF.
+pub struct SharedVecReader {
+    head: *const SharedVecHeader,
+    memory: ReadOnlyMemory,
+}
+
+impl SharedVecReader {
+    /// Attach to the vector `name`, e.g. an entry of a `DirectoryReader`.
+    pub fn open(name: &str) -> errors::Result<SharedVecReader> {
+        unsafe {
+            let memory = open_readonly(name)?;
+            let head = memory.mem as *const SharedVecHeader;
+            if memory.size < HEADER_SPACE
+                || (*head).magic != SHARED_VEC_MAGIC
+                || (*head).data_offset + (*head).capacity * (*head).value_size > memory.size
+            {
+                return Err(ErrorKind::ConfigurationError(format!(
+                    "{} is not a shared vector",
+                    name
+                )));
+            }
+            Ok(SharedVecReader { head, memory })
+        }
+    }
+
+    /// Size of a value in bytes.
+    pub fn value_size(&self) -> usize {
+        unsafe { (*self.head).value_size }
+    }
+
+    pub fn capacity(&self) -> usize {
+        unsafe { (*self.head).capacity }
+    }
+
+    /// The last committed version.
+    pub fn version(&self) -> usize {
+        unsafe { (*self.head).committed_version.load(Ordering::Acquire) }
+    }
+
+    /// A consistent copy of the values as bytes.
+    pub fn read_bytes(&self) -> errors::Result<Vec<u8>> {
+        unsafe {
+            let head = &*self.head;
+            read_consistent(&head.current_version, &head.committed_version, || {
+                let len = head.len.load(Ordering::Acquire).min(head.capacity) * head.value_size;
+                let data = self.memory.mem.add(head.data_offset);
+                slice::from_raw_parts(data, len).to_vec()
+            })
+        }
+    }
+
+    /// A consistent copy of the values, which must be of the type written by the other process.
+    pub fn read<T: Copy>(&self) -> errors::Result<Vec<T>> {
+        if size_of::<T>() != self.value_size() || size_of::<T>() == 0 {
+            return Err(ErrorKind::BadSize(
+                size_of::<T>(),
+                format!("values of the shared vector have {} bytes", self.value_size()),
+            ));
+        }
+        let bytes = self.read_bytes()?;
+        Ok(bytes
+            .chunks(size_of::<T>())
+            .map(|value| unsafe { ptr::read_unaligned(value.as_ptr() as *const T) })
+            .collect())
     }
 }
@@ -1,25 +1,25 @@
 use std::collections::hash_map::Iter;
 use std::collections::HashMap;
 use std::hash::BuildHasherDefault;
-use std::ops::AddAssign;
 use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
 use twox_hash::XxHash;
 
+use super::Mergeable;
 use utils::FiveTupleV4;
 
 type XxHasher = BuildHasherDefault<XxHash>;
 const VEC_SIZE: usize = 1 << 24;
 
 /// A generic store for associating some merge-able type with each flow. Note, the merge must be commutative, we do not
-/// guarantee ordering for things being merged. The merge function is implemented by implementing the
-/// [`AddAssign`](https://doc.rust-lang.org/std/ops/trait.AddAssign.html) trait and overriding the `add_assign` method
-/// there. We assume that the stored quantity needs to only be accessed from the control plane, and cannot be accessed
-/// from the data plane.
+/// guarantee ordering for things being merged. The merge function is implemented by implementing the `Mergeable` trait,
+/// which all [`AddAssign`](https://doc.rust-lang.org/std/ops/trait.AddAssign.html) types implement by adding, see
+/// `Max`, `Min`, `LastWriter`, `HyperLogLog` and `CountMinSketch` for other merges. We assume that the stored quantity
+/// needs to only be accessed from the control plane, and cannot be accessed from the data plane.
 ///
 /// #[TODO]
 /// Garbage collection.
 #[derive(Clone)]
-pub struct CpMergeableStoreDataPath<T: AddAssign<T> + Default + Clone> {
+pub struct CpMergeableStoreDataPath<T: Mergeable + Default + Clone> {
     /// Contains the counts on the data path.
     cache: Vec<(FiveTupleV4, T)>,
     /// How many updates has this counter seen.
@@ -29,13 +29,13 @@ pub struct CpMergeableStoreDataPath<T: AddAssign<T> + Default + Clone> {
     channel: SyncSender<Vec<(FiveTupleV4, T)>>,
 }
 
-pub struct CpMergeableStoreControlPlane<T: AddAssign<T> + Default + Clone> {
+pub struct CpMergeableStoreControlPlane<T: Mergeable + Default + Clone> {
     /// The actual values.
     flow_counters: HashMap<FiveTupleV4, T, XxHasher>,
     channel: Receiver<Vec<(FiveTupleV4, T)>>,
 }
 
-impl<T: AddAssign<T> + Default + Clone> CpMergeableStoreDataPath<T> {
+impl<T: Mergeable + Default + Clone> CpMergeableStoreDataPath<T> {
     /// Change the value for the given `Flow`.
     #[inline]
     pub fn update(&mut self, flow: FiveTupleV4, inc: T) {
@@ -50,10 +50,10 @@ impl<T: AddAssign<T> + Default + Clone> CpMergeableStoreDataPath<T> {
     }
 }
 
-impl<T: AddAssign<T> + Default + Clone> CpMergeableStoreControlPlane<T> {
+impl<T: Mergeable + Default + Clone> CpMergeableStoreControlPlane<T> {
     fn update_internal(&mut self, v: Vec<(FiveTupleV4, T)>) {
         for (flow, c) in v {
-            *(self.flow_counters.entry(flow).or_insert_with(Default::default)) += c;
+            self.flow_counters.entry(flow).or_insert_with(Default::default).merge(c);
         }
     }
 
@@ -93,7 +93,7 @@ impl<T: AddAssign<T> + Default + Clone> CpMergeableStoreControlPlane<T> {
 
 /// Create a `CpMergeableStore`. `delay` specifies the number of buckets buffered together, while `channel_size`
 /// specifies the number of outstanding messages.
-pub fn new_cp_mergeable_store<T: AddAssign<T> + Default + Clone>(
+pub fn new_cp_mergeable_store<T: Mergeable + Default + Clone>(
     delay: usize,
     channel_size: usize,
 ) -> (CpMergeableStoreDataPath<T>, Box<CpMergeableStoreControlPlane<T>>) {
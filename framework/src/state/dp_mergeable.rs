@@ -3,23 +3,23 @@ use fnv::FnvHasher;
 use std::collections::hash_map::Iter;
 use std::collections::HashMap;
 use std::hash::BuildHasherDefault;
-use std::ops::AddAssign;
 
+use super::Mergeable;
 use utils::FiveTupleV4;
 
 /// A generic store for associating some merge-able type with each flow. Note, the merge must be commutative, we do not
-/// guarantee ordering for things being merged. The merge function is implemented by implementing the
-/// [`AddAssign`](https://doc.rust-lang.org/std/ops/trait.AddAssign.html) trait and overriding the `add_assign` method
-/// there. We assume that the quantity stored here does not need to be accessed by the control plane and can only be
-/// accessed from the data plane. The `cache_size` should be tuned depending on whether gets or puts are the most common
-/// operation in this table.
+/// guarantee ordering for things being merged. The merge function is implemented by implementing the `Mergeable` trait,
+/// which all [`AddAssign`](https://doc.rust-lang.org/std/ops/trait.AddAssign.html) types implement by adding, see
+/// `Max`, `Min`, `LastWriter`, `HyperLogLog` and `CountMinSketch` for other merges. We assume that the quantity stored
+/// here does not need to be accessed by the control plane and can only be accessed from the data plane. The
+/// `cache_size` should be tuned depending on whether gets or puts are the most common operation in this table.
 ///
 /// #[TODO]
 /// Garbage collection.
 type FnvHash = BuildHasherDefault<FnvHasher>;
 const VEC_SIZE: usize = 1 << 24;
 #[derive(Clone)]
-pub struct DpMergeableStore<T: AddAssign<T> + Default> {
+pub struct DpMergeableStore<T: Mergeable + Default> {
     /// Contains the counts on the data path.
     state: HashMap<FiveTupleV4, T, FnvHash>,
     cache: Vec<(FiveTupleV4, T)>,
@@ -27,7 +27,7 @@ pub struct DpMergeableStore<T: AddAssign<T> + Default> {
 }
 
 const CACHE_SIZE: usize = 1 << 14;
-impl<T: AddAssign<T> + Default> DpMergeableStore<T> {
+impl<T: Mergeable + Default> DpMergeableStore<T> {
     pub fn with_cache_and_size(cache: usize, size: usize) -> DpMergeableStore<T> {
         DpMergeableStore {
             state: HashMap::with_capacity_and_hasher(size, Default::default()),
@@ -41,7 +41,9 @@ impl<T: AddAssign<T> + Default> DpMergeableStore<T> {
     }
 
     fn merge_cache(&mut self) {
-        self.state.extend(self.cache.drain(0..));
+        for (flow, inc) in self.cache.drain(0..) {
+            self.state.entry(flow).or_insert_with(Default::default).merge(inc);
+        }
     }
 
     /// Change the value for the given `Flow`.
@@ -0,0 +1,249 @@
+use fnv::FnvHasher;
+
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+use std::mem;
+use std::time::Duration;
+
+use utils::FiveTupleV4;
+
+type FnvHash = BuildHasherDefault<FnvHasher>;
+
+/// Capacity and timeouts of a `FlowTable`. Timestamps and timeouts are TSC cycles, see `utils::rdtsc_unsafe`.
+#[derive(Clone, Copy, Debug)]
+pub struct FlowTableConfig {
+    /// maximum number of flows, the least recently used flows are evicted when a new flow does not fit
+    pub capacity: usize,
+    /// a flow expires if it did not see a packet for this number of cycles, 0 disables the idle timeout
+    pub idle_timeout: u64,
+    /// a flow expires this number of cycles after its first packet, 0 disables the hard timeout
+    pub hard_timeout: u64,
+}
+
+impl FlowTableConfig {
+    /// A configuration with timeouts given as durations, `tsc_hz` is the frequency of the TSC, e.g. as returned by
+    /// `rte_get_tsc_hz` after the EAL was initialized.
+    pub fn with_durations(capacity: usize, idle: Duration, hard: Duration, tsc_hz: u64) -> FlowTableConfig {
+        let cycles = |d: Duration| d.as_secs() * tsc_hz + d.subsec_nanos() as u64 * tsc_hz / 1_000_000_000;
+        FlowTableConfig {
+            capacity,
+            idle_timeout: cycles(idle),
+            hard_timeout: cycles(hard),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExpiryReason {
+    /// no packet within the idle timeout
+    Idle,
+    /// the hard timeout elapsed since the first packet
+    Hard,
+    /// removed to make room for a new flow
+    Evicted,
+}
+
+/// Called with the flows leaving a `FlowTable` due to a timeout or eviction, but not for flows removed explicitly.
+pub type ExpiryFn<T> = Box<dyn FnMut(FiveTupleV4, T, ExpiryReason) + Send>;
+
+struct FlowEntry<T> {
+    flow: FiveTupleV4,
+    first_seen: u64,
+    last_seen: u64,
+    /// set on each access, cleared by the clock hand
+    referenced: bool,
+    value: T,
+}
+
+/// A per-core table of flow state keyed by the five-tuple, with idle and hard timeouts and a bounded capacity. Entries
+/// are kept in a preallocated slot array, the hash map only maps flows to slots. When the table is full, a slot is
+/// reclaimed with the CLOCK algorithm, an approximation of LRU: the clock hand skips and clears the reference bit of
+/// recently used flows and evicts the first flow which was not used since the hand passed it the last time. Expired
+/// flows are removed when they are looked up, when the clock hand passes them and by `expire`, which should be called
+/// periodically to bound the memory held by idle flows.
+pub struct FlowTable<T> {
+    config: FlowTableConfig,
+    index: HashMap<FiveTupleV4, usize, FnvHash>,
+    slots: Vec<Option<FlowEntry<T>>>,
+    free: Vec<usize>,
+    /// next slot examined for eviction
+    hand: usize,
+    /// next slot examined by `expire`
+    sweep: usize,
+    on_expiry: Option<ExpiryFn<T>>,
+}
+
+impl<T> FlowTable<T> {
+    /// A table for `config.capacity` flows, at least one.
+    pub fn new(config: FlowTableConfig) -> FlowTable<T> {
+        let capacity = config.capacity.max(1);
+        FlowTable {
+            config: FlowTableConfig { capacity, ..config },
+            index: HashMap::with_capacity_and_hasher(capacity, Default::default()),
+            slots: (0..capacity).map(|_| None).collect(),
+            free: (0..capacity).rev().collect(),
+            hand: 0,
+            sweep: 0,
+            on_expiry: None,
+        }
+    }
+
+    /// Call `f` for each flow which expires or is evicted.
+    pub fn on_expiry(&mut self, f: ExpiryFn<T>) {
+        self.on_expiry = Some(f);
+    }
+
+    pub fn config(&self) -> &FlowTableConfig {
+        &self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.config.capacity
+    }
+
+    /// The state of `flow` without marking it as used, an expired flow is returned until it is removed.
+    pub fn peek(&self, flow: &FiveTupleV4) -> Option<&T> {
+        self.index
+            .get(flow)
+            .and_then(|&slot| self.slots[slot].as_ref())
+            .map(|entry| &entry.value)
+    }
+
+    /// The state of `flow`, which is marked as seen at `now`. An expired flow is removed and None is returned.
+    pub fn get_mut(&mut self, flow: &FiveTupleV4, now: u64) -> Option<&mut T> {
+        let slot = self.live_slot(flow, now)?;
+        let entry = self.slots[slot].as_mut().unwrap();
+        entry.last_seen = now;
+        entry.referenced = true;
+        Some(&mut entry.value)
+    }
+
+    /// Insert or replace the state of `flow` seen at `now`, returns the replaced state. Replacing the state of a live
+    /// flow does not restart its hard timeout. If the table is full, a flow is evicted.
+    pub fn insert(&mut self, flow: FiveTupleV4, value: T, now: u64) -> Option<T> {
+        if let Some(slot) = self.live_slot(&flow, now) {
+            let entry = self.slots[slot].as_mut().unwrap();
+            entry.last_seen = now;
+            entry.referenced = true;
+            return Some(mem::replace(&mut entry.value, value));
+        }
+        let slot = self.alloc_slot(now);
+        self.slots[slot] = Some(FlowEntry {
+            flow,
+            first_seen: now,
+            last_seen: now,
+            referenced: true,
+            value,
+        });
+        self.index.insert(flow, slot);
+        None
+    }
+
+    /// The state of `flow` seen at `now`, created with `f` if the flow is not in the table.
+    pub fn get_or_insert_with<F: FnOnce() -> T>(&mut self, flow: FiveTupleV4, now: u64, f: F) -> &mut T {
+        if self.live_slot(&flow, now).is_none() {
+            self.insert(flow, f(), now);
+        }
+        self.get_mut(&flow, now).unwrap()
+    }
+
+    /// Remove `flow` without calling the expiry callback.
+    pub fn remove(&mut self, flow: &FiveTupleV4) -> Option<T> {
+        let slot = self.index.remove(flow)?;
+        self.free.push(slot);
+        self.slots[slot].take().map(|entry| entry.value)
+    }
+
+    /// Remove the flows expired at `now`, examining at most `max_slots` slots from where the previous call stopped, so
+    /// that the work per call can be bounded on the data path. Returns the number of removed flows.
+    pub fn expire(&mut self, now: u64, max_slots: usize) -> usize {
+        let mut expired = 0;
+        for _ in 0..max_slots.min(self.slots.len()) {
+            let slot = self.sweep;
+            self.sweep = (self.sweep + 1) % self.slots.len();
+            if let Some(reason) = self.slots[slot].as_ref().and_then(|entry| self.expiry(entry, now)) {
+                self.release(slot, reason);
+                expired += 1;
+            }
+        }
+        expired
+    }
+
+    /// Remove all flows expired at `now`, returns their number.
+    pub fn expire_all(&mut self, now: u64) -> usize {
+        let slots = self.slots.len();
+        self.expire(now, slots)
+    }
+
+    /// Iterate over the flows and their state.
+    pub fn iter(&self) -> impl Iterator<Item = (&FiveTupleV4, &T)> {
+        self.slots
+            .iter()
+            .filter_map(|slot| slot.as_ref().map(|entry| (&entry.flow, &entry.value)))
+    }
+
+    fn expiry(&self, entry: &FlowEntry<T>, now: u64) -> Option<ExpiryReason> {
+        if self.config.hard_timeout > 0 && now.wrapping_sub(entry.first_seen) >= self.config.hard_timeout {
+            Some(ExpiryReason::Hard)
+        } else if self.config.idle_timeout > 0 && now.wrapping_sub(entry.last_seen) >= self.config.idle_timeout {
+            Some(ExpiryReason::Idle)
+        } else {
+            None
+        }
+    }
+
+    /// the slot of `flow` if it has not expired at `now`, otherwise an expired flow is removed
+    fn live_slot(&mut self, flow: &FiveTupleV4, now: u64) -> Option<usize> {
+        let slot = *self.index.get(flow)?;
+        match self.expiry(self.slots[slot].as_ref().unwrap(), now) {
+            Some(reason) => {
+                self.release(slot, reason);
+                None
+            }
+            None => Some(slot),
+        }
+    }
+
+    fn alloc_slot(&mut self, now: u64) -> usize {
+        if let Some(slot) = self.free.pop() {
+            return slot;
+        }
+        // every slot is occupied, the hand finds an unreferenced or expired flow within two rounds
+        loop {
+            let slot = self.hand;
+            self.hand = (self.hand + 1) % self.slots.len();
+            let reason = {
+                let entry = self.slots[slot].as_mut().unwrap();
+                if entry.referenced {
+                    entry.referenced = false;
+                    None
+                } else {
+                    Some(ExpiryReason::Evicted)
+                }
+            };
+            let reason = self.expiry(self.slots[slot].as_ref().unwrap(), now).or(reason);
+            if let Some(reason) = reason {
+                self.release(slot, reason);
+                return self.free.pop().unwrap();
+            }
+        }
+    }
+
+    fn release(&mut self, slot: usize, reason: ExpiryReason) {
+        if let Some(entry) = self.slots[slot].take() {
+            self.index.remove(&entry.flow);
+            self.free.push(slot);
+            if let Some(ref mut f) = self.on_expiry {
+                f(entry.flow, entry.value, reason);
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use fnv::FnvHasher;
+
+use std::collections::hash_map::{Entry, Iter, IterMut};
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+
+use super::Mergeable;
+use interface::Pdu;
+
+type FnvHash = BuildHasherDefault<FnvHasher>;
+const VEC_SIZE: usize = 1 << 16;
+
+/// Per-session state of a GTP-U user plane function, keyed by the local tunnel endpoint identifier (TEID). The table
+/// hands out TEIDs for new sessions, TEID 0 is reserved and never allocated. Sessions are typically installed by the
+/// control plane before the data plane looks them up.
+pub struct GtpuSessionTable<T> {
+    sessions: HashMap<u32, T, FnvHash>,
+    /// next TEID tried by `allocate`
+    next_teid: u32,
+}
+
+impl<T> GtpuSessionTable<T> {
+    pub fn with_capacity(size: usize) -> GtpuSessionTable<T> {
+        GtpuSessionTable {
+            sessions: HashMap::with_capacity_and_hasher(size, Default::default()),
+            next_teid: 1,
+        }
+    }
+
+    pub fn new() -> GtpuSessionTable<T> {
+        GtpuSessionTable::with_capacity(VEC_SIZE)
+    }
+
+    /// Store a new session under an unused TEID and return the TEID. Returns `None` if all TEIDs are in use.
+    pub fn allocate(&mut self, session: T) -> Option<u32> {
+        if self.sessions.len() >= u32::max_value() as usize {
+            return None;
+        }
+        loop {
+            let teid = self.next_teid;
+            self.next_teid = self.next_teid.wrapping_add(1).max(1);
+            if let Entry::Vacant(e) = self.sessions.entry(teid) {
+                e.insert(session);
+                return Some(teid);
+            }
+        }
+    }
+
+    /// Store a session under a TEID chosen by the caller, e.g. by the peer. Returns the session previously stored under
+    /// this TEID.
+    #[inline]
+    pub fn insert(&mut self, teid: u32, session: T) -> Option<T> {
+        self.sessions.insert(teid, session)
+    }
+
+    #[inline]
+    pub fn get(&self, teid: u32) -> Option<&T> {
+        self.sessions.get(&teid)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, teid: u32) -> Option<&mut T> {
+        self.sessions.get_mut(&teid)
+    }
+
+    #[inline]
+    pub fn remove(&mut self, teid: u32) -> Option<T> {
+        self.sessions.remove(&teid)
+    }
+
+    /// The session a GTP-U packet belongs to, i.e. the session of the TEID in its outer GTP-U header.
+    #[inline]
+    pub fn lookup(&self, pdu: &Pdu) -> Option<&T> {
+        pdu_teid(pdu).and_then(move |teid| self.sessions.get(&teid))
+    }
+
+    #[inline]
+    pub fn lookup_mut(&mut self, pdu: &Pdu) -> Option<&mut T> {
+        match pdu_teid(pdu) {
+            Some(teid) => self.sessions.get_mut(&teid),
+            None => None,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<u32, T> {
+        self.sessions.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<u32, T> {
+        self.sessions.iter_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+impl<T: Mergeable> GtpuSessionTable<T> {
+    /// Merge `inc` into the session, e.g. to update per session counters. Returns false if there is no such session.
+    #[inline]
+    pub fn update(&mut self, teid: u32, inc: T) -> bool {
+        match self.sessions.get_mut(&teid) {
+            Some(session) => {
+                session.merge(inc);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T> Default for GtpuSessionTable<T> {
+    fn default() -> GtpuSessionTable<T> {
+        GtpuSessionTable::new()
+    }
+}
+
+/// TEID of the first GTP-U header of the packet
+#[inline]
+fn pdu_teid(pdu: &Pdu) -> Option<u32> {
+    let headers = pdu.headers();
+    (0..headers.count())
+        .filter_map(|i| headers.get(i).as_gtpu())
+        .next()
+        .map(|gtpu| gtpu.teid())
+}
@@ -0,0 +1,468 @@
+use fnv::FnvHasher;
+
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+use std::mem;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+
+use super::Mergeable;
+use utils::FiveTupleV4;
+
+type FnvHash = BuildHasherDefault<FnvHasher>;
+const DEFAULT_CAPACITY: usize = 128;
+
+/// The quantity by which flows are ranked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeavyHitterMetric {
+    Bytes,
+    Packets,
+}
+
+impl Default for HeavyHitterMetric {
+    fn default() -> HeavyHitterMetric {
+        HeavyHitterMetric::Bytes
+    }
+}
+
+/// Packets and bytes counted for a flow. A flow which replaced another one in a full `HeavyHitters` inherits its count,
+/// `error` bounds the overestimate of the ranked quantity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlowCounter {
+    pub flow: FiveTupleV4,
+    pub packets: u64,
+    pub bytes: u64,
+    pub error: u64,
+}
+
+impl FlowCounter {
+    #[inline]
+    fn count(&self, metric: HeavyHitterMetric) -> u64 {
+        match metric {
+            HeavyHitterMetric::Bytes => self.bytes,
+            HeavyHitterMetric::Packets => self.packets,
+        }
+    }
+}
+
+/// The top flows of a packet stream, tracked with the Space-Saving algorithm in constant space: a flow which is not
+/// tracked replaces the flow with the smallest count when the table is full. Every flow with more than 1/capacity of
+/// the total is tracked. The counters are kept in a min-heap, so updates take logarithmic time.
+///
+/// Merging combines the counters of both summaries and keeps the largest, e.g. the summaries of several cores. As in
+/// the mergeable summaries of Agarwal et al., a flow missing from a full summary is credited with the smallest count of
+/// that summary, which bounds the count the flow may have had there, and the credit is added to its error.
+#[derive(Clone, Debug)]
+pub struct HeavyHitters {
+    capacity: usize,
+    metric: HeavyHitterMetric,
+    heap: Vec<FlowCounter>,
+    index: HashMap<FiveTupleV4, usize, FnvHash>,
+}
+
+impl Default for HeavyHitters {
+    fn default() -> HeavyHitters {
+        HeavyHitters::new(DEFAULT_CAPACITY, HeavyHitterMetric::Bytes)
+    }
+}
+
+impl HeavyHitters {
+    pub fn new(capacity: usize, metric: HeavyHitterMetric) -> HeavyHitters {
+        let capacity = capacity.max(1);
+        HeavyHitters {
+            capacity,
+            metric,
+            heap: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity_and_hasher(capacity, Default::default()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn metric(&self) -> HeavyHitterMetric {
+        self.metric
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.index.clear();
+    }
+
+    /// Count a packet of `bytes` for `flow`.
+    #[inline]
+    pub fn update(&mut self, flow: FiveTupleV4, bytes: u64) {
+        self.add(flow, 1, bytes, 0);
+    }
+
+    pub fn get(&self, flow: &FiveTupleV4) -> Option<&FlowCounter> {
+        self.index.get(flow).map(|&i| &self.heap[i])
+    }
+
+    /// The tracked flows, largest first.
+    pub fn top(&self) -> Vec<FlowCounter> {
+        let mut top = self.heap.clone();
+        let metric = self.metric;
+        top.sort_by(|a, b| b.count(metric).cmp(&a.count(metric)));
+        top
+    }
+
+    /// the counter of the smallest flow of a full summary, which bounds the counts of the flows it does not track
+    fn untracked(&self) -> Option<FlowCounter> {
+        if self.heap.len() < self.capacity {
+            None
+        } else {
+            self.heap.first().cloned()
+        }
+    }
+
+    fn add(&mut self, flow: FiveTupleV4, packets: u64, bytes: u64, error: u64) {
+        if let Some(&i) = self.index.get(&flow) {
+            let counter = &mut self.heap[i];
+            counter.packets += packets;
+            counter.bytes += bytes;
+            counter.error += error;
+            self.sift_down(i);
+        } else if self.heap.len() < self.capacity {
+            self.heap.push(FlowCounter {
+                flow,
+                packets,
+                bytes,
+                error,
+            });
+            let i = self.heap.len() - 1;
+            self.index.insert(flow, i);
+            self.sift_up(i);
+        } else {
+            // replace the smallest flow, whose count bounds the count the new flow may have had before
+            let min = self.heap[0];
+            self.index.remove(&min.flow);
+            let inherited = min.count(self.metric);
+            self.heap[0] = FlowCounter {
+                flow,
+                packets: packets + min.packets,
+                bytes: bytes + min.bytes,
+                error: error + inherited,
+            };
+            self.index.insert(flow, 0);
+            self.sift_down(0);
+        }
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.heap.swap(i, j);
+        self.index.insert(self.heap[i].flow, i);
+        self.index.insert(self.heap[j].flow, j);
+    }
+
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if self.heap[i].count(self.metric) >= self.heap[parent].count(self.metric) {
+                break;
+            }
+            self.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        loop {
+            let mut smallest = i;
+            for child in &[2 * i + 1, 2 * i + 2] {
+                if *child < self.heap.len()
+                    && self.heap[*child].count(self.metric) < self.heap[smallest].count(self.metric)
+                {
+                    smallest = *child;
+                }
+            }
+            if smallest == i {
+                break;
+            }
+            self.swap(i, smallest);
+            i = smallest;
+        }
+    }
+}
+
+impl Mergeable for HeavyHitters {
+    /// The merged summary keeps the larger capacity and the metric of `self`, unless `self` is empty.
+    fn merge(&mut self, other: HeavyHitters) {
+        if self.is_empty() {
+            self.metric = other.metric;
+        }
+        let capacity = self.capacity.max(other.capacity);
+        let metric = self.metric;
+        let untracked = [self.untracked(), other.untracked()];
+        // the counters of a flow in both summaries
+        let mut parts: HashMap<FiveTupleV4, [Option<FlowCounter>; 2], FnvHash> = HashMap::default();
+        for (i, heap) in [&self.heap, &other.heap].iter().enumerate() {
+            for counter in heap.iter() {
+                parts.entry(counter.flow).or_insert([None, None])[i] = Some(*counter);
+            }
+        }
+        let mut counters: Vec<_> = parts
+            .into_iter()
+            .map(|(flow, parts)| {
+                let mut merged = FlowCounter {
+                    flow,
+                    packets: 0,
+                    bytes: 0,
+                    error: 0,
+                };
+                for (part, min) in parts.iter().zip(&untracked) {
+                    match (*part, *min) {
+                        (Some(counter), _) => {
+                            merged.packets += counter.packets;
+                            merged.bytes += counter.bytes;
+                            merged.error += counter.error;
+                        }
+                        (None, Some(min)) => {
+                            merged.packets += min.packets;
+                            merged.bytes += min.bytes;
+                            merged.error += min.count(metric);
+                        }
+                        (None, None) => (),
+                    }
+                }
+                merged
+            })
+            .collect();
+        counters.sort_by(|a, b| b.count(metric).cmp(&a.count(metric)));
+        counters.truncate(capacity);
+        *self = HeavyHitters::new(capacity, metric);
+        for counter in counters {
+            self.add(counter.flow, counter.packets, counter.bytes, counter.error);
+        }
+    }
+}
+
+/// Parameters of the heavy hitter telemetry of each core.
+#[derive(Clone, Copy, Debug)]
+pub struct HeavyHitterConfig {
+    /// flows tracked per core
+    pub capacity: usize,
+    pub metric: HeavyHitterMetric,
+    /// on average one of `sample_rate` packets is exported as a `FlowSample`, 0 disables sampling
+    pub sample_rate: u32,
+    /// samples exported per report at most
+    pub max_samples: usize,
+    /// TSC cycles between reports of a core
+    pub export_interval: u64,
+}
+
+impl Default for HeavyHitterConfig {
+    fn default() -> HeavyHitterConfig {
+        HeavyHitterConfig {
+            capacity: DEFAULT_CAPACITY,
+            metric: HeavyHitterMetric::Bytes,
+            sample_rate: 1024,
+            max_samples: 1024,
+            // about a second at 2 GHz
+            export_interval: 2_000_000_000,
+        }
+    }
+}
+
+/// A sampled packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlowSample {
+    pub flow: FiveTupleV4,
+    /// length of the packet
+    pub bytes: u64,
+    /// TSC when the packet was seen
+    pub timestamp: u64,
+}
+
+/// The top flows and samples of a core during an interval.
+#[derive(Clone, Debug)]
+pub struct HeavyHitterReport {
+    pub exporter: usize,
+    /// TSC of the start and end of the interval
+    pub start: u64,
+    pub end: u64,
+    pub top: HeavyHitters,
+    pub samples: Vec<FlowSample>,
+}
+
+/// Counts the packets of a core and sends a report to the `HeavyHitterCollector` at the end of each interval. Reports
+/// are sent without blocking, if the channel is full the interval is extended to the next export.
+pub struct HeavyHitterExporter {
+    id: usize,
+    config: HeavyHitterConfig,
+    top: HeavyHitters,
+    samples: Vec<FlowSample>,
+    /// packets to skip until the next sample
+    skip: u32,
+    rng: u64,
+    start: Option<u64>,
+    channel: SyncSender<HeavyHitterReport>,
+}
+
+impl HeavyHitterExporter {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn config(&self) -> &HeavyHitterConfig {
+        &self.config
+    }
+
+    /// The summary of the current interval.
+    pub fn top(&self) -> &HeavyHitters {
+        &self.top
+    }
+
+    /// Count a packet of `flow` with `bytes` seen at `now`.
+    #[inline]
+    pub fn record(&mut self, flow: FiveTupleV4, bytes: u64, now: u64) {
+        if self.start.is_none() {
+            self.start = Some(now);
+        }
+        self.top.update(flow, bytes);
+        if self.config.sample_rate > 0 {
+            if self.skip == 0 {
+                if self.samples.len() < self.config.max_samples {
+                    self.samples.push(FlowSample {
+                        flow,
+                        bytes,
+                        timestamp: now,
+                    });
+                }
+                self.skip = self.next_skip();
+            } else {
+                self.skip -= 1;
+            }
+        }
+    }
+
+    /// random gaps with a mean of the sample rate, so that periodic traffic patterns are not aliased
+    fn next_skip(&mut self) -> u32 {
+        // xorshift64*
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let random = self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32;
+        (random % (2 * self.config.sample_rate as u64 - 1)) as u32
+    }
+
+    /// Send the report if the export interval elapsed at `now`.
+    #[inline]
+    pub fn poll(&mut self, now: u64) {
+        if let Some(start) = self.start {
+            if now.wrapping_sub(start) >= self.config.export_interval {
+                self.export(now);
+            }
+        }
+    }
+
+    /// Send the report of the current interval ending at `now` and start a new one. Returns false if the channel is
+    /// full, the interval then continues.
+    pub fn export(&mut self, now: u64) -> bool {
+        let top = HeavyHitters::new(self.config.capacity, self.config.metric);
+        let report = HeavyHitterReport {
+            exporter: self.id,
+            start: self.start.unwrap_or(now),
+            end: now,
+            top: mem::replace(&mut self.top, top),
+            samples: mem::replace(&mut self.samples, Vec::with_capacity(self.config.max_samples)),
+        };
+        match self.channel.try_send(report) {
+            Ok(()) => {
+                self.start = None;
+                true
+            }
+            Err(TrySendError::Full(report)) | Err(TrySendError::Disconnected(report)) => {
+                self.top = report.top;
+                self.samples = report.samples;
+                false
+            }
+        }
+    }
+}
+
+/// Collects the reports of the `HeavyHitterExporter`s of all cores on the control plane.
+pub struct HeavyHitterCollector {
+    config: HeavyHitterConfig,
+    channel: Receiver<HeavyHitterReport>,
+    sender: SyncSender<HeavyHitterReport>,
+    exporters: usize,
+    /// last report of each exporter
+    reports: HashMap<usize, HeavyHitterReport, FnvHash>,
+    samples: Vec<FlowSample>,
+}
+
+impl HeavyHitterCollector {
+    /// A collector for exporters with `config`, `channel_size` reports can be outstanding.
+    pub fn new(config: HeavyHitterConfig, channel_size: usize) -> HeavyHitterCollector {
+        let (sender, receiver) = sync_channel(channel_size);
+        HeavyHitterCollector {
+            config,
+            channel: receiver,
+            sender,
+            exporters: 0,
+            reports: HashMap::default(),
+            samples: Vec::new(),
+        }
+    }
+
+    /// An exporter for a core.
+    pub fn exporter(&mut self) -> HeavyHitterExporter {
+        let id = self.exporters;
+        self.exporters += 1;
+        let mut exporter = HeavyHitterExporter {
+            id,
+            config: self.config,
+            top: HeavyHitters::new(self.config.capacity, self.config.metric),
+            samples: Vec::with_capacity(self.config.max_samples),
+            skip: 0,
+            rng: 0x9e37_79b9_7f4a_7c15 ^ (id as u64 + 1).wrapping_mul(0xbf58_476d_1ce4_e5b9),
+            start: None,
+            channel: self.sender.clone(),
+        };
+        if self.config.sample_rate > 0 {
+            exporter.skip = exporter.next_skip();
+        }
+        exporter
+    }
+
+    /// Call periodically to drain the reports, returns their number.
+    pub fn recv(&mut self) -> usize {
+        let mut received = 0;
+        while let Ok(mut report) = self.channel.try_recv() {
+            self.samples.append(&mut report.samples);
+            self.reports.insert(report.exporter, report);
+            received += 1;
+        }
+        received
+    }
+
+    /// The last report of each exporter.
+    pub fn reports(&self) -> impl Iterator<Item = &HeavyHitterReport> {
+        self.reports.values()
+    }
+
+    /// The top `n` flows of the last reports of all exporters.
+    pub fn top(&self, n: usize) -> Vec<FlowCounter> {
+        let mut merged = HeavyHitters::new(self.config.capacity, self.config.metric);
+        for report in self.reports.values() {
+            merged.merge(report.top.clone());
+        }
+        let mut top = merged.top();
+        top.truncate(n);
+        top
+    }
+
+    /// The samples received since the last call, they are kept until they are taken.
+    pub fn take_samples(&mut self) -> Vec<FlowSample> {
+        mem::take(&mut self.samples)
+    }
+}
@@ -0,0 +1,325 @@
+use common::errors;
+use common::errors::ErrorKind;
+use fnv::FnvHasher;
+
+use std::hash::{Hash, Hasher};
+use std::ops::AddAssign;
+
+/// State which is updated on the data path and combined across cores by the mergeable stores. `merge` combines the
+/// state with an update or with the state of another core, it must be commutative and associative since the order of
+/// merges is not defined. The default value is the identity of the merge.
+///
+/// Every `AddAssign` type is `Mergeable` by adding, e.g. counters.
+pub trait Mergeable {
+    fn merge(&mut self, other: Self);
+}
+
+impl<T: AddAssign<T>> Mergeable for T {
+    #[inline]
+    fn merge(&mut self, other: T) {
+        *self += other;
+    }
+}
+
+/// The maximum of the merged values.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Max<T>(pub Option<T>);
+
+impl<T> Max<T> {
+    pub fn new(value: T) -> Max<T> {
+        Max(Some(value))
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.0.as_ref()
+    }
+}
+
+impl<T: PartialOrd> Mergeable for Max<T> {
+    fn merge(&mut self, other: Max<T>) {
+        if let Some(value) = other.0 {
+            if self.0.as_ref().map_or(true, |current| value > *current) {
+                self.0 = Some(value);
+            }
+        }
+    }
+}
+
+/// The minimum of the merged values.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Min<T>(pub Option<T>);
+
+impl<T> Min<T> {
+    pub fn new(value: T) -> Min<T> {
+        Min(Some(value))
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.0.as_ref()
+    }
+}
+
+impl<T: PartialOrd> Mergeable for Min<T> {
+    fn merge(&mut self, other: Min<T>) {
+        if let Some(value) = other.0 {
+            if self.0.as_ref().map_or(true, |current| value < *current) {
+                self.0 = Some(value);
+            }
+        }
+    }
+}
+
+/// The value with the latest timestamp, e.g. the TSC. Of values with the same timestamp the larger one wins, so that
+/// merges are commutative.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LastWriter<T> {
+    pub timestamp: u64,
+    pub value: Option<T>,
+}
+
+impl<T> LastWriter<T> {
+    pub fn new(timestamp: u64, value: T) -> LastWriter<T> {
+        LastWriter {
+            timestamp,
+            value: Some(value),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+}
+
+impl<T: PartialOrd> Mergeable for LastWriter<T> {
+    fn merge(&mut self, other: LastWriter<T>) {
+        let newer = match (&self.value, &other.value) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(current), Some(value)) => {
+                other.timestamp > self.timestamp || (other.timestamp == self.timestamp && value > current)
+            }
+        };
+        if newer {
+            *self = other;
+        }
+    }
+}
+
+/// 64-bit hash of `item`, FNV followed by the finalizer of MurmurHash3 so that all bits depend on the input.
+#[inline]
+fn sketch_hash<H: Hash + ?Sized>(item: &H) -> u64 {
+    let mut hasher = FnvHasher::default();
+    item.hash(&mut hasher);
+    let mut h = hasher.finish();
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+pub const HLL_DEFAULT_PRECISION: u8 = 10;
+
+/// HyperLogLog estimate of the number of distinct items, e.g. of the sources sending to a prefix. A sketch with
+/// precision `p` takes 2^p bytes and has a standard error of about 1.04 / sqrt(2^p). Sketches can only be merged with
+/// sketches of the same precision, `merge` skips others. The default sketch is empty and takes the precision of the
+/// first merged sketch, or `HLL_DEFAULT_PRECISION` when an item is inserted.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    /// A sketch with 2^`precision` registers, `precision` is clamped to 4..=16.
+    pub fn new(precision: u8) -> HyperLogLog {
+        let precision = precision.max(4).min(16);
+        HyperLogLog {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    /// A sketch containing `item`, to be merged into the state of a flow.
+    pub fn with_item<H: Hash + ?Sized>(precision: u8, item: &H) -> HyperLogLog {
+        let mut hll = HyperLogLog::new(precision);
+        hll.insert(item);
+        hll
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    pub fn insert<H: Hash + ?Sized>(&mut self, item: &H) {
+        self.insert_hash(sketch_hash(item));
+    }
+
+    /// Insert an item by its uniformly distributed 64-bit hash.
+    pub fn insert_hash(&mut self, hash: u64) {
+        if self.registers.is_empty() {
+            *self = HyperLogLog::new(HLL_DEFAULT_PRECISION);
+        }
+        let p = self.precision as u32;
+        let index = (hash >> (64 - p)) as usize;
+        // position of the first set bit of the remaining bits, at most 64 - p + 1
+        let rank = ((hash << p) | (1 << (p - 1))).leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// Estimated number of distinct items.
+    pub fn estimate(&self) -> f64 {
+        if self.registers.is_empty() {
+            return 0.0;
+        }
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // linear counting is more accurate for small cardinalities
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        }
+    }
+
+    /// Merge `other` into this sketch, fails if both sketches are not empty and have a different precision.
+    pub fn try_merge(&mut self, other: HyperLogLog) -> errors::Result<()> {
+        if other.registers.is_empty() {
+            return Ok(());
+        }
+        if self.registers.is_empty() {
+            *self = other;
+            return Ok(());
+        }
+        if self.precision != other.precision {
+            return Err(ErrorKind::RunTimeError(format!(
+                "cannot merge HyperLogLog sketches of precision {} and {}",
+                self.precision, other.precision
+            )));
+        }
+        for (register, other) in self.registers.iter_mut().zip(other.registers) {
+            if other > *register {
+                *register = other;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Mergeable for HyperLogLog {
+    /// Skips `other` if both sketches are not empty and have a different precision, see `try_merge`.
+    fn merge(&mut self, other: HyperLogLog) {
+        let _ = self.try_merge(other);
+    }
+}
+
+pub const CMS_DEFAULT_WIDTH: usize = 1024;
+pub const CMS_DEFAULT_DEPTH: usize = 4;
+
+/// Count-Min sketch of the frequencies of items, e.g. of the bytes sent by each source of a prefix. Estimates never
+/// underestimate, and overestimate by at most e/width of the total count with probability 1 - e^-depth. Sketches can
+/// only be merged with sketches of the same dimensions, `merge` skips others. The default sketch is empty and takes
+/// the dimensions of the first merged sketch, or `CMS_DEFAULT_WIDTH` and `CMS_DEFAULT_DEPTH` when an item is added.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Vec<u64>,
+    total: u64,
+}
+
+impl CountMinSketch {
+    pub fn new(width: usize, depth: usize) -> CountMinSketch {
+        let (width, depth) = (width.max(1), depth.max(1));
+        CountMinSketch {
+            width,
+            depth,
+            counters: vec![0; width * depth],
+            total: 0,
+        }
+    }
+
+    /// A sketch counting `count` for `item`, to be merged into the state of a flow.
+    pub fn with_item<H: Hash + ?Sized>(width: usize, depth: usize, item: &H, count: u64) -> CountMinSketch {
+        let mut sketch = CountMinSketch::new(width, depth);
+        sketch.add(item, count);
+        sketch
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Sum of all counts.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// the counter of `item` in each row, derived from a single hash by double hashing
+    #[inline]
+    fn cells(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let (width, h1, h2) = (self.width as u64, hash as u32 as u64, (hash >> 32) | 1);
+        (0..self.depth as u64).map(move |row| (row * width + h1.wrapping_add(row.wrapping_mul(h2)) % width) as usize)
+    }
+
+    /// Add `count` to the frequency of `item`.
+    pub fn add<H: Hash + ?Sized>(&mut self, item: &H, count: u64) {
+        if self.counters.is_empty() {
+            *self = CountMinSketch::new(CMS_DEFAULT_WIDTH, CMS_DEFAULT_DEPTH);
+        }
+        let hash = sketch_hash(item);
+        for cell in self.cells(hash) {
+            self.counters[cell] = self.counters[cell].saturating_add(count);
+        }
+        self.total = self.total.saturating_add(count);
+    }
+
+    /// Estimated frequency of `item`.
+    pub fn estimate<H: Hash + ?Sized>(&self, item: &H) -> u64 {
+        let hash = sketch_hash(item);
+        self.cells(hash).map(|cell| self.counters[cell]).min().unwrap_or(0)
+    }
+
+    /// Merge `other` into this sketch, fails if both sketches are not empty and have different dimensions.
+    pub fn try_merge(&mut self, other: CountMinSketch) -> errors::Result<()> {
+        if other.counters.is_empty() {
+            return Ok(());
+        }
+        if self.counters.is_empty() {
+            *self = other;
+            return Ok(());
+        }
+        if self.width != other.width || self.depth != other.depth {
+            return Err(ErrorKind::RunTimeError(format!(
+                "cannot merge Count-Min sketches of {}x{} and {}x{} counters",
+                self.width, self.depth, other.width, other.depth
+            )));
+        }
+        for (counter, other) in self.counters.iter_mut().zip(other.counters) {
+            *counter = counter.saturating_add(other);
+        }
+        self.total = self.total.saturating_add(other.total);
+        Ok(())
+    }
+}
+
+impl Mergeable for CountMinSketch {
+    /// Skips `other` if both sketches are not empty and have different dimensions, see `try_merge`.
+    fn merge(&mut self, other: CountMinSketch) {
+        let _ = self.try_merge(other);
+    }
+}
@@ -1,81 +1,169 @@
 use fnv::FnvHasher;
-use std::cmp::max;
 
 use std::collections::hash_map::Iter;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::BuildHasherDefault;
-use std::ops::AddAssign;
-use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use super::Mergeable;
 use utils::FiveTupleV4;
 
 /// A generic store for associating some merge-able type with each flow. Note, the merge must be commutative, we do not
-/// guarantee ordering for things being merged. The merge function is implemented by implementing the
-/// [`AddAssign`](https://doc.rust-lang.org/std/ops/trait.AddAssign.html) trait and overriding the `add_assign` method
-/// there. We assume that the quantity stored here does not need to be accessed by the control plane and can only be
-/// accessed from the data plane. The `cache_size` should be tuned depending on whether gets or puts are the most common
-/// operation in this table.
+/// guarantee ordering for things being merged. The merge function is implemented by implementing the `Mergeable`
+/// trait, which all [`AddAssign`](https://doc.rust-lang.org/std/ops/trait.AddAssign.html) types implement by adding,
+/// see `Max`, `Min`, `LastWriter`, `HyperLogLog` and `CountMinSketch` for other merges. Each data path store
+/// (`MergeableStoreDP`) owns its table, the control plane merges snapshots of them.
+///
+/// Snapshots are taken in epochs: the control plane requests a new epoch, and each data path store sends the flows
+/// which changed since its previous snapshot over a channel when it notices the request, i.e. in its next `update`
+/// or `publish`. Data path updates never take a lock or wait for the control plane, and the control plane always holds
+/// the complete state of each store at the epoch of its last snapshot.
 ///
 /// #[TODO]
-/// Garbage collection.
-/// The current version does not work well with large flow tables. The problem is we need to record a set of differences
-/// rather than copying the entire hashmap. This of course comes with some consistency issues, so we need to fix this.
+/// Garbage collection, flows are never removed.
 type FnvHash = BuildHasherDefault<FnvHasher>;
 const VEC_SIZE: usize = 1 << 10;
 const CACHE_SIZE: usize = 1 << 10;
-const MAX_CACHE_SIZE: usize = 1 << 20;
-const CHAN_SIZE: usize = 128;
 
-pub struct MergeableStoreCP<T: AddAssign<T> + Default + Clone> {
+/// Epochs shared by the control plane and the data path stores.
+struct EpochControl {
+    /// last epoch requested by the control plane
+    requested: AtomicUsize,
+    /// number of data path stores created
+    stores: AtomicUsize,
+}
+
+/// The flows of a data path store which changed since its previous snapshot, removed flows have no value.
+struct Snapshot<T> {
+    store: usize,
+    epoch: usize,
+    changes: Vec<(FiveTupleV4, Option<T>)>,
+    /// the store was dropped, this is its last snapshot
+    closed: bool,
+}
+
+/// The state of a data path store as known to the control plane.
+struct StoreView<T> {
+    epoch: usize,
+    closed: bool,
     flow_counters: HashMap<FiveTupleV4, T, FnvHash>,
-    hashmaps: Vec<Arc<RwLock<HashMap<FiveTupleV4, T, FnvHash>>>>,
 }
 
-impl<T: AddAssign<T> + Default + Clone> MergeableStoreCP<T> {
+pub struct MergeableStoreCP<T: Mergeable + Default + Clone> {
+    /// merged state of all stores
+    flow_counters: HashMap<FiveTupleV4, T, FnvHash>,
+    stores: HashMap<usize, StoreView<T>, FnvHash>,
+    control: Arc<EpochControl>,
+    sender: Sender<Snapshot<T>>,
+    receiver: Receiver<Snapshot<T>>,
+    /// snapshots were received since the merged state was computed
+    dirty: bool,
+}
+
+impl<T: Mergeable + Default + Clone> MergeableStoreCP<T> {
     pub fn new() -> MergeableStoreCP<T> {
+        let (sender, receiver) = channel();
         MergeableStoreCP {
             flow_counters: HashMap::with_capacity_and_hasher(VEC_SIZE << 6, Default::default()),
-            hashmaps: Vec::with_capacity(CHAN_SIZE),
+            stores: HashMap::default(),
+            control: Arc::new(EpochControl {
+                requested: AtomicUsize::new(0),
+                stores: AtomicUsize::new(0),
+            }),
+            sender,
+            receiver,
+            dirty: false,
         }
     }
 
     pub fn dp_store_with_cache_and_size(&mut self, cache: usize, size: usize) -> MergeableStoreDP<T> {
-        let hmap = Arc::new(RwLock::new(HashMap::with_capacity_and_hasher(size, Default::default())));
-        self.hashmaps.push(hmap.clone());
-        MergeableStoreDP {
-            flow_counters: hmap,
-            cache: Vec::with_capacity(cache),
-            cache_size: cache,
-            base_cache_size: cache,
-            len: 0,
-        }
+        MergeableStoreDP::register(&self.control, &self.sender, cache, size)
     }
 
     pub fn dp_store(&mut self) -> MergeableStoreDP<T> {
         MergeableStoreCP::dp_store_with_cache_and_size(self, CACHE_SIZE, VEC_SIZE)
     }
 
-    fn hmap_to_vec(hash: &RwLockReadGuard<HashMap<FiveTupleV4, T, FnvHash>>) -> Vec<(FiveTupleV4, T)> {
-        let mut t = Vec::with_capacity(hash.len());
-        t.extend(hash.iter().map(|(f, v)| (*f, v.clone())));
-        t
+    /// Last epoch requested from the data path stores.
+    pub fn epoch(&self) -> usize {
+        self.control.requested.load(Ordering::Acquire)
     }
 
-    pub fn sync(&mut self) {
-        let mut copies: Vec<Vec<_>> = Vec::with_capacity(self.hashmaps.len());
-        {
-            for hmap in &self.hashmaps {
-                {
-                    if let Ok(g) = hmap.try_read() {
-                        copies.push(MergeableStoreCP::hmap_to_vec(&g));
-                    }
-                }
+    /// Merge the snapshots received from the data path stores without waiting and request the next epoch. Stores
+    /// which did not answer yet, e.g. idle ones, are merged with their last snapshot. Returns true if every store sent
+    /// its snapshot of the epoch requested by the previous call. Calling this periodically yields the state of the
+    /// previous period.
+    pub fn sync(&mut self) -> bool {
+        while let Ok(snapshot) = self.receiver.try_recv() {
+            self.apply(snapshot);
+        }
+        self.merge_views();
+        let complete = self.complete();
+        self.control.requested.fetch_add(1, Ordering::AcqRel);
+        complete
+    }
+
+    /// Request a new epoch and wait up to `timeout` for the snapshots of all data path stores. Returns false if a store
+    /// did not answer in time, e.g. because it did not see any packets and the application does not call `publish`
+    /// while idle. The merged state then contains the last snapshot of such stores.
+    pub fn sync_timeout(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        self.control.requested.fetch_add(1, Ordering::AcqRel);
+        while !self.complete() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match self.receiver.recv_timeout(deadline - now) {
+                Ok(snapshot) => self.apply(snapshot),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
             }
         }
+        self.merge_views();
+        self.complete()
+    }
+
+    fn apply(&mut self, snapshot: Snapshot<T>) {
+        let view = self.stores.entry(snapshot.store).or_insert_with(|| StoreView {
+            epoch: 0,
+            closed: false,
+            flow_counters: HashMap::default(),
+        });
+        for (flow, value) in snapshot.changes {
+            match value {
+                Some(value) => view.flow_counters.insert(flow, value),
+                None => view.flow_counters.remove(&flow),
+            };
+        }
+        view.epoch = view.epoch.max(snapshot.epoch);
+        view.closed |= snapshot.closed;
+        self.dirty = true;
+    }
+
+    /// all stores sent a snapshot of the requested epoch
+    fn complete(&self) -> bool {
+        let requested = self.control.requested.load(Ordering::Acquire);
+        self.stores.len() == self.control.stores.load(Ordering::Acquire)
+            && self.stores.values().all(|view| view.closed || view.epoch >= requested)
+    }
+
+    fn merge_views(&mut self) {
+        if !self.dirty {
+            return;
+        }
         self.flow_counters.clear();
-        for mut copy in copies {
-            self.flow_counters.extend(copy.drain(0..));
+        for view in self.stores.values() {
+            for (flow, value) in &view.flow_counters {
+                self.flow_counters
+                    .entry(*flow)
+                    .or_insert_with(Default::default)
+                    .merge(value.clone());
+            }
         }
+        self.dirty = false;
     }
 
     pub fn get(&self, flow: &FiveTupleV4) -> T {
@@ -98,60 +186,117 @@ impl<T: AddAssign<T> + Default + Clone> MergeableStoreCP<T> {
     }
 }
 
-#[derive(Clone)]
-pub struct MergeableStoreDP<T: AddAssign<T> + Default + Clone> {
+/// The data path side of a `MergeableStoreCP`, use `MergeableStoreCP::dp_store` for each pipeline.
+pub struct MergeableStoreDP<T: Mergeable + Default + Clone> {
+    id: usize,
     /// Contains the counts on the data path.
-    flow_counters: Arc<RwLock<HashMap<FiveTupleV4, T, FnvHash>>>,
+    flow_counters: HashMap<FiveTupleV4, T, FnvHash>,
+    /// flows updated or removed since the last snapshot
+    changed: HashSet<FiveTupleV4, FnvHash>,
     cache: Vec<(FiveTupleV4, T)>,
-    base_cache_size: usize,
     cache_size: usize,
-    len: usize,
+    /// epoch of the last snapshot
+    published: usize,
+    control: Arc<EpochControl>,
+    sender: Sender<Snapshot<T>>,
 }
 
-impl<T: AddAssign<T> + Default + Clone> MergeableStoreDP<T> {
+impl<T: Mergeable + Default + Clone> MergeableStoreDP<T> {
+    fn register(
+        control: &Arc<EpochControl>,
+        sender: &Sender<Snapshot<T>>,
+        cache_size: usize,
+        size: usize,
+    ) -> MergeableStoreDP<T> {
+        let store = MergeableStoreDP {
+            id: control.stores.fetch_add(1, Ordering::AcqRel),
+            flow_counters: HashMap::with_capacity_and_hasher(size, Default::default()),
+            changed: HashSet::with_capacity_and_hasher(cache_size, Default::default()),
+            cache: Vec::with_capacity(cache_size),
+            cache_size: cache_size.max(1),
+            published: control.requested.load(Ordering::Acquire),
+            control: control.clone(),
+            sender: sender.clone(),
+        };
+        // announce the empty store, so that the control plane knows that the current epoch is complete for it
+        store.send(store.published, Vec::new(), false);
+        store
+    }
+
     fn merge_cache(&mut self) {
-        match self.flow_counters.try_write() {
-            Ok(mut g) => {
-                g.extend(self.cache.drain(0..));
-                self.cache_size = self.base_cache_size;
-                self.len = g.len();
-            }
-            _ => self.cache_size = max(self.cache_size * 2, MAX_CACHE_SIZE),
+        for (flow, inc) in self.cache.drain(0..) {
+            self.flow_counters
+                .entry(flow)
+                .or_insert_with(Default::default)
+                .merge(inc);
+            self.changed.insert(flow);
         }
     }
 
+    fn send(&self, epoch: usize, changes: Vec<(FiveTupleV4, Option<T>)>, closed: bool) {
+        // the control plane may be gone already, nobody is interested in the state then
+        let _ = self.sender.send(Snapshot {
+            store: self.id,
+            epoch,
+            changes,
+            closed,
+        });
+    }
+
+    fn take_changes(&mut self) -> Vec<(FiveTupleV4, Option<T>)> {
+        self.merge_cache();
+        let flow_counters = &self.flow_counters;
+        self.changed
+            .drain()
+            .map(|flow| (flow, flow_counters.get(&flow).cloned()))
+            .collect()
+    }
+
     /// Change the value for the given `Flow`.
     #[inline]
     pub fn update(&mut self, flow: FiveTupleV4, inc: T) {
-        {
-            self.cache.push((flow, inc));
-        }
+        self.cache.push((flow, inc));
         if self.cache.len() >= self.cache_size {
             self.merge_cache();
         }
+        if self.control.requested.load(Ordering::Relaxed) != self.published {
+            self.publish();
+        }
+    }
+
+    /// Send a snapshot if the control plane requested one. `update` does this as well, stores which may not be updated
+    /// for a while should be published from time to time so that `MergeableStoreCP::sync` does not wait for them.
+    pub fn publish(&mut self) {
+        let requested = self.control.requested.load(Ordering::Acquire);
+        if requested != self.published {
+            let changes = self.take_changes();
+            self.send(requested, changes, false);
+            self.published = requested;
+        }
     }
 
     /// Remove an entry from the table.
     #[inline]
     pub fn remove(&mut self, flow: &FiveTupleV4) -> T {
-        // self.merge_cache();
-        match self.flow_counters.write() {
-            Ok(mut g) => {
-                g.extend(self.cache.drain(0..));
-                self.cache_size = self.base_cache_size;
-                self.len = g.len();
-                g.remove(flow).unwrap_or_else(Default::default)
-            }
-            _ => panic!("Could not acquire write lock"),
-        }
+        self.merge_cache();
+        self.changed.insert(*flow);
+        self.flow_counters.remove(flow).unwrap_or_else(Default::default)
     }
 
-    /// Approximate length of the table.
-    pub fn len(&mut self) -> usize {
-        self.len
+    /// Length of the table, not counting flows only updated since the last merge of the cache.
+    pub fn len(&self) -> usize {
+        self.flow_counters.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.len != 0
+        self.flow_counters.is_empty() && self.cache.is_empty()
+    }
+}
+
+impl<T: Mergeable + Default + Clone> Drop for MergeableStoreDP<T> {
+    fn drop(&mut self) {
+        let changes = self.take_changes();
+        let published = self.published;
+        self.send(published, changes, true);
     }
 }
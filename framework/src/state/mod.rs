@@ -1,10 +1,22 @@
 pub use self::cp_mergeable::*;
 pub use self::dp_mergeable::*;
+pub use self::flow_table::*;
+pub use self::gtpu_sessions::*;
+pub use self::heavy_hitters::*;
+pub use self::merge::*;
 pub use self::mergeable::*;
+pub use self::neighbor_cache::*;
+pub use self::reassembly::*;
 pub use self::reordered_buffer::*;
 pub use self::ring_buffer::*;
 mod cp_mergeable;
 mod dp_mergeable;
+mod flow_table;
+mod gtpu_sessions;
+mod heavy_hitters;
+mod merge;
 mod mergeable;
+mod neighbor_cache;
+mod reassembly;
 pub mod reordered_buffer;
 mod ring_buffer;
@@ -0,0 +1,274 @@
+use eui48::MacAddress;
+use fnv::FnvHasher;
+
+use std::collections::hash_map::Iter;
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hash};
+use std::time::{Duration, Instant};
+
+type FnvHash = BuildHasherDefault<FnvHasher>;
+
+/// Timers and limits of a `NeighborCache`.
+#[derive(Clone, Debug)]
+pub struct NeighborConfig {
+    /// a neighbor is considered reachable for this time after its address was learned or confirmed, afterwards it is
+    /// probed again while its address is still used
+    pub reachable_time: Duration,
+    /// unused entries are removed this time after their last confirmation
+    pub stale_time: Duration,
+    /// time between solicitations for the same address
+    pub retry_interval: Duration,
+    /// solicitations sent before a neighbor is considered unreachable
+    pub max_retries: u32,
+    /// unreachable neighbors are not solicited again for this time
+    pub failed_time: Duration,
+    pub max_entries: usize,
+}
+
+impl Default for NeighborConfig {
+    fn default() -> NeighborConfig {
+        NeighborConfig {
+            reachable_time: Duration::from_secs(30),
+            stale_time: Duration::from_secs(300),
+            retry_interval: Duration::from_secs(1),
+            max_retries: 3,
+            failed_time: Duration::from_secs(20),
+            max_entries: 1024,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NeighborState {
+    /// address resolution in progress, `sent` is the time of the last solicitation
+    Incomplete {
+        sent: Option<Instant>,
+        retries: u32,
+    },
+    Reachable {
+        mac: MacAddress,
+        confirmed: Instant,
+    },
+    /// the address is in use but was not confirmed within the reachable time, it is solicited again
+    Probe {
+        mac: MacAddress,
+        confirmed: Instant,
+        sent: Option<Instant>,
+        retries: u32,
+    },
+    /// the neighbor did not answer
+    Failed {
+        since: Instant,
+    },
+    /// configured address, never expires
+    Static {
+        mac: MacAddress,
+    },
+}
+
+/// Outcome of resolving an address.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    Resolved(MacAddress),
+    /// resolution is in progress, solicitations are returned by `NeighborCache::poll`
+    Pending,
+    /// the neighbor did not answer or the cache is full
+    Unreachable,
+}
+
+/// Maps protocol addresses (IPv4 for ARP, IPv6 for NDP) of neighbors to their MAC addresses. The cache implements the
+/// timers of address resolution, sending solicitations is up to the protocol: `poll` returns the addresses to solicit.
+pub struct NeighborCache<A: Copy + Eq + Hash> {
+    config: NeighborConfig,
+    entries: HashMap<A, NeighborState, FnvHash>,
+}
+
+impl<A: Copy + Eq + Hash> NeighborCache<A> {
+    pub fn new(config: NeighborConfig) -> NeighborCache<A> {
+        NeighborCache {
+            entries: HashMap::with_capacity_and_hasher(config.max_entries, Default::default()),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &NeighborConfig {
+        &self.config
+    }
+
+    /// The MAC address of `addr`. Unknown addresses are added to the cache and solicited by the next `poll`, stale
+    /// addresses are returned and probed.
+    pub fn resolve(&mut self, addr: A, now: Instant) -> Resolution {
+        let config = &self.config;
+        if let Some(state) = self.entries.get_mut(&addr) {
+            let (resolution, next) = match *state {
+                NeighborState::Static { mac } => (Resolution::Resolved(mac), None),
+                NeighborState::Reachable { mac, confirmed } => {
+                    if now.duration_since(confirmed) < config.reachable_time {
+                        (Resolution::Resolved(mac), None)
+                    } else {
+                        let probe = NeighborState::Probe {
+                            mac,
+                            confirmed,
+                            sent: None,
+                            retries: 0,
+                        };
+                        (Resolution::Resolved(mac), Some(probe))
+                    }
+                }
+                NeighborState::Probe { mac, .. } => (Resolution::Resolved(mac), None),
+                NeighborState::Incomplete { .. } => (Resolution::Pending, None),
+                NeighborState::Failed { since } => {
+                    if now.duration_since(since) < config.failed_time {
+                        (Resolution::Unreachable, None)
+                    } else {
+                        let incomplete = NeighborState::Incomplete { sent: None, retries: 0 };
+                        (Resolution::Pending, Some(incomplete))
+                    }
+                }
+            };
+            if let Some(next) = next {
+                *state = next;
+            }
+            return resolution;
+        }
+        if self.entries.len() >= self.config.max_entries {
+            self.entries.retain(|_, state| match *state {
+                NeighborState::Failed { .. } => false,
+                _ => true,
+            });
+            if self.entries.len() >= self.config.max_entries {
+                return Resolution::Unreachable;
+            }
+        }
+        self.entries
+            .insert(addr, NeighborState::Incomplete { sent: None, retries: 0 });
+        Resolution::Pending
+    }
+
+    /// The outcome of `resolve` if resolving `addr` does not change the cache, e.g. for a neighbor which is
+    /// reachable, None otherwise.
+    pub fn lookup(&self, addr: &A, now: Instant) -> Option<Resolution> {
+        match self.entries.get(addr) {
+            Some(&NeighborState::Static { mac }) | Some(&NeighborState::Probe { mac, .. }) => {
+                Some(Resolution::Resolved(mac))
+            }
+            Some(&NeighborState::Reachable { mac, confirmed })
+                if now.duration_since(confirmed) < self.config.reachable_time =>
+            {
+                Some(Resolution::Resolved(mac))
+            }
+            Some(&NeighborState::Incomplete { .. }) => Some(Resolution::Pending),
+            Some(&NeighborState::Failed { since }) if now.duration_since(since) < self.config.failed_time => {
+                Some(Resolution::Unreachable)
+            }
+            _ => None,
+        }
+    }
+
+    /// The MAC address of `addr` if it is known, without starting address resolution.
+    pub fn get(&self, addr: &A) -> Option<MacAddress> {
+        match self.entries.get(addr) {
+            Some(&NeighborState::Static { mac })
+            | Some(&NeighborState::Reachable { mac, .. })
+            | Some(&NeighborState::Probe { mac, .. }) => Some(mac),
+            _ => None,
+        }
+    }
+
+    pub fn state(&self, addr: &A) -> Option<&NeighborState> {
+        self.entries.get(addr)
+    }
+
+    /// Learn or confirm the MAC address of a neighbor, e.g. from a reply or from a solicitation sent to us.
+    pub fn update(&mut self, addr: A, mac: MacAddress, now: Instant) {
+        match self.entries.get(&addr) {
+            Some(&NeighborState::Static { .. }) => return,
+            None if self.entries.len() >= self.config.max_entries => return,
+            _ => (),
+        }
+        self.entries
+            .insert(addr, NeighborState::Reachable { mac, confirmed: now });
+    }
+
+    /// Update the MAC address of a neighbor only if it is in the cache already, returns true if it is.
+    pub fn update_existing(&mut self, addr: A, mac: MacAddress, now: Instant) -> bool {
+        if self.entries.contains_key(&addr) {
+            self.update(addr, mac, now);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Add a static entry, it replaces a learned one.
+    pub fn insert_static(&mut self, addr: A, mac: MacAddress) {
+        self.entries.insert(addr, NeighborState::Static { mac });
+    }
+
+    pub fn remove(&mut self, addr: &A) -> Option<NeighborState> {
+        self.entries.remove(addr)
+    }
+
+    /// Advance the timers: returns the addresses which need to be solicited now, marks neighbors which did not answer
+    /// as failed and removes expired entries.
+    pub fn poll(&mut self, now: Instant) -> Vec<A> {
+        let config = &self.config;
+        let mut solicit = Vec::new();
+        let due = |sent: Option<Instant>| sent.map_or(true, |sent| now.duration_since(sent) >= config.retry_interval);
+        self.entries.retain(|addr, state| {
+            let next = match *state {
+                NeighborState::Incomplete { sent, retries } if due(sent) => {
+                    if retries >= config.max_retries {
+                        Some(NeighborState::Failed { since: now })
+                    } else {
+                        solicit.push(*addr);
+                        Some(NeighborState::Incomplete {
+                            sent: Some(now),
+                            retries: retries + 1,
+                        })
+                    }
+                }
+                NeighborState::Probe {
+                    mac,
+                    confirmed,
+                    sent,
+                    retries,
+                } if due(sent) => {
+                    if retries >= config.max_retries {
+                        Some(NeighborState::Failed { since: now })
+                    } else {
+                        solicit.push(*addr);
+                        Some(NeighborState::Probe {
+                            mac,
+                            confirmed,
+                            sent: Some(now),
+                            retries: retries + 1,
+                        })
+                    }
+                }
+                NeighborState::Reachable { confirmed, .. } if now.duration_since(confirmed) >= config.stale_time => {
+                    return false;
+                }
+                NeighborState::Failed { since } if now.duration_since(since) >= config.failed_time => return false,
+                _ => None,
+            };
+            if let Some(next) = next {
+                *state = next;
+            }
+            true
+        });
+        solicit
+    }
+
+    pub fn iter(&self) -> Iter<A, NeighborState> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
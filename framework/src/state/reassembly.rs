@@ -0,0 +1,336 @@
+use fnv::FnvHasher;
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+use std::time::{Duration, Instant};
+
+use common::errors;
+use interface::{FragmentInfo, FragmentKey, Pdu, MAX_DATAGRAM_SIZE};
+
+type FnvHash = BuildHasherDefault<FnvHasher>;
+
+/// How fragments overlapping already received data are treated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverlapPolicy {
+    /// discard the whole datagram, as required for IPv6 by RFC 5722
+    Drop,
+    /// keep the data received first
+    First,
+    /// the data received last overwrites earlier data
+    Last,
+}
+
+/// Limits of a `Reassembler`. Fragments exceeding a limit are dropped, memory usage is bounded by `max_bytes` of
+/// fragment data.
+#[derive(Clone, Debug)]
+pub struct ReassemblyConfig {
+    /// datagrams being reassembled at the same time
+    pub max_datagrams: usize,
+    /// fragments per datagram
+    pub max_fragments: usize,
+    /// fragment data buffered for all datagrams
+    pub max_bytes: usize,
+    /// incomplete datagrams are discarded after this time
+    pub timeout: Duration,
+    pub overlap: OverlapPolicy,
+}
+
+impl Default for ReassemblyConfig {
+    fn default() -> ReassemblyConfig {
+        ReassemblyConfig {
+            max_datagrams: 1024,
+            max_fragments: 64,
+            max_bytes: 4 << 20,
+            timeout: Duration::from_secs(5),
+            overlap: OverlapPolicy::Drop,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReassemblyStats {
+    pub fragments: u64,
+    pub reassembled: u64,
+    /// incomplete datagrams discarded after the timeout
+    pub timed_out: u64,
+    /// fragments overlapping received data, with `OverlapPolicy::Drop` the datagram was discarded
+    pub overlaps: u64,
+    /// fragments received twice, they are ignored
+    pub duplicates: u64,
+    /// fragments with inconsistent length or offset, the datagram was discarded
+    pub invalid: u64,
+    /// fragments dropped because a limit of the `ReassemblyConfig` was reached
+    pub no_resources: u64,
+}
+
+/// A reassembled datagram: the headers of the first fragment, the protocol of the payload and the payload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Datagram {
+    pub key: FragmentKey,
+    pub header: Vec<u8>,
+    pub next_header: u8,
+    pub payload: Vec<u8>,
+}
+
+struct PendingDatagram {
+    first_seen: Instant,
+    /// headers of the first fragment, empty until it arrived
+    header: Vec<u8>,
+    next_header: u8,
+    /// length of the payload, known when the last fragment arrived
+    total: Option<usize>,
+    /// offset and data of the fragments in the order of arrival
+    fragments: Vec<(usize, Vec<u8>)>,
+    bytes: usize,
+}
+
+impl PendingDatagram {
+    fn is_complete(&self) -> bool {
+        let total = match self.total {
+            Some(total) => total,
+            None => return false,
+        };
+        let mut intervals: Vec<(usize, usize)> = self.fragments.iter().map(|&(o, ref d)| (o, o + d.len())).collect();
+        intervals.sort();
+        let mut covered = 0;
+        for (start, end) in intervals {
+            if start > covered {
+                return false;
+            }
+            covered = covered.max(end);
+        }
+        covered >= total
+    }
+
+    fn assemble(self, key: FragmentKey, overlap: OverlapPolicy) -> Datagram {
+        let mut payload = vec![0u8; self.total.unwrap_or(0)];
+        let mut write = |&(offset, ref data): &(usize, Vec<u8>)| {
+            payload[offset..offset + data.len()].copy_from_slice(data);
+        };
+        // later writes win
+        if overlap == OverlapPolicy::First {
+            self.fragments.iter().rev().for_each(&mut write);
+        } else {
+            self.fragments.iter().for_each(&mut write);
+        }
+        Datagram {
+            key,
+            header: self.header,
+            next_header: self.next_header,
+            payload,
+        }
+    }
+}
+
+/// Reassembles IPv4 and IPv6 fragments into datagrams. The fragment data is copied, so the fragments can be dropped
+/// after they were added. With RSS all fragments of a datagram reach the same reassembler only if the device hashes
+/// fragments on the IP addresses.
+pub struct Reassembler {
+    config: ReassemblyConfig,
+    datagrams: HashMap<FragmentKey, PendingDatagram, FnvHash>,
+    bytes: usize,
+    stats: ReassemblyStats,
+}
+
+impl Reassembler {
+    pub fn new(config: ReassemblyConfig) -> Reassembler {
+        Reassembler {
+            datagrams: HashMap::with_capacity_and_hasher(config.max_datagrams, Default::default()),
+            config,
+            bytes: 0,
+            stats: Default::default(),
+        }
+    }
+
+    pub fn config(&self) -> &ReassemblyConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &ReassemblyStats {
+        &self.stats
+    }
+
+    /// number of incomplete datagrams
+    pub fn len(&self) -> usize {
+        self.datagrams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.datagrams.is_empty()
+    }
+
+    /// fragment data currently buffered
+    pub fn buffered_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Discard incomplete datagrams older than the timeout, returns the number of discarded datagrams.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let timeout = self.config.timeout;
+        let before = self.datagrams.len();
+        let mut freed = 0;
+        self.datagrams.retain(|_, d| {
+            let keep = now.duration_since(d.first_seen) < timeout;
+            if !keep {
+                freed += d.bytes;
+            }
+            keep
+        });
+        self.bytes -= freed;
+        let expired = before - self.datagrams.len();
+        self.stats.timed_out += expired as u64;
+        expired
+    }
+
+    fn discard(&mut self, key: &FragmentKey) {
+        if let Some(d) = self.datagrams.remove(key) {
+            self.bytes -= d.bytes;
+        }
+    }
+
+    /// Add the fragment described by `info` with the given `data`. `header` are the first `info.header_len` bytes of
+    /// the fragment, they are kept from the first fragment. Returns the datagram once all fragments were received.
+    pub fn add_fragment(&mut self, info: &FragmentInfo, header: &[u8], data: &[u8], now: Instant) -> Option<Datagram> {
+        self.stats.fragments += 1;
+        let key = info.key;
+        let end = info.offset + data.len();
+        if data.is_empty() || (info.more && data.len() % 8 != 0) {
+            self.stats.invalid += 1;
+            return None;
+        }
+        if end > MAX_DATAGRAM_SIZE {
+            self.stats.invalid += 1;
+            self.discard(&key);
+            return None;
+        }
+        if self.bytes + data.len() > self.config.max_bytes {
+            self.stats.no_resources += 1;
+            return None;
+        }
+        if !self.datagrams.contains_key(&key) && self.datagrams.len() >= self.config.max_datagrams {
+            self.expire(now);
+            if self.datagrams.len() >= self.config.max_datagrams {
+                self.stats.no_resources += 1;
+                return None;
+            }
+        }
+
+        let config = &self.config;
+        let stats = &mut self.stats;
+        let mut discard = false;
+        let complete = match self.datagrams.entry(key) {
+            Entry::Vacant(e) => {
+                let datagram = e.insert(PendingDatagram {
+                    first_seen: now,
+                    header: Vec::new(),
+                    next_header: 0,
+                    total: None,
+                    fragments: Vec::with_capacity(4),
+                    bytes: 0,
+                });
+                Reassembler::store(datagram, info, header, data);
+                datagram.is_complete()
+            }
+            Entry::Occupied(mut e) => {
+                {
+                    let datagram = e.get_mut();
+                    let total = if info.more { datagram.total } else { Some(end) };
+                    let consistent = match (datagram.total, total) {
+                        (Some(known), Some(total)) => known == total && end <= total,
+                        (None, Some(total)) => datagram.fragments.iter().all(|&(o, ref d)| o + d.len() <= total),
+                        _ => true,
+                    };
+                    if !consistent {
+                        stats.invalid += 1;
+                        discard = true;
+                    } else if datagram
+                        .fragments
+                        .iter()
+                        .any(|&(o, ref d)| o == info.offset && d.len() == data.len())
+                    {
+                        stats.duplicates += 1;
+                        return None;
+                    } else if datagram.fragments.len() >= config.max_fragments {
+                        stats.no_resources += 1;
+                        discard = true;
+                    } else {
+                        if datagram
+                            .fragments
+                            .iter()
+                            .any(|&(o, ref d)| o < end && info.offset < o + d.len())
+                        {
+                            stats.overlaps += 1;
+                            discard = config.overlap == OverlapPolicy::Drop;
+                        }
+                        if !discard {
+                            datagram.total = total;
+                            Reassembler::store(datagram, info, header, data);
+                        }
+                    }
+                }
+                !discard && e.get().is_complete()
+            }
+        };
+        if discard {
+            self.discard(&key);
+            return None;
+        }
+        self.bytes += data.len();
+        if complete {
+            let datagram = self.datagrams.remove(&key).unwrap();
+            self.bytes -= datagram.bytes;
+            self.stats.reassembled += 1;
+            Some(datagram.assemble(key, self.config.overlap))
+        } else {
+            None
+        }
+    }
+
+    fn store(datagram: &mut PendingDatagram, info: &FragmentInfo, header: &[u8], data: &[u8]) {
+        if !info.more {
+            datagram.total = Some(info.offset + data.len());
+        }
+        if info.offset == 0 {
+            datagram.header = header.to_vec();
+            datagram.next_header = info.next_header;
+        }
+        datagram.fragments.push((info.offset, data.to_vec()));
+        datagram.bytes += data.len();
+    }
+
+    /// Add a fragment packet, see `add_fragment`. Returns the reassembled packet, which is received on the port of the
+    /// last fragment. The caller still owns the fragment.
+    pub fn add_pdu(&mut self, pdu: &Pdu, info: &FragmentInfo, now: Instant) -> errors::Result<Option<Pdu<'static>>> {
+        self.add_pdu_with(pdu, info, now, Pdu::new_pdu)
+    }
+
+    /// Same as `add_pdu`, but the reassembled packet is allocated by `alloc`.
+    pub fn add_pdu_with<F>(
+        &mut self,
+        pdu: &Pdu,
+        info: &FragmentInfo,
+        now: Instant,
+        alloc: F,
+    ) -> errors::Result<Option<Pdu<'static>>>
+    where
+        F: FnMut() -> Option<Pdu<'static>>,
+    {
+        let data = pdu.fragment_data(info);
+        let header = pdu
+            .segments()
+            .next()
+            .map_or(&[][..], |s| &s[..info.header_len.min(s.len())]);
+        match self.add_fragment(info, header, &data, now) {
+            Some(datagram) => Pdu::from_fragments_with(
+                &datagram.header,
+                datagram.next_header,
+                &datagram.payload,
+                pdu.port_id(),
+                alloc,
+            )
+            .map(Some),
+            None => Ok(None),
+        }
+    }
+}
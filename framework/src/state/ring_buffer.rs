@@ -128,6 +128,20 @@ impl RingBuffer {
         self.wrapped_read(offset, &mut data[..to_read])
     }
 
+    /// Read data at an offset from the head without moving the head, e.g. to retransmit data which was not
+    /// acknowledged yet. Returns bytes read.
+    #[inline]
+    pub fn read_at_offset_from_head(&mut self, offset: usize, data: &mut [u8]) -> usize {
+        let available = self.available();
+        if offset >= available {
+            0
+        } else {
+            let to_read = min(available - offset, data.len());
+            let index = self.head.wrapping_add(offset) & self.mask;
+            self.wrapped_read(index, &mut data[..to_read])
+        }
+    }
+
     /// Read from the buffer, incrementing the read head. Returns bytes read.
     #[inline]
     pub fn read_from_head(&mut self, data: &mut [u8]) -> usize {
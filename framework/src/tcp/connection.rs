@@ -0,0 +1,917 @@
+use common::errors;
+use state::{ReorderedBuffer, RingBuffer};
+use std::cmp::{max, min};
+use std::time::{Duration, Instant};
+use utils::round_to_power_of_2;
+
+pub const TCP_FLAG_FIN: u8 = 0x01;
+pub const TCP_FLAG_SYN: u8 = 0x02;
+pub const TCP_FLAG_RST: u8 = 0x04;
+pub const TCP_FLAG_PSH: u8 = 0x08;
+pub const TCP_FLAG_ACK: u8 = 0x10;
+
+/// MSS assumed if the peer does not announce one (RFC 1122).
+pub const TCP_DEFAULT_MSS: u16 = 536;
+/// without window scaling the receive window is limited to 16 bits
+const MAX_WINDOW: usize = 65535;
+const DUP_ACK_THRESHOLD: u32 = 3;
+
+/// a < b in sequence number space
+#[inline]
+pub fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+#[inline]
+pub fn seq_le(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
+}
+
+#[inline]
+pub fn seq_gt(a: u32, b: u32) -> bool {
+    seq_lt(b, a)
+}
+
+#[inline]
+pub fn seq_ge(a: u32, b: u32) -> bool {
+    seq_le(b, a)
+}
+
+/// Parameters of TCP connections.
+#[derive(Clone, Debug)]
+pub struct TcpConfig {
+    /// MSS announced to peers, i.e. the largest segment we receive
+    pub mss: u16,
+    /// bytes of received data buffered until they are read, rounded up to a power of 2
+    pub recv_buffer: usize,
+    /// bytes of data buffered until they are acknowledged, rounded up to a power of 2
+    pub send_buffer: usize,
+    pub initial_rto: Duration,
+    pub min_rto: Duration,
+    pub max_rto: Duration,
+    /// retransmissions of a segment, or unanswered zero window probes, before the connection is dropped
+    pub max_retransmissions: u32,
+    /// initial congestion window in segments (RFC 6928)
+    pub initial_cwnd: u32,
+    pub time_wait: Duration,
+    /// connections of a `TcpStack`, SYNs beyond are answered with a reset
+    pub max_connections: usize,
+    /// connections of a `TcpStack` in the handshake, i.e. not yet queued for `accept`, SYNs beyond are dropped
+    pub syn_backlog: usize,
+}
+
+impl Default for TcpConfig {
+    fn default() -> TcpConfig {
+        TcpConfig {
+            mss: 1460,
+            recv_buffer: 64 * 1024,
+            send_buffer: 64 * 1024,
+            initial_rto: Duration::from_secs(1),
+            min_rto: Duration::from_millis(200),
+            max_rto: Duration::from_secs(60),
+            max_retransmissions: 8,
+            initial_cwnd: 10,
+            time_wait: Duration::from_secs(60),
+            max_connections: 64 * 1024,
+            syn_backlog: 1024,
+        }
+    }
+}
+
+/// Connection states of RFC 793, a listening socket is not a connection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TcpState {
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+    Closed,
+}
+
+/// Why a connection was closed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CloseReason {
+    /// both sides closed the connection
+    Normal,
+    /// the peer reset the connection or refused to connect
+    Reset,
+    /// the peer did not acknowledge data or the handshake
+    Timeout,
+    /// the connection was aborted locally
+    Aborted,
+}
+
+/// A TCP segment without addresses and ports, as received from or sent to the peer.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Segment {
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+    pub window: u16,
+    /// MSS option, only in segments with SYN
+    pub mss: Option<u16>,
+    pub payload: Vec<u8>,
+}
+
+impl Segment {
+    #[inline]
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// length in sequence number space, SYN and FIN occupy one sequence number each
+    #[inline]
+    pub fn seq_len(&self) -> u32 {
+        self.payload.len() as u32 + self.has(TCP_FLAG_SYN) as u32 + self.has(TCP_FLAG_FIN) as u32
+    }
+
+    /// The reset sent in response to a segment which does not belong to a connection (RFC 793, section 3.4).
+    pub fn reset_for(segment: &Segment) -> Option<Segment> {
+        if segment.has(TCP_FLAG_RST) {
+            None
+        } else if segment.has(TCP_FLAG_ACK) {
+            Some(Segment {
+                seq: segment.ack,
+                flags: TCP_FLAG_RST,
+                ..Default::default()
+            })
+        } else {
+            Some(Segment {
+                ack: segment.seq.wrapping_add(segment.seq_len()),
+                flags: TCP_FLAG_RST | TCP_FLAG_ACK,
+                ..Default::default()
+            })
+        }
+    }
+}
+
+/// State of one end of a TCP connection (RFC 793, RFC 5681, RFC 6298), independent of addresses and packets: received
+/// segments are passed to `on_segment`, segments to send are returned by `poll`. Out-of-order data is held in a
+/// `ReorderedBuffer`, sent data in a `RingBuffer` until it is acknowledged. Window scaling, SACK and timestamps are not
+/// supported.
+pub struct TcpConnection {
+    config: TcpConfig,
+    state: TcpState,
+    close_reason: Option<CloseReason>,
+
+    iss: u32,
+    snd_una: u32,
+    snd_nxt: u32,
+    /// highest sequence number sent, differs from `snd_nxt` after a retransmission timeout
+    snd_max: u32,
+    snd_wnd: u32,
+    snd_wl1: u32,
+    snd_wl2: u32,
+    snd_mss: u16,
+    /// sequence number of the first byte in `send_buf`
+    snd_buf_seq: u32,
+    send_buf: RingBuffer,
+    /// the application closed the connection, a FIN follows the buffered data
+    fin_queued: bool,
+    fin_seq: Option<u32>,
+
+    irs: u32,
+    /// sequence number of the next byte read by the application
+    rcv_read_seq: u32,
+    recv_buf: ReorderedBuffer,
+    fin_received: bool,
+    /// window announced in the last segment sent
+    rcv_adv_wnd: u32,
+
+    rto: Duration,
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    /// sequence number and send time of the segment timed for an RTT sample
+    rtt_sample: Option<(u32, Instant)>,
+    retransmit_at: Option<Instant>,
+    retransmissions: u32,
+    /// zero window probes sent since the last ACK, probes are not retransmissions (RFC 1122 4.2.2.17)
+    probes: u32,
+    time_wait_until: Option<Instant>,
+
+    cwnd: u32,
+    ssthresh: u32,
+    dup_acks: u32,
+    /// end of the data outstanding when fast recovery started (RFC 6582)
+    recover: Option<u32>,
+    fast_retransmit: bool,
+
+    ack_pending: bool,
+    /// out-of-order segments received, each is acknowledged immediately to trigger fast retransmit at the peer
+    dup_acks_pending: u32,
+    reset_pending: Option<u32>,
+}
+
+impl TcpConnection {
+    fn new(config: TcpConfig, iss: u32, state: TcpState) -> errors::Result<TcpConnection> {
+        let send_buf = RingBuffer::new(round_to_power_of_2(config.send_buffer))?;
+        let recv_buf = ReorderedBuffer::new(config.recv_buffer)?;
+        let mss = config.mss;
+        Ok(TcpConnection {
+            state,
+            close_reason: None,
+            iss,
+            snd_una: iss,
+            snd_nxt: iss,
+            snd_max: iss,
+            snd_wnd: 0,
+            snd_wl1: 0,
+            snd_wl2: 0,
+            snd_mss: TCP_DEFAULT_MSS,
+            snd_buf_seq: iss.wrapping_add(1),
+            send_buf,
+            fin_queued: false,
+            fin_seq: None,
+            irs: 0,
+            rcv_read_seq: 0,
+            recv_buf,
+            fin_received: false,
+            rcv_adv_wnd: 0,
+            rto: config.initial_rto,
+            srtt: None,
+            rttvar: Duration::from_secs(0),
+            rtt_sample: None,
+            retransmit_at: None,
+            retransmissions: 0,
+            probes: 0,
+            time_wait_until: None,
+            cwnd: config.initial_cwnd * mss as u32,
+            ssthresh: u32::max_value(),
+            dup_acks: 0,
+            recover: None,
+            fast_retransmit: false,
+            ack_pending: false,
+            dup_acks_pending: 0,
+            reset_pending: None,
+            config,
+        })
+    }
+
+    /// A connection opened by us, the SYN is sent by the first `poll`.
+    pub fn connect(config: TcpConfig, iss: u32) -> errors::Result<TcpConnection> {
+        TcpConnection::new(config, iss, TcpState::SynSent)
+    }
+
+    /// A connection accepted in response to `syn`, the SYN-ACK is sent by the first `poll`.
+    pub fn accept(config: TcpConfig, iss: u32, syn: &Segment) -> errors::Result<TcpConnection> {
+        let mut connection = TcpConnection::new(config, iss, TcpState::SynReceived)?;
+        connection.synchronize(syn);
+        Ok(connection)
+    }
+
+    /// initialize the receive side from the SYN of the peer
+    fn synchronize(&mut self, syn: &Segment) {
+        self.irs = syn.seq;
+        self.rcv_read_seq = syn.seq.wrapping_add(1);
+        self.recv_buf.reset();
+        self.recv_buf.seq(self.rcv_read_seq, &[]);
+        self.snd_wnd = syn.window as u32;
+        self.snd_wl1 = syn.seq;
+        self.snd_wl2 = syn.ack;
+        self.snd_mss = syn.mss.unwrap_or(TCP_DEFAULT_MSS).min(self.config.mss);
+        self.cwnd = self.config.initial_cwnd * self.snd_mss as u32;
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.state
+    }
+
+    pub fn close_reason(&self) -> Option<CloseReason> {
+        self.close_reason
+    }
+
+    pub fn is_established(&self) -> bool {
+        self.state == TcpState::Established
+    }
+
+    /// true once the handshake completed, also after the connection was closed
+    pub fn is_synchronized(&self) -> bool {
+        match self.state {
+            TcpState::SynSent | TcpState::SynReceived => false,
+            TcpState::Closed => self.close_reason == Some(CloseReason::Normal),
+            _ => true,
+        }
+    }
+
+    /// Next sequence number to send.
+    pub fn snd_nxt(&self) -> u32 {
+        self.snd_nxt
+    }
+
+    /// MSS used for sending, the smaller of the announced MSS of both sides
+    pub fn mss(&self) -> u16 {
+        self.snd_mss
+    }
+
+    pub fn cwnd(&self) -> u32 {
+        self.cwnd
+    }
+
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    /// true if the peer closed its side of the connection, no more data will be received
+    pub fn peer_closed(&self) -> bool {
+        self.fin_received
+    }
+
+    /// in-order data which can be read
+    pub fn recv_available(&self) -> usize {
+        self.recv_buf.available()
+    }
+
+    /// bytes which can be passed to `send` now
+    pub fn send_capacity(&self) -> usize {
+        match self.state {
+            TcpState::SynSent | TcpState::SynReceived | TcpState::Established | TcpState::CloseWait
+                if !self.fin_queued =>
+            {
+                self.send_buf.len() - 1 - self.send_buf.available()
+            }
+            _ => 0,
+        }
+    }
+
+    /// bytes sent but not acknowledged, including SYN and FIN
+    #[inline]
+    fn flight_size(&self) -> u32 {
+        self.snd_nxt.wrapping_sub(self.snd_una)
+    }
+
+    /// Next sequence number expected from the peer.
+    #[inline]
+    pub fn rcv_nxt(&self) -> u32 {
+        let nxt = self.rcv_read_seq.wrapping_add(self.recv_buf.available() as u32);
+        nxt.wrapping_add(self.fin_received as u32)
+    }
+
+    #[inline]
+    fn rcv_wnd(&self) -> u32 {
+        let free = self.recv_buf.buffer_size() - 1 - self.recv_buf.available();
+        min(free, MAX_WINDOW) as u32
+    }
+
+    /// end of the buffered data in sequence number space
+    #[inline]
+    fn data_end(&self) -> u32 {
+        self.snd_buf_seq.wrapping_add(self.send_buf.available() as u32)
+    }
+
+    /// Queue data for sending, returns the number of bytes accepted.
+    pub fn send(&mut self, data: &[u8]) -> usize {
+        let len = min(data.len(), self.send_capacity());
+        if len > 0 {
+            self.send_buf.write_at_tail(&data[..len])
+        } else {
+            0
+        }
+    }
+
+    /// Read received data in order, returns the number of bytes read.
+    pub fn recv(&mut self, data: &mut [u8]) -> usize {
+        let read = self.recv_buf.read_data(data);
+        self.rcv_read_seq = self.rcv_read_seq.wrapping_add(read as u32);
+        // announce the opened window once it grew by a segment or half the buffer (RFC 1122, 4.2.3.3)
+        let threshold = min(self.config.mss as usize, self.recv_buf.buffer_size() / 2) as u32;
+        if read > 0 && self.rcv_wnd().saturating_sub(self.rcv_adv_wnd) >= threshold && self.can_receive() {
+            self.ack_pending = true;
+        }
+        read
+    }
+
+    fn can_receive(&self) -> bool {
+        match self.state {
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2 => true,
+            _ => false,
+        }
+    }
+
+    /// Close our side of the connection: a FIN is sent after the buffered data. Data can still be received until the
+    /// peer closes its side.
+    pub fn close(&mut self) {
+        match self.state {
+            TcpState::SynSent => self.closed(CloseReason::Normal),
+            // the FIN is sent once the handshake completed
+            TcpState::SynReceived => self.fin_queued = true,
+            TcpState::Established => {
+                self.fin_queued = true;
+                self.state = TcpState::FinWait1;
+            }
+            TcpState::CloseWait => {
+                self.fin_queued = true;
+                self.state = TcpState::LastAck;
+            }
+            _ => (),
+        }
+    }
+
+    /// Reset the connection, buffered data is discarded.
+    pub fn abort(&mut self) {
+        match self.state {
+            TcpState::Closed | TcpState::TimeWait => (),
+            TcpState::SynSent => self.closed(CloseReason::Aborted),
+            _ => {
+                self.reset_pending = Some(self.snd_nxt);
+                self.closed(CloseReason::Aborted);
+            }
+        }
+    }
+
+    fn closed(&mut self, reason: CloseReason) {
+        self.state = TcpState::Closed;
+        if self.close_reason.is_none() {
+            self.close_reason = Some(reason);
+        }
+        self.retransmit_at = None;
+        self.time_wait_until = None;
+        self.ack_pending = false;
+        self.dup_acks_pending = 0;
+    }
+
+    fn enter_time_wait(&mut self, now: Instant) {
+        self.state = TcpState::TimeWait;
+        self.retransmit_at = None;
+        self.time_wait_until = Some(now + self.config.time_wait);
+    }
+
+    /// Process a segment received from the peer (RFC 793, section 3.9, "SEGMENT ARRIVES").
+    pub fn on_segment(&mut self, seg: &Segment, now: Instant) {
+        match self.state {
+            TcpState::Closed => (),
+            TcpState::SynSent => self.on_segment_syn_sent(seg, now),
+            _ => self.on_segment_synchronized(seg, now),
+        }
+    }
+
+    fn on_segment_syn_sent(&mut self, seg: &Segment, now: Instant) {
+        let ack_ok = seg.has(TCP_FLAG_ACK);
+        if ack_ok && (seq_le(seg.ack, self.iss) || seq_gt(seg.ack, self.snd_max)) {
+            if !seg.has(TCP_FLAG_RST) {
+                self.reset_pending = Some(seg.ack);
+            }
+            return;
+        }
+        if seg.has(TCP_FLAG_RST) {
+            if ack_ok {
+                self.closed(CloseReason::Reset);
+            }
+            return;
+        }
+        if !seg.has(TCP_FLAG_SYN) {
+            return;
+        }
+        self.synchronize(seg);
+        self.ack_pending = true;
+        if ack_ok {
+            self.on_ack_of_syn(seg.ack, now);
+            self.state = TcpState::Established;
+        } else {
+            // simultaneous open, the SYN is sent again with ACK
+            self.state = TcpState::SynReceived;
+            self.snd_nxt = self.iss;
+            self.retransmit_at = None;
+        }
+    }
+
+    fn on_ack_of_syn(&mut self, ack: u32, now: Instant) {
+        self.snd_una = ack;
+        self.update_rtt(ack, now);
+        self.retransmissions = 0;
+        self.retransmit_at = None;
+    }
+
+    /// RFC 793 acceptability test
+    fn acceptable(&self, seg: &Segment) -> bool {
+        let rcv_nxt = self.rcv_nxt();
+        let wnd = self.rcv_wnd();
+        let len = seg.seq_len();
+        let in_window = |seq: u32| seq_le(rcv_nxt, seq) && seq_lt(seq, rcv_nxt.wrapping_add(wnd));
+        match (len, wnd) {
+            (0, 0) => seg.seq == rcv_nxt,
+            (0, _) => in_window(seg.seq),
+            (_, 0) => false,
+            _ => in_window(seg.seq) || in_window(seg.seq.wrapping_add(len - 1)),
+        }
+    }
+
+    fn on_segment_synchronized(&mut self, seg: &Segment, now: Instant) {
+        if !self.acceptable(seg) {
+            if !seg.has(TCP_FLAG_RST) {
+                self.ack_pending = true;
+            }
+            return;
+        }
+        if seg.has(TCP_FLAG_RST) {
+            // only a reset at the expected sequence number is accepted, others are challenged (RFC 5961)
+            if seg.seq == self.rcv_nxt() {
+                self.closed(CloseReason::Reset);
+            } else {
+                self.ack_pending = true;
+            }
+            return;
+        }
+        if seg.has(TCP_FLAG_SYN) {
+            self.ack_pending = true;
+            return;
+        }
+        if !seg.has(TCP_FLAG_ACK) {
+            return;
+        }
+
+        if self.state == TcpState::SynReceived {
+            if seq_lt(self.snd_una, seg.ack) && seq_le(seg.ack, self.snd_max) {
+                self.on_ack_of_syn(self.iss.wrapping_add(1), now);
+                self.state = if self.fin_queued {
+                    TcpState::FinWait1
+                } else {
+                    TcpState::Established
+                };
+                self.snd_wnd = seg.window as u32;
+                self.snd_wl1 = seg.seq;
+                self.snd_wl2 = seg.ack;
+            } else {
+                self.reset_pending = Some(seg.ack);
+                return;
+            }
+        }
+        if seq_gt(seg.ack, self.snd_max) {
+            self.ack_pending = true;
+            return;
+        }
+        self.process_ack(seg, now);
+        if self.state == TcpState::Closed {
+            return;
+        }
+
+        if !seg.payload.is_empty() && self.can_receive() {
+            self.receive_data(seg);
+        }
+        if seg.has(TCP_FLAG_FIN) {
+            self.receive_fin(seg, now);
+        }
+    }
+
+    fn process_ack(&mut self, seg: &Segment, now: Instant) {
+        let ack = seg.ack;
+        // the peer is alive, the connection stays open while it answers probes of a closed window
+        self.probes = 0;
+        if seq_gt(ack, self.snd_una) {
+            let acked = ack.wrapping_sub(self.snd_una);
+            self.snd_una = ack;
+            if seq_lt(self.snd_nxt, ack) {
+                self.snd_nxt = ack;
+            }
+            // drop acknowledged data, the FIN is not part of the buffer
+            let data_acked = min(ack.wrapping_sub(self.snd_buf_seq) as usize, self.send_buf.available());
+            if seq_gt(ack, self.snd_buf_seq) {
+                self.send_buf.seek_head(data_acked);
+                self.snd_buf_seq = self.snd_buf_seq.wrapping_add(data_acked as u32);
+            }
+            self.update_rtt(ack, now);
+            self.on_new_ack(acked, ack);
+            self.retransmissions = 0;
+            self.retransmit_at = if self.flight_size() > 0 {
+                Some(now + self.rto)
+            } else {
+                None
+            };
+        } else if ack == self.snd_una
+            && seg.payload.is_empty()
+            && !seg.has(TCP_FLAG_FIN)
+            && seg.window as u32 == self.snd_wnd
+            && self.flight_size() > 0
+        {
+            self.on_dup_ack();
+        }
+
+        if seq_lt(self.snd_wl1, seg.seq) || (self.snd_wl1 == seg.seq && seq_le(self.snd_wl2, ack)) {
+            if self.snd_wnd == 0 && seg.window > 0 && self.flight_size() > 0 {
+                // the window opened, resend the rejected probe with the data following it
+                self.snd_nxt = self.snd_una;
+                self.retransmit_at = None;
+            }
+            self.snd_wnd = seg.window as u32;
+            self.snd_wl1 = seg.seq;
+            self.snd_wl2 = ack;
+        }
+
+        let fin_acked = self.fin_seq.map_or(false, |fin_seq| seq_gt(ack, fin_seq));
+        if fin_acked {
+            match self.state {
+                TcpState::FinWait1 => self.state = TcpState::FinWait2,
+                TcpState::Closing => self.enter_time_wait(now),
+                TcpState::LastAck => self.closed(CloseReason::Normal),
+                _ => (),
+            }
+        }
+    }
+
+    /// congestion control on an ACK of new data (RFC 5681, RFC 6582)
+    fn on_new_ack(&mut self, acked: u32, ack: u32) {
+        let mss = self.snd_mss as u32;
+        self.dup_acks = 0;
+        match self.recover {
+            Some(recover) if seq_lt(ack, recover) => {
+                // partial ACK: retransmit the next unacknowledged segment and deflate the window
+                self.fast_retransmit = true;
+                self.cwnd = self.cwnd.saturating_sub(acked) + mss;
+            }
+            Some(_) => {
+                self.recover = None;
+                self.cwnd = self.ssthresh;
+            }
+            None if self.cwnd < self.ssthresh => self.cwnd += min(acked, mss),
+            None => self.cwnd += max(mss * mss / self.cwnd, 1),
+        }
+    }
+
+    fn on_dup_ack(&mut self) {
+        let mss = self.snd_mss as u32;
+        self.dup_acks += 1;
+        if self.recover.is_some() {
+            self.cwnd += mss;
+        } else if self.dup_acks == DUP_ACK_THRESHOLD {
+            self.ssthresh = max(self.flight_size() / 2, 2 * mss);
+            self.cwnd = self.ssthresh + DUP_ACK_THRESHOLD * mss;
+            self.recover = Some(self.snd_max);
+            self.fast_retransmit = true;
+            self.rtt_sample = None;
+        }
+    }
+
+    /// RTT estimation of RFC 6298, retransmitted segments are not timed (Karn's algorithm)
+    fn update_rtt(&mut self, ack: u32, now: Instant) {
+        let sent = match self.rtt_sample {
+            Some((seq, sent)) if seq_gt(ack, seq) => sent,
+            _ => return,
+        };
+        self.rtt_sample = None;
+        let rtt = now.duration_since(sent);
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                let delta = if srtt > rtt { srtt - rtt } else { rtt - srtt };
+                self.rttvar = self.rttvar * 3 / 4 + delta / 4;
+                self.srtt = Some(srtt * 7 / 8 + rtt / 8);
+            }
+        }
+        let rto = self.srtt.unwrap() + max(self.rttvar * 4, Duration::from_millis(1));
+        self.rto = max(self.config.min_rto, min(rto, self.config.max_rto));
+    }
+
+    fn receive_data(&mut self, seg: &Segment) {
+        let rcv_nxt = self.rcv_nxt();
+        let mut seq = seg.seq;
+        let mut data = &seg.payload[..];
+        // trim data which was received before and data beyond the window
+        if seq_lt(seq, rcv_nxt) {
+            let skip = min(rcv_nxt.wrapping_sub(seq) as usize, data.len());
+            data = &data[skip..];
+            seq = rcv_nxt;
+        }
+        let window_end = rcv_nxt.wrapping_add(self.rcv_wnd());
+        let room = window_end.wrapping_sub(seq) as usize;
+        if data.len() > room {
+            data = &data[..room];
+        }
+        if !data.is_empty() {
+            if seq != rcv_nxt {
+                self.dup_acks_pending += 1;
+            }
+            self.recv_buf.add_data(seq, data);
+        }
+        self.ack_pending = true;
+    }
+
+    fn receive_fin(&mut self, seg: &Segment, now: Instant) {
+        let fin_seq = seg.seq.wrapping_add(seg.payload.len() as u32);
+        // a FIN is processed once all data before it was received
+        if self.fin_received || fin_seq != self.rcv_nxt() {
+            self.ack_pending = true;
+            if self.state == TcpState::TimeWait {
+                self.time_wait_until = Some(now + self.config.time_wait);
+            }
+            return;
+        }
+        self.fin_received = true;
+        self.ack_pending = true;
+        match self.state {
+            TcpState::SynReceived | TcpState::Established => self.state = TcpState::CloseWait,
+            TcpState::FinWait1 => {
+                let fin_acked = self.fin_seq.map_or(false, |fin_seq| seq_gt(self.snd_una, fin_seq));
+                if fin_acked {
+                    self.enter_time_wait(now);
+                } else {
+                    self.state = TcpState::Closing;
+                }
+            }
+            TcpState::FinWait2 => self.enter_time_wait(now),
+            _ => (),
+        }
+    }
+
+    fn segment(&mut self, seq: u32, flags: u8) -> Segment {
+        let window = self.rcv_wnd();
+        self.rcv_adv_wnd = window;
+        self.ack_pending = false;
+        let (ack, flags) = if self.state == TcpState::SynSent {
+            (0, flags)
+        } else {
+            (self.rcv_nxt(), flags | TCP_FLAG_ACK)
+        };
+        Segment {
+            seq,
+            ack,
+            flags,
+            window: window as u16,
+            mss: None,
+            payload: Vec::new(),
+        }
+    }
+
+    /// A segment starting at `seq` with up to `max_len` bytes of buffered data, with FIN if it reaches the end of the
+    /// data after the application closed the connection.
+    fn data_segment(&mut self, seq: u32, max_len: usize) -> Segment {
+        let offset = seq.wrapping_sub(self.snd_buf_seq) as usize;
+        let len = min(self.send_buf.available().saturating_sub(offset), max_len);
+        let mut payload = vec![0u8; len];
+        self.send_buf.read_at_offset_from_head(offset, &mut payload);
+        let end = seq.wrapping_add(len as u32);
+        let mut flags = if len > 0 && end == self.data_end() {
+            TCP_FLAG_PSH
+        } else {
+            0
+        };
+        if self.fin_queued && end == self.data_end() {
+            flags |= TCP_FLAG_FIN;
+        }
+        let mut segment = self.segment(seq, flags);
+        segment.payload = payload;
+        segment
+    }
+
+    fn sent(&mut self, segment: &Segment, now: Instant) {
+        let end = segment.seq.wrapping_add(segment.seq_len());
+        if segment.has(TCP_FLAG_FIN) {
+            self.fin_seq = Some(end.wrapping_sub(1));
+        }
+        if seq_gt(end, self.snd_max) {
+            // time new data only
+            if self.rtt_sample.is_none() && seq_ge(segment.seq, self.snd_max) {
+                self.rtt_sample = Some((segment.seq, now));
+            }
+            self.snd_max = end;
+        }
+        if segment.seq_len() > 0 && self.retransmit_at.is_none() {
+            self.retransmit_at = Some(now + self.rto);
+        }
+    }
+
+    fn on_retransmit_timeout(&mut self, now: Instant) {
+        // while the window of the peer is closed the timer is the persist timer, which probes the window
+        let persist = self.snd_wnd == 0 && self.is_synchronized();
+        let attempts = if persist {
+            self.probes += 1;
+            self.probes
+        } else {
+            self.retransmissions += 1;
+            self.retransmissions
+        };
+        if attempts > self.config.max_retransmissions {
+            self.closed(CloseReason::Timeout);
+            return;
+        }
+        let mss = self.snd_mss as u32;
+        self.rto = min(self.rto * 2, self.config.max_rto);
+        if self.flight_size() > 0 {
+            // a probe outside the window is no sign of congestion
+            if !persist {
+                self.ssthresh = max(self.flight_size() / 2, 2 * mss);
+                self.cwnd = mss;
+            }
+            // go back to the first unacknowledged byte
+            self.snd_nxt = self.snd_una;
+        }
+        self.recover = None;
+        self.dup_acks = 0;
+        self.rtt_sample = None;
+        self.retransmit_at = Some(now + self.rto);
+    }
+
+    /// Advance the timers and return the segments to send now.
+    pub fn poll(&mut self, now: Instant) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        if let Some(seq) = self.reset_pending.take() {
+            segments.push(Segment {
+                seq,
+                flags: TCP_FLAG_RST,
+                ..Default::default()
+            });
+        }
+        if self.state == TcpState::TimeWait && self.time_wait_until.map_or(false, |until| now >= until) {
+            self.closed(CloseReason::Normal);
+        }
+        if self.state == TcpState::Closed {
+            return segments;
+        }
+        let mut probe = false;
+        if self.retransmit_at.map_or(false, |at| now >= at) {
+            self.on_retransmit_timeout(now);
+            if self.state == TcpState::Closed {
+                return segments;
+            }
+            // persist timer: probe a closed window with one byte
+            probe = self.flight_size() == 0;
+        }
+
+        match self.state {
+            TcpState::SynSent | TcpState::SynReceived => {
+                if self.snd_nxt == self.iss {
+                    let flags = if self.state == TcpState::SynSent {
+                        TCP_FLAG_SYN
+                    } else {
+                        TCP_FLAG_SYN | TCP_FLAG_ACK
+                    };
+                    let mut syn = self.segment(self.iss, flags);
+                    syn.mss = Some(self.config.mss);
+                    self.snd_nxt = self.iss.wrapping_add(1);
+                    self.sent(&syn, now);
+                    segments.push(syn);
+                }
+            }
+            TcpState::TimeWait | TcpState::FinWait2 => (),
+            _ => self.output_data(&mut segments, probe, now),
+        }
+        let acks = max(self.dup_acks_pending, self.ack_pending as u32);
+        for _ in 0..acks {
+            let ack = self.segment(self.snd_nxt, 0);
+            segments.push(ack);
+        }
+        self.dup_acks_pending = 0;
+        segments
+    }
+
+    fn output_data(&mut self, segments: &mut Vec<Segment>, probe: bool, now: Instant) {
+        let mss = self.snd_mss as usize;
+        if self.fast_retransmit {
+            self.fast_retransmit = false;
+            let seq = self.snd_una;
+            let segment = self.data_segment(seq, mss);
+            if segment.seq_len() > 0 {
+                self.sent(&segment, now);
+                segments.push(segment);
+            }
+        }
+        loop {
+            let unsent = self.data_end().wrapping_sub(self.snd_nxt) as usize;
+            let fin_due = self.fin_queued && self.fin_seq.map_or(true, |fin_seq| seq_le(self.snd_nxt, fin_seq));
+            if unsent == 0 && !fin_due {
+                break;
+            }
+            let window = min(self.snd_wnd, self.cwnd).saturating_sub(self.flight_size()) as usize;
+            let mut len = min(min(unsent, mss), window);
+            if len == 0 && unsent > 0 {
+                if probe && self.snd_wnd == 0 {
+                    len = 1;
+                } else {
+                    break;
+                }
+            }
+            // avoid sending small segments while data is outstanding (Nagle, RFC 1122)
+            if len < unsent && len < mss && self.flight_size() > 0 && !probe {
+                break;
+            }
+            let seq = self.snd_nxt;
+            let segment = self.data_segment(seq, len);
+            if segment.seq_len() == 0 {
+                break;
+            }
+            self.snd_nxt = seq.wrapping_add(segment.seq_len());
+            self.sent(&segment, now);
+            segments.push(segment);
+            if probe {
+                break;
+            }
+        }
+        if self.snd_wnd == 0
+            && self.flight_size() == 0
+            && self.data_end() != self.snd_nxt
+            && self.retransmit_at.is_none()
+        {
+            self.retransmit_at = Some(now + self.rto);
+        }
+    }
+}
@@ -0,0 +1,6 @@
+pub use self::connection::*;
+pub use self::splice::*;
+pub use self::stack::*;
+mod connection;
+mod splice;
+mod stack;
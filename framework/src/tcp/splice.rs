@@ -0,0 +1,254 @@
+use fnv::FnvHasher;
+use headers::*;
+use interface::Pdu;
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+use utils::{update_checksum_incremental, FiveTupleV4};
+
+type FnvHash = BuildHasherDefault<FnvHasher>;
+
+/// Rewrites the packets of one direction of two spliced TCP connections, e.g. after a load balancer accepted a client
+/// connection, inspected the first request and handed it off to a connection with a backend: packets of the client are
+/// sent as packets of the backend connection and vice versa.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Splice {
+    /// addresses and ports of rewritten packets
+    pub flow: FiveTupleV4,
+    /// added to sequence numbers
+    pub seq_delta: u32,
+    /// added to acknowledgment numbers and SACK blocks
+    pub ack_delta: u32,
+    /// added to the TSval of timestamp options
+    pub tsval_delta: u32,
+    /// added to the TSecr of timestamp options
+    pub tsecr_delta: u32,
+}
+
+impl Splice {
+    /// The splice of packets received on the client connection and sent on the backend connection with `flow`, given
+    /// the sequence numbers at the handoff: the next sequence number expected from and sent to the client, and sent
+    /// to and expected from the backend, see `TcpConnection::rcv_nxt` and `snd_nxt`. The timestamp clocks of the two
+    /// connections differ by `tsval_offset`, added to the TSval of client packets, and `tsecr_offset`, added to the
+    /// TSecr echoed by the client.
+    pub fn new(
+        flow: FiveTupleV4,
+        client_rcv_nxt: u32,
+        client_snd_nxt: u32,
+        backend_snd_nxt: u32,
+        backend_rcv_nxt: u32,
+        tsval_offset: u32,
+        tsecr_offset: u32,
+    ) -> Splice {
+        Splice {
+            flow,
+            seq_delta: backend_snd_nxt.wrapping_sub(client_rcv_nxt),
+            ack_delta: backend_rcv_nxt.wrapping_sub(client_snd_nxt),
+            tsval_delta: tsval_offset,
+            tsecr_delta: tsecr_offset,
+        }
+    }
+
+    /// The splice of the opposite direction, which rewrites packets to `flow`.
+    pub fn reverse(&self, flow: FiveTupleV4) -> Splice {
+        Splice {
+            flow,
+            seq_delta: self.ack_delta.wrapping_neg(),
+            ack_delta: self.seq_delta.wrapping_neg(),
+            tsval_delta: self.tsecr_delta.wrapping_neg(),
+            tsecr_delta: self.tsval_delta.wrapping_neg(),
+        }
+    }
+
+    /// Rewrite a TCP/IPv4 packet: addresses and ports, sequence and acknowledgment numbers, SACK blocks and
+    /// timestamps. The TCP checksum is updated incrementally. Returns false if `pdu` is not a TCP/IPv4 packet.
+    pub fn apply(&self, pdu: &mut Pdu) -> bool {
+        let ip_ix = match tcp_ipv4_index(pdu) {
+            Some(ix) => ix,
+            None => return false,
+        };
+        let (src, dst) = {
+            let ip = pdu.headers_mut().ip_mut(ip_ix);
+            let addresses = (ip.src(), ip.dst());
+            ip.set_src(self.flow.src_ip);
+            ip.set_dst(self.flow.dst_ip);
+            ip.update_checksum();
+            addresses
+        };
+        let available = pdu.header_available(ip_ix + 1);
+        let tcp = pdu.headers_mut().tcp_mut(ip_ix + 1);
+        // the addresses are part of the pseudo header
+        update_checksum_u32(tcp, src, self.flow.src_ip);
+        update_checksum_u32(tcp, dst, self.flow.dst_ip);
+
+        let (src_port, dst_port) = (tcp.src_port(), tcp.dst_port());
+        tcp.set_src_port(self.flow.src_port);
+        tcp.update_checksum_incremental(src_port, self.flow.src_port);
+        tcp.set_dst_port(self.flow.dst_port);
+        tcp.update_checksum_incremental(dst_port, self.flow.dst_port);
+
+        let seq = tcp.seq_num();
+        tcp.set_seq_num(seq.wrapping_add(self.seq_delta));
+        update_checksum_u32(tcp, seq, seq.wrapping_add(self.seq_delta));
+        let ack_flag = tcp.ack_flag();
+        if ack_flag {
+            let ack = tcp.ack_num();
+            tcp.set_ack_num(ack.wrapping_add(self.ack_delta));
+            update_checksum_u32(tcp, ack, ack.wrapping_add(self.ack_delta));
+        }
+        self.rewrite_options(tcp, ack_flag, available);
+        true
+    }
+
+    fn rewrite_options(&self, tcp: &mut TcpHeader, ack_flag: bool, available: usize) {
+        // offsets of the values to rewrite with their deltas, SACK blocks and timestamps are not aligned necessarily
+        let mut fields = Vec::new();
+        {
+            let mut options = match tcp.options(available) {
+                Some(options) => options,
+                None => return,
+            };
+            while let Some(option) = options.next() {
+                match option {
+                    TcpOption::Sack(blocks) => {
+                        let edges = blocks.as_bytes().len() / 4;
+                        fields.extend((0..edges).map(|i| (options.offset() + 2 + 4 * i, self.ack_delta)));
+                    }
+                    TcpOption::Timestamp { .. } => {
+                        fields.push((options.offset() + 2, self.tsval_delta));
+                        // the echoed timestamp is only valid in segments with ACK
+                        if ack_flag {
+                            fields.push((options.offset() + 6, self.tsecr_delta));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        if fields.is_empty() {
+            return;
+        }
+        let mut csum = tcp.checksum();
+        {
+            let options = tcp.option_bytes_mut(available).unwrap();
+            for (offset, delta) in fields {
+                add_u32(options, offset, delta, &mut csum);
+            }
+        }
+        tcp.set_checksum(csum);
+    }
+}
+
+#[inline]
+fn tcp_ipv4_index(pdu: &Pdu) -> Option<usize> {
+    let headers = pdu.headers();
+    (1..headers.count())
+        .find(|&i| headers.get(i - 1).kind() == HeaderKind::Ip && headers.get(i).kind() == HeaderKind::Tcp)
+        .map(|i| i - 1)
+}
+
+#[inline]
+fn update_checksum_u32(tcp: &mut TcpHeader, old: u32, new: u32) {
+    tcp.update_checksum_incremental((old >> 16) as u16, (new >> 16) as u16);
+    tcp.update_checksum_incremental(old as u16, new as u16);
+}
+
+/// Add `delta` to the big-endian u32 at `offset` of the TCP options `hdr`, which may not be aligned to 16 bits.
+fn add_u32(hdr: &mut [u8], offset: usize, delta: u32, csum: &mut u16) {
+    if delta == 0 {
+        return;
+    }
+    let start = offset & !1;
+    let end = (offset + 5) & !1;
+    let mut old = [0u8; 6];
+    old[..end - start].copy_from_slice(&hdr[start..end]);
+    let value = (hdr[offset] as u32) << 24
+        | (hdr[offset + 1] as u32) << 16
+        | (hdr[offset + 2] as u32) << 8
+        | hdr[offset + 3] as u32;
+    let value = value.wrapping_add(delta);
+    hdr[offset] = (value >> 24) as u8;
+    hdr[offset + 1] = (value >> 16) as u8;
+    hdr[offset + 2] = (value >> 8) as u8;
+    hdr[offset + 3] = value as u8;
+    for (i, word) in (start..end).step_by(2).enumerate() {
+        let old_word = (old[2 * i] as u16) << 8 | old[2 * i + 1] as u16;
+        let new_word = (hdr[word] as u16) << 8 | hdr[word + 1] as u16;
+        *csum = update_checksum_incremental(*csum, old_word, new_word);
+    }
+}
+
+/// The splices of a core, keyed by the flow of received packets. A splice of two connections consists of a splice for
+/// each direction.
+#[derive(Default)]
+pub struct SpliceTable {
+    splices: HashMap<FiveTupleV4, Splice, FnvHash>,
+}
+
+impl SpliceTable {
+    pub fn new() -> SpliceTable {
+        SpliceTable {
+            splices: HashMap::default(),
+        }
+    }
+
+    /// Splice the client connection, whose packets are received with `client_flow`, with the backend connection of
+    /// `splice`. Packets of the backend are rewritten with the reverse splice.
+    pub fn splice(&mut self, client_flow: FiveTupleV4, splice: Splice) {
+        let reverse = splice.reverse(client_flow.reverse_flow());
+        self.splices.insert(splice.flow.reverse_flow(), reverse);
+        self.splices.insert(client_flow, splice);
+    }
+
+    /// Remove the splice of `client_flow` and its reverse.
+    pub fn unsplice(&mut self, client_flow: &FiveTupleV4) -> Option<Splice> {
+        let splice = self.splices.remove(client_flow);
+        if let Some(ref splice) = splice {
+            self.splices.remove(&splice.flow.reverse_flow());
+        }
+        splice
+    }
+
+    /// Install the splice of a single direction.
+    pub fn insert(&mut self, flow: FiveTupleV4, splice: Splice) -> Option<Splice> {
+        self.splices.insert(flow, splice)
+    }
+
+    pub fn get(&self, flow: &FiveTupleV4) -> Option<&Splice> {
+        self.splices.get(flow)
+    }
+
+    pub fn remove(&mut self, flow: &FiveTupleV4) -> Option<Splice> {
+        self.splices.remove(flow)
+    }
+
+    /// Number of spliced directions.
+    pub fn len(&self) -> usize {
+        self.splices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.splices.is_empty()
+    }
+
+    /// Rewrite a packet of a spliced flow, returns false if the packet does not belong to one. A reset removes the
+    /// splice of both directions, after it was rewritten.
+    pub fn apply(&mut self, pdu: &mut Pdu) -> bool {
+        let (flow, reset) = match tcp_ipv4_index(pdu) {
+            Some(ix) => match pdu.headers().ip(ix).flow() {
+                Some(flow) => (flow, pdu.headers().tcp(ix + 1).rst_flag()),
+                None => return false,
+            },
+            None => return false,
+        };
+        let splice = match self.splices.get(&flow) {
+            Some(splice) => *splice,
+            None => return false,
+        };
+        splice.apply(pdu);
+        if reset {
+            self.splices.remove(&flow);
+            self.splices.remove(&splice.flow.reverse_flow());
+        }
+        true
+    }
+}
@@ -0,0 +1,445 @@
+use super::connection::*;
+use common::errors;
+use common::errors::ErrorKind;
+use fnv::FnvHasher;
+use headers::*;
+use interface::{Pdu, PmdPort};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+use std::mem;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::slice;
+use std::time::Instant;
+use utils::{ipv4_checksum, FiveTupleV4};
+
+type FnvHash = BuildHasherDefault<FnvHasher>;
+
+const IP_PROTO_TCP: u8 = 6;
+const EPHEMERAL_PORT_START: u16 = 49152;
+const DEFAULT_TTL: u8 = 64;
+
+/// Addresses and ports of a connection, from our point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SocketId {
+    pub local: SocketAddrV4,
+    pub remote: SocketAddrV4,
+}
+
+impl SocketId {
+    /// The five tuple of packets received on the connection.
+    pub fn flow(&self) -> FiveTupleV4 {
+        FiveTupleV4 {
+            src_ip: u32::from(*self.remote.ip()),
+            dst_ip: u32::from(*self.local.ip()),
+            src_port: self.remote.port(),
+            dst_port: self.local.port(),
+            proto: IP_PROTO_TCP,
+        }
+    }
+}
+
+struct Socket {
+    conn: TcpConnection,
+    /// the connection was queued for `accept`, or opened by `connect`
+    queued: bool,
+    /// the application closed the socket, it is dropped once the connection is closed
+    released: bool,
+}
+
+/// A userspace TCP endpoint for the IPv4 address of a port, with a socket-like API for NFs. Received packets are passed
+/// to `input`, packets to send are returned by `poll`, which also drives the timers. Outgoing packets have no MAC
+/// addresses, they are filled in by `ArpService::set_destination`. A stack only sees the packets arriving on its queue,
+/// so all packets of a connection must be steered to the same queue.
+pub struct TcpStack {
+    ip: Ipv4Addr,
+    config: TcpConfig,
+    listeners: HashSet<u16, FnvHash>,
+    sockets: HashMap<SocketId, Socket, FnvHash>,
+    accept_queue: VecDeque<SocketId>,
+    /// connections opened by a SYN which were not queued for `accept` yet
+    handshakes: usize,
+    next_port: u16,
+    /// resets for segments which do not belong to a connection
+    resets: Vec<(SocketId, Segment)>,
+    /// initial sequence numbers are derived from a keyed hash and a clock (RFC 6528)
+    secret: RandomState,
+    start: Instant,
+}
+
+impl TcpStack {
+    /// A stack for the IPv4 address in the `NetSpec` of `port`.
+    pub fn new(port: &PmdPort, config: TcpConfig) -> errors::Result<TcpStack> {
+        match port.ip_addr() {
+            Some(ip) => TcpStack::with_address(ip, config),
+            None => Err(ErrorKind::ConfigurationError(format!(
+                "port {} has no IP address",
+                port.name()
+            ))),
+        }
+    }
+
+    pub fn with_address(ip: Ipv4Addr, config: TcpConfig) -> errors::Result<TcpStack> {
+        Ok(TcpStack {
+            ip,
+            config,
+            listeners: HashSet::default(),
+            sockets: HashMap::default(),
+            accept_queue: VecDeque::new(),
+            handshakes: 0,
+            next_port: EPHEMERAL_PORT_START,
+            resets: Vec::new(),
+            secret: RandomState::new(),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn ip_addr(&self) -> Ipv4Addr {
+        self.ip
+    }
+
+    pub fn config(&self) -> &TcpConfig {
+        &self.config
+    }
+
+    /// Accept connections to `port`.
+    pub fn listen(&mut self, port: u16) {
+        self.listeners.insert(port);
+    }
+
+    /// Stop accepting connections to `port`, existing connections are not affected.
+    pub fn unlisten(&mut self, port: u16) {
+        self.listeners.remove(&port);
+    }
+
+    /// The next established connection to a listening port.
+    pub fn accept(&mut self) -> Option<SocketId> {
+        self.accept_queue.pop_front()
+    }
+
+    /// Open a connection to `remote` from an ephemeral port, the SYN is sent by the next `poll`.
+    pub fn connect(&mut self, remote: SocketAddrV4, now: Instant) -> errors::Result<SocketId> {
+        let id = match self.free_port(remote) {
+            Some(port) => SocketId {
+                local: SocketAddrV4::new(self.ip, port),
+                remote,
+            },
+            None => {
+                return Err(ErrorKind::RunTimeError(format!(
+                    "no free port to connect to {}",
+                    remote
+                )))
+            }
+        };
+        let iss = self.iss(&id, now);
+        let conn = TcpConnection::connect(self.config.clone(), iss)?;
+        self.sockets.insert(
+            id,
+            Socket {
+                conn,
+                queued: true,
+                released: false,
+            },
+        );
+        Ok(id)
+    }
+
+    fn free_port(&mut self, remote: SocketAddrV4) -> Option<u16> {
+        let ports = (u16::max_value() - EPHEMERAL_PORT_START) as usize + 1;
+        for _ in 0..ports {
+            let port = self.next_port;
+            self.next_port = if port == u16::max_value() {
+                EPHEMERAL_PORT_START
+            } else {
+                port + 1
+            };
+            let id = SocketId {
+                local: SocketAddrV4::new(self.ip, port),
+                remote,
+            };
+            if !self.listeners.contains(&port) && !self.sockets.contains_key(&id) {
+                return Some(port);
+            }
+        }
+        None
+    }
+
+    fn iss(&self, id: &SocketId, now: Instant) -> u32 {
+        let mut hasher = self.secret.build_hasher();
+        id.hash(&mut hasher);
+        let elapsed = now.duration_since(self.start);
+        let clock = elapsed.as_secs().wrapping_mul(250_000) + (elapsed.subsec_nanos() / 4000) as u64;
+        (hasher.finish() as u32).wrapping_add(clock as u32)
+    }
+
+    fn socket_mut(&mut self, id: &SocketId) -> errors::Result<&mut Socket> {
+        match self.sockets.get_mut(id) {
+            Some(ref socket) if socket.released => Err(ErrorKind::RunTimeError(format!("socket {:?} was closed", id))),
+            Some(socket) => Ok(socket),
+            None => Err(ErrorKind::RunTimeError(format!("no socket {:?}", id))),
+        }
+    }
+
+    /// Queue data for sending, returns the number of bytes accepted which is limited by the send buffer.
+    pub fn send(&mut self, id: &SocketId, data: &[u8]) -> errors::Result<usize> {
+        Ok(self.socket_mut(id)?.conn.send(data))
+    }
+
+    /// Read received data in order, returns the number of bytes read. Zero bytes are read if no data is available or
+    /// the peer closed the connection, see `TcpConnection::peer_closed`.
+    pub fn recv(&mut self, id: &SocketId, data: &mut [u8]) -> errors::Result<usize> {
+        Ok(self.socket_mut(id)?.conn.recv(data))
+    }
+
+    /// Close the sending side of the connection once the buffered data was sent, data can still be received.
+    pub fn shutdown(&mut self, id: &SocketId) -> errors::Result<()> {
+        self.socket_mut(id)?.conn.close();
+        Ok(())
+    }
+
+    /// Close the connection like `shutdown` and release the socket, data received afterwards is discarded. The
+    /// connection is dropped once it is closed.
+    pub fn close(&mut self, id: &SocketId) -> errors::Result<()> {
+        let socket = self.socket_mut(id)?;
+        socket.conn.close();
+        socket.released = true;
+        Ok(())
+    }
+
+    /// Reset the connection and release the socket.
+    pub fn abort(&mut self, id: &SocketId) -> errors::Result<()> {
+        let socket = self.socket_mut(id)?;
+        socket.conn.abort();
+        socket.released = true;
+        Ok(())
+    }
+
+    pub fn state(&self, id: &SocketId) -> Option<TcpState> {
+        self.sockets.get(id).map(|socket| socket.conn.state())
+    }
+
+    pub fn connection(&self, id: &SocketId) -> Option<&TcpConnection> {
+        self.sockets.get(id).map(|socket| &socket.conn)
+    }
+
+    pub fn connection_mut(&mut self, id: &SocketId) -> Option<&mut TcpConnection> {
+        self.sockets.get_mut(id).map(|socket| &mut socket.conn)
+    }
+
+    /// Number of connections, including connections closed by the application which are not closed yet.
+    pub fn len(&self) -> usize {
+        self.sockets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sockets.is_empty()
+    }
+
+    /// Process a received packet. Returns false if the packet is not a TCP/IPv4 packet to the address of the stack,
+    /// otherwise the packet was consumed and can be dropped. Segments with an invalid checksum are dropped.
+    pub fn input(&mut self, pdu: &Pdu, now: Instant) -> bool {
+        let count = pdu.headers().count();
+        let ip_ix = match (1..count).find(|&i| {
+            pdu.headers().get(i - 1).kind() == HeaderKind::Ip && pdu.headers().get(i).kind() == HeaderKind::Tcp
+        }) {
+            Some(i) => i - 1,
+            None => return false,
+        };
+        let (src, dst, tcp_len) = {
+            let ip = pdu.headers().ip(ip_ix);
+            if ip.is_fragment() {
+                return false;
+            }
+            (ip.src(), ip.dst(), (ip.length() as usize).saturating_sub(ip.offset()))
+        };
+        if Ipv4Addr::from(dst) != self.ip {
+            return false;
+        }
+        let mut bytes = pdu.payload_to_vec(ip_ix);
+        if bytes.len() < tcp_len || tcp_len < TcpHeader::size() {
+            return true;
+        }
+        // drop Ethernet padding
+        bytes.truncate(tcp_len);
+        // summing the checksum field too yields zero for a valid segment
+        if ipv4_checksum(bytes.as_mut_ptr(), tcp_len, tcp_len, &[], src, dst, IP_PROTO_TCP as u32) != 0 {
+            return true;
+        }
+        let available = pdu.header_available(ip_ix + 1);
+        let (segment, id) = {
+            let tcp = pdu.headers().tcp(ip_ix + 1);
+            let data_offset = tcp.offset();
+            if data_offset < TcpHeader::size() || data_offset > tcp_len {
+                return true;
+            }
+            let segment = Segment {
+                seq: tcp.seq_num(),
+                ack: tcp.ack_num(),
+                flags: segment_flags(tcp),
+                window: tcp.window_size(),
+                mss: tcp.mss(available),
+                payload: bytes[data_offset..].to_vec(),
+            };
+            let id = SocketId {
+                local: SocketAddrV4::new(self.ip, tcp.dst_port()),
+                remote: SocketAddrV4::new(Ipv4Addr::from(src), tcp.src_port()),
+            };
+            (segment, id)
+        };
+        self.dispatch(id, &segment, now);
+        true
+    }
+
+    fn dispatch(&mut self, id: SocketId, segment: &Segment, now: Instant) {
+        if let Some(socket) = self.sockets.get_mut(&id) {
+            socket.conn.on_segment(segment, now);
+            if !socket.queued && socket.conn.is_synchronized() {
+                socket.queued = true;
+                self.handshakes -= 1;
+                self.accept_queue.push_back(id);
+            }
+            return;
+        }
+        let is_syn = segment.flags & (TCP_FLAG_SYN | TCP_FLAG_ACK | TCP_FLAG_RST) == TCP_FLAG_SYN;
+        if is_syn && self.listeners.contains(&id.local.port()) && self.sockets.len() < self.config.max_connections {
+            // the peer retries the SYN once handshakes completed or timed out
+            if self.handshakes >= self.config.syn_backlog {
+                return;
+            }
+            let iss = self.iss(&id, now);
+            if let Ok(conn) = TcpConnection::accept(self.config.clone(), iss, segment) {
+                self.sockets.insert(
+                    id,
+                    Socket {
+                        conn,
+                        queued: false,
+                        released: false,
+                    },
+                );
+                self.handshakes += 1;
+            }
+        } else if let Some(reset) = Segment::reset_for(segment) {
+            self.resets.push((id, reset));
+        }
+    }
+
+    /// Advance the timers of all connections and return the packets to send, the caller owns the packets. Segments
+    /// which could not be turned into packets are lost and retransmitted later.
+    pub fn poll(&mut self, now: Instant) -> errors::Result<Vec<Pdu<'static>>> {
+        let mut outgoing: Vec<(SocketId, Segment)> = self.resets.drain(..).collect();
+        let mut discard = [0u8; 2048];
+        for (id, socket) in &mut self.sockets {
+            if socket.released {
+                while socket.conn.recv(&mut discard) > 0 {}
+            }
+            outgoing.extend(socket.conn.poll(now).into_iter().map(|segment| (*id, segment)));
+        }
+        // closed connections are kept until the application released them, unless they were never accepted
+        let handshakes = &mut self.handshakes;
+        self.sockets.retain(|_, socket| {
+            let keep = socket.conn.state() != TcpState::Closed || (socket.queued && !socket.released);
+            if !keep && !socket.queued {
+                *handshakes -= 1;
+            }
+            keep
+        });
+
+        let mut pdus = Vec::with_capacity(outgoing.len());
+        for (id, segment) in outgoing {
+            match segment_pdu(&id, &segment) {
+                Ok(pdu) => pdus.push(pdu),
+                Err(e) => {
+                    pdus.into_iter().for_each(|pdu: Pdu| pdu.free());
+                    return Err(e);
+                }
+            }
+        }
+        Ok(pdus)
+    }
+}
+
+fn segment_flags(tcp: &TcpHeader) -> u8 {
+    let mut flags = 0;
+    if tcp.fin_flag() {
+        flags |= TCP_FLAG_FIN;
+    }
+    if tcp.syn_flag() {
+        flags |= TCP_FLAG_SYN;
+    }
+    if tcp.rst_flag() {
+        flags |= TCP_FLAG_RST;
+    }
+    if tcp.psh_flag() {
+        flags |= TCP_FLAG_PSH;
+    }
+    if tcp.ack_flag() {
+        flags |= TCP_FLAG_ACK;
+    }
+    flags
+}
+
+#[inline]
+fn bytes_of<T>(value: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) }
+}
+
+/// An Ethernet/IPv4/TCP packet carrying `segment`, with the MSS option if present.
+fn segment_pdu(id: &SocketId, segment: &Segment) -> errors::Result<Pdu<'static>> {
+    let mut tcp = TcpHeader::new();
+    tcp.set_src_port(id.local.port());
+    tcp.set_dst_port(id.remote.port());
+    tcp.set_seq_num(segment.seq);
+    tcp.set_ack_num(segment.ack);
+    tcp.set_window_size(segment.window);
+    tcp.set_data_offset(if segment.mss.is_some() { 6 } else { 5 });
+    if segment.has(TCP_FLAG_FIN) {
+        tcp.set_fin_flag();
+    }
+    if segment.has(TCP_FLAG_SYN) {
+        tcp.set_syn_flag();
+    }
+    if segment.has(TCP_FLAG_RST) {
+        tcp.set_rst_flag();
+    }
+    if segment.has(TCP_FLAG_PSH) {
+        tcp.set_psh_flag();
+    }
+    if segment.has(TCP_FLAG_ACK) {
+        tcp.set_ack_flag();
+    }
+    let mut bytes = Vec::with_capacity(tcp.offset() + segment.payload.len());
+    bytes.extend_from_slice(bytes_of(&tcp));
+    if let Some(mss) = segment.mss {
+        TcpOption::Mss(mss).encode(&mut bytes);
+    }
+    bytes.extend_from_slice(&segment.payload);
+    let (src, dst) = (u32::from(*id.local.ip()), u32::from(*id.remote.ip()));
+    let csum = ipv4_checksum(bytes.as_mut_ptr(), bytes.len(), 8, &[], src, dst, IP_PROTO_TCP as u32);
+    bytes[16] = (csum >> 8) as u8;
+    bytes[17] = csum as u8;
+
+    let mut eth = MacHeader::new();
+    eth.set_etype(0x0800);
+    let mut ip = IpHeader::new();
+    ip.set_version(4);
+    ip.set_ihl(5);
+    ip.set_ttl(DEFAULT_TTL);
+    ip.set_protocol(IP_PROTO_TCP);
+    ip.set_flags(IP_FLAG_DF);
+    ip.set_src(src);
+    ip.set_dst(dst);
+    ip.set_length((IpHeader::size() + bytes.len()) as u16);
+    ip.update_checksum();
+
+    let mut pdu = match Pdu::new_pdu() {
+        Some(pdu) => pdu,
+        None => return Err(ErrorKind::FailedAllocation),
+    };
+    if !pdu.push_header(&eth) || !pdu.push_header(&ip) || pdu.add_to_payload_tail(bytes.len()).is_err() {
+        pdu.free();
+        return Err(ErrorKind::FailedAllocation);
+    }
+    pdu.write_at(MacHeader::size() + IpHeader::size(), &bytes);
+    pdu.reparse();
+    Ok(pdu)
+}
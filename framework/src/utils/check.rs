@@ -39,25 +39,17 @@ pub type u16be = u16;
 /// `skipword` will be skipped. Each word is treated as big endian.
 use std::slice;
 #[inline]
-fn sum_be_words(data: &[u8], mut skipword: usize) -> u32 {
-    let len = data.len();
-    let wdata: &[u16] = unsafe { slice::from_raw_parts(data.as_ptr() as *const u16, len / 2) };
-    skipword = ::std::cmp::min(skipword, wdata.len());
-
-    let mut sum = 0u32;
-    let mut i = 0;
-    while i < skipword {
-        sum += u16::from_be(unsafe { *wdata.get_unchecked(i) }) as u32;
-        i += 1;
-    }
-    i += 1;
-    while i < wdata.len() {
-        sum += u16::from_be(unsafe { *wdata.get_unchecked(i) }) as u32;
-        i += 1;
-    }
+fn sum_be_words(data: &[u8], skipword: usize) -> u32 {
+    // the data is not necessarily aligned for u16, e.g. a packed header on the stack
+    let mut sum = data
+        .chunks_exact(2)
+        .enumerate()
+        .filter(|&(i, _)| i != skipword)
+        .map(|(_, word)| u16::from_be_bytes([word[0], word[1]]) as u32)
+        .sum();
     // If the length is odd, make sure to checksum the final byte
-    if len & 1 != 0 {
-        sum += (unsafe { *data.get_unchecked(len - 1) } as u32) << 8;
+    if let [last] = data.chunks_exact(2).remainder() {
+        sum += (*last as u32) << 8;
     }
 
     sum
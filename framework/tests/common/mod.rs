@@ -0,0 +1,61 @@
+// helpers shared by the integration tests, not every test uses all of them
+#![allow(dead_code)]
+
+use e2d2::interface::Pdu;
+use e2d2::native::zcsi::MBuf;
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+
+/// An mbuf over `data`, which does not belong to a mempool. `next` is the following segment or null.
+pub fn mbuf_over(data: &mut [u8], next: *mut MBuf) -> MBuf {
+    let mut mbuf: MBuf = unsafe { mem::zeroed() };
+    mbuf.buf_addr = data.as_mut_ptr() as *mut c_void;
+    mbuf.buf_len = data.len() as u16;
+    mbuf.data_len = data.len() as u16;
+    mbuf.pkt_len = data.len() as u32;
+    mbuf.nb_segs = 1;
+    mbuf.refcnt = 1;
+    mbuf.next = next;
+    mbuf
+}
+
+/// An mbuf over the frame which follows `headroom` bytes in `buffer`, e.g. for headers inserted in front.
+pub fn mbuf_with_headroom(buffer: &mut [u8], headroom: usize) -> MBuf {
+    let mut mbuf = mbuf_over(buffer, ptr::null_mut());
+    mbuf.data_off = headroom as u16;
+    mbuf.data_len -= headroom as u16;
+    mbuf.pkt_len -= headroom as u32;
+    mbuf
+}
+
+/// The data of the first segment of `mbuf`.
+pub fn mbuf_data(mbuf: &MBuf) -> &[u8] {
+    unsafe {
+        let start = (mbuf.buf_addr as *const u8).add(mbuf.data_off as usize);
+        slice::from_raw_parts(start, mbuf.data_len as usize)
+    }
+}
+
+/// An empty packet over a leaked buffer of 2048 bytes, to be passed where the framework allocates packets. The mbuf is
+/// referenced twice, so that freeing it only decrements the reference count.
+pub fn leaked_pdu() -> Option<Pdu<'static>> {
+    let buffer = Box::leak(vec![0u8; 2048].into_boxed_slice());
+    let mbuf = Box::leak(Box::new(mbuf_over(buffer, ptr::null_mut())));
+    mbuf.data_len = 0;
+    mbuf.pkt_len = 0;
+    mbuf.refcnt = 2;
+    Some(Pdu::pdu_from_mbuf_no_increment(mbuf))
+}
+
+/// Sum of the 16 bit words of `data` in one's complement, 0xffff over data including a valid checksum.
+pub fn ones_complement_sum(data: &[u8], mut sum: u32) -> u16 {
+    for word in data.chunks(2) {
+        sum += (word[0] as u32) << 8 | *word.get(1).unwrap_or(&0) as u32;
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
@@ -0,0 +1,139 @@
+extern crate e2d2;
+use e2d2::metrics::*;
+use e2d2::state::*;
+use e2d2::utils::FiveTupleV4;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+fn be16(buf: &[u8], offset: usize) -> u16 {
+    (buf[offset] as u16) << 8 | buf[offset + 1] as u16
+}
+
+fn be32(buf: &[u8], offset: usize) -> u32 {
+    (be16(buf, offset) as u32) << 16 | be16(buf, offset + 2) as u32
+}
+
+fn collector() -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    socket
+}
+
+fn flow() -> FiveTupleV4 {
+    FiveTupleV4 {
+        src_ip: 0x0a00_0001,
+        dst_ip: 0x0a00_0002,
+        src_port: 40000,
+        dst_port: 80,
+        proto: 6,
+    }
+}
+
+#[test]
+fn flow_export_ipfix_expired_flows() {
+    let collector = collector();
+    let config = FlowExportConfig::new(collector.local_addr().unwrap(), FlowExportProtocol::Ipfix);
+    let mut exporter = FlowExporter::new(config).unwrap();
+    let mut table = FlowTable::new(FlowTableConfig {
+        capacity: 16,
+        idle_timeout: 100,
+        hard_timeout: 0,
+    });
+    table.on_expiry(exporter.expiry_fn());
+    let stats = table.get_or_insert_with(flow(), 0, FlowStats::default);
+    stats.update(60, 0x02, 1_000);
+    stats.update(1500, 0x10, 1_020);
+    assert_eq!(table.expire_all(200), 1);
+    assert_eq!(exporter.flush().unwrap(), 1);
+
+    let mut buf = [0u8; 2048];
+    let len = collector.recv(&mut buf).unwrap();
+    assert_eq!(be16(&buf, 0), 10);
+    assert_eq!(be16(&buf, 2) as usize, len);
+    assert_eq!(be32(&buf, 8), 0);
+    // template set with 11 fields, followed by the data set of one record
+    assert_eq!(be16(&buf, 16), 2);
+    let template_len = be16(&buf, 18) as usize;
+    assert_eq!(template_len, 8 + 4 * 11);
+    assert_eq!(be16(&buf, 20), 256);
+    let data = 16 + template_len;
+    assert_eq!(be16(&buf, data), 256);
+    assert_eq!(be16(&buf, data + 2) as usize, len - data);
+    let record = data + 4;
+    assert_eq!(be32(&buf, record), 0x0a00_0001);
+    assert_eq!(be16(&buf, record + 8), 40000);
+    assert_eq!(buf[record + 12], 6);
+    assert_eq!(buf[record + 13], 0x12);
+    assert_eq!(be32(&buf, record + 18), 2);
+    assert_eq!(be32(&buf, record + 26), 1560);
+    assert_eq!(buf[len - 1], FlowEndReason::IdleTimeout as u8);
+
+    // the template is not repeated before the interval elapsed
+    assert_eq!(exporter.flush().unwrap(), 0);
+}
+
+#[test]
+fn flow_export_netflow_v9_splits_messages() {
+    let collector = collector();
+    let mut config = FlowExportConfig::new(collector.local_addr().unwrap(), FlowExportProtocol::NetflowV9);
+    config.max_message_size = 200;
+    let mut exporter = FlowExporter::new(config).unwrap();
+    for _ in 0..10 {
+        exporter.export(FlowRecord {
+            flow: flow(),
+            stats: FlowStats::default(),
+            end_reason: FlowEndReason::EndOfFlow,
+        });
+    }
+    let messages = exporter.flush().unwrap();
+    assert!(messages > 1);
+    let mut records = 0;
+    let mut buf = [0u8; 2048];
+    for sequence in 0..messages {
+        collector.recv(&mut buf).unwrap();
+        assert_eq!(be16(&buf, 0), 9);
+        assert_eq!(be32(&buf, 12), sequence as u32);
+        records += be16(&buf, 2) as usize;
+    }
+    // the first message carries the template record
+    assert_eq!(records, 11);
+}
+
+#[test]
+fn flow_export_drops_records_beyond_channel_size() {
+    let collector = collector();
+    let mut config = FlowExportConfig::new(collector.local_addr().unwrap(), FlowExportProtocol::Ipfix);
+    config.channel_size = 2;
+    let mut exporter = FlowExporter::new(config).unwrap();
+    let mut table = FlowTable::new(FlowTableConfig {
+        capacity: 16,
+        idle_timeout: 100,
+        hard_timeout: 0,
+    });
+    table.on_expiry(exporter.expiry_fn());
+    for port in 0..5 {
+        let flow = FiveTupleV4 {
+            src_port: port,
+            ..flow()
+        };
+        table
+            .get_or_insert_with(flow, 0, FlowStats::default)
+            .update(60, 0x10, 1_000);
+    }
+    assert_eq!(table.expire_all(200), 5);
+    assert_eq!(exporter.dropped(), 3);
+    assert_eq!(exporter.flush().unwrap(), 1);
+
+    let mut buf = [0u8; 2048];
+    collector.recv(&mut buf).unwrap();
+    assert_eq!(be32(&buf, 8), 0);
+    // the sequence number of the next message counts the two records sent
+    exporter.export(FlowRecord {
+        flow: flow(),
+        stats: FlowStats::default(),
+        end_reason: FlowEndReason::EndOfFlow,
+    });
+    assert_eq!(exporter.flush().unwrap(), 1);
+    collector.recv(&mut buf).unwrap();
+    assert_eq!(be32(&buf, 8), 2);
+}
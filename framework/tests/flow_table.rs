@@ -0,0 +1,86 @@
+extern crate e2d2;
+use e2d2::state::*;
+use e2d2::utils::FiveTupleV4;
+use std::sync::{Arc, Mutex};
+
+fn flow(port: u16) -> FiveTupleV4 {
+    FiveTupleV4 {
+        src_ip: 0x0a00_0001,
+        dst_ip: 0x0a00_0002,
+        src_port: port,
+        dst_port: 80,
+        proto: 6,
+    }
+}
+
+fn table(
+    capacity: usize,
+    idle_timeout: u64,
+    hard_timeout: u64,
+) -> (FlowTable<u32>, Arc<Mutex<Vec<(u16, ExpiryReason)>>>) {
+    let mut table = FlowTable::new(FlowTableConfig {
+        capacity,
+        idle_timeout,
+        hard_timeout,
+    });
+    let expired = Arc::new(Mutex::new(Vec::new()));
+    let log = expired.clone();
+    table.on_expiry(Box::new(move |flow, _, reason| {
+        log.lock().unwrap().push((flow.src_port, reason))
+    }));
+    (table, expired)
+}
+
+#[test]
+fn flow_table_timeouts() {
+    let (mut table, expired) = table(16, 100, 1000);
+    table.insert(flow(1), 1, 0);
+    table.insert(flow(2), 2, 0);
+    for now in &[50, 100] {
+        *table.get_or_insert_with(flow(1), *now, || 0) += 1;
+    }
+    assert_eq!(table.expire_all(150), 1);
+    assert_eq!(table.peek(&flow(1)), Some(&3));
+    assert!(table.get_mut(&flow(1), 1000).is_none());
+    assert!(table.is_empty());
+    assert_eq!(
+        *expired.lock().unwrap(),
+        vec![(2, ExpiryReason::Idle), (1, ExpiryReason::Hard)]
+    );
+}
+
+#[test]
+fn flow_table_evicts_unused() {
+    let (mut table, expired) = table(3, 0, 0);
+    for port in 1..4 {
+        table.insert(flow(port), port as u32, 0);
+    }
+    // the clock hand clears all reference bits and evicts the first flow, which is then not used again
+    table.insert(flow(4), 4, 1);
+    table.get_mut(&flow(2), 2);
+    table.insert(flow(5), 5, 3);
+    assert_eq!(table.len(), 3);
+    assert!(table.peek(&flow(2)).is_some());
+    assert_eq!(
+        *expired.lock().unwrap(),
+        vec![(1, ExpiryReason::Evicted), (3, ExpiryReason::Evicted)]
+    );
+    assert_eq!(table.remove(&flow(2)), Some(2));
+    assert_eq!(expired.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn flow_table_replace_keeps_hard_timeout() {
+    let (mut table, expired) = table(16, 0, 1000);
+    table.insert(flow(1), 1, 0);
+    assert_eq!(table.insert(flow(1), 2, 900), Some(1));
+    assert_eq!(table.expire_all(999), 0);
+    assert_eq!(table.expire_all(1000), 1);
+    assert_eq!(*expired.lock().unwrap(), vec![(1, ExpiryReason::Hard)]);
+
+    // an expired flow is replaced by a new one
+    table.insert(flow(2), 1, 0);
+    assert_eq!(table.insert(flow(2), 2, 1500), None);
+    assert_eq!(table.expire_all(2000), 0);
+    assert_eq!(table.peek(&flow(2)), Some(&2));
+}
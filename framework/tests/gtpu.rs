@@ -0,0 +1,166 @@
+extern crate e2d2;
+extern crate eui48;
+mod common;
+use common::{mbuf_over, mbuf_with_headroom};
+use e2d2::headers::*;
+use e2d2::interface::{Pdu, Tunnel, TunnelSpec};
+use e2d2::state::GtpuSessionTable;
+use eui48::MacAddress;
+use std::net::Ipv4Addr;
+use std::ptr;
+
+#[test]
+fn gtpu_extension_headers() {
+    // E flag, sequence number 0x1234, PDU session container with QFI 9, then an empty 4 byte extension of type 0x40
+    let bytes: [u8; 24] = [
+        0x36, 0xff, 0x00, 0x10, 0xde, 0xad, 0xbe, 0xef, 0x12, 0x34, 0x00, 0x85, 0x01, 0x10, 0x09, 0x40, 0x01, 0x00,
+        0x00, 0x00, 0x45, 0x00, 0x00, 0x14,
+    ];
+    let gtpu = unsafe { &*(bytes.as_ptr() as *const GtpuHeader) };
+    assert_eq!(gtpu.version(), 1);
+    assert_eq!(gtpu.msg_type(), GTPU_MSG_GPDU);
+    assert_eq!(gtpu.teid(), 0xdead_beef);
+    assert_eq!(gtpu.seq_num(), Some(0x1234));
+    assert_eq!(gtpu.npdu_num(), None);
+    assert_eq!(gtpu.offset(), 20);
+    assert_eq!(gtpu.header_len(19), None);
+    assert_eq!(
+        gtpu.extensions().map(|ext| ext.ext_type).collect::<Vec<_>>(),
+        vec![0x85, 0x40]
+    );
+    assert_eq!(gtpu.qfi(), Some(9));
+}
+
+#[test]
+fn gtpu_extension_chain_bounds() {
+    // the length field covers the header up to the first extension only, the chain continues in the payload
+    let mut bytes = [0u8; 200];
+    bytes[..12].copy_from_slice(&[0x34, 0xff, 0x00, 0x04, 0, 0, 0, 1, 0, 0, 0, 0x40]);
+    for ext in bytes[12..].chunks_mut(4) {
+        ext.copy_from_slice(&[0x01, 0x00, 0x00, 0x40]);
+    }
+    let gtpu = unsafe { &mut *(bytes.as_mut_ptr() as *mut GtpuHeader) };
+    assert_eq!(gtpu.header_len(200), None);
+    assert_eq!(gtpu.offset(), 8);
+
+    // within the length field, but more extension headers than are followed
+    gtpu.set_length(192);
+    assert_eq!(gtpu.header_len(200), None);
+    assert_eq!(gtpu.offset(), 8);
+    assert_eq!(gtpu.extensions().count(), 0);
+
+    // the chain ends after two extensions
+    bytes[19] = 0;
+    let gtpu = unsafe { &*(bytes.as_ptr() as *const GtpuHeader) };
+    assert_eq!(gtpu.header_len(200), Some(20));
+    assert_eq!(gtpu.offset(), 20);
+}
+
+#[test]
+fn gtpu_plain_header() {
+    let mut gtpu = GtpuHeader::new();
+    gtpu.set_teid(7);
+    assert_eq!(gtpu.offset(), 8);
+    assert_eq!(gtpu.next_ext_type(), None);
+    assert_eq!(gtpu.teid(), 7);
+}
+
+#[test]
+fn session_allocation() {
+    let mut table = GtpuSessionTable::with_capacity(16);
+    assert_eq!(table.insert(2, 0u64), None);
+    assert_eq!(table.allocate(10), Some(1));
+    // TEID 2 is taken already
+    assert_eq!(table.allocate(20), Some(3));
+    assert!(table.update(3, 5));
+    assert!(!table.update(4, 5));
+    assert_eq!(table.get(3), Some(&25));
+    assert_eq!(table.remove(1), Some(10));
+    assert_eq!(table.len(), 2);
+}
+
+/// An Ethernet frame with a G-PDU on UDP/IPv4, carrying a UDP/IPv4 packet after a PDU session container with QFI 9.
+fn gtpu_frame() -> Vec<u8> {
+    let mut frame = vec![0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1, 0x08, 0];
+    frame.extend_from_slice(&[0x45, 0, 0, 72, 0, 0, 0, 0, 64, 17, 0, 0, 192, 168, 0, 1, 192, 168, 0, 2]);
+    frame.extend_from_slice(&[0x08, 0x68, 0x08, 0x68, 0, 52, 0, 0]);
+    frame.extend_from_slice(&[0x34, 0xff, 0, 36, 0, 0, 0, 7, 0, 0, 0, 0x85, 0x01, 0x10, 0x09, 0x00]);
+    frame.extend_from_slice(&[0x45, 0, 0, 28, 0, 0, 0, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+    frame.extend_from_slice(&[0x30, 0x39, 0, 53, 0, 8, 0, 0]);
+    frame
+}
+
+#[test]
+fn gtpu_parse() {
+    let mut frame = gtpu_frame();
+    let mut mbuf = mbuf_over(&mut frame, ptr::null_mut());
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    let headers = pdu.headers();
+    assert_eq!(headers.count(), 6);
+    assert_eq!(headers.get(3).kind(), HeaderKind::Gtpu);
+    assert_eq!(headers.inner_start(), Some(4));
+    let gtpu = headers.gtpu(3);
+    assert_eq!(gtpu.teid(), 7);
+    assert_eq!(gtpu.offset(), 16);
+    assert_eq!(gtpu.qfi(), Some(9));
+    assert_eq!(headers.get(4).kind(), HeaderKind::Ip);
+    assert_eq!(headers.udp(5).dst_port(), 53);
+}
+
+#[test]
+fn gtpu_parse_malformed_extensions() {
+    // the extension header claims more bytes than the length field covers, though not more than the frame
+    let mut frame = gtpu_frame();
+    frame[54] = 0x09;
+    frame.extend_from_slice(&[0; 16]);
+    let mut mbuf = mbuf_over(&mut frame, ptr::null_mut());
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    assert_eq!(pdu.headers().count(), 3);
+    assert_eq!(pdu.headers().inner_start(), None);
+
+    // truncated in the middle of the extension chain
+    let mut frame = gtpu_frame();
+    frame.truncate(56);
+    let mut mbuf = mbuf_over(&mut frame, ptr::null_mut());
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    assert_eq!(pdu.headers().count(), 3);
+}
+
+const HEADROOM: usize = 64;
+
+#[test]
+fn gtpu_encap_decap() {
+    let mut buffer = vec![0u8; HEADROOM];
+    buffer.extend_from_slice(&[0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1, 0x08, 0]);
+    buffer.extend_from_slice(&[0x45, 0, 0, 28, 0, 0, 0, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+    buffer.extend_from_slice(&[0x30, 0x39, 0, 53, 0, 8, 0, 0]);
+    let frame = buffer[HEADROOM..].to_vec();
+    let spec = TunnelSpec {
+        src_mac: MacAddress::new([0x02, 0, 0, 0, 0, 0x11]),
+        dst_mac: MacAddress::new([0x02, 0, 0, 0, 0, 0x22]),
+        src_ip: Ipv4Addr::new(192, 168, 0, 1),
+        dst_ip: Ipv4Addr::new(192, 168, 0, 2),
+        ttl: 64,
+        tunnel: Tunnel::Gtpu(0xdead_beef),
+    };
+    let mut mbuf = mbuf_with_headroom(&mut buffer, HEADROOM);
+    let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    pdu.encap_tunnel(&spec).unwrap();
+    assert_eq!(pdu.data_len(), frame.len() + spec.overhead());
+    {
+        let headers = pdu.headers();
+        assert_eq!(headers.count(), 6);
+        assert_eq!(headers.inner_start(), Some(4));
+        assert_eq!(headers.ip(1).length() as usize, pdu.data_len() - 14);
+        assert_eq!(headers.udp(2).dst_port(), GTPU_PORT);
+        let gtpu = headers.gtpu(3);
+        assert_eq!((gtpu.teid(), gtpu.msg_type()), (0xdead_beef, GTPU_MSG_GPDU));
+        assert_eq!(gtpu.length() as usize, frame.len() - 14);
+        assert_eq!(headers.udp(5).dst_port(), 53);
+    }
+    pdu.decap_tunnel().unwrap();
+    assert_eq!(pdu.headers().count(), 3);
+    // the outer Ethernet header is kept
+    assert_eq!({ pdu.headers().mac(0).dst }, spec.dst_mac);
+    assert_eq!(&buffer[HEADROOM + 12..], &frame[12..]);
+}
@@ -0,0 +1,120 @@
+extern crate e2d2;
+use e2d2::state::*;
+use e2d2::utils::FiveTupleV4;
+
+fn flow(port: u16) -> FiveTupleV4 {
+    FiveTupleV4 {
+        src_ip: 0x0a00_0001,
+        dst_ip: 0x0a00_0002,
+        src_port: port,
+        dst_port: 80,
+        proto: 6,
+    }
+}
+
+#[test]
+fn heavy_hitters_track_top_flows() {
+    let mut top = HeavyHitters::new(8, HeavyHitterMetric::Bytes);
+    // three elephants among many mice
+    for i in 0..10000u16 {
+        top.update(flow(1000 + i % 1000), 64);
+        if i % 4 == 0 {
+            top.update(flow(1), 1500);
+            top.update(flow(2), 1000);
+        }
+        if i % 8 == 0 {
+            top.update(flow(3), 1500);
+        }
+    }
+    let flows: Vec<_> = top.top().iter().take(3).map(|c| c.flow).collect();
+    assert_eq!(flows, vec![flow(1), flow(2), flow(3)]);
+    let elephant = top.get(&flow(1)).unwrap();
+    assert!(elephant.bytes - elephant.error <= 2500 * 1500 && elephant.bytes >= 2500 * 1500);
+    assert_eq!(top.len(), 8);
+}
+
+fn summary(counts: &[(u16, u64)]) -> HeavyHitters {
+    let mut top = HeavyHitters::new(counts.len(), HeavyHitterMetric::Bytes);
+    for &(port, bytes) in counts {
+        top.update(flow(port), bytes);
+    }
+    top
+}
+
+#[test]
+fn heavy_hitters_merge_credits_untracked_flows() {
+    let mut top = summary(&[(1, 10), (2, 6), (4, 5)]);
+    top.merge(summary(&[(1, 8), (3, 4), (5, 2)]));
+    let merged = top.top();
+    let flows: Vec<_> = merged.iter().map(|c| (c.flow.src_port, c.bytes, c.error)).collect();
+    // flow 3 may have had up to 5 bytes in the first summary, flow 2 up to 2 in the second
+    assert_eq!(flows, vec![(1, 18, 0), (3, 9, 5), (2, 8, 2)]);
+    assert_eq!(top.get(&flow(3)).unwrap().packets, 2);
+
+    let mut top = HeavyHitters::new(4, HeavyHitterMetric::Bytes);
+    top.update(flow(1), 10);
+    top.merge(summary(&[(2, 6)]));
+    // a summary which is not full tracks every flow exactly
+    let mut partial = HeavyHitters::new(4, HeavyHitterMetric::Bytes);
+    partial.update(flow(2), 3);
+    top.merge(partial);
+    assert_eq!(top.get(&flow(1)).unwrap().error, 6);
+    assert_eq!(top.get(&flow(2)).unwrap().error, 0);
+    assert_eq!(top.get(&flow(2)).unwrap().bytes, 9);
+}
+
+#[test]
+fn heavy_hitters_keep_interval_while_channel_full() {
+    let config = HeavyHitterConfig {
+        capacity: 4,
+        sample_rate: 0,
+        export_interval: 10,
+        ..Default::default()
+    };
+    let mut collector = HeavyHitterCollector::new(config, 1);
+    let mut core = collector.exporter();
+    core.record(flow(1), 100, 0);
+    assert!(core.export(5));
+    core.record(flow(2), 200, 6);
+    assert!(!core.export(8));
+    // the counts of the interval are kept until the report can be sent
+    assert_eq!(core.top().get(&flow(2)).unwrap().bytes, 200);
+    assert_eq!(collector.recv(), 1);
+    core.record(flow(2), 200, 9);
+    assert!(core.export(10));
+    assert!(core.top().is_empty());
+    assert_eq!(collector.recv(), 1);
+    let report = collector.reports().next().unwrap();
+    assert_eq!((report.start, report.end), (6, 10));
+    assert_eq!(report.top.get(&flow(2)).unwrap().bytes, 400);
+}
+
+#[test]
+fn heavy_hitters_collect_cores() {
+    let config = HeavyHitterConfig {
+        capacity: 4,
+        sample_rate: 10,
+        export_interval: 1000,
+        ..Default::default()
+    };
+    let mut collector = HeavyHitterCollector::new(config, 16);
+    let mut cores = vec![collector.exporter(), collector.exporter()];
+    for now in 0..1000u64 {
+        let core = &mut cores[(now % 2) as usize];
+        core.record(flow((now % 3) as u16), 100 * (now % 3 + 1), now);
+        core.poll(now);
+    }
+    assert_eq!(collector.recv(), 0);
+    for core in &mut cores {
+        core.poll(1001);
+    }
+    assert_eq!(collector.recv(), 2);
+    let top = collector.top(2);
+    assert_eq!(top.len(), 2);
+    assert_eq!(top[0].flow, flow(2));
+    assert_eq!(top[0].packets, 333);
+    let samples = collector.take_samples().len();
+    assert!(samples > 50 && samples < 200, "{} samples", samples);
+    assert!(collector.take_samples().is_empty());
+    assert!(cores[0].top().is_empty());
+}
@@ -0,0 +1,88 @@
+extern crate e2d2;
+use e2d2::config::*;
+
+const CONFIG: &'static str = r#"
+[netbricks]
+name = "mempool-test"
+master_core = 0
+cores = [1, 2, 9]
+mbuf_data_room = 9344
+mbuf_cnt = "auto"
+
+[[netbricks.ports]]
+name = "0000:01:00.0"
+rx_cores = [1, 9]
+tx_cores = [1, 9]
+rxd = 1024
+txd = 1024
+
+[[netbricks.ports]]
+name = "0000:82:00.0"
+cores = [2]
+mempool = 1
+"#;
+
+// cores 0..8 on socket 0, 8..16 on socket 1
+fn socket_of_core(core: i32) -> i32 {
+    core / 8
+}
+
+// the first port is attached to socket 1
+fn socket_of_device(name: &str) -> Option<i32> {
+    if name == "0000:01:00.0" {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+#[test]
+fn read_mempool_configuration() {
+    let config = read_configuration_from_str(CONFIG, "mempool-test").unwrap();
+    assert_eq!(config.mbuf_data_room, 9344);
+    assert_eq!(config.mbuf_cnt, None);
+    let config = read_configuration_from_str(&CONFIG.replace("mbuf_cnt = \"auto\"\n", ""), "mempool-test").unwrap();
+    assert_eq!(config.mbuf_cnt, Some(DEFAULT_MBUF_CNT));
+    assert_eq!(config.ports[0].mempool, MempoolSelection::Local);
+    assert_eq!(config.ports[1].mempool, MempoolSelection::Socket(1));
+}
+
+#[test]
+fn size_mempools_from_topology() {
+    let mut config = read_configuration_from_str(CONFIG, "mempool-test").unwrap();
+    let counts = config.mbufs_per_socket(socket_of_core, socket_of_device);
+    assert_eq!(counts.len(), 2);
+    // socket 0: cores 0, 1, 2 => 3 * (48 + 2048) + 1024 + 1024 + 128 = 8464
+    assert_eq!(counts[0], 16383);
+    // socket 1: core 9 => 48 + 2048 + 1024 + 1024 + 128 (rx queue of second port) = 4272
+    assert_eq!(counts[1], 8191);
+
+    // the rx descriptors of the first port move to the pool of its device
+    config.ports[0].mempool = MempoolSelection::Device;
+    let counts = config.mbufs_per_socket(socket_of_core, socket_of_device);
+    // socket 0: 3 * (48 + 2048) + 1024 + 128 = 7440
+    assert_eq!(counts[0], 8191);
+    // socket 1: 48 + 2048 + 2 * 1024 + 1024 + 128 = 5296
+    assert_eq!(counts[1], 8191);
+    config.ports[1].mempool = MempoolSelection::Device;
+    config.ports[0].mempool = MempoolSelection::Local;
+    // the second device has no known socket, its queue uses the pool local to core 2
+    assert_eq!(
+        config.mbufs_per_socket(socket_of_core, socket_of_device),
+        vec![16383, 8191]
+    );
+
+    config.mbuf_cnt = Some(65535);
+    assert_eq!(
+        config.mbufs_per_socket(socket_of_core, socket_of_device),
+        vec![65535, 65535]
+    );
+}
+
+#[test]
+fn reject_bad_mempool_selection() {
+    let config = CONFIG.replace("mempool = 1", "mempool = \"remote\"");
+    assert!(read_configuration_from_str(&config, "mempool-test").is_err());
+    let config = CONFIG.replace("mbuf_data_room = 9344", "mbuf_data_room = 64");
+    assert!(read_configuration_from_str(&config, "mempool-test").is_err());
+}
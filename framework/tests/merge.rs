@@ -0,0 +1,87 @@
+extern crate e2d2;
+use e2d2::state::*;
+use e2d2::utils::FiveTupleV4;
+
+#[test]
+fn merge_max_min_last_writer() {
+    let mut max = Max::default();
+    let mut min = Min::default();
+    for v in &[3, 9, 1, 4] {
+        max.merge(Max::new(*v));
+        min.merge(Min::new(*v));
+    }
+    assert_eq!(max.get(), Some(&9));
+    assert_eq!(min.get(), Some(&1));
+
+    let mut a = LastWriter::new(10, "new");
+    a.merge(LastWriter::new(5, "old"));
+    a.merge(LastWriter::default());
+    assert_eq!(a.get(), Some(&"new"));
+}
+
+#[test]
+fn merge_sketches_across_stores() {
+    let prefix = FiveTupleV4 {
+        src_ip: 0,
+        dst_ip: 0x0a00_0000,
+        src_port: 0,
+        dst_port: 0,
+        proto: 0,
+    };
+    let mut cp = MergeableStoreCP::new();
+    let mut cores = vec![cp.dp_store(), cp.dp_store()];
+    assert!(cp.sync());
+    // both cores see the sources 0..6000, which are counted once
+    for src in 0..12000u32 {
+        let core = &mut cores[(src % 2) as usize];
+        core.update(prefix, HyperLogLog::with_item(12, &(src % 6000)));
+    }
+    // the updates after the first one of each core are sent with the snapshot of the next epoch
+    assert!(cp.sync());
+    for core in &mut cores {
+        core.publish();
+    }
+    assert!(cp.sync());
+    let estimate = cp.get(&prefix).estimate();
+    assert!((estimate - 6000.0).abs() < 6000.0 * 0.05, "estimate {}", estimate);
+
+    let mut a = CountMinSketch::new(1024, 4);
+    let mut b = CountMinSketch::default();
+    for src in 0..1000u32 {
+        a.add(&src, 1);
+    }
+    a.add(&7u32, 500);
+    b.merge(CountMinSketch::with_item(1024, 4, &7u32, 100));
+    a.merge(b);
+    assert_eq!(a.total(), 1600);
+    let seven = a.estimate(&7u32);
+    assert!(seven >= 601 && seven < 601 + 20, "estimate {}", seven);
+}
+
+#[test]
+fn merge_skips_mismatched_sketches() {
+    let mut hll = HyperLogLog::with_item(10, &1u32);
+    assert!(hll.try_merge(HyperLogLog::with_item(12, &2u32)).is_err());
+    hll.merge(HyperLogLog::with_item(12, &2u32));
+    assert_eq!(hll.precision(), 10);
+    assert!(hll.try_merge(HyperLogLog::with_item(10, &2u32)).is_ok());
+    assert!((hll.estimate() - 2.0).abs() < 0.5);
+
+    let mut cms = CountMinSketch::with_item(1024, 4, &1u32, 10);
+    assert!(cms.try_merge(CountMinSketch::with_item(512, 4, &1u32, 10)).is_err());
+    cms.merge(CountMinSketch::with_item(1024, 2, &1u32, 10));
+    assert_eq!((cms.width(), cms.depth(), cms.total()), (1024, 4, 10));
+    cms.merge(CountMinSketch::with_item(1024, 4, &1u32, 5));
+    assert_eq!(cms.estimate(&1u32), 15);
+}
+
+#[test]
+fn default_count_min_sketch_is_usable() {
+    let mut cms = CountMinSketch::default();
+    assert_eq!(cms.estimate(&1u32), 0);
+    cms.add(&1u32, 3);
+    assert_eq!((cms.width(), cms.depth()), (CMS_DEFAULT_WIDTH, CMS_DEFAULT_DEPTH));
+    assert_eq!((cms.total(), cms.estimate(&1u32)), (3, 3));
+    cms.merge(CountMinSketch::with_item(CMS_DEFAULT_WIDTH, CMS_DEFAULT_DEPTH, &1u32, 2));
+    assert_eq!(cms.estimate(&1u32), 5);
+}
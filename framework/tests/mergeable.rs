@@ -0,0 +1,55 @@
+extern crate e2d2;
+use e2d2::state::*;
+use e2d2::utils::FiveTupleV4;
+use std::time::Duration;
+
+fn flow(port: u16) -> FiveTupleV4 {
+    FiveTupleV4 {
+        src_ip: 0x0a00_0001,
+        dst_ip: 0x0a00_0002,
+        src_port: port,
+        dst_port: 80,
+        proto: 6,
+    }
+}
+
+#[test]
+fn mergeable_snapshots_are_complete() {
+    let mut cp = MergeableStoreCP::new();
+    let mut a = cp.dp_store();
+    let mut b = cp.dp_store();
+    assert!(cp.sync());
+    assert_eq!(cp.epoch(), 1);
+
+    // the first update after the request publishes the snapshot, later updates belong to the next epoch
+    a.update(flow(1), 1);
+    a.update(flow(1), 2);
+    b.update(flow(1), 4);
+    b.update(flow(2), 1);
+    assert!(cp.sync());
+    assert_eq!(cp.get(&flow(1)), 5);
+    assert_eq!(cp.get(&flow(2)), 0);
+
+    a.publish();
+    b.publish();
+    assert!(cp.sync());
+    assert_eq!(cp.get(&flow(1)), 7);
+    assert_eq!(cp.get(&flow(2)), 1);
+
+    // a dropped store keeps its last state, an idle store does not hold back the others
+    drop(a);
+    assert_eq!(b.remove(&flow(2)), 1);
+    let mut c = cp.dp_store();
+    assert!(!cp.sync());
+    c.update(flow(3), 1);
+    assert!(!cp.sync());
+    assert_eq!(cp.get(&flow(3)), 1);
+    assert_eq!(cp.get(&flow(2)), 1);
+    assert!(!cp.sync_timeout(Duration::from_millis(1)));
+    b.publish();
+    c.publish();
+    assert!(cp.sync());
+    assert_eq!(cp.get(&flow(1)), 7);
+    assert_eq!(cp.get(&flow(2)), 0);
+    assert_eq!(cp.len(), 2);
+}
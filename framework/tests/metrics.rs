@@ -0,0 +1,70 @@
+extern crate e2d2;
+use e2d2::interface::is_drop_or_error_xstat;
+use e2d2::metrics::*;
+use std::collections::HashMap;
+
+#[test]
+fn render_families() {
+    let mut metrics = Metrics::new();
+    metrics.add_counter("rx_total", "Received packets.", &[("port", "eth0"), ("queue", "0")], 10);
+    metrics.add_gauge("mbufs", "Free mbufs.", &[], 512.0);
+    metrics.add_counter("rx_total", "Received packets.", &[("port", "eth0"), ("queue", "1")], 20);
+    assert_eq!(metrics.len(), 2);
+    assert_eq!(
+        metrics.render(),
+        "# HELP rx_total Received packets.\n\
+         # TYPE rx_total counter\n\
+         rx_total{port=\"eth0\",queue=\"0\"} 10\n\
+         rx_total{port=\"eth0\",queue=\"1\"} 20\n\
+         # HELP mbufs Free mbufs.\n\
+         # TYPE mbufs gauge\n\
+         mbufs 512\n"
+    );
+}
+
+#[test]
+fn render_escapes_label_values() {
+    let mut metrics = Metrics::new();
+    metrics.add_gauge("m", "a\nb", &[("task", "say \"hi\"\\")], 1.5);
+    assert_eq!(
+        metrics.render(),
+        "# HELP m a\\nb\n# TYPE m gauge\nm{task=\"say \\\"hi\\\"\\\\\"} 1.5\n"
+    );
+}
+
+#[test]
+fn scheduler_stats() {
+    let mut tasks = HashMap::new();
+    tasks.insert("b", ("fwd".to_string(), 1000u64, 10u64, 4u32));
+    tasks.insert("a", ("fwd".to_string(), 500u64, 5u64, 2u32));
+    let mut data = HashMap::new();
+    data.insert(1, tasks);
+    let mut metrics = Metrics::new();
+    collect_scheduler_stats(&mut metrics, &data);
+    let families = metrics.families();
+    assert_eq!(families.len(), 3);
+    assert_eq!(families[0].name, "netbricks_task_cycles_total");
+    assert_eq!(families[0].samples.len(), 2);
+    assert_eq!(families[0].samples[0].1, 500.0);
+    assert_eq!(families[1].samples[1].1, 10.0);
+    assert_eq!(families[2].metric_type, MetricType::Gauge);
+}
+
+#[test]
+fn xstats() {
+    let xstats = vec![("rx_good_packets".to_string(), 10u64), ("rx_crc_errors".to_string(), 2u64)];
+    let mut metrics = Metrics::new();
+    add_xstats(&mut metrics, "eth0", &xstats);
+    assert_eq!(
+        metrics.render(),
+        "# HELP netbricks_eth_xstat_total Extended statistics reported by the driver.\n\
+         # TYPE netbricks_eth_xstat_total counter\n\
+         netbricks_eth_xstat_total{port=\"eth0\",name=\"rx_good_packets\"} 10\n\
+         netbricks_eth_xstat_total{port=\"eth0\",name=\"rx_crc_errors\"} 2\n"
+    );
+    let drops: Vec<_> = xstats.iter().filter(|x| is_drop_or_error_xstat(&x.0)).collect();
+    assert_eq!(drops, vec![&xstats[1]]);
+    assert!(is_drop_or_error_xstat("rx_missed_errors"));
+    assert!(is_drop_or_error_xstat("rx_q0_drops"));
+    assert!(!is_drop_or_error_xstat("tx_good_bytes"));
+}
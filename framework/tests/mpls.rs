@@ -0,0 +1,97 @@
+extern crate e2d2;
+mod common;
+use common::{mbuf_data, mbuf_with_headroom};
+use e2d2::headers::*;
+use e2d2::interface::Pdu;
+
+#[test]
+fn mpls_entry_fields() {
+    let mut entry = MplsHeader::new(0x12345, 5, true, 64);
+    assert_eq!(entry.label(), 0x12345);
+    assert_eq!(entry.tc(), 5);
+    assert!(entry.bottom_of_stack());
+    assert_eq!(entry.ttl(), 64);
+
+    entry.set_label(MPLS_MAX_LABEL);
+    entry.set_bottom_of_stack(false);
+    entry.set_ttl(1);
+    assert_eq!(entry.label(), MPLS_MAX_LABEL);
+    assert_eq!(entry.tc(), 5);
+    assert!(!entry.bottom_of_stack());
+    assert_eq!(entry.ttl(), 1);
+}
+
+#[test]
+fn mpls_wire_format() {
+    // label 16, tc 0, bottom of stack, ttl 255
+    let bytes: [u8; 4] = [0x00, 0x01, 0x01, 0xff];
+    let entry = unsafe { &*(bytes.as_ptr() as *const MplsHeader) };
+    assert_eq!(entry.label(), 16);
+    assert_eq!(entry.tc(), 0);
+    assert!(entry.bottom_of_stack());
+    assert_eq!(entry.ttl(), 255);
+    assert_eq!(*entry, MplsHeader::new(16, 0, true, 255));
+}
+
+const HEADROOM: usize = 16;
+
+/// An Ethernet frame with an IPv4 packet, tagged with VLAN 5 if `vlan`, preceded by `HEADROOM` bytes.
+fn ipv4_frame_with_headroom(vlan: bool) -> Vec<u8> {
+    let mut buffer = vec![0u8; HEADROOM];
+    buffer.extend_from_slice(&[0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1]);
+    if vlan {
+        buffer.extend_from_slice(&[0x81, 0x00, 0x00, 0x05]);
+    }
+    buffer.extend_from_slice(&[0x08, 0x00]);
+    buffer.extend_from_slice(&[0x45, 0, 0, 20, 0, 0, 0, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+    buffer
+}
+
+fn check_label_round_trip(vlan: bool) {
+    let mut buffer = ipv4_frame_with_headroom(vlan);
+    let frame = buffer[HEADROOM..].to_vec();
+    let mac_len = frame.len() - 20;
+    let mut mbuf = mbuf_with_headroom(&mut buffer, HEADROOM);
+    {
+        let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+        assert!(pdu.top_label().is_none());
+        assert!(pdu.pop_label().is_err());
+        assert!(pdu.swap_label(100).is_err());
+        assert!(pdu.push_label(MPLS_MAX_LABEL + 1, 0, 64).is_err());
+
+        pdu.push_label(100, 1, 64).unwrap();
+        pdu.push_label(200, 2, 63).unwrap();
+        assert_eq!(pdu.top_label(), Some(MplsHeader::new(200, 2, false, 63)));
+        pdu.swap_label(300).unwrap();
+        assert_eq!(pdu.top_label(), Some(MplsHeader::new(300, 2, false, 63)));
+        // the payload is still parsed behind the label stack
+        assert_eq!(pdu.headers().count(), 4);
+        assert!(pdu.headers().get(3).as_ip().is_some());
+    }
+    let labeled = mbuf_data(&mbuf).to_vec();
+    assert_eq!(&labeled[..mac_len - 2], &frame[..mac_len - 2]);
+    assert_eq!(&labeled[mac_len - 2..mac_len], &[0x88, 0x47]);
+    assert_eq!(&labeled[mac_len..mac_len + 4], &[0x00, 0x12, 0xc4, 63]);
+    assert_eq!(&labeled[mac_len + 4..mac_len + 8], &[0x00, 0x06, 0x43, 64]);
+    assert_eq!(&labeled[mac_len + 8..], &frame[mac_len..]);
+
+    {
+        let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+        assert_eq!(pdu.pop_label().unwrap(), MplsHeader::new(300, 2, false, 63));
+        assert_eq!(pdu.top_label(), Some(MplsHeader::new(100, 1, true, 64)));
+        assert_eq!(pdu.pop_label().unwrap(), MplsHeader::new(100, 1, true, 64));
+        assert!(pdu.top_label().is_none());
+        assert!(pdu.headers().get(1).as_ip().is_some());
+    }
+    assert_eq!(mbuf_data(&mbuf), &frame[..]);
+}
+
+#[test]
+fn mpls_push_swap_pop() {
+    check_label_round_trip(false);
+}
+
+#[test]
+fn mpls_push_swap_pop_with_vlan_tag() {
+    check_label_round_trip(true);
+}
@@ -0,0 +1,271 @@
+extern crate e2d2;
+extern crate eui48;
+extern crate ipnet;
+mod common;
+use common::mbuf_over;
+use e2d2::headers::*;
+use e2d2::interface::*;
+use e2d2::state::Resolution;
+use eui48::MacAddress;
+use ipnet::Ipv6Net;
+use std::net::Ipv6Addr;
+use std::ptr;
+use std::time::{Duration, Instant};
+
+#[test]
+fn ndp_addresses() {
+    let mac = MacAddress::new([0x00, 0x1b, 0x21, 0x3a, 0x4c, 0x5d]);
+    assert_eq!(
+        link_local_address(&mac),
+        "fe80::21b:21ff:fe3a:4c5d".parse::<Ipv6Addr>().unwrap()
+    );
+    let addr = "2001:db8::1:2345:6789".parse::<Ipv6Addr>().unwrap();
+    let solicited = solicited_node_address(&addr);
+    assert_eq!(solicited, "ff02::1:ff45:6789".parse::<Ipv6Addr>().unwrap());
+    assert_eq!(
+        multicast_mac(&solicited),
+        MacAddress::new([0x33, 0x33, 0xff, 0x45, 0x67, 0x89])
+    );
+}
+
+#[test]
+fn ndp_options() {
+    let mac = MacAddress::new([0, 1, 2, 3, 4, 5]);
+    let mut data = ndp_ll_addr_option(NDP_OPT_SOURCE_LL_ADDR, &mac).to_vec();
+    data.extend_from_slice(&[NDP_OPT_MTU, 1, 0, 0, 0, 0, 0x05, 0xdc]);
+    let mut options = NdpOptions::new(&data);
+    let source = options.next().unwrap();
+    assert_eq!(source.opt_type, NDP_OPT_SOURCE_LL_ADDR);
+    assert_eq!(source.content, mac.as_bytes());
+    assert_eq!(options.next().unwrap().opt_type, NDP_OPT_MTU);
+    assert!(options.next().is_none());
+    assert!(!options.malformed());
+
+    // an option with length zero ends the iteration
+    let data = [NDP_OPT_MTU, 0, 0, 0, 0, 0, 0, 0];
+    let mut options = NdpOptions::new(&data);
+    assert!(options.next().is_none());
+    assert!(options.malformed());
+}
+
+#[test]
+fn icmpv6_checksum_verifies() {
+    let src = "fe80::1".parse::<Ipv6Addr>().unwrap();
+    let dst = "ff02::1:ff00:2".parse::<Ipv6Addr>().unwrap();
+    let mut message = vec![ICMPV6_NEIGHBOR_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+    message.extend_from_slice(&"fe80::2".parse::<Ipv6Addr>().unwrap().octets());
+    message.extend_from_slice(&[NDP_OPT_SOURCE_LL_ADDR, 1, 0, 1, 2, 3, 4, 5]);
+    let csum = icmpv6_checksum(&src, &dst, &message);
+    assert_ne!(csum, 0);
+    message[2] = (csum >> 8) as u8;
+    message[3] = csum as u8;
+    assert_eq!(icmpv6_checksum(&src, &dst, &message), 0);
+    message[10] ^= 1;
+    assert_ne!(icmpv6_checksum(&src, &dst, &message), 0);
+}
+
+fn addr(s: &str) -> Ipv6Addr {
+    s.parse().unwrap()
+}
+
+const NEIGHBOR_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x77];
+
+/// A service for 2001:db8::2/64, duplicate address detection is completed if `dad` is false.
+fn service(dad: bool) -> NdpService {
+    let mac = MacAddress::new([0x02, 0, 0, 0, 0, 0x02]);
+    let mut service = NdpService::with_address(mac, "2001:db8::2/64".parse::<Ipv6Net>().unwrap(), Default::default());
+    if !dad {
+        service.set_dad_transmits(0);
+    }
+    service
+}
+
+/// An Ethernet frame from the neighbor with the ICMPv6 `message`, whose checksum is filled in.
+fn ndp_packet(src: Ipv6Addr, dst: Ipv6Addr, hop_limit: u8, mut message: Vec<u8>) -> Vec<u8> {
+    let csum = icmpv6_checksum(&src, &dst, &message);
+    message[2] = (csum >> 8) as u8;
+    message[3] = csum as u8;
+    let mut packet = vec![0x33, 0x33, 0, 0, 0, 1];
+    packet.extend_from_slice(&NEIGHBOR_MAC);
+    packet.extend_from_slice(&[0x86, 0xdd, 0x60, 0, 0, 0]);
+    packet.extend_from_slice(&[0, message.len() as u8, IP_PROTO_ICMPV6, hop_limit]);
+    packet.extend_from_slice(&src.octets());
+    packet.extend_from_slice(&dst.octets());
+    packet.extend(message);
+    packet
+}
+
+fn neighbor_message(msg_type: u8, flags: u32, target: Ipv6Addr, option: Option<u8>) -> Vec<u8> {
+    let mut message = vec![msg_type, 0, 0, 0];
+    message.extend_from_slice(&flags.to_be_bytes());
+    message.extend_from_slice(&target.octets());
+    if let Some(opt_type) = option {
+        message.extend_from_slice(&ndp_ll_addr_option(opt_type, &MacAddress::new(NEIGHBOR_MAC)));
+    }
+    message
+}
+
+fn router_advertisement(lifetime: u16, source_ll_addr: bool) -> Vec<u8> {
+    let mut message = vec![ICMPV6_ROUTER_ADVERTISEMENT, 0, 0, 0, 64, 0];
+    message.extend_from_slice(&lifetime.to_be_bytes());
+    message.extend_from_slice(&[0; 8]);
+    if source_ll_addr {
+        message.extend_from_slice(&ndp_ll_addr_option(
+            NDP_OPT_SOURCE_LL_ADDR,
+            &MacAddress::new(NEIGHBOR_MAC),
+        ));
+    }
+    message
+}
+
+/// Pass `packet` to `service`, returns true if it was consumed without a reply.
+fn handle(service: &mut NdpService, mut packet: Vec<u8>, now: Instant) -> bool {
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    match service.handle_ndp(&pdu, now).unwrap() {
+        NdpAction::Consumed => true,
+        NdpAction::NotNdp => false,
+        NdpAction::Reply(_) => panic!("unexpected reply"),
+    }
+}
+
+#[test]
+fn ndp_duplicate_address_detection() {
+    let now = Instant::now();
+    let mut ndp = service(true);
+    let global = addr("2001:db8::2");
+    let link_local = ndp.link_local();
+    let tentative = AddressState::Tentative { sent: 0, last: None };
+    assert_eq!(ndp.address_state(&global), Some(tentative));
+
+    // another node probes for the global address
+    let ns = neighbor_message(ICMPV6_NEIGHBOR_SOLICITATION, 0, global, None);
+    let probe = ndp_packet(Ipv6Addr::UNSPECIFIED, solicited_node_address(&global), 255, ns);
+    assert!(handle(&mut ndp, probe, now));
+    assert_eq!(ndp.address_state(&global), Some(AddressState::Duplicate));
+    assert_eq!(ndp.address_state(&link_local), Some(tentative));
+
+    // another node advertises the link-local address
+    let na = neighbor_message(ICMPV6_NEIGHBOR_ADVERTISEMENT, NDP_NA_FLAG_OVERRIDE, link_local, None);
+    assert!(handle(
+        &mut ndp,
+        ndp_packet(addr("fe80::77"), IPV6_ALL_NODES, 255, na),
+        now
+    ));
+    assert_eq!(ndp.address_state(&link_local), Some(AddressState::Duplicate));
+
+    // without detection both addresses are used right away
+    let ndp = service(false);
+    assert_eq!(ndp.address_state(&global), Some(AddressState::Preferred));
+    assert_eq!(ndp.address_state(&link_local), Some(AddressState::Preferred));
+}
+
+#[test]
+fn ndp_solicitations_and_advertisements() {
+    let now = Instant::now();
+    let mut ndp = service(false);
+    let neighbor = addr("2001:db8::77");
+    let mac = MacAddress::new(NEIGHBOR_MAC);
+
+    // solicitations for other targets and invalid messages are consumed without learning the sender
+    let ns = neighbor_message(
+        ICMPV6_NEIGHBOR_SOLICITATION,
+        0,
+        addr("2001:db8::9"),
+        Some(NDP_OPT_SOURCE_LL_ADDR),
+    );
+    assert!(handle(
+        &mut ndp,
+        ndp_packet(neighbor, addr("ff02::1:ff00:9"), 255, ns),
+        now
+    ));
+    let ns = neighbor_message(
+        ICMPV6_NEIGHBOR_SOLICITATION,
+        0,
+        addr("2001:db8::2"),
+        Some(NDP_OPT_SOURCE_LL_ADDR),
+    );
+    assert!(handle(
+        &mut ndp,
+        ndp_packet(neighbor, addr("ff02::1:ff00:2"), 64, ns.clone()),
+        now
+    ));
+    let mut packet = ndp_packet(neighbor, addr("ff02::1:ff00:2"), 255, ns);
+    packet[70] ^= 1;
+    assert!(handle(&mut ndp, packet, now));
+    assert!(ndp.cache().is_empty());
+
+    // advertisements only update neighbors which are being resolved
+    let na = neighbor_message(
+        ICMPV6_NEIGHBOR_ADVERTISEMENT,
+        NDP_NA_FLAG_SOLICITED,
+        neighbor,
+        Some(NDP_OPT_TARGET_LL_ADDR),
+    );
+    let advertisement = ndp_packet(neighbor, addr("2001:db8::2"), 255, na);
+    assert!(handle(&mut ndp, advertisement.clone(), now));
+    assert!(ndp.cache().get(&neighbor).is_none());
+    assert_eq!(ndp.cache_mut().resolve(neighbor, now), Resolution::Pending);
+    assert!(handle(&mut ndp, advertisement, now));
+    assert_eq!(ndp.cache_mut().resolve(neighbor, now), Resolution::Resolved(mac));
+
+    // other ICMPv6 messages are not handled
+    let echo = vec![ICMPV6_ECHO_REQUEST, 0, 0, 0, 0, 1, 0, 1];
+    assert!(!handle(
+        &mut ndp,
+        ndp_packet(neighbor, addr("2001:db8::2"), 64, echo),
+        now
+    ));
+}
+
+#[test]
+fn ndp_router_advertisements() {
+    let now = Instant::now();
+    let mut ndp = service(false);
+    let router = addr("fe80::77");
+    let remote = addr("2001:db9::1");
+    assert_eq!(ndp.next_hop(remote), None);
+
+    // advertisements from global addresses are invalid
+    assert!(handle(
+        &mut ndp,
+        ndp_packet(
+            addr("2001:db8::77"),
+            IPV6_ALL_NODES,
+            255,
+            router_advertisement(1800, true)
+        ),
+        now
+    ));
+    assert_eq!(ndp.gateway(), None);
+
+    assert!(handle(
+        &mut ndp,
+        ndp_packet(router, IPV6_ALL_NODES, 255, router_advertisement(1800, true)),
+        now
+    ));
+    assert_eq!(ndp.gateway(), Some(router));
+    assert_eq!(ndp.next_hop(remote), Some(router));
+    assert_eq!(ndp.next_hop(addr("2001:db8::5")), Some(addr("2001:db8::5")));
+    assert_eq!(ndp.cache().get(&router), Some(MacAddress::new(NEIGHBOR_MAC)));
+
+    // a lifetime of zero withdraws the router
+    assert!(handle(
+        &mut ndp,
+        ndp_packet(router, IPV6_ALL_NODES, 255, router_advertisement(0, true)),
+        now
+    ));
+    assert_eq!(ndp.gateway(), None);
+
+    // the router expires after its lifetime
+    let mut ndp = service(false);
+    assert!(handle(
+        &mut ndp,
+        ndp_packet(router, IPV6_ALL_NODES, 255, router_advertisement(60, false)),
+        now
+    ));
+    assert!(ndp.poll(now + Duration::from_secs(59)).unwrap().is_empty());
+    assert_eq!(ndp.gateway(), Some(router));
+    assert!(ndp.poll(now + Duration::from_secs(60)).unwrap().is_empty());
+    assert_eq!(ndp.gateway(), None);
+}
@@ -0,0 +1,209 @@
+extern crate e2d2;
+extern crate eui48;
+mod common;
+use common::mbuf_over;
+use e2d2::headers::*;
+use e2d2::interface::{ArpAction, ArpService, Pdu};
+use e2d2::state::*;
+use eui48::MacAddress;
+use std::net::Ipv4Addr;
+use std::ptr;
+use std::slice;
+use std::time::{Duration, Instant};
+
+fn config() -> NeighborConfig {
+    NeighborConfig {
+        reachable_time: Duration::from_secs(10),
+        stale_time: Duration::from_secs(60),
+        retry_interval: Duration::from_secs(1),
+        max_retries: 2,
+        failed_time: Duration::from_secs(5),
+        max_entries: 4,
+    }
+}
+
+#[test]
+fn resolution_retries_and_fails() {
+    let mut cache = NeighborCache::new(config());
+    let addr = Ipv4Addr::new(10, 0, 0, 2);
+    let t0 = Instant::now();
+    assert_eq!(cache.resolve(addr, t0), Resolution::Pending);
+    assert_eq!(cache.poll(t0), vec![addr]);
+    // not due again before the retry interval
+    assert!(cache.poll(t0 + Duration::from_millis(500)).is_empty());
+    assert_eq!(cache.poll(t0 + Duration::from_secs(1)), vec![addr]);
+    assert!(cache.poll(t0 + Duration::from_secs(2)).is_empty());
+    assert_eq!(
+        cache.resolve(addr, t0 + Duration::from_secs(2)),
+        Resolution::Unreachable
+    );
+    // solicited again after the failed time
+    assert_eq!(cache.resolve(addr, t0 + Duration::from_secs(7)), Resolution::Pending);
+}
+
+#[test]
+fn learned_addresses_are_probed_when_stale() {
+    let mut cache = NeighborCache::new(config());
+    let addr = Ipv4Addr::new(10, 0, 0, 2);
+    let mac = MacAddress::new([0, 1, 2, 3, 4, 5]);
+    let t0 = Instant::now();
+    cache.update(addr, mac, t0);
+    assert_eq!(cache.resolve(addr, t0), Resolution::Resolved(mac));
+    assert!(cache.poll(t0).is_empty());
+    let later = t0 + Duration::from_secs(11);
+    assert_eq!(cache.resolve(addr, later), Resolution::Resolved(mac));
+    assert_eq!(cache.poll(later), vec![addr]);
+    cache.update(addr, mac, later);
+    assert!(cache.poll(later + Duration::from_secs(1)).is_empty());
+    // unused entries are removed
+    cache.poll(later + Duration::from_secs(60));
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn static_entries_are_kept() {
+    let mut cache = NeighborCache::new(config());
+    let addr = Ipv4Addr::new(10, 0, 0, 1);
+    let mac = MacAddress::new([0, 1, 2, 3, 4, 5]);
+    let t0 = Instant::now();
+    cache.insert_static(addr, mac);
+    cache.update(addr, MacAddress::broadcast(), t0);
+    cache.poll(t0 + Duration::from_secs(3600));
+    assert_eq!(cache.get(&addr), Some(mac));
+}
+
+#[test]
+fn arp_reply_swaps_sender_and_target() {
+    let mac = MacAddress::new([0, 1, 2, 3, 4, 5]);
+    let peer = MacAddress::new([6, 7, 8, 9, 10, 11]);
+    let mut arp = ArpIpv4Header::new_request(&peer, Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 1));
+    assert!(arp.is_request());
+    arp.make_reply(&mac);
+    assert!(arp.is_reply());
+    assert_eq!(arp.sender_hw_addr(), mac);
+    assert_eq!(arp.sender_ip_addr(), Ipv4Addr::new(10, 0, 0, 1));
+    assert_eq!(arp.target_hw_addr(), peer);
+    assert_eq!(arp.target_ip_addr(), Ipv4Addr::new(10, 0, 0, 2));
+}
+
+/// An Ethernet frame with `arp`, sent by `src`.
+fn arp_packet(src: &MacAddress, arp: &ArpIpv4Header) -> Vec<u8> {
+    let mut packet = vec![0xff; 6];
+    packet.extend_from_slice(src.as_bytes());
+    packet.extend_from_slice(&[0x08, 0x06]);
+    packet.extend_from_slice(unsafe {
+        slice::from_raw_parts(arp as *const ArpIpv4Header as *const u8, ArpIpv4Header::size())
+    });
+    packet
+}
+
+fn handle_arp(service: &mut ArpService, packet: &mut Vec<u8>, now: Instant) -> ArpAction {
+    let mut mbuf = mbuf_over(packet, ptr::null_mut());
+    let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    service.handle_arp(&mut pdu, now)
+}
+
+#[test]
+fn arp_requests_and_replies() {
+    let now = Instant::now();
+    let mac = MacAddress::new([0x02, 0, 0, 0, 0, 0x02]);
+    let mut arp = ArpService::with_address(mac, "10.0.0.2/24".parse().unwrap(), config());
+    let neighbor = MacAddress::new([0x02, 0, 0, 0, 0, 0x01]);
+    let neighbor_ip = Ipv4Addr::new(10, 0, 0, 1);
+
+    // a request for the address of the port is turned into the reply
+    let request = ArpIpv4Header::new_request(&neighbor, neighbor_ip, Ipv4Addr::new(10, 0, 0, 2));
+    let mut packet = arp_packet(&neighbor, &request);
+    assert_eq!(handle_arp(&mut arp, &mut packet, now), ArpAction::Reply);
+    assert_eq!(packet, {
+        let mut reply = arp_packet(&mac, &ArpIpv4Header::new_reply(&request, &mac));
+        reply[..6].copy_from_slice(neighbor.as_bytes());
+        reply
+    });
+    assert_eq!(arp.cache().get(&neighbor_ip), Some(neighbor));
+
+    // requests for other addresses do not add their sender
+    let other = MacAddress::new([0x02, 0, 0, 0, 0, 0x03]);
+    let other_ip = Ipv4Addr::new(10, 0, 0, 3);
+    let mut packet = arp_packet(
+        &other,
+        &ArpIpv4Header::new_request(&other, other_ip, Ipv4Addr::new(10, 0, 0, 9)),
+    );
+    assert_eq!(handle_arp(&mut arp, &mut packet, now), ArpAction::Consumed);
+    assert!(arp.cache().get(&other_ip).is_none());
+
+    // a reply resolves a pending neighbor
+    assert_eq!(arp.cache_mut().resolve(other_ip, now), Resolution::Pending);
+    let request = ArpIpv4Header::new_request(&mac, Ipv4Addr::new(10, 0, 0, 2), other_ip);
+    let mut packet = arp_packet(&other, &ArpIpv4Header::new_reply(&request, &other));
+    assert_eq!(handle_arp(&mut arp, &mut packet, now), ArpAction::Consumed);
+    assert_eq!(arp.cache_mut().resolve(other_ip, now), Resolution::Resolved(other));
+
+    // other packets are left alone
+    let mut packet = arp_packet(
+        &other,
+        &ArpIpv4Header::new_request(&other, other_ip, Ipv4Addr::new(10, 0, 0, 2)),
+    );
+    packet[12] = 0x08;
+    packet[13] = 0x00;
+    let copy = packet.clone();
+    assert_eq!(handle_arp(&mut arp, &mut packet, now), ArpAction::NotArp);
+    assert_eq!(packet, copy);
+}
+
+#[test]
+fn arp_requests_with_vlan_tags() {
+    let now = Instant::now();
+    let mac = MacAddress::new([0x02, 0, 0, 0, 0, 0x02]);
+    let mut arp = ArpService::with_address(mac, "10.0.0.2/24".parse().unwrap(), config());
+    let neighbor = MacAddress::new([0x02, 0, 0, 0, 0, 0x01]);
+    let neighbor_ip = Ipv4Addr::new(10, 0, 0, 1);
+    let request = ArpIpv4Header::new_request(&neighbor, neighbor_ip, Ipv4Addr::new(10, 0, 0, 2));
+    let tagged = |packet: Vec<u8>| {
+        let mut tagged = packet[..12].to_vec();
+        tagged.extend_from_slice(&[0x81, 0x00, 0x00, 0x05]);
+        tagged.extend_from_slice(&packet[12..]);
+        tagged
+    };
+    let mut packet = tagged(arp_packet(&neighbor, &request));
+    assert_eq!(handle_arp(&mut arp, &mut packet, now), ArpAction::Reply);
+    assert_eq!(packet, {
+        let mut reply = arp_packet(&mac, &ArpIpv4Header::new_reply(&request, &mac));
+        reply[..6].copy_from_slice(neighbor.as_bytes());
+        tagged(reply)
+    });
+    assert_eq!(arp.cache().get(&neighbor_ip), Some(neighbor));
+}
+
+#[test]
+fn arp_services_share_the_cache() {
+    let now = Instant::now();
+    let mac = MacAddress::new([0x02, 0, 0, 0, 0, 0x02]);
+    let mut arp = ArpService::with_address(mac, "10.0.0.2/24".parse().unwrap(), config());
+    // the service of another core
+    let mut other_core = arp.clone();
+    let neighbor = MacAddress::new([0x02, 0, 0, 0, 0, 0x01]);
+    let neighbor_ip = Ipv4Addr::new(10, 0, 0, 1);
+
+    let mut packet = vec![0; 14];
+    packet[12] = 0x08;
+    packet.extend_from_slice(&[0x45, 0, 0, 20, 0, 0, 0, 0, 64, 17, 0, 0, 10, 0, 0, 2, 10, 0, 0, 1]);
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    {
+        let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+        assert_eq!(other_core.set_destination(&mut pdu, now), Resolution::Pending);
+    }
+    assert_eq!(arp.cache_mut().poll(now), vec![neighbor_ip]);
+    assert!(other_core.cache_mut().poll(now).is_empty());
+
+    // the reply arrives on the first core
+    let request = ArpIpv4Header::new_request(&mac, Ipv4Addr::new(10, 0, 0, 2), neighbor_ip);
+    let mut reply = arp_packet(&neighbor, &ArpIpv4Header::new_reply(&request, &neighbor));
+    assert_eq!(handle_arp(&mut arp, &mut reply, now), ArpAction::Consumed);
+    {
+        let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+        assert_eq!(other_core.set_destination(&mut pdu, now), Resolution::Resolved(neighbor));
+    }
+    assert_eq!(&packet[..6], neighbor.as_bytes());
+    assert_eq!(&packet[6..12], mac.as_bytes());
+}
@@ -0,0 +1,387 @@
+extern crate e2d2;
+mod common;
+use common::{mbuf_over, ones_complement_sum};
+use e2d2::headers::*;
+use e2d2::interface::Pdu;
+use e2d2::native::zcsi::MBuf;
+use std::ptr;
+
+fn mac_header(etype: u16) -> Vec<u8> {
+    let mut header = vec![0x02, 0, 0, 0, 0, 1, 0x02, 0, 0, 0, 0, 2];
+    header.extend_from_slice(&[(etype >> 8) as u8, etype as u8]);
+    header
+}
+
+fn udp_header(dst_port: u16, length: usize) -> Vec<u8> {
+    vec![
+        0x30,
+        0x39,
+        (dst_port >> 8) as u8,
+        dst_port as u8,
+        (length >> 8) as u8,
+        length as u8,
+        0,
+        0,
+    ]
+}
+
+#[test]
+fn parse_stops_when_header_stack_is_full() {
+    // MPLS label stack, IPv6 with a fragment header, UDP, VXLAN and an inner frame: more headers than fit
+    let mut packet = mac_header(ETYPE_MPLS_UNICAST);
+    let labels = 9;
+    for i in 0..labels {
+        let bos = if i == labels - 1 { 1 } else { 0 };
+        packet.extend_from_slice(&[0, 0x10 + i as u8, bos, 64]);
+    }
+    let inner_ip_len = 20 + UdpHeader::size();
+    let udp_len = UdpHeader::size() + VxlanHeader::size() + 14 + inner_ip_len;
+    let payload_len = Ipv6FragmentHeader::size() + udp_len;
+    packet.extend_from_slice(&[0x60, 0, 0, 0, 0, payload_len as u8, IPV6_NEXT_HEADER_FRAGMENT, 64]);
+    packet.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    packet.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+    packet.extend_from_slice(&[17, 0, 0, 0, 0, 0, 0, 4]);
+    packet.extend(udp_header(VXLAN_PORT, udp_len));
+    packet.extend_from_slice(&[0x08, 0, 0, 0, 0, 0, 0x2a, 0]);
+    packet.extend(mac_header(0x0800));
+    packet.extend_from_slice(&[0x45, 0, 0, inner_ip_len as u8, 0, 0, 0, 0, 64, 17, 0, 0]);
+    packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    packet.extend(udp_header(53, UdpHeader::size()));
+
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    let headers = pdu.headers();
+    assert!(headers.is_full());
+    assert_eq!(headers.count(), 16);
+    assert!(headers.get(9).as_mpls().is_some());
+    assert!(headers.get(11).as_ipv6_fragment().is_some());
+    assert!(headers.get(13).as_vxlan().is_some());
+    assert_eq!(headers.inner_start(), Some(14));
+    // the inner UDP header does not fit anymore
+    assert!(headers.get(15).as_ip().is_some());
+}
+
+/// Ethernet, IPv4 and a TCP header with a MSS option, for a segment with `payload_len` bytes of data.
+fn tcp_packet_headers(payload_len: usize) -> Vec<u8> {
+    let ip_len = 20 + 24 + payload_len;
+    let mut packet = mac_header(0x0800);
+    packet.extend_from_slice(&[0x45, 0, (ip_len >> 8) as u8, ip_len as u8, 0, 0, 0, 0, 64, 6, 0, 0]);
+    packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    packet.extend_from_slice(&[
+        0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, 0, 0x60, 0x18, 0xff, 0xff, 0, 0, 0, 0,
+    ]);
+    packet.extend_from_slice(&[TCP_OPT_MSS, 4, 0x05, 0xb4]);
+    packet
+}
+
+#[test]
+fn parse_tcp_header_of_multi_segment_packet() {
+    // the payload is in the second segment
+    let mut payload = vec![7u8; 100];
+    let mut second = mbuf_over(&mut payload, ptr::null_mut());
+    let mut headers = tcp_packet_headers(payload.len());
+    let mut first = mbuf_over(&mut headers, &mut second);
+    first.nb_segs = 2;
+    first.pkt_len = (headers.len() + payload.len()) as u32;
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut first);
+    assert_eq!(pdu.headers().count(), 3);
+    let tcp = pdu.headers().tcp(2);
+    assert_eq!(tcp.dst_port(), 80);
+    assert_eq!(tcp.mss(pdu.header_available(2)), Some(1460));
+    assert_eq!(pdu.payload_to_vec(2), vec![7u8; 100]);
+
+    // the options continue in the second segment, the TCP header is not parsed
+    let mut headers = tcp_packet_headers(payload.len());
+    let mut options = headers.split_off(headers.len() - 2);
+    options.extend_from_slice(&payload);
+    let mut second = mbuf_over(&mut options, ptr::null_mut());
+    let mut first = mbuf_over(&mut headers, &mut second);
+    first.nb_segs = 2;
+    first.pkt_len = (headers.len() + options.len()) as u32;
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut first);
+    assert_eq!(pdu.headers().count(), 2);
+}
+
+/// CRC32c (Castagnoli) as used by SCTP.
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[test]
+fn parse_sctp_header_and_checksum() {
+    assert_eq!(crc32c(&[0u8; 32]), 0x8a91_36aa);
+    // common header and an INIT chunk
+    let mut sctp = vec![0x0b, 0x59, 0x0b, 0x59, 0, 0, 0, 0, 0, 0, 0, 0];
+    sctp.extend_from_slice(&[
+        1, 0, 0, 20, 0x12, 0x34, 0x56, 0x78, 0, 1, 0, 0, 0, 10, 0, 10, 0, 0, 0, 1,
+    ]);
+    let crc = crc32c(&sctp);
+    sctp[8..12].copy_from_slice(&crc.to_le_bytes());
+    let ip_len = 20 + sctp.len();
+    let mut packet = mac_header(0x0800);
+    packet.extend_from_slice(&[0x45, 0, 0, ip_len as u8, 0, 0, 0, 0, 64, 132, 0, 0]);
+    packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    packet.extend_from_slice(&sctp);
+
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    assert_eq!(pdu.headers().count(), 3);
+    assert_eq!(pdu.headers().get(2).kind(), HeaderKind::Sctp);
+    {
+        let header = pdu.headers().sctp(2);
+        assert_eq!(header.src_port(), 2905);
+        assert_eq!(header.dst_port(), 2905);
+        assert_eq!(header.vtag(), 0);
+        assert_eq!(header.checksum(), crc);
+    }
+    assert_eq!(pdu.payload_to_vec(2), sctp[12..].to_vec());
+
+    // the checksum is written in the byte order of the CRC
+    let mut expected = sctp.clone();
+    expected[4..12].copy_from_slice(&[0x12, 0x34, 0x56, 0x78, 0, 0, 0, 0]);
+    let crc = crc32c(&expected);
+    expected[8..12].copy_from_slice(&crc.to_le_bytes());
+    {
+        let header = pdu.headers_mut().sctp_mut(2);
+        header.set_vtag(0x1234_5678);
+        header.set_checksum(crc);
+    }
+    assert_eq!(pdu.payload_to_vec(1), expected);
+}
+
+#[test]
+fn tcp_segmentation_offload_setup() {
+    let mut packet = tcp_packet_headers(1000);
+    packet.extend_from_slice(&[7u8; 1000]);
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    assert!(pdu.set_tcp_segmentation_offload(0).is_err());
+    assert!(!pdu.tcp_segmentation_offload());
+    pdu.set_tcp_segmentation_offload(536).unwrap();
+    assert!(pdu.tcp_segmentation_offload());
+    assert_eq!(pdu.tso_segsz(), 536);
+    assert_eq!((pdu.l2_len(), pdu.l3_len(), pdu.l4_len()), (14, 20, 24));
+    assert_eq!(pdu.headers().ip(1).csum(), 0);
+    // the TCP checksum is seeded with the pseudo header checksum without the length
+    assert_eq!(pdu.headers().tcp(2).checksum(), 0x0a00 + 0x0001 + 0x0a00 + 0x0002 + 6);
+
+    let mut packet = mac_header(0x0800);
+    packet.extend_from_slice(&[0x45, 0, 0, 28, 0, 0, 0, 0, 64, 17, 0, 0]);
+    packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    packet.extend(udp_header(53, UdpHeader::size()));
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    assert!(pdu.set_tcp_segmentation_offload(536).is_err());
+    assert!(pdu.tcp_segments(536).is_err());
+}
+
+#[test]
+fn tcp_segments_of_packet_without_payload() {
+    let mut packet = tcp_packet_headers(0);
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    assert!(pdu.tcp_segments(0).is_err());
+    assert!(pdu.tcp_segments(536).unwrap().is_empty());
+}
+
+/// Empty mbufs for the segments, over buffers of 256 bytes each.
+fn segment_mbufs(buffers: &mut [Vec<u8>]) -> Vec<MBuf> {
+    buffers
+        .iter_mut()
+        .map(|buffer| {
+            let mut mbuf = mbuf_over(buffer, ptr::null_mut());
+            mbuf.data_len = 0;
+            mbuf.pkt_len = 0;
+            mbuf
+        })
+        .collect()
+}
+
+/// Check the TCP/IPv4 packet starting at `ip_offset` in `segment`, carrying `chunk` at sequence number `seq`.
+fn check_segment(segment: &[u8], ip_offset: usize, seq: u32, chunk: &[u8]) {
+    let ip = &segment[ip_offset..];
+    let ip_len = 20 + 24 + chunk.len();
+    assert_eq!(segment.len(), ip_offset + ip_len);
+    assert_eq!(((ip[2] as usize) << 8) | ip[3] as usize, ip_len);
+    assert_eq!(ones_complement_sum(&ip[..20], 0), 0xffff);
+    let tcp = &ip[20..ip_len];
+    assert_eq!(
+        &tcp[4..8],
+        &[(seq >> 24) as u8, (seq >> 16) as u8, (seq >> 8) as u8, seq as u8]
+    );
+    assert_eq!(&tcp[24..], chunk);
+    let pseudo_header = ones_complement_sum(&ip[12..20], 6 + tcp.len() as u32);
+    assert_eq!(ones_complement_sum(tcp, pseudo_header as u32), 0xffff);
+}
+
+#[test]
+fn tcp_segments_split_payload() {
+    let payload: Vec<u8> = (0..250).map(|i| i as u8).collect();
+    let mut packet = tcp_packet_headers(payload.len());
+    packet.extend_from_slice(&payload);
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+
+    let mut buffers = vec![vec![0u8; 256]; 3];
+    let mut mbufs = segment_mbufs(&mut buffers);
+    let mut free = mbufs.iter_mut().map(|mbuf| mbuf as *mut MBuf);
+    let segments = pdu
+        .tcp_segments_with(100, || free.next().map(Pdu::pdu_from_mbuf_no_increment))
+        .unwrap();
+    assert_eq!(segments.len(), 3);
+    for (i, segment) in segments.iter().enumerate() {
+        let tcp = segment.headers().tcp(2);
+        // only the last segment keeps the PSH flag
+        assert_eq!(tcp.psh_flag(), i == 2);
+        assert!(tcp.ack_flag());
+        assert_eq!(segment.headers().ip(1).id(), i as u16);
+    }
+    drop(segments);
+    for (i, chunk) in payload.chunks(100).enumerate() {
+        let len = mbufs[i].data_len as usize;
+        check_segment(&buffers[i][..len], 14, 1 + 100 * i as u32, chunk);
+    }
+
+    // a failed allocation frees the segments allocated so far
+    let mut buffers = vec![vec![0u8; 256]; 2];
+    let mut mbufs = segment_mbufs(&mut buffers);
+    for mbuf in &mut mbufs {
+        mbuf.refcnt = 2;
+    }
+    let mut free = mbufs.iter_mut().map(|mbuf| mbuf as *mut MBuf);
+    assert!(pdu
+        .tcp_segments_with(100, || free.next().map(Pdu::pdu_from_mbuf_no_increment))
+        .is_err());
+    assert!(mbufs.iter().all(|mbuf| mbuf.refcnt == 1));
+}
+
+#[test]
+fn tcp_segments_adjust_tunnel_headers() {
+    let payload = vec![7u8; 150];
+    let inner = tcp_packet_headers(payload.len());
+    let udp_len = UdpHeader::size() + VxlanHeader::size() + inner.len() + payload.len();
+    let ip_len = 20 + udp_len;
+    let mut packet = mac_header(0x0800);
+    packet.extend_from_slice(&[0x45, 0, (ip_len >> 8) as u8, ip_len as u8, 0, 0, 0, 0, 64, 17, 0, 0]);
+    packet.extend_from_slice(&[192, 168, 0, 1, 192, 168, 0, 2]);
+    packet.extend(udp_header(VXLAN_PORT, udp_len));
+    packet[40..42].copy_from_slice(&[0x12, 0x34]);
+    packet.extend_from_slice(&[0x08, 0, 0, 0, 0, 0, 0x2a, 0]);
+    packet.extend(inner);
+    packet.extend_from_slice(&payload);
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+
+    let mut buffers = vec![vec![0u8; 256]; 2];
+    let mut mbufs = segment_mbufs(&mut buffers);
+    let mut free = mbufs.iter_mut().map(|mbuf| mbuf as *mut MBuf);
+    let segments = pdu
+        .tcp_segments_with(100, || free.next().map(Pdu::pdu_from_mbuf_no_increment))
+        .unwrap();
+    assert_eq!(segments.len(), 2);
+    assert!(segments.iter().all(|segment| segment.headers().count() == 7));
+    drop(segments);
+    for (i, chunk) in payload.chunks(100).enumerate() {
+        let segment = &buffers[i][..mbufs[i].data_len as usize];
+        let outer_ip_len = segment.len() - 14;
+        assert_eq!(((segment[16] as usize) << 8) | segment[17] as usize, outer_ip_len);
+        assert_eq!(ones_complement_sum(&segment[14..34], 0), 0xffff);
+        let udp_len = outer_ip_len - 20;
+        assert_eq!(&segment[38..42], &[(udp_len >> 8) as u8, udp_len as u8, 0, 0]);
+        check_segment(segment, 14 + 20 + 8 + 8 + 14, 1 + 100 * i as u32, chunk);
+    }
+}
+
+/// A packet with a TCP payload of `first_payload` bytes in the first and `second_payload` bytes in the second segment,
+/// each segment has `tailroom` bytes left. The payload bytes count up from 0.
+fn two_segment_packet(first_payload: usize, second_payload: usize, tailroom: usize) -> (Vec<u8>, Vec<u8>) {
+    let mut first = tcp_packet_headers(first_payload + second_payload);
+    first.extend((0..first_payload).map(|i| i as u8));
+    first.resize(first.len() + tailroom, 0);
+    let mut second: Vec<u8> = (first_payload..first_payload + second_payload)
+        .map(|i| i as u8)
+        .collect();
+    second.resize(second_payload + tailroom, 0);
+    (first, second)
+}
+
+/// Chain mbufs over the buffers of `two_segment_packet`.
+fn chain(first: &mut [u8], second: &mut [u8], tailroom: usize) -> (MBuf, Box<MBuf>) {
+    let mut tail = Box::new(mbuf_over(second, ptr::null_mut()));
+    tail.data_len -= tailroom as u16;
+    tail.pkt_len -= tailroom as u32;
+    let mut head = mbuf_over(first, &mut *tail);
+    head.data_len -= tailroom as u16;
+    head.nb_segs = 2;
+    head.pkt_len = (head.data_len + tail.data_len) as u32;
+    (head, tail)
+}
+
+#[test]
+fn write_payload_across_segments() {
+    let (mut first, mut second) = two_segment_packet(40, 60, 0);
+    let (mut head, _tail) = chain(&mut first, &mut second, 0);
+    let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut head);
+    assert_eq!(pdu.write_payload(2, 30, &[0xff; 20]), 20);
+    let mut expected: Vec<u8> = (0..100).collect();
+    expected[30..50].copy_from_slice(&[0xff; 20]);
+    assert_eq!(pdu.payload_to_vec(2), expected);
+    // the packet is not extended
+    assert_eq!(pdu.write_payload(2, 90, &[0xee; 20]), 10);
+    assert_eq!(pdu.total_payload_size(2), 100);
+}
+
+#[test]
+fn extend_payload_in_tailroom_of_last_segment() {
+    let (mut first, mut second) = two_segment_packet(40, 60, 50);
+    let (mut head, tail) = chain(&mut first, &mut second, 50);
+    let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut head);
+    pdu.add_to_payload_tail(30).unwrap();
+    assert_eq!(pdu.total_payload_size(2), 130);
+    assert_eq!(tail.data_len, 90);
+
+    // the copy extends the packet by another 10 bytes and is scattered across both segments
+    let payload: Vec<u8> = (0..140).map(|i| 255 - i as u8).collect();
+    assert_eq!(pdu.copy_payload_from_u8_slice(&payload, 2), 140);
+    assert_eq!(pdu.payload_to_vec(2), payload);
+    assert_eq!(tail.data_len, 100);
+    assert_eq!(&first[first.len() - 50 - 40..first.len() - 50], &payload[..40]);
+}
+
+#[test]
+fn linearize_copies_segments_into_the_first() {
+    // the first segment is too small for the packet
+    let (mut first, mut second) = two_segment_packet(40, 60, 50);
+    let (mut head, _tail) = chain(&mut first, &mut second, 50);
+    let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut head);
+    assert!(pdu.linearize().is_err());
+    assert!(!pdu.is_contiguous());
+    assert_eq!(pdu.payload_to_vec(2), (0..100).collect::<Vec<u8>>());
+
+    let (mut first, mut second) = two_segment_packet(40, 60, 100);
+    let (mut head, mut tail) = chain(&mut first, &mut second, 100);
+    // the detached segment is freed, which only drops this reference
+    tail.refcnt = 2;
+    let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut head);
+    pdu.linearize().unwrap();
+    assert!(pdu.is_contiguous());
+    assert_eq!(pdu.headers().count(), 3);
+    assert_eq!(pdu.payload_to_vec(2), (0..100).collect::<Vec<u8>>());
+    assert_eq!(
+        (head.nb_segs, head.data_len as u32, head.pkt_len),
+        (1, head.pkt_len, 14 + 44 + 100)
+    );
+    assert!(head.next.is_null());
+    assert_eq!(tail.refcnt, 1);
+}
@@ -0,0 +1,159 @@
+extern crate e2d2;
+extern crate libc;
+use e2d2::shared_state::{remove_persistent, PersistentRegion, SharedHashMap, SharedHashMapReader};
+use e2d2::state::*;
+use e2d2::utils::FiveTupleV4;
+use std::ffi::CString;
+use std::process;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Binding {
+    addr: u32,
+    port: u16,
+}
+
+#[test]
+fn persistent_region_survives_reopen() {
+    let name = format!("/e2d2-persistent-test-{}", process::id());
+    unsafe {
+        let mut region = PersistentRegion::<Binding>::open(&name, 100, 1).unwrap();
+        assert!(region.created());
+        assert_eq!(region.len(), 100);
+        assert_eq!(region[99], Binding { addr: 0, port: 0 });
+        region[7] = Binding {
+            addr: 0x0a00_0001,
+            port: 40000,
+        };
+        drop(region);
+
+        // a restarted process attaches to the same state
+        let region = PersistentRegion::<Binding>::open(&name, 100, 1).unwrap();
+        assert!(!region.created());
+        assert_eq!(region[7].port, 40000);
+
+        // the layout is checked
+        assert!(PersistentRegion::<Binding>::open(&name, 100, 2).is_err());
+        assert!(PersistentRegion::<u64>::open(&name, 100, 1).is_err());
+    }
+    PersistentRegion::<Binding>::remove(&name).unwrap();
+    assert!(PersistentRegion::<Binding>::remove(&name).is_err());
+}
+
+#[test]
+fn persistent_region_reinitializes_incomplete_region() {
+    let name = format!("/e2d2-persistent-incomplete-{}", process::id());
+    unsafe {
+        // a creator which stopped after sizing the segment left an uninitialized header
+        let c_name = CString::new(name.clone()).unwrap();
+        let fd = libc::shm_open(c_name.as_ptr(), libc::O_CREAT | libc::O_RDWR, 0o700);
+        assert!(fd >= 0);
+        assert_eq!(libc::ftruncate(fd, 4096), 0);
+        libc::close(fd);
+
+        let region = PersistentRegion::<Binding>::open(&name, 100, 1).unwrap();
+        assert!(region.created());
+        assert_eq!(region.mapped_size(), 4096);
+        drop(region);
+        assert!(!PersistentRegion::<Binding>::open(&name, 100, 1).unwrap().created());
+    }
+    PersistentRegion::<Binding>::remove(&name).unwrap();
+}
+
+/// A NAT binding kept in a persistent region, zeroed slots are unused.
+#[derive(Clone, Copy)]
+struct FlowSlot {
+    used: bool,
+    flow: FiveTupleV4,
+    binding: Binding,
+}
+
+fn flow(port: u16) -> FiveTupleV4 {
+    FiveTupleV4 {
+        src_ip: 0x0a00_0001,
+        dst_ip: 0x0a00_0002,
+        src_port: port,
+        dst_port: 80,
+        proto: 6,
+    }
+}
+
+/// The flows of the occupied slots of `region`, mapped to their slots.
+fn restore_flows(region: &PersistentRegion<FlowSlot>, now: u64) -> FlowTable<usize> {
+    let mut table = FlowTable::new(FlowTableConfig {
+        capacity: region.len(),
+        idle_timeout: 0,
+        hard_timeout: 0,
+    });
+    for (i, slot) in region.iter().enumerate().filter(|&(_, slot)| slot.used) {
+        table.insert(slot.flow, i, now);
+    }
+    table
+}
+
+#[test]
+fn flow_table_restored_from_persistent_region() {
+    let name = format!("/e2d2-persistent-flows-{}", process::id());
+    unsafe {
+        let mut region = PersistentRegion::<FlowSlot>::open(&name, 16, 1).unwrap();
+        let mut table = restore_flows(&region, 0);
+        assert!(table.is_empty());
+        for (i, port) in [1000u16, 1001, 1002].iter().enumerate() {
+            region[i] = FlowSlot {
+                used: true,
+                flow: flow(*port),
+                binding: Binding {
+                    addr: 0x0a00_0100,
+                    port: 40000 + i as u16,
+                },
+            };
+            table.insert(flow(*port), i, 1);
+        }
+        drop(table);
+        drop(region);
+
+        // a restarted process finds the bindings of the existing flows
+        let region = PersistentRegion::<FlowSlot>::open(&name, 16, 1).unwrap();
+        let mut table = restore_flows(&region, 2);
+        assert_eq!(table.len(), 3);
+        let slot = *table.get_mut(&flow(1001), 2).unwrap();
+        assert_eq!(region[slot].binding.port, 40001);
+        assert!(table.get_mut(&flow(1003), 2).is_none());
+    }
+    PersistentRegion::<FlowSlot>::remove(&name).unwrap();
+}
+
+#[test]
+fn shared_hash_map_reattaches_to_persistent_map() {
+    let name = format!("/e2d2-persistent-map-{}", process::id());
+    unsafe {
+        let mut bindings = SharedHashMap::<FiveTupleV4, Binding>::open_persistent(&name, 64, 1).unwrap();
+        assert!(bindings.is_empty());
+        for port in 1000..1010 {
+            let binding = Binding {
+                addr: 0x0a00_0100,
+                port: port + 39000,
+            };
+            assert!(bindings.insert(flow(port), binding));
+        }
+        assert_eq!(bindings.remove(&flow(1003)).unwrap().port, 40003);
+        drop(bindings);
+
+        // a restarted process finds the bindings, a reader sees them as well
+        let mut bindings = SharedHashMap::<FiveTupleV4, Binding>::open_persistent(&name, 64, 1).unwrap();
+        assert_eq!(bindings.len(), 9);
+        assert_eq!(bindings.get(&flow(1001)).unwrap().port, 40001);
+        assert!(bindings.get(&flow(1003)).is_none());
+        let reader = SharedHashMapReader::<FiveTupleV4, Binding>::open(&name).unwrap();
+        assert_eq!(reader.get(&flow(1009)).unwrap().unwrap().port, 40009);
+        assert!(bindings.insert(flow(1003), Binding { addr: 1, port: 2 }));
+        assert_eq!(reader.len(), 10);
+
+        // the layout is checked
+        assert!(SharedHashMap::<FiveTupleV4, Binding>::open_persistent(&name, 64, 2).is_err());
+        assert!(SharedHashMap::<FiveTupleV4, u16>::open_persistent(&name, 64, 1).is_err());
+        assert!(SharedHashMap::<FiveTupleV4, Binding>::open_persistent(&name, 32, 1).is_err());
+        assert!(PersistentRegion::<Binding>::open(&name, 100, 1).is_err());
+    }
+    remove_persistent(&name).unwrap();
+    assert!(remove_persistent(&name).is_err());
+}
@@ -0,0 +1,33 @@
+extern crate e2d2;
+use e2d2::config::*;
+
+const CONFIG: &'static str = r#"
+[netbricks]
+name = "mtu-test"
+master_core = 0
+cores = [1]
+
+[[netbricks.ports]]
+name = "0000:01:00.0"
+cores = [1]
+mtu = 9000
+
+[[netbricks.ports]]
+name = "0000:02:00.0"
+cores = [1]
+"#;
+
+#[test]
+fn read_port_mtu() {
+    let config = read_configuration_from_str(CONFIG, "mtu-test").unwrap();
+    assert_eq!(config.ports[0].mtu, Some(9000));
+    assert_eq!(config.ports[1].mtu, None);
+}
+
+#[test]
+fn reject_bad_mtu() {
+    let config = CONFIG.replace("mtu = 9000", "mtu = 65000");
+    assert!(read_configuration_from_str(&config, "mtu-test").is_err());
+    let config = CONFIG.replace("mtu = 9000", "mtu = \"jumbo\"");
+    assert!(read_configuration_from_str(&config, "mtu-test").is_err());
+}
@@ -0,0 +1,146 @@
+extern crate e2d2;
+extern crate uuid;
+mod common;
+use common::mbuf_over;
+use e2d2::interface::{PacketRx, Pdu};
+use e2d2::native::zcsi::MBuf;
+use e2d2::operators::*;
+use e2d2::queues::*;
+use e2d2::scheduler::{Runnable, Scheduler};
+use std::ptr;
+use uuid::Uuid;
+
+// the queues only move the pointers, as long as nothing is dropped they are never dereferenced
+fn mbufs(n: usize) -> Vec<*mut MBuf> {
+    (1..=n).map(|i| (i * 256) as *mut MBuf).collect()
+}
+
+/// UDP/IPv4 packets to `ports` and mbufs over them. The mbufs are referenced twice, so that freeing them only
+/// decrements the reference count.
+fn udp_packets(ports: &[u16]) -> (Vec<Vec<u8>>, Vec<MBuf>) {
+    let mut data: Vec<Vec<u8>> = ports
+        .iter()
+        .map(|port| {
+            let mut packet = vec![0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1, 0x08, 0];
+            packet.extend_from_slice(&[0x45, 0, 0, 28, 0, 0, 0, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+            packet.extend_from_slice(&[0x30, 0x39, (port >> 8) as u8, *port as u8, 0, 8, 0, 0]);
+            packet
+        })
+        .collect();
+    let mbufs = data
+        .iter_mut()
+        .map(|packet| {
+            let mut mbuf = mbuf_over(packet, ptr::null_mut());
+            mbuf.refcnt = 2;
+            mbuf
+        })
+        .collect();
+    (data, mbufs)
+}
+
+/// The UDP destination ports of the packets received by `batch`.
+fn received_ports<T: PacketRx>(batch: &mut ReceiveBatch<T>) -> Vec<u16> {
+    batch.act();
+    let ports = (0..)
+        .map(|i| batch.next_payload(i).map(|pdu| pdu.headers().udp(2).dst_port()))
+        .take_while(|port| port.is_some())
+        .map(|port| port.unwrap())
+        .collect();
+    // the mbufs belong to the test
+    batch.clear_packets();
+    ports
+}
+
+struct TaskList(Vec<Runnable>);
+
+impl Scheduler for TaskList {
+    fn add_runnable(&mut self, runnable: Runnable) -> usize {
+        self.0.push(runnable);
+        self.0.len() - 1
+    }
+}
+
+#[test]
+fn watermarks_signal_backpressure() {
+    let mut config = QueueConfig::new(8);
+    config.high_watermark = 6;
+    config.low_watermark = 2;
+    let (producer, consumer) = new_mpmc_queue_pair_with_config(&config);
+    let sent = mbufs(6);
+    assert_eq!(producer.enqueue_mbufs(&sent[..5]), 5);
+    assert!(!producer.is_congested());
+    assert_eq!(producer.enqueue_mbufs(&sent[5..]), 1);
+    assert!(producer.is_congested());
+
+    // a second consumer takes its share of the packets
+    let other = consumer.clone();
+    let mut received = vec![ptr::null_mut(); 3];
+    assert_eq!(consumer.recv(&mut received).unwrap().0, 3);
+    assert!(producer.is_congested());
+    assert_eq!(other.recv(&mut received[..1]).unwrap().0, 1);
+    assert!(!producer.is_congested());
+    assert_eq!(received[0], sent[3]);
+    assert_eq!(producer.dropped(), 0);
+}
+
+#[test]
+fn watermarks_are_off_by_default() {
+    let (producer, consumer) = new_mpmc_queue_pair_with_size(8);
+    let sent = mbufs(7);
+    assert_eq!(producer.enqueue_mbufs(&sent), 7);
+    assert_eq!(producer.free_slots(), 0);
+    assert!(!producer.is_congested());
+    let mut received = vec![ptr::null_mut(); 7];
+    assert_eq!(consumer.recv(&mut received).unwrap().0, 7);
+    assert_eq!(received, sent);
+}
+
+#[test]
+fn spsc_queue_pair_keeps_order() {
+    let mut config = QueueConfig::new(8);
+    config.high_watermark = 6;
+    config.low_watermark = 2;
+    let (mut producer, mut consumer) = new_spsc_queue_pair_with_config(&config);
+    let (_data, mut mbufs) = udp_packets(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    let pointers: Vec<*mut MBuf> = mbufs.iter_mut().map(|mbuf| mbuf as *mut MBuf).collect();
+    // one slot stays empty
+    assert_eq!(producer.enqueue_mbufs(&pointers), 7);
+    assert_eq!(producer.used_slots(), 7);
+    assert_eq!(producer.free_slots(), 0);
+    assert!(producer.is_congested());
+    assert_eq!(received_ports(&mut consumer), vec![1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(producer.used_slots(), 0);
+    assert_eq!(producer.free_slots(), 7);
+    assert!(!producer.is_congested());
+    assert_eq!(producer.dropped(), 0);
+}
+
+#[test]
+fn group_by_drops_the_head_of_full_groups() {
+    let mut config = QueueConfig::new(4);
+    config.drop_policy = DropPolicy::HeadDrop;
+    let (producer, consumer) = new_mpmc_queue_pair();
+    let mut tasks = TaskList(Vec::new());
+    let mut groups = GroupBy::new_with_queue_config(
+        ReceiveBatch::new(consumer),
+        2,
+        Box::new(|pdu: &mut Pdu| (pdu.headers().udp(2).dst_port() % 2) as usize),
+        &mut tasks,
+        "group_by".to_string(),
+        Uuid::new_v4(),
+        &config,
+    );
+    let mut even = groups.get_group(0).unwrap();
+    let mut odd = groups.get_group(1).unwrap();
+
+    let (_data, mut mbufs) = udp_packets(&[0, 2, 4, 1, 6, 8, 10]);
+    let pointers: Vec<*mut MBuf> = mbufs.iter_mut().map(|mbuf| mbuf as *mut MBuf).collect();
+    assert_eq!(producer.enqueue_mbufs(&pointers), 7);
+    assert_eq!(tasks.0[0].task.execute().0, 7);
+
+    // a group queue holds 3 packets, the oldest ones of the even group were dropped
+    assert_eq!(received_ports(&mut even), vec![6, 8, 10]);
+    assert_eq!(received_ports(&mut odd), vec![1]);
+    let refcnts: Vec<u16> = mbufs.iter().map(|mbuf| mbuf.refcnt).collect();
+    assert_eq!(refcnts, vec![1, 1, 1, 2, 2, 2, 2]);
+}
@@ -0,0 +1,317 @@
+extern crate e2d2;
+mod common;
+use common::{leaked_pdu, mbuf_over};
+use e2d2::headers::*;
+use e2d2::interface::{FragmentInfo, FragmentKey, Pdu};
+use e2d2::native::zcsi::MBuf;
+use e2d2::operators::*;
+use e2d2::queues::new_mpmc_queue_pair;
+use e2d2::state::*;
+use std::ptr;
+use std::time::{Duration, Instant};
+
+const KEY: FragmentKey = FragmentKey::Ipv4 {
+    src: 0x0a00_0001,
+    dst: 0x0a00_0002,
+    protocol: 17,
+    id: 42,
+};
+
+fn fragment(offset: usize, len: usize, more: bool) -> FragmentInfo {
+    FragmentInfo {
+        key: KEY,
+        offset,
+        len,
+        more,
+        next_header: 17,
+        header_len: 34,
+        data_offset: 34,
+    }
+}
+
+fn data(offset: usize, len: usize, fill: u8) -> (FragmentInfo, Vec<u8>) {
+    (fragment(offset, len, true), vec![fill; len])
+}
+
+#[test]
+fn fragment_fields() {
+    let mut ip = IpHeader::new();
+    ip.set_id(0x1234);
+    ip.set_fragment_offset(185);
+    ip.set_flags(IP_FLAG_MF);
+    assert_eq!(ip.id(), 0x1234);
+    assert_eq!(ip.fragment_offset(), 185);
+    assert!(ip.more_fragments());
+    assert!(!ip.dont_fragment());
+    assert!(ip.is_fragment());
+
+    let mut frag = Ipv6FragmentHeader::new();
+    frag.set_fragment_offset(185);
+    frag.set_more_fragments(true);
+    frag.set_id(7);
+    assert_eq!(frag.fragment_offset(), 185);
+    assert!(frag.more_fragments());
+    frag.set_more_fragments(false);
+    assert_eq!(frag.fragment_offset(), 185);
+    assert!(!frag.more_fragments());
+}
+
+#[test]
+fn reassemble_out_of_order() {
+    let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+    let now = Instant::now();
+    let header = [0u8; 34];
+    let (last, last_data) = (fragment(16, 4, false), vec![3u8; 4]);
+    assert!(reassembler.add_fragment(&last, &header, &last_data, now).is_none());
+    let (second, second_data) = data(8, 8, 2);
+    assert!(reassembler.add_fragment(&second, &header, &second_data, now).is_none());
+    let (first, first_data) = data(0, 8, 1);
+    let datagram = reassembler.add_fragment(&first, &header, &first_data, now).unwrap();
+    assert_eq!(datagram.key, KEY);
+    assert_eq!(datagram.payload.len(), 20);
+    assert_eq!(&datagram.payload[6..10], &[1, 1, 2, 2]);
+    assert_eq!(datagram.payload[19], 3);
+    assert!(reassembler.is_empty());
+    assert_eq!(reassembler.buffered_bytes(), 0);
+    assert_eq!(reassembler.stats().reassembled, 1);
+}
+
+#[test]
+fn overlap_policies() {
+    let now = Instant::now();
+    let header = [0u8; 34];
+    for &(policy, expected) in &[
+        (OverlapPolicy::First, Some(1u8)),
+        (OverlapPolicy::Last, Some(2u8)),
+        (OverlapPolicy::Drop, None),
+    ] {
+        let mut reassembler = Reassembler::new(ReassemblyConfig {
+            overlap: policy,
+            ..Default::default()
+        });
+        let (a, a_data) = data(0, 16, 1);
+        let (b, b_data) = data(8, 16, 2);
+        let (c, c_data) = (fragment(24, 8, false), vec![3u8; 8]);
+        assert!(reassembler.add_fragment(&a, &header, &a_data, now).is_none());
+        assert!(reassembler.add_fragment(&b, &header, &b_data, now).is_none());
+        let datagram = reassembler.add_fragment(&c, &header, &c_data, now);
+        assert_eq!(datagram.map(|d| d.payload[12]), expected);
+        assert_eq!(reassembler.stats().overlaps, 1);
+    }
+}
+
+#[test]
+fn limits_and_timeout() {
+    let now = Instant::now();
+    let header = [0u8; 34];
+    let mut reassembler = Reassembler::new(ReassemblyConfig {
+        max_datagrams: 1,
+        timeout: Duration::from_millis(100),
+        ..Default::default()
+    });
+    let (a, a_data) = data(0, 8, 1);
+    assert!(reassembler.add_fragment(&a, &header, &a_data, now).is_none());
+    // duplicate
+    assert!(reassembler.add_fragment(&a, &header, &a_data, now).is_none());
+    assert_eq!(reassembler.stats().duplicates, 1);
+    // a second datagram does not fit
+    let mut other = a;
+    other.key = FragmentKey::Ipv4 {
+        src: 1,
+        dst: 2,
+        protocol: 6,
+        id: 1,
+    };
+    assert!(reassembler.add_fragment(&other, &header, &a_data, now).is_none());
+    assert_eq!(reassembler.stats().no_resources, 1);
+    // unless the first one timed out
+    let later = now + Duration::from_millis(200);
+    assert!(reassembler.add_fragment(&other, &header, &a_data, later).is_none());
+    assert_eq!(reassembler.stats().timed_out, 1);
+    assert_eq!(reassembler.len(), 1);
+    assert_eq!(reassembler.buffered_bytes(), 8);
+}
+
+/// Ethernet, IPv4 and UDP headers followed by `payload_len` bytes counting up from 0.
+fn udp_ipv4_packet(payload_len: usize, flags: u8) -> Vec<u8> {
+    let udp_len = 8 + payload_len;
+    let ip_len = 20 + udp_len;
+    let mut packet = vec![0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1, 0x08, 0];
+    packet.extend_from_slice(&[
+        0x45,
+        0,
+        (ip_len >> 8) as u8,
+        ip_len as u8,
+        0,
+        42,
+        flags << 5,
+        0,
+        64,
+        17,
+        0,
+        0,
+    ]);
+    packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    packet.extend_from_slice(&[0x30, 0x39, 0, 53, (udp_len >> 8) as u8, udp_len as u8, 0, 0]);
+    packet.extend((0..payload_len).map(|i| i as u8));
+    packet
+}
+
+/// Ethernet, IPv6 and UDP headers followed by `payload_len` bytes counting up from 0.
+fn udp_ipv6_packet(payload_len: usize) -> Vec<u8> {
+    let udp_len = 8 + payload_len;
+    let mut packet = vec![0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1, 0x86, 0xdd];
+    packet.extend_from_slice(&[0x60, 0, 0, 0, (udp_len >> 8) as u8, udp_len as u8, 17, 64]);
+    packet.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    packet.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+    packet.extend_from_slice(&[0x30, 0x39, 0, 53, (udp_len >> 8) as u8, udp_len as u8, 0, 0]);
+    packet.extend((0..payload_len).map(|i| i as u8));
+    packet
+}
+
+fn ipv4_checksum_valid(ip: &IpHeader) -> bool {
+    let mut copy = *ip;
+    copy.update_checksum();
+    copy.csum() == ip.csum()
+}
+
+#[test]
+fn ipv4_fragments_reassemble() {
+    let mut packet = udp_ipv4_packet(100, 0);
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    assert!(pdu.ipv6_fragments_with(68, 1, leaked_pdu).is_err());
+    // 48 bytes of data fit next to the IP header
+    let fragments = pdu.ipv4_fragments_with(68, leaked_pdu).unwrap();
+    let infos: Vec<FragmentInfo> = fragments.iter().map(|f| f.fragment_info().unwrap()).collect();
+    let layout: Vec<(usize, usize, bool)> = infos.iter().map(|i| (i.offset, i.len, i.more)).collect();
+    assert_eq!(layout, vec![(0, 48, true), (48, 48, true), (96, 12, false)]);
+    for fragment in &fragments {
+        let ip = fragment.headers().ip(1);
+        assert_eq!(ip.id(), 42);
+        assert!(ipv4_checksum_valid(ip));
+        assert_eq!(fragment.data_len(), 14 + ip.length() as usize);
+    }
+
+    let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+    let now = Instant::now();
+    let mut reassembled = None;
+    for (fragment, info) in fragments.iter().zip(&infos).rev() {
+        assert!(reassembled.is_none());
+        reassembled = reassembler.add_pdu_with(fragment, info, now, leaked_pdu).unwrap();
+    }
+    let reassembled = reassembled.unwrap();
+    assert!(reassembled.fragment_info().is_none());
+    let ip = reassembled.headers().ip(1);
+    assert_eq!(ip.length(), 128);
+    assert!(ipv4_checksum_valid(ip));
+    assert_eq!(reassembled.headers().udp(2).length(), 108);
+    assert_eq!(reassembled.payload_to_vec(2), (0..100).collect::<Vec<u8>>());
+
+    // don't fragment
+    let mut packet = udp_ipv4_packet(100, IP_FLAG_DF);
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    assert!(pdu.ipv4_fragments_with(68, leaked_pdu).is_err());
+}
+
+#[test]
+fn ipv6_fragments_reassemble() {
+    let mut packet = udp_ipv6_packet(100);
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    assert!(pdu.ipv4_fragments_with(96, leaked_pdu).is_err());
+    assert!(pdu.ipv6_fragments_with(48, 7, leaked_pdu).is_err());
+    let fragments = pdu.ipv6_fragments_with(96, 7, leaked_pdu).unwrap();
+    let infos: Vec<FragmentInfo> = fragments.iter().map(|f| f.fragment_info().unwrap()).collect();
+    let layout: Vec<(usize, usize, bool)> = infos.iter().map(|i| (i.offset, i.len, i.more)).collect();
+    assert_eq!(layout, vec![(0, 48, true), (48, 48, true), (96, 12, false)]);
+    for (fragment, info) in fragments.iter().zip(&infos) {
+        assert_eq!(info.next_header, 17);
+        assert_eq!(info.header_len, 54);
+        match info.key {
+            FragmentKey::Ipv6 { id, .. } => assert_eq!(id, 7),
+            _ => panic!("expected an IPv6 fragment"),
+        }
+        assert_eq!(fragment.headers().ipv6(1).payload_len() as usize, 8 + info.len);
+    }
+    let mut data = Vec::new();
+    for (fragment, info) in fragments.iter().zip(&infos) {
+        data.extend(fragment.fragment_data(info));
+    }
+
+    let header = &udp_ipv6_packet(0)[..54];
+    let reassembled = Pdu::from_fragments_with(header, 17, &data, 0, leaked_pdu).unwrap();
+    assert_eq!(reassembled.headers().count(), 3);
+    assert_eq!(reassembled.headers().ipv6(1).payload_len(), 108);
+    assert_eq!(reassembled.payload_to_vec(2), (0..100).collect::<Vec<u8>>());
+    assert!(Pdu::from_fragments_with(header, 17, &data, 0, || None).is_err());
+}
+
+/// Receive the packets of `batch` and take their mbufs out of the batch.
+fn take_mbufs<T: Batch + BatchIterator + Act>(batch: &mut T) -> Vec<*mut MBuf> {
+    let mut mbufs = Vec::new();
+    while let Some(pdu) = batch.next_payload(mbufs.len()) {
+        mbufs.push(unsafe { pdu.get_mbuf() });
+    }
+    batch.clear_packets();
+    mbufs
+}
+
+#[test]
+fn fragment_and_reassemble_batches() {
+    let mut small = udp_ipv4_packet(10, 0);
+    let mut large = udp_ipv4_packet(100, 0);
+    let mut mbufs: Vec<MBuf> = vec![
+        mbuf_over(&mut small, ptr::null_mut()),
+        mbuf_over(&mut large, ptr::null_mut()),
+    ];
+    // the fragmented packet is freed by the batch
+    mbufs[1].refcnt = 2;
+    let pointers: Vec<*mut MBuf> = mbufs.iter_mut().map(|mbuf| mbuf as *mut MBuf).collect();
+
+    let (producer, consumer) = new_mpmc_queue_pair();
+    let mut fragmenting = ReceiveBatch::new(consumer).fragment_to_mtu(68);
+    fragmenting.set_allocator(leaked_pdu);
+    assert_eq!(producer.enqueue_mbufs(&pointers), 2);
+    assert_eq!(fragmenting.act().0, 4);
+    let fragmented = take_mbufs(&mut fragmenting);
+    assert_eq!(fragmented.len(), 4);
+    assert_eq!(fragmented[0], pointers[0]);
+    assert_eq!(mbufs[1].refcnt, 1);
+
+    let (producer, consumer) = new_mpmc_queue_pair();
+    let mut reassembling = ReceiveBatch::new(consumer).reassemble(ReassemblyConfig::default());
+    reassembling.set_allocator(leaked_pdu);
+    assert_eq!(producer.enqueue_mbufs(&fragmented), 4);
+    assert_eq!(reassembling.act().0, 2);
+    let small_pdu = reassembling.next_payload(0).unwrap();
+    assert_eq!(small_pdu.headers().ip(1).length(), 38);
+    let reassembled = reassembling.next_payload(1).unwrap();
+    assert_eq!(reassembled.headers().ip(1).length(), 128);
+    assert_eq!(reassembled.payload_to_vec(2), (0..100).collect::<Vec<u8>>());
+    assert!(reassembling.next_payload(2).is_none());
+    reassembling.clear_packets();
+    assert_eq!(reassembling.reassembler().len(), 0);
+}
+
+#[test]
+fn fragments_exceeding_the_batch_capacity_are_dropped() {
+    // a full batch of 32 packets, the fragments of the last one do not fit
+    let mut packets: Vec<Vec<u8>> = (0..31).map(|_| udp_ipv4_packet(10, 0)).collect();
+    packets.push(udp_ipv4_packet(100, 0));
+    let mut mbufs: Vec<MBuf> = packets
+        .iter_mut()
+        .map(|packet| mbuf_over(packet, ptr::null_mut()))
+        .collect();
+    mbufs[31].refcnt = 2;
+    let pointers: Vec<*mut MBuf> = mbufs.iter_mut().map(|mbuf| mbuf as *mut MBuf).collect();
+
+    let (producer, consumer) = new_mpmc_queue_pair();
+    let mut fragmenting = ReceiveBatch::new(consumer).fragment_to_mtu(68);
+    fragmenting.set_allocator(leaked_pdu);
+    assert_eq!(producer.enqueue_mbufs(&pointers), 32);
+    assert_eq!(fragmenting.act().0, 31);
+    assert_eq!(take_mbufs(&mut fragmenting), &pointers[..31]);
+    assert_eq!(mbufs[31].refcnt, 1);
+}
@@ -0,0 +1,103 @@
+extern crate e2d2;
+use e2d2::shared_state::directory::{Directory, DirectoryReader};
+use e2d2::shared_state::{SharedHashMap, SharedHashMapReader, SharedVec, SharedVecReader};
+use std::process;
+
+#[test]
+fn directory_entries_are_readable() {
+    let name = format!("/e2d2-directory-test-{}", process::id());
+    let mut directory = Directory::new(&name);
+    directory.begin_snapshot();
+    assert_eq!(directory.register_new_entry("flows"), Some(0));
+    assert_eq!(directory.register_new_entry("counters"), Some(1));
+    directory.end_snapshot();
+
+    let reader = DirectoryReader::open(&name).unwrap();
+    assert_eq!(
+        reader.entries().unwrap(),
+        vec!["flows".to_string(), "counters".to_string()]
+    );
+}
+
+#[test]
+fn shared_vec_is_readable() {
+    let name = format!("/e2d2-shared-vec-test-{}", process::id());
+    let mut vec = SharedVec::<u32>::new_with_capacity(&name, 4);
+    vec.begin_snapshot();
+    for i in 0..5 {
+        assert_eq!(vec.push(i * 10), i < 4);
+    }
+    vec[1] = 11;
+    vec.end_snapshot();
+
+    let reader = SharedVecReader::open(&name).unwrap();
+    assert_eq!(reader.capacity(), 4);
+    assert_eq!(reader.read::<u32>().unwrap(), vec![0, 11, 20, 30]);
+    assert!(reader.read::<u64>().is_err());
+}
+
+#[test]
+fn shared_hash_map_is_readable() {
+    let name = format!("/e2d2-shared-map-test-{}", process::id());
+    let mut map = SharedHashMap::<(u32, u16), u64>::new_with_capacity(&name, 100);
+    for i in 0..100 {
+        assert!(map.insert((i, 80), u64::from(i)));
+    }
+    assert!(!map.insert((100, 80), 100));
+    assert!(map.insert((7, 80), 700));
+    assert_eq!(map.remove(&(8, 80)), Some(8));
+    assert!(map.insert((100, 80), 100));
+
+    let reader = unsafe { SharedHashMapReader::<(u32, u16), u64>::open(&name).unwrap() };
+    assert_eq!(reader.len(), 100);
+    assert_eq!(reader.get(&(7, 80)).unwrap(), Some(700));
+    assert_eq!(reader.get(&(8, 80)).unwrap(), None);
+    assert_eq!(reader.get(&(100, 80)).unwrap(), Some(100));
+    assert_eq!(reader.entries().unwrap().len(), 100);
+
+    map.clear();
+    assert_eq!(reader.get(&(7, 80)).unwrap(), None);
+    assert!(unsafe { SharedHashMapReader::<u32, u64>::open(&name).is_err() });
+}
+
+#[test]
+fn shared_hash_map_reclaims_removed_slots() {
+    let name = format!("/e2d2-shared-map-churn-test-{}", process::id());
+    let mut map = SharedHashMap::<u32, u32>::new_with_capacity(&name, 13);
+    let reader = unsafe { SharedHashMapReader::<u32, u32>::open(&name).unwrap() };
+    for i in 0..12 {
+        assert!(map.insert(i, i));
+    }
+    // replacing keys leaves removed slots behind, remove does not reclaim them on its own
+    for i in 0..500 {
+        assert!(map.insert(i + 12, i + 12));
+        assert_eq!(map.remove(&i), Some(i));
+        assert_eq!(reader.get(&i).unwrap(), None);
+        assert_eq!(reader.get(&(i + 1)).unwrap(), Some(i + 1));
+        assert_eq!(reader.get(&(i + 12)).unwrap(), Some(i + 12));
+    }
+    assert!(map.needs_compaction());
+    map.compact();
+    assert_eq!(map.removed_slots(), 0);
+    assert_eq!(map.len(), 12);
+    assert_eq!(reader.get(&511).unwrap(), Some(511));
+
+    // compacting when needed keeps the removed slots bounded
+    let mut compactions = 0;
+    for i in 500..1000 {
+        assert!(map.insert(i + 12, i + 12));
+        assert_eq!(map.remove(&i), Some(i));
+        assert_eq!(reader.get(&i).unwrap(), None);
+        assert_eq!(reader.get(&(i + 12)).unwrap(), Some(i + 12));
+        if map.needs_compaction() {
+            map.compact();
+            compactions += 1;
+        }
+        assert!(map.removed_slots() <= 4);
+    }
+    assert!(compactions > 0);
+    assert_eq!(map.len(), 12);
+    let mut keys: Vec<u32> = reader.entries().unwrap().into_iter().map(|(key, _)| key).collect();
+    keys.sort();
+    assert_eq!(keys, (1000..1012).collect::<Vec<_>>());
+}
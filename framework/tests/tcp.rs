@@ -0,0 +1,361 @@
+extern crate e2d2;
+mod common;
+use common::mbuf_over;
+use e2d2::interface::Pdu;
+use e2d2::native::zcsi::MBuf;
+use e2d2::operators::*;
+use e2d2::queues::new_mpmc_queue_pair;
+use e2d2::tcp::*;
+use e2d2::utils::{ipv4_checksum, FiveTupleV4};
+use std::cell::RefCell;
+use std::net::Ipv4Addr;
+use std::ptr;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Deliver the segments of `from` to `to`, returns the number of segments.
+fn deliver(from: &mut TcpConnection, to: &mut TcpConnection, now: Instant) -> usize {
+    let segments = from.poll(now);
+    for segment in &segments {
+        to.on_segment(segment, now);
+    }
+    segments.len()
+}
+
+fn exchange(a: &mut TcpConnection, b: &mut TcpConnection, now: Instant) {
+    while deliver(a, b, now) + deliver(b, a, now) > 0 {}
+}
+
+fn handshake(now: Instant) -> (TcpConnection, TcpConnection) {
+    let mut client = TcpConnection::connect(TcpConfig::default(), 0xffff_fff0).unwrap();
+    let syn = client.poll(now);
+    assert_eq!(syn.len(), 1);
+    assert_eq!(syn[0].flags, TCP_FLAG_SYN);
+    assert_eq!(syn[0].mss, Some(1460));
+    let mut server = TcpConnection::accept(TcpConfig::default(), 1000, &syn[0]).unwrap();
+    assert_eq!(server.state(), TcpState::SynReceived);
+    exchange(&mut server, &mut client, now);
+    assert_eq!(client.state(), TcpState::Established);
+    assert_eq!(server.state(), TcpState::Established);
+    (client, server)
+}
+
+#[test]
+fn tcp_seq_compare_wraps() {
+    assert!(seq_lt(0xffff_fff0, 0x10));
+    assert!(seq_gt(0x10, 0xffff_fff0));
+    assert!(seq_le(5, 5));
+    assert!(!seq_lt(5, 5));
+}
+
+#[test]
+fn tcp_transfer_and_close() {
+    let now = Instant::now();
+    let (mut client, mut server) = handshake(now);
+
+    // the sequence numbers of the client wrap during the transfer
+    let data: Vec<u8> = (0..10000).map(|i| i as u8).collect();
+    assert_eq!(client.send(&data), data.len());
+    exchange(&mut client, &mut server, now);
+    let mut received = vec![0u8; 20000];
+    assert_eq!(server.recv(&mut received), data.len());
+    assert_eq!(&received[..data.len()], &data[..]);
+
+    client.close();
+    exchange(&mut client, &mut server, now);
+    assert_eq!(client.state(), TcpState::FinWait2);
+    assert_eq!(server.state(), TcpState::CloseWait);
+    assert!(server.peer_closed());
+
+    server.close();
+    exchange(&mut server, &mut client, now);
+    assert_eq!(server.state(), TcpState::Closed);
+    assert_eq!(server.close_reason(), Some(CloseReason::Normal));
+    assert_eq!(client.state(), TcpState::TimeWait);
+    client.poll(now + Duration::from_secs(61));
+    assert_eq!(client.state(), TcpState::Closed);
+}
+
+#[test]
+fn tcp_retransmits_lost_segment() {
+    let now = Instant::now();
+    let (mut client, mut server) = handshake(now);
+    client.send(b"hello");
+    let lost = client.poll(now);
+    assert_eq!(lost.len(), 1);
+    assert!(client.poll(now).is_empty());
+
+    let later = now + client.rto();
+    let retransmitted = client.poll(later);
+    assert_eq!(retransmitted.len(), 1);
+    assert_eq!(retransmitted[0].seq, lost[0].seq);
+    assert_eq!(retransmitted[0].payload, b"hello".to_vec());
+    server.on_segment(&retransmitted[0], later);
+    exchange(&mut server, &mut client, later);
+    let mut buf = [0u8; 16];
+    assert_eq!(server.recv(&mut buf), 5);
+    assert!(client.poll(later + Duration::from_secs(10)).is_empty());
+}
+
+#[test]
+fn tcp_probes_closed_window() {
+    let now = Instant::now();
+    let mut client = TcpConnection::connect(TcpConfig::default(), 1).unwrap();
+    let syn = client.poll(now);
+    let config = TcpConfig {
+        recv_buffer: 4096,
+        ..Default::default()
+    };
+    let mut server = TcpConnection::accept(config, 1000, &syn[0]).unwrap();
+    exchange(&mut server, &mut client, now);
+    let data = vec![7u8; 8192];
+    assert_eq!(client.send(&data), data.len());
+    exchange(&mut client, &mut server, now);
+    // the buffer holds one byte less than its size
+    assert_eq!(server.recv_available(), 4095);
+
+    // the server does not read, but acknowledges every probe of its closed window
+    let max = TcpConfig::default().max_retransmissions;
+    let mut later = now;
+    for _ in 0..2 * max {
+        later += client.rto();
+        let probes = client.poll(later);
+        assert_eq!(probes.len(), 1);
+        assert_eq!(probes[0].payload.len(), 1);
+        server.on_segment(&probes[0], later);
+        deliver(&mut server, &mut client, later);
+    }
+    assert_eq!(client.state(), TcpState::Established);
+
+    let mut received = vec![0u8; 8192];
+    let mut read = 0;
+    for _ in 0..4 {
+        read += server.recv(&mut received[read..]);
+        exchange(&mut server, &mut client, later);
+        exchange(&mut client, &mut server, later);
+    }
+    assert_eq!(read, data.len());
+    assert_eq!(received, data);
+
+    // unanswered probes time out
+    client.send(&data);
+    exchange(&mut client, &mut server, later);
+    for _ in 0..=max {
+        later += client.rto();
+        client.poll(later);
+    }
+    assert_eq!(client.close_reason(), Some(CloseReason::Timeout));
+}
+
+#[test]
+fn tcp_reset_refuses_connection() {
+    let now = Instant::now();
+    let mut client = TcpConnection::connect(TcpConfig::default(), 7).unwrap();
+    let syn = client.poll(now);
+    let reset = Segment::reset_for(&syn[0]).unwrap();
+    assert_eq!(reset.flags, TCP_FLAG_RST | TCP_FLAG_ACK);
+    assert_eq!(reset.ack, 8);
+    client.on_segment(&reset, now);
+    assert_eq!(client.state(), TcpState::Closed);
+    assert_eq!(client.close_reason(), Some(CloseReason::Reset));
+}
+
+#[test]
+fn tcp_splice_maps_sequence_numbers() {
+    let client = FiveTupleV4 {
+        src_ip: 0x0a00_0001,
+        dst_ip: 0x0a00_0002,
+        src_port: 40000,
+        dst_port: 80,
+        proto: 6,
+    };
+    let backend = FiveTupleV4 {
+        src_ip: 0x0a00_0002,
+        dst_ip: 0x0a00_0103,
+        src_port: 50000,
+        dst_port: 8080,
+        proto: 6,
+    };
+    // the client sent up to 1100 and received up to 0xffff_fff0, the backend connection continues at 7000 and 300
+    let splice = Splice::new(backend, 1100, 0xffff_fff0, 7000, 300, 0x100, 0xffff_ff00);
+    assert_eq!(1100u32.wrapping_add(splice.seq_delta), 7000);
+    assert_eq!(0xffff_fff0u32.wrapping_add(splice.ack_delta), 300);
+    let reverse = splice.reverse(client.reverse_flow());
+    assert_eq!(300u32.wrapping_add(reverse.seq_delta), 0xffff_fff0);
+    assert_eq!(7000u32.wrapping_add(reverse.ack_delta), 1100);
+    assert_eq!(reverse.tsval_delta, 0x100);
+    assert_eq!(reverse.tsecr_delta, 0xffff_ff00);
+
+    let mut table = SpliceTable::new();
+    table.splice(client, splice);
+    assert_eq!(table.len(), 2);
+    assert_eq!(table.get(&backend.reverse_flow()), Some(&reverse));
+    assert_eq!(table.unsplice(&client), Some(splice));
+    assert!(table.is_empty());
+}
+
+/// An Ethernet/IPv4 packet with an ACK from 10.0.0.1:40000 to 10.0.0.2:80 with a timestamp option.
+fn timestamp_packet(tsval: u32, tsecr: u32) -> Vec<u8> {
+    let (src, dst) = (0x0a00_0001u32, 0x0a00_0002u32);
+    let mut tcp = vec![
+        0x9c, 0x40, 0, 80, 0, 0, 0x04, 0x4c, 0xff, 0xff, 0xff, 0xf0, 0x80, 0x10, 0xff, 0xff,
+    ];
+    tcp.extend_from_slice(&[0, 0, 0, 0, 1, 1, 8, 10]);
+    tcp.extend_from_slice(&tsval.to_be_bytes());
+    tcp.extend_from_slice(&tsecr.to_be_bytes());
+    let csum = ipv4_checksum(tcp.as_mut_ptr(), tcp.len(), 8, &[], src, dst, 6);
+    tcp[16] = (csum >> 8) as u8;
+    tcp[17] = csum as u8;
+    let mut packet = vec![0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1, 0x08, 0];
+    packet.extend_from_slice(&[0x45, 0, 0, 52, 0, 0, 0, 0, 64, 6, 0, 0]);
+    packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    packet.extend(tcp);
+    packet
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+}
+
+#[test]
+fn tcp_splice_rewrites_timestamps() {
+    let backend = FiveTupleV4 {
+        src_ip: 0x0a00_0002,
+        dst_ip: 0x0a00_0103,
+        src_port: 50000,
+        dst_port: 8080,
+        proto: 6,
+    };
+    let splice = Splice::new(backend, 1100, 0xffff_fff0, 7000, 300, 0x100, 0xffff_ff00);
+    let mut packet = timestamp_packet(0x1234_5678, 0x10);
+    {
+        let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+        let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+        assert!(splice.apply(&mut pdu));
+    }
+    let tcp = &mut packet[34..];
+    assert_eq!(be_u32(&tcp[4..8]), 7000);
+    assert_eq!(be_u32(&tcp[8..12]), 300);
+    assert_eq!(be_u32(&tcp[24..28]), 0x1234_5778);
+    assert_eq!(be_u32(&tcp[28..32]), 0x10u32.wrapping_add(0xffff_ff00));
+    // the incremental update matches the checksum over the rewritten segment and pseudo header
+    let csum = (tcp[16] as u16) << 8 | tcp[17] as u16;
+    assert_eq!(
+        ipv4_checksum(tcp.as_mut_ptr(), tcp.len(), 8, &[], 0x0a00_0002, 0x0a00_0103, 6),
+        csum
+    );
+}
+
+#[test]
+fn splice_batch_rewrites_packets_once() {
+    let client = FiveTupleV4 {
+        src_ip: 0x0a00_0001,
+        dst_ip: 0x0a00_0002,
+        src_port: 40000,
+        dst_port: 80,
+        proto: 6,
+    };
+    let backend = FiveTupleV4 {
+        src_ip: 0x0a00_0002,
+        dst_ip: 0x0a00_0103,
+        src_port: 50000,
+        dst_port: 8080,
+        proto: 6,
+    };
+    let table = Rc::new(RefCell::new(SpliceTable::new()));
+    let splice = Splice::new(backend, 1100, 0xffff_fff0, 7000, 300, 0x100, 0xffff_ff00);
+    table.borrow_mut().splice(client, splice);
+    let mut packets = vec![timestamp_packet(1, 2), timestamp_packet(3, 4)];
+    // referenced twice, so that freeing them at the end of the batch only decrements the reference count
+    let mut mbufs: Vec<MBuf> = packets
+        .iter_mut()
+        .map(|packet| {
+            let mut mbuf = mbuf_over(packet, ptr::null_mut());
+            mbuf.refcnt = 2;
+            mbuf
+        })
+        .collect();
+    let (producer, consumer) = new_mpmc_queue_pair();
+    let mut batch = ReceiveBatch::new(consumer).splice(table);
+
+    assert_eq!(producer.enqueue_mbufs(&[&mut mbufs[0] as *mut MBuf]), 1);
+    assert_eq!(batch.act().0, 1);
+    // acting again does not apply the deltas twice
+    assert_eq!(batch.act().0, 0);
+    batch.done();
+    assert_eq!(producer.enqueue_mbufs(&[&mut mbufs[1] as *mut MBuf]), 1);
+    assert_eq!(batch.act().0, 1);
+    batch.done();
+
+    for packet in &packets {
+        assert_eq!(be_u32(&packet[38..42]), 7000);
+        assert_eq!(be_u32(&packet[42..46]), 300);
+    }
+}
+
+/// An Ethernet/IPv4 packet with a SYN from 10.0.0.1:`src_port` to 10.0.0.2:80.
+fn syn_packet(src_port: u16) -> Vec<u8> {
+    let (src, dst) = (0x0a00_0001u32, 0x0a00_0002u32);
+    let mut tcp = vec![
+        (src_port >> 8) as u8,
+        src_port as u8,
+        0,
+        80,
+        0,
+        0,
+        0,
+        1,
+        0,
+        0,
+        0,
+        0,
+        0x50,
+        0x02,
+        0xff,
+        0xff,
+    ];
+    tcp.extend_from_slice(&[0, 0, 0, 0]);
+    let csum = ipv4_checksum(tcp.as_mut_ptr(), tcp.len(), 8, &[], src, dst, 6);
+    tcp[16] = (csum >> 8) as u8;
+    tcp[17] = csum as u8;
+    let mut packet = vec![0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1, 0x08, 0];
+    packet.extend_from_slice(&[0x45, 0, 0, 40, 0, 0, 0, 0, 64, 6, 0, 0]);
+    packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    packet.extend(tcp);
+    packet
+}
+
+fn input(stack: &mut TcpStack, mut packet: Vec<u8>, now: Instant) -> bool {
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    stack.input(&pdu, now)
+}
+
+#[test]
+fn tcp_stack_limits_handshakes_and_connections() {
+    let now = Instant::now();
+    let config = TcpConfig {
+        syn_backlog: 2,
+        ..Default::default()
+    };
+    let mut stack = TcpStack::with_address(Ipv4Addr::new(10, 0, 0, 2), config).unwrap();
+    stack.listen(80);
+    for port in 1000..1004 {
+        assert!(input(&mut stack, syn_packet(port), now));
+    }
+    // SYNs beyond the backlog are dropped, a retransmitted SYN belongs to its connection
+    assert_eq!(stack.len(), 2);
+    assert!(input(&mut stack, syn_packet(1000), now));
+    assert_eq!(stack.len(), 2);
+
+    let config = TcpConfig {
+        max_connections: 3,
+        ..Default::default()
+    };
+    let mut stack = TcpStack::with_address(Ipv4Addr::new(10, 0, 0, 2), config).unwrap();
+    stack.listen(80);
+    for port in 1000..1004 {
+        assert!(input(&mut stack, syn_packet(port), now));
+    }
+    assert_eq!(stack.len(), 3);
+}
@@ -0,0 +1,201 @@
+extern crate e2d2;
+mod common;
+use common::{mbuf_data, mbuf_over, mbuf_with_headroom, ones_complement_sum};
+use e2d2::headers::*;
+use e2d2::interface::Pdu;
+use e2d2::native::zcsi::MBuf;
+use std::ptr;
+
+#[test]
+fn tcp_options_parse() {
+    let data = [
+        TCP_OPT_MSS,
+        4,
+        0x05,
+        0xb4,
+        TCP_OPT_NOP,
+        TCP_OPT_WINDOW_SCALE,
+        3,
+        7,
+        TCP_OPT_SACK_PERMITTED,
+        2,
+        TCP_OPT_TIMESTAMP,
+        10,
+        0,
+        0,
+        0,
+        1,
+        0,
+        0,
+        0,
+        2,
+        TCP_OPT_SACK,
+        10,
+        0,
+        0,
+        1,
+        0,
+        0,
+        0,
+        2,
+        0,
+        TCP_OPT_END,
+        0,
+    ];
+    let options: Vec<TcpOption> = TcpOptions::new(&data).collect();
+    assert_eq!(options.len(), 5);
+    assert_eq!(options[0], TcpOption::Mss(1460));
+    assert_eq!(options[1], TcpOption::WindowScale(7));
+    assert_eq!(options[2], TcpOption::SackPermitted);
+    assert_eq!(options[3], TcpOption::Timestamp { tsval: 1, tsecr: 2 });
+    match options[4] {
+        TcpOption::Sack(blocks) => assert_eq!(blocks.collect::<Vec<_>>(), vec![(0x100, 0x200)]),
+        option => panic!("unexpected option {:?}", option),
+    }
+
+    let mut encoded = Vec::new();
+    for option in &options {
+        option.encode(&mut encoded);
+    }
+    let mut expected = data[..4].to_vec();
+    expected.extend_from_slice(&data[5..30]);
+    assert_eq!(encoded, expected);
+}
+
+#[test]
+fn tcp_options_malformed() {
+    let blocks = [0, 0, 0, 1, 0, 0, 0, 5, 0, 0, 0, 9, 0, 0, 0, 12];
+    let mut data = vec![TCP_OPT_SACK, 18];
+    data.extend_from_slice(&blocks);
+    data.extend_from_slice(&[TCP_OPT_MSS, 4, 0x05]);
+    let mut options = TcpOptions::new(&data);
+    match options.next() {
+        Some(TcpOption::Sack(sack)) => assert_eq!(sack.collect::<Vec<_>>(), vec![(1, 5), (9, 12)]),
+        option => panic!("unexpected option {:?}", option),
+    }
+    assert!(options.next().is_none());
+    assert!(options.malformed());
+
+    // a SACK option with a truncated block is not a valid SACK option
+    let data = [TCP_OPT_SACK, 6, 0, 0, 0, 1];
+    let option = TcpOptions::new(&data).next().unwrap();
+    assert_eq!(option.kind(), TCP_OPT_SACK);
+    assert_eq!(option.len(), 6);
+    assert!(!option.is_empty());
+    assert!(TcpOption::SackPermitted.is_empty());
+}
+
+#[test]
+fn tcp_options_beyond_available_bytes() {
+    let mut bytes = vec![0u8; 24];
+    // data offset of 6 words, i.e. 4 bytes of options
+    bytes[12] = 0x60;
+    bytes[20..24].copy_from_slice(&[TCP_OPT_MSS, 4, 0x05, 0xb4]);
+    let tcp = unsafe { &mut *(bytes.as_mut_ptr() as *mut TcpHeader) };
+    assert_eq!(tcp.mss(24), Some(1460));
+    assert!(tcp.option_bytes(23).is_none());
+    assert!(tcp.option_bytes_mut(20).is_none());
+    assert_eq!(tcp.mss(20), None);
+    assert!(!tcp.clamp_mss(1400, 20));
+    assert_eq!(tcp.mss(24), Some(1460));
+    assert!(tcp.clamp_mss(1400, 24));
+    assert_eq!(tcp.mss(24), Some(1400));
+}
+
+const HEADROOM: usize = 64;
+const PAYLOAD: &[u8] = b"data";
+
+/// A TCP/IPv4 SYN with `options` and valid checksums, preceded by `HEADROOM` bytes for longer options.
+fn syn_with_headroom(options: &[u8]) -> Vec<u8> {
+    let mut buffer = vec![0u8; HEADROOM];
+    buffer.extend_from_slice(&[0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1, 0x08, 0]);
+    let ip_len = 20 + 20 + options.len() + PAYLOAD.len();
+    buffer.extend_from_slice(&[0x45, 0, 0, ip_len as u8, 0, 0, 0, 0, 64, 6, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+    let data_offset = ((20 + options.len()) / 4) as u8;
+    buffer.extend_from_slice(&[0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, 0, data_offset << 4, 0x02, 0xff, 0xff]);
+    buffer.extend_from_slice(&[0, 0, 0, 0]);
+    buffer.extend_from_slice(options);
+    buffer.extend_from_slice(PAYLOAD);
+    let ip = HEADROOM + 14;
+    let csum = !ones_complement_sum(&buffer[ip..ip + 20], 0);
+    buffer[ip + 10..ip + 12].copy_from_slice(&[(csum >> 8) as u8, csum as u8]);
+    let pseudo_header = ones_complement_sum(&buffer[ip + 12..ip + 20], 6 + (ip_len - 20) as u32);
+    let csum = !ones_complement_sum(&buffer[ip + 20..], pseudo_header as u32);
+    buffer[ip + 36..ip + 38].copy_from_slice(&[(csum >> 8) as u8, csum as u8]);
+    buffer
+}
+
+/// Check that the packet in `mbuf` carries `options` and the payload, with matching lengths and valid checksums.
+fn check_syn(mbuf: &MBuf, options: &[u8]) {
+    let ip = &mbuf_data(mbuf)[14..];
+    let ip_len = 20 + 20 + options.len() + PAYLOAD.len();
+    assert_eq!(ip.len(), ip_len);
+    assert_eq!(((ip[2] as usize) << 8) | ip[3] as usize, ip_len);
+    assert_eq!(ones_complement_sum(&ip[..20], 0), 0xffff);
+    let tcp = &ip[20..];
+    assert_eq!(tcp[12] >> 4, ((20 + options.len()) / 4) as u8);
+    assert_eq!(&tcp[20..20 + options.len()], options);
+    assert_eq!(&tcp[20 + options.len()..], PAYLOAD);
+    let pseudo_header = ones_complement_sum(&ip[12..20], 6 + tcp.len() as u32);
+    assert_eq!(ones_complement_sum(tcp, pseudo_header as u32), 0xffff);
+}
+
+const SYN_OPTIONS: [u8; 8] = [TCP_OPT_MSS, 4, 0x05, 0xb4, TCP_OPT_SACK_PERMITTED, 2, TCP_OPT_NOP, TCP_OPT_NOP];
+
+#[test]
+fn tcp_options_clamp_mss() {
+    let mut buffer = syn_with_headroom(&SYN_OPTIONS);
+    let mut mbuf = mbuf_with_headroom(&mut buffer, HEADROOM);
+    check_syn(&mbuf, &SYN_OPTIONS);
+    {
+        let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+        assert!(!pdu.clamp_tcp_mss(1500));
+        assert!(pdu.clamp_tcp_mss(1400));
+        assert_eq!(pdu.headers().tcp(2).mss(pdu.header_available(2)), Some(1400));
+    }
+    let mut clamped = SYN_OPTIONS;
+    clamped[2..4].copy_from_slice(&[0x05, 0x78]);
+    check_syn(&mbuf, &clamped);
+}
+
+#[test]
+fn tcp_options_edit() {
+    let mut buffer = syn_with_headroom(&SYN_OPTIONS);
+    let mut mbuf = mbuf_with_headroom(&mut buffer, HEADROOM);
+
+    // appending an option grows the header, padding is dropped
+    let timestamp = TcpOption::Timestamp { tsval: 1, tsecr: 2 };
+    Pdu::pdu_from_mbuf_no_increment(&mut mbuf).set_tcp_option(2, &timestamp).unwrap();
+    let mut expected = SYN_OPTIONS[..6].to_vec();
+    expected.extend_from_slice(&[TCP_OPT_TIMESTAMP, 10, 0, 0, 0, 1, 0, 0, 0, 2]);
+    check_syn(&mbuf, &expected);
+
+    // replacing an option keeps the length
+    Pdu::pdu_from_mbuf_no_increment(&mut mbuf).set_tcp_option(2, &TcpOption::Mss(1200)).unwrap();
+    expected[2..4].copy_from_slice(&[0x04, 0xb0]);
+    check_syn(&mbuf, &expected);
+
+    // removing an option shrinks the header, the options are padded to full words
+    {
+        let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+        assert!(pdu.remove_tcp_option(2, TCP_OPT_TIMESTAMP).unwrap());
+        assert!(!pdu.remove_tcp_option(2, TCP_OPT_TIMESTAMP).unwrap());
+    }
+    check_syn(&mbuf, &[TCP_OPT_MSS, 4, 0x04, 0xb0, TCP_OPT_SACK_PERMITTED, 2, TCP_OPT_END, 0]);
+
+    Pdu::pdu_from_mbuf_no_increment(&mut mbuf).set_tcp_options(2, &[]).unwrap();
+    check_syn(&mbuf, &[]);
+
+    // at most 40 bytes of options, and only on TCP headers
+    let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    assert!(pdu.set_tcp_options(2, &[TCP_OPT_NOP; 41]).is_err());
+    assert!(pdu.set_tcp_options(1, &[]).is_err());
+    assert!(pdu.remove_tcp_option(1, TCP_OPT_MSS).is_err());
+
+    // longer options need headroom, the packet is not modified without
+    let mut packet = syn_with_headroom(&SYN_OPTIONS)[HEADROOM..].to_vec();
+    let mut mbuf = mbuf_over(&mut packet, ptr::null_mut());
+    let window_scale = TcpOption::WindowScale(7);
+    assert!(Pdu::pdu_from_mbuf_no_increment(&mut mbuf).set_tcp_option(2, &window_scale).is_err());
+    check_syn(&mbuf, &SYN_OPTIONS);
+}
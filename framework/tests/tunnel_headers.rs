@@ -0,0 +1,151 @@
+extern crate e2d2;
+extern crate eui48;
+mod common;
+use common::mbuf_with_headroom;
+use e2d2::headers::*;
+use e2d2::interface::{Pdu, Tunnel, TunnelSpec};
+use e2d2::native::zcsi::MBuf;
+use e2d2::operators::*;
+use e2d2::queues::new_mpmc_queue_pair;
+use eui48::MacAddress;
+use std::net::Ipv4Addr;
+
+#[test]
+fn vxlan_vni() {
+    let mut vxlan = VxlanHeader::new();
+    assert!(vxlan.vni_valid());
+    vxlan.set_vni(0x00ab_cdef);
+    assert_eq!(vxlan.vni(), 0x00ab_cdef);
+    assert_eq!(vxlan.offset(), 8);
+}
+
+#[test]
+fn nvgre_flags() {
+    let gre = GreHeader::new_nvgre();
+    assert!(gre.key_present());
+    assert!(!gre.checksum_present());
+    assert_eq!(gre.protocol(), ETYPE_TRANSPARENT_ETHERNET);
+    assert_eq!(gre.offset(), 8);
+}
+
+#[test]
+fn geneve_options_length() {
+    let mut geneve = GeneveHeader::new();
+    geneve.set_vni(42);
+    assert_eq!(geneve.vni(), 42);
+    assert_eq!(geneve.offset(), 8);
+    geneve.set_opt_len(2);
+    assert_eq!(geneve.offset(), 16);
+}
+
+#[test]
+fn gre_optional_fields() {
+    // checksum, key and sequence number present, the header starts at an odd address
+    let mut bytes = vec![0u8; 1 + 16];
+    bytes[1..5].copy_from_slice(&[0xb0, 0, 0x65, 0x58]);
+    bytes[9..13].copy_from_slice(&[0x12, 0x34, 0x56, 0x07]);
+    bytes[13..17].copy_from_slice(&[0, 0, 0, 42]);
+    let gre = unsafe { &mut *(bytes.as_mut_ptr().offset(1) as *mut GreHeader) };
+    assert_eq!(gre.offset(), 16);
+    assert_eq!(gre.key(), Some(0x1234_5607));
+    assert_eq!((gre.vsid(), gre.flow_id()), (Some(0x12_3456), Some(7)));
+    assert_eq!(gre.seq_num(), Some(42));
+    gre.set_key(0xabcd_ef01);
+    assert_eq!(&bytes[5..17], &[0, 0, 0, 0, 0xab, 0xcd, 0xef, 0x01, 0, 0, 0, 42]);
+}
+
+const HEADROOM: usize = 64;
+
+/// An Ethernet frame with a UDP/IPv4 packet, preceded by `HEADROOM` bytes for the outer headers.
+fn frame_with_headroom() -> Vec<u8> {
+    let mut buffer = vec![0u8; HEADROOM];
+    buffer.extend_from_slice(&[0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1, 0x08, 0]);
+    buffer.extend_from_slice(&[0x45, 0, 0, 32, 0, 0, 0, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+    buffer.extend_from_slice(&[0x30, 0x39, 0, 53, 0, 12, 0, 0, 1, 2, 3, 4]);
+    buffer
+}
+
+fn nvgre_spec() -> TunnelSpec {
+    TunnelSpec {
+        src_mac: MacAddress::new([0x02, 0, 0, 0, 0, 0x11]),
+        dst_mac: MacAddress::new([0x02, 0, 0, 0, 0, 0x22]),
+        src_ip: Ipv4Addr::new(192, 168, 0, 1),
+        dst_ip: Ipv4Addr::new(192, 168, 0, 2),
+        ttl: 64,
+        tunnel: Tunnel::Nvgre(0x12_3456, 7),
+    }
+}
+
+/// Check the NVGRE encapsulation of the frame returned by `frame_with_headroom`.
+fn check_nvgre(pdu: &Pdu) {
+    let headers = pdu.headers();
+    assert_eq!(headers.count(), 6);
+    assert_eq!(headers.inner_start(), Some(3));
+    let ip = headers.ip(1);
+    assert_eq!(ip.protocol(), IP_PROTO_GRE);
+    assert_eq!(ip.length() as usize, pdu.data_len() - 14);
+    let gre = headers.gre(2);
+    assert_eq!(gre.protocol(), ETYPE_TRANSPARENT_ETHERNET);
+    assert_eq!((gre.vsid(), gre.flow_id()), (Some(0x12_3456), Some(7)));
+    assert_eq!(headers.udp(5).dst_port(), 53);
+}
+
+#[test]
+fn nvgre_encap_decap() {
+    let mut buffer = frame_with_headroom();
+    let frame = buffer[HEADROOM..].to_vec();
+    let spec = nvgre_spec();
+    let mut mbuf = mbuf_with_headroom(&mut buffer, HEADROOM);
+    let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    assert!(pdu.decap_tunnel().is_err());
+    pdu.encap_tunnel(&spec).unwrap();
+    assert_eq!(pdu.data_len(), frame.len() + spec.overhead());
+    check_nvgre(&pdu);
+    pdu.decap_tunnel().unwrap();
+    assert_eq!(pdu.headers().count(), 3);
+    assert_eq!(&buffer[HEADROOM..], &frame[..]);
+
+    // not enough headroom
+    let mut buffer = frame_with_headroom();
+    let mut mbuf = mbuf_with_headroom(&mut buffer, HEADROOM);
+    mbuf.data_off = 16;
+    let mut pdu = Pdu::pdu_from_mbuf_no_increment(&mut mbuf);
+    assert!(pdu.encap_tunnel(&spec).is_err());
+}
+
+/// Receive the packets of `batch` and take their mbufs out of the batch.
+fn take_mbufs<T: Batch + BatchIterator + Act>(batch: &mut T) -> Vec<*mut MBuf> {
+    let mut mbufs = Vec::new();
+    while let Some(pdu) = batch.next_payload(mbufs.len()) {
+        mbufs.push(unsafe { pdu.get_mbuf() });
+    }
+    batch.clear_packets();
+    mbufs
+}
+
+#[test]
+fn encap_decap_batches() {
+    let mut buffers = vec![frame_with_headroom(), frame_with_headroom()];
+    let frame = buffers[0][HEADROOM..].to_vec();
+    let mut mbufs: Vec<MBuf> = buffers.iter_mut().map(|buffer| mbuf_with_headroom(buffer, HEADROOM)).collect();
+    let pointers: Vec<*mut MBuf> = mbufs.iter_mut().map(|mbuf| mbuf as *mut MBuf).collect();
+
+    let (producer, consumer) = new_mpmc_queue_pair();
+    let mut encap = ReceiveBatch::new(consumer).encap(nvgre_spec());
+    assert_eq!(producer.enqueue_mbufs(&pointers), 2);
+    assert_eq!(encap.act().0, 2);
+    for i in 0..2 {
+        check_nvgre(&encap.next_payload(i).unwrap());
+    }
+    let encapsulated = take_mbufs(&mut encap);
+    assert_eq!(encapsulated, pointers);
+
+    let (producer, consumer) = new_mpmc_queue_pair();
+    let mut decap = ReceiveBatch::new(consumer).decap();
+    assert_eq!(producer.enqueue_mbufs(&encapsulated), 2);
+    assert_eq!(decap.act().0, 2);
+    assert_eq!(take_mbufs(&mut decap), pointers);
+    for buffer in &buffers {
+        assert_eq!(&buffer[HEADROOM..], &frame[..]);
+    }
+}
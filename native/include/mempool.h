@@ -12,10 +12,12 @@ RTE_DECLARE_PER_LCORE(int, _mempool_core);
 typedef struct rte_mbuf* restrict* restrict mbuf_array_t;
 /* Called by system initialization */
 int init_mempool_core(int core);
-int init_mempool(int master_core, unsigned int mempool_size, unsigned int mcache_size, unsigned short slots);
+int init_mempool(int master_core, unsigned int n_mbufs[], int n_sockets, unsigned int mcache_size, unsigned short slots,
+                 uint16_t data_room_size);
 int init_secondary_mempool(const char* mempool_name);
 int find_secondary_mempool();
 unsigned int mbuf_avail_count();
+int mbuf_avail_count_pool(int pool_id);
 struct rte_mbuf* mbuf_alloc();
 void mbuf_free(struct rte_mbuf* buf);
 int mbuf_alloc_bulk(struct rte_mbuf **array, unsigned int cnt);
@@ -4,7 +4,8 @@ int num_pmd_ports();
 int get_pmd_ports(struct rte_eth_dev_info* info, int len);
 void enumerate_pmd_ports();
 int init_pmd_port(int port, int rxqs, int txqs, int rxq_core[], int txq_core[], int nrxd, int ntxd,
-                  int loopback, int tso, int csumoffload, struct rte_fdir_conf const* p_fdir_conf);
+                  int loopback, int tso, int csumoffload, struct rte_fdir_conf const* p_fdir_conf, int pool_sid,
+                  uint16_t mtu);
 int free_pmd_port(int port);
 int recv_pkts(int port, int qid, mbuf_array_t pkts, int len);
 int send_pkts(int port, int qid, mbuf_array_t pkts, int len);
@@ -175,7 +175,8 @@ int init_secondary(const char* name, int nlen, unsigned long long lcore_mask, in
 }
 
 int init_system_whitelisted(const char* name, int nlen, unsigned long long lcore_mask, int main_lcore, char* whitelist[], int wlcount,
-                            unsigned int mempool_size, unsigned int mcache_size, unsigned int mbuf_cnt, int slots, char* vdevs[], int vdevcount ) {
+                            unsigned int mempool_size, unsigned int mcache_size, unsigned int mbuf_cnt[], int n_sockets,
+                            uint16_t data_room_size, int slots, char* vdevs[], int vdevcount ) {
     int ret = 0;
     if (name == NULL || nlen >= MAX_NAME_LEN) {
         return -EINVAL;
@@ -188,7 +189,8 @@ int init_system_whitelisted(const char* name, int nlen, unsigned long long lcore
     if ((ret = init_eal(clean_name, 0, lcore_mask, main_lcore, mempool_size, whitelist, wlcount, vdevs, vdevcount)) < 0) {
         return ret;
     }
-    return init_mempool(main_lcore, mbuf_cnt, mcache_size, slots); // we request here #mbufs, not MB as in rte_eal_init !
+    // we request here #mbufs per socket, not MB as in rte_eal_init !
+    return init_mempool(main_lcore, mbuf_cnt, n_sockets, mcache_size, slots, data_room_size);
 }
 
 /* Call this from the main thread on ZCSI to initialize things. This initializes
@@ -32,6 +32,7 @@ static int mempool_initialized[RTE_MAX_LCORE];
 static unsigned int core_mempool_size;
 static unsigned int core_mempool_cache_size;
 static unsigned short core_metadata_slots;
+static uint16_t core_data_room_size;
 #else
 /* Creating one pool per NUMA node. */
 static struct rte_mempool *pframe_pool[RTE_MAX_NUMA_NODES];
@@ -66,7 +67,7 @@ int init_mempool_core(int core) {
     sid               = rte_lcore_to_socket_id(core);
     pframe_pool[core] = rte_pktmbuf_pool_create(name, core_mempool_size, core_mempool_cache_size,
                                                 core_metadata_slots * METADATA_SLOT_SIZE,
-                                                RTE_MBUF_DEFAULT_BUF_SIZE, sid);
+                                                core_data_room_size, sid);
     if (pframe_pool[core] == NULL) {
         return -ENOMEM;
     }
@@ -94,11 +95,16 @@ struct rte_mempool *get_mempool_for_core(int coreid) {
     return get_pframe_pool(coreid, rte_lcore_to_socket_id(coreid));
 }
 
-static int init_mempool_socket(int sid, unsigned int n_mbufs, unsigned int mcache_size, uint16_t metadata_slots) {
+static int init_mempool_socket(int sid, unsigned int n_mbufs, unsigned int mcache_size, uint16_t metadata_slots,
+                               uint16_t data_room_size) {
     char name[256];
     sprintf(name, "pframe%d", sid);
     pframe_pool[sid] = rte_pktmbuf_pool_create(name, n_mbufs, mcache_size, metadata_slots * METADATA_SLOT_SIZE,
-                                               RTE_MBUF_DEFAULT_BUF_SIZE, sid);
+                                               data_room_size, sid);
+    if (pframe_pool[sid] != NULL) {
+        RTE_LOG(INFO, MEMPOOL, "created mempool %s with %u mbufs (data room %u) on socket %d\n", name, n_mbufs,
+                data_room_size, sid);
+    }
     return pframe_pool[sid] != NULL;
 }
 
@@ -106,20 +112,59 @@ unsigned int mbuf_avail_count() {
     return rte_mempool_avail_count(current_pframe_pool());
 }
 
-int init_mempool(int master_core, unsigned int n_mbufs, unsigned int mcache_size, unsigned short metadata_slots) {
+/* Number of free mbufs in the pool with index pool_id (NUMA socket, or core when PER_CORE is set).
+ * In contrast to mbuf_avail_count this can be called from threads which are not bound to an lcore. */
+int mbuf_avail_count_pool(int pool_id) {
+    if (pool_id < 0 || pool_id >= (int)(sizeof(pframe_pool) / sizeof(pframe_pool[0])) || pframe_pool[pool_id] == NULL) {
+        return -ENOENT;
+    }
+    return rte_mempool_avail_count(pframe_pool[pool_id]);
+}
+
+/* Create the packet mempools. n_mbufs[sid] is the number of mbufs in the pool of NUMA socket sid, sockets with
+ * n_mbufs[sid] == 0 get no pool, unless an enabled lcore is located on them. For those the largest configured size is
+ * used. */
+int init_mempool(int master_core, unsigned int n_mbufs[], int n_sockets, unsigned int mcache_size,
+                 unsigned short metadata_slots, uint16_t data_room_size) {
+    unsigned int max_mbufs = 0;
+    for (int sid = 0; sid < n_sockets; sid++) {
+        if (n_mbufs[sid] > max_mbufs) {
+            max_mbufs = n_mbufs[sid];
+        }
+    }
 #if (!PER_CORE)
     int initialized[RTE_MAX_NUMA_NODES];
     for (int i = 0; i < RTE_MAX_NUMA_NODES; i++) {
         initialized[i] = 0;
     }
 
-    /* Loop through all cores, to see if any of them belong to this
-     * socket. */
+    /* Pools requested explicitly, e.g. because a port selected the pool of a socket without lcores. */
+    for (int sid = 0; sid < n_sockets && sid < RTE_MAX_NUMA_NODES; sid++) {
+        if (n_mbufs[sid] > 0) {
+            struct rte_mbuf *mbuf;
+            if (!init_mempool_socket(sid, n_mbufs[sid], mcache_size, metadata_slots, data_room_size)) {
+                goto fail;
+            }
+            /* Initialize mbuf template */
+            mbuf               = rte_pktmbuf_alloc(pframe_pool[sid]);
+            mbuf_template[sid] = *mbuf;
+            rte_pktmbuf_free(mbuf);
+            initialized[sid] = 1;
+        }
+    }
+
+    /* Loop through all enabled cores, to make sure every socket with an lcore has a pool. */
     for (int i = 0; i < RTE_MAX_LCORE; i++) {
-        int sid = rte_lcore_to_socket_id(i);
+        int sid;
+        if (!rte_lcore_is_enabled(i) && i != master_core) {
+            continue;
+        }
+        sid = rte_lcore_to_socket_id(i);
         if (!initialized[sid]) {
             struct rte_mbuf *mbuf;
-            if (!init_mempool_socket(sid, n_mbufs, mcache_size, metadata_slots)) {
+            RTE_LOG(WARNING, MEMPOOL, "no mempool size given for socket %d of lcore %d, using %u mbufs\n", sid, i,
+                    max_mbufs);
+            if (!init_mempool_socket(sid, max_mbufs, mcache_size, metadata_slots, data_room_size)) {
                 goto fail;
             }
             /* Initialize mbuf template */
@@ -136,9 +181,10 @@ fail:
     return -ENOMEM;
 #else
 
-    core_mempool_size       = n_mbufs;
+    core_mempool_size       = max_mbufs;
     core_mempool_cache_size = mcache_size;
     core_metadata_slots     = metadata_slots;
+    core_data_room_size     = data_room_size;
     memset(mempool_initialized, 0, sizeof(int) * RTE_MAX_LCORE);
     return init_mempool_core(master_core);
 #endif
@@ -231,7 +231,8 @@ assert_link_status(int port_id) {
 
 
 int init_pmd_port(uint16_t port, uint16_t rxqs, uint16_t txqs, int rxq_core[], int txq_core[], uint16_t nrxd, uint16_t ntxd,
-                  int loopback, int tso, int csumoffload, enum rte_eth_rx_mq_mode rx_mq_mode, struct rte_fdir_conf const *p_fdir_conf) {
+                  int loopback, int tso, int csumoffload, enum rte_eth_rx_mq_mode rx_mq_mode, struct rte_fdir_conf const *p_fdir_conf,
+                  int pool_sid, uint16_t mtu) {
     struct rte_eth_dev_info dev_info = {};
     struct rte_eth_conf eth_conf;
     struct rte_eth_rxconf eth_rxconf;
@@ -259,6 +260,26 @@ int init_pmd_port(uint16_t port, uint16_t rxqs, uint16_t txqs, int rxq_core[], i
         eth_conf.txmode.offloads = DEV_TX_OFFLOAD_IPV4_CKSUM | DEV_TX_OFFLOAD_UDP_CKSUM | DEV_TX_OFFLOAD_TCP_CKSUM;
         eth_conf.rxmode.offloads = DEV_RX_OFFLOAD_IPV4_CKSUM | DEV_RX_OFFLOAD_UDP_CKSUM | DEV_RX_OFFLOAD_TCP_CKSUM;
     }
+    if (tso) {
+        if (dev_info.tx_offload_capa & DEV_TX_OFFLOAD_TCP_TSO) {
+            /* TSO needs the checksum offloads, segments are taken from chained mbufs */
+            eth_conf.txmode.offloads |= DEV_TX_OFFLOAD_TCP_TSO | DEV_TX_OFFLOAD_IPV4_CKSUM | DEV_TX_OFFLOAD_TCP_CKSUM;
+            if (dev_info.tx_offload_capa & DEV_TX_OFFLOAD_MULTI_SEGS) eth_conf.txmode.offloads |= DEV_TX_OFFLOAD_MULTI_SEGS;
+        } else {
+            RTE_LOG(WARNING, PMD, "port %d does not support TSO, segment in software\n", port);
+        }
+    }
+    if (mtu > RTE_ETHER_MTU) {
+        /* jumbo frames: let the device receive frames up to mtu, larger frames are scattered into mbuf chains */
+        eth_conf.rxmode.max_rx_pkt_len = mtu + RTE_ETHER_HDR_LEN + RTE_ETHER_CRC_LEN;
+        if (dev_info.rx_offload_capa & DEV_RX_OFFLOAD_JUMBO_FRAME) {
+            eth_conf.rxmode.offloads |= DEV_RX_OFFLOAD_JUMBO_FRAME;
+        } else {
+            RTE_LOG(WARNING, PMD, "port %d does not support jumbo frames\n", port);
+        }
+        if (dev_info.rx_offload_capa & DEV_RX_OFFLOAD_SCATTER) eth_conf.rxmode.offloads |= DEV_RX_OFFLOAD_SCATTER;
+        if (dev_info.tx_offload_capa & DEV_TX_OFFLOAD_MULTI_SEGS) eth_conf.txmode.offloads |= DEV_TX_OFFLOAD_MULTI_SEGS;
+    }
     eth_rxconf = dev_info.default_rxconf;
     /* Drop packets when no descriptors are available */
     //eth_rxconf.rx_drop_en = 0; // changed that to 0, because 82574L seems not supporting this
@@ -300,12 +321,32 @@ int init_pmd_port(uint16_t port, uint16_t rxqs, uint16_t txqs, int rxq_core[], i
         return ret; /* Don't need to clean up here */
     }
 
+    if (mtu > 0) {
+        ret = rte_eth_dev_set_mtu(port, mtu);
+        if (ret != 0) {
+            RTE_LOG(CRIT, PMD, "Failed to set mtu %d on port %d\n", mtu, port);
+            return ret;
+        }
+    }
+
     /* Set to promiscuous mode */
     rte_eth_promiscuous_enable(port);
 
     for (i = 0; i < rxqs; i++) {
         int sid = rte_lcore_to_socket_id(rxq_core[i]);
-        ret = rte_eth_rx_queue_setup(port, i, nrxd, sid, &eth_rxconf, get_pframe_pool(rxq_core[i], sid));
+        /* pool_sid < 0 selects the pool on the socket of the core serving the queue */
+        struct rte_mempool *pool = NULL;
+        if (pool_sid >= 0 && pool_sid < RTE_MAX_NUMA_NODES) {
+            pool = get_pframe_pool(rxq_core[i], pool_sid);
+        } else {
+            pool = get_pframe_pool(rxq_core[i], sid);
+        }
+        if (pool == NULL) {
+            /* the pools are sized for the queues using them, another pool could run dry */
+            RTE_LOG(CRIT, PMD, "No mempool on socket %d for rxq %d of port %d\n", pool_sid, i, port);
+            return -ENOENT;
+        }
+        ret = rte_eth_rx_queue_setup(port, i, nrxd, sid, &eth_rxconf, pool);
         if (ret != 0) {
             RTE_LOG(CRIT, PMD, "Failed to initialize rxq\n");
             return ret; /* Clean things up? */
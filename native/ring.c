@@ -1,12 +1,15 @@
 #include <assert.h>
+#include <errno.h>
 #include <stdio.h>
 #include <stdlib.h>
 #include <string.h>
-#include <unistd.h>
 
+#include <rte_bus_vdev.h>
 #include <rte_config.h>
+#include <rte_eal.h>
 #include <rte_eth_ring.h>
 #include <rte_ethdev.h>
+#include <rte_lcore.h>
 #include <rte_log.h>
 #include <rte_ring.h>
 
@@ -118,3 +121,105 @@ static int init_ovs_ring(int iface, struct rte_mempool *mempool) {
 int init_ovs_eth_ring(int iface, int core) {
     return init_ovs_ring(iface, get_mempool_for_core(core));
 }
+
+/* Look up the ring NAME_DIRQUEUE, and create it with COUNT slots if it does not exist and CREATE is set. */
+static struct rte_ring *named_ring(const char *name, const char *dir, int queue, unsigned count, int create) {
+    char ring_name[RTE_RING_NAMESIZE];
+    struct rte_ring *ring;
+
+    if (snprintf(ring_name, RTE_RING_NAMESIZE, "%s_%s%d", name, dir, queue) >= RTE_RING_NAMESIZE) {
+        RTE_LOG(WARNING, PMD, "Ring name %s is too long\n", name);
+        return NULL;
+    }
+    ring = rte_ring_lookup(ring_name);
+    if (ring == NULL && create) {
+        /* each queue of the port is used by a single core in each process */
+        ring = rte_ring_create(ring_name, count, rte_socket_id(), RING_F_SP_ENQ | RING_F_SC_DEQ);
+    }
+    if (ring == NULL) {
+        RTE_LOG(WARNING, PMD, "Could not find ring %s\n", ring_name);
+    }
+    return ring;
+}
+
+/* Attach to the ring port PORT_NAME created by a previous secondary process, whose device outlives it. Returns the
+ * port, or a negative value if there is no such port. */
+static int attach_ring_port(const char *port_name) {
+    /* the name of the device created by rte_eth_from_rings */
+    char dev_name[PORT_NAME_LEN + 16];
+    uint16_t port;
+
+    snprintf(dev_name, sizeof(dev_name), "net_ring_%s", port_name);
+    if (rte_eth_dev_get_port_by_name(dev_name, &port) == 0) {
+        return port;
+    }
+    /* the ring PMD attaches a secondary process to the existing device when probed without arguments */
+    if (rte_vdev_init(dev_name, "") != 0 || rte_eth_dev_get_port_by_name(dev_name, &port) != 0) {
+        return -ENODEV;
+    }
+    return port;
+}
+
+/**
+ * Connect two NetBricks processes which share DPDK memory, i.e. one is a secondary process of the other. The
+ * primary process creates the rings NAME_p2s<queue> and NAME_s2p<queue> if they do not exist yet and sends on the
+ * p2s rings, the secondary process attaches to them and sends on the s2p rings. The rings outlive a restart of the
+ * secondary process. RX_SIZE and TX_SIZE must be powers of 2 and are only used when the rings are created.
+ **/
+int init_named_eth_ring(const char *name, int nrxq, int ntxq, unsigned rx_size, unsigned tx_size, int core) {
+    struct rte_ring *rxqs[MAX_QUEUES_PER_DIR];
+    struct rte_ring *txqs[MAX_QUEUES_PER_DIR];
+    struct rte_mempool *mempool = get_mempool_for_core(core);
+    char port_name[PORT_NAME_LEN];
+    int primary = rte_eal_process_type() == RTE_PROC_PRIMARY;
+    int port;
+    int ret;
+    int i;
+
+    if (nrxq < 1 || ntxq < 1 || nrxq > MAX_QUEUES_PER_DIR || ntxq > MAX_QUEUES_PER_DIR) {
+        return -EINVAL;
+    }
+    for (i = 0; i < nrxq; i++) {
+        rxqs[i] = named_ring(name, primary ? "s2p" : "p2s", i, rx_size, primary);
+        if (rxqs[i] == NULL) {
+            return -ENOENT;
+        }
+    }
+    for (i = 0; i < ntxq; i++) {
+        txqs[i] = named_ring(name, primary ? "p2s" : "s2p", i, tx_size, primary);
+        if (txqs[i] == NULL) {
+            return -ENOENT;
+        }
+    }
+    /* Ethernet devices are shared by the processes, so both ends need their own. The device of a secondary process
+     * is not released when it exits, a restarted secondary process attaches to it again. Its queues are set up
+     * already, they refer to the same rings. */
+    snprintf(port_name, PORT_NAME_LEN, "ring_%s_%s", name, primary ? "p" : "s");
+    if (!primary) {
+        port = attach_ring_port(port_name);
+        if (port >= 0) {
+            RTE_LOG(INFO, PMD, "Attached to ring port %s, port %d\n", port_name, port);
+            return port;
+        }
+    }
+    port = rte_eth_from_rings(port_name, rxqs, nrxq, txqs, ntxq, rte_socket_id());
+    if (port < 0) {
+        return port;
+    }
+    for (i = 0; i < nrxq; i++) {
+        ret = rte_eth_rx_queue_setup(port, i, 32, 0, NULL, mempool);
+        if (ret < 0) {
+            RTE_LOG(WARNING, PMD, "Could not set up RX queue %d of ring port %s: %d\n", i, port_name, ret);
+            return ret;
+        }
+    }
+    for (i = 0; i < ntxq; i++) {
+        ret = rte_eth_tx_queue_setup(port, i, 32, 0, NULL);
+        if (ret < 0) {
+            RTE_LOG(WARNING, PMD, "Could not set up TX queue %d of ring port %s: %d\n", i, port_name, ret);
+            return ret;
+        }
+    }
+    RTE_LOG(INFO, PMD, "Ring port %s is port %d\n", port_name, port);
+    return port;
+}
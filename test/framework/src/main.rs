@@ -36,16 +36,14 @@ fn monitor<T: 'static + Batch>(
         })
 }
 
-fn recv_thread(ports: Vec<CacheAligned<PortQueue>>, core: i32, counter: MergeableStoreDP<isize>) {
+fn recv_thread(ports: Vec<CacheAligned<PortQueue>>, core: i32, counters: Vec<MergeableStoreDP<isize>>) {
     init_thread(core, core);
     println!("Receiving started");
 
     let pipelines: Vec<_> = ports
         .iter()
-        .map(|port| {
-            let ctr = counter.clone();
-            box monitor(ReceiveBatch::new(port.clone()), ctr).send(port.clone()) as Box<dyn Batch>
-        })
+        .zip(counters)
+        .map(|(port, ctr)| box monitor(ReceiveBatch::new(port.clone()), ctr).send(port.clone()) as Box<dyn Batch>)
         .collect();
     println!("Running {} pipelines", pipelines.len());
     let mut combined = merge_batches(pipelines);
@@ -132,7 +130,8 @@ fn main() {
         .iter()
         .map(|(core, ports)| {
             let c = core.clone();
-            let mon = consumer.dp_store();
+            // a store for each pipeline
+            let mon: Vec<_> = ports.iter().map(|_| consumer.dp_store()).collect();
             let p: Vec<_> = ports.iter().map(|p| p.clone()).collect();
             std::thread::spawn(move || recv_thread(p, c, mon))
         })